@@ -0,0 +1,39 @@
+#!/usr/bin/env python3
+# Copyright (C) 2024 Checkmk GmbH - License: GNU General Public License v2
+# This file is part of Checkmk (https://checkmk.com). It is subject to the terms and
+# conditions defined in the file COPYING, which is part of this source code package.
+"""Thin wrapper around the mk-sql shared library(built with the `ffi` feature)
+
+>>> collector = MkSql("/path/to/libmk_sql.so")
+>>> print(collector.collect(config_yaml), end="")
+"""
+
+import ctypes
+from pathlib import Path
+
+
+class MkSqlError(RuntimeError):
+    pass
+
+
+class MkSql:
+    def __init__(self, library: Path | str) -> None:
+        self._lib = ctypes.CDLL(str(library))
+        self._lib.mk_sql_collect.argtypes = [
+            ctypes.c_char_p,
+            ctypes.POINTER(ctypes.c_void_p),
+        ]
+        self._lib.mk_sql_collect.restype = ctypes.c_int
+        self._lib.mk_sql_free.argtypes = [ctypes.c_void_p]
+        self._lib.mk_sql_free.restype = None
+
+    def collect(self, config_yaml: str) -> str:
+        output = ctypes.c_void_p()
+        code = self._lib.mk_sql_collect(config_yaml.encode("utf-8"), ctypes.byref(output))
+        try:
+            text = ctypes.string_at(output).decode("utf-8") if output.value else ""
+        finally:
+            self._lib.mk_sql_free(output)
+        if code != 0:
+            raise MkSqlError(text)
+        return text
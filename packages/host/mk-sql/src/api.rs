@@ -0,0 +1,84 @@
+// Copyright (C) 2024 Checkmk GmbH - License: GNU General Public License v2
+// This file is part of Checkmk (https://checkmk.com). It is subject to the terms and
+// conditions defined in the file COPYING, which is part of this source code package.
+
+//! Public API to embed MS SQL collection into other tools without starting the binary
+//!
+//! ```no_run
+//! # async fn example() -> anyhow::Result<()> {
+//! use mk_sql::api::{CheckConfig, Env, MsSqlRunner};
+//! let config = CheckConfig::load_file(std::path::Path::new("mk-sql.yml"))?;
+//! let mut output = String::new();
+//! config
+//!     .exec_with(&Env::default(), &MsSqlRunner, &mut output)
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+//! Kerberos keytab and credential cache are process-wide: call `prepare_kerberos` with
+//! `Config::kerberos` before the runtime starts.
+
+use crate::ms_sql::instance;
+use anyhow::Result;
+use std::future::Future;
+
+pub use crate::config::ms_sql::{Config, Discovery};
+pub use crate::config::CheckConfig;
+pub use crate::ms_sql::client::prepare_kerberos;
+pub use crate::ms_sql::registry::{register as register_section, SectionDefinition};
+pub use crate::setup::Env;
+
+/// Receives agent output, one call per config
+pub trait Emitter {
+    fn emit(&mut self, data: &str) -> Result<()>;
+}
+
+impl Emitter for String {
+    fn emit(&mut self, data: &str) -> Result<()> {
+        self.push_str(data);
+        Ok(())
+    }
+}
+
+/// Prints output immediately, the same as the binary does
+#[derive(Default)]
+pub struct StdoutEmitter;
+
+impl Emitter for StdoutEmitter {
+    fn emit(&mut self, data: &str) -> Result<()> {
+        use std::io::Write;
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(data.as_bytes())?;
+        stdout.flush()?;
+        Ok(())
+    }
+}
+
+/// Generates all sections of all instances defined by one config
+pub trait SectionRunner {
+    fn run(&self, config: &Config, environment: &Env) -> impl Future<Output = Result<String>>;
+}
+
+/// Standard runner: discovers instances and queries configured sections
+#[derive(Default)]
+pub struct MsSqlRunner;
+
+impl SectionRunner for MsSqlRunner {
+    async fn run(&self, config: &Config, environment: &Env) -> Result<String> {
+        instance::prepare_cache_sub_dir(environment, &config.config_cache_dir());
+        instance::generate_data(config, environment).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_string_emitter() {
+        let mut output = String::new();
+        output.emit("a").unwrap();
+        output.emit("b\n").unwrap();
+        assert_eq!(output, "ab\n");
+    }
+}
@@ -3,12 +3,15 @@
 // conditions defined in the file COPYING, which is part of this source code package.
 
 use crate::constants;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use std::path::PathBuf;
 
 #[derive(Parser, Default)]
 #[command(about = "Check SQL plugin.", version = constants::VERSION)]
 pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// Enable verbose output. Use once (-v) for logging level DEBUG and twice (-vv) for logging
     /// level TRACE.
     #[arg(short, long, action = clap::ArgAction::Count)]
@@ -29,7 +32,8 @@ pub struct Args {
     #[arg(long)]
     pub temp_dir: Option<PathBuf>,
 
-    /// Use custom state dir
+    /// Use custom state dir, created if absent.
+    /// Together with custom config file allows independent runs on the same host
     #[arg(long)]
     pub state_dir: Option<PathBuf>,
 
@@ -41,9 +45,118 @@ pub struct Args {
     #[arg(long)]
     pub clean_caches: bool,
 
+    /// Removes cache and state files of removed instances and sections of the current config
+    /// immediately, otherwise files not updated for a week are removed. Dirs of other configs
+    /// sharing the state dir are removed only when stale
+    #[arg(long)]
+    pub purge_state: bool,
+
     /// Use custom config file
-    #[arg(short, long)]
+    #[arg(short, long, visible_alias = "config")]
     pub config_file: Option<PathBuf>,
+
+    /// Runs as daemon serving already cached sections as JSON over HTTP, e.g. `127.0.0.1:8090`,
+    /// a bare port is bound to loopback. Other addresses require the bearer token in the
+    /// environment variable `MK_SQL_REST_TOKEN`: data of the sections is served unencrypted
+    #[arg(long, value_name = "ADDRESS")]
+    pub rest_api: Option<String>,
+
+    /// Writes the output into the file instead of stdout, the file is replaced atomically.
+    /// Used by the Scheduled Task writing into the agent spool dir
+    #[arg(long, value_name = "FILE")]
+    pub spool_file: Option<PathBuf>,
+
+    /// Records results of all queries into the file
+    #[arg(long, value_name = "FILE", conflicts_with = "replay_fixture")]
+    pub record_fixture: Option<PathBuf>,
+
+    /// Uses results of queries from the recorded file, no connection to SQL Server is made
+    #[arg(long, value_name = "FILE")]
+    pub replay_fixture: Option<PathBuf>,
+
+    /// Pseudonymizes names of instances, databases, logins, jobs and hosts in the recorded file
+    #[arg(long, requires = "record_fixture")]
+    pub anonymize: bool,
+
+    /// Sections used by the monitoring site, one name per line, written by the agent.
+    /// Other sections are skipped, absent file - all configured sections are generated
+    #[arg(long, value_name = "FILE")]
+    pub used_sections: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug, Clone, PartialEq)]
+pub enum Command {
+    /// Refreshes caches of all async sections, nothing is printed but a summary
+    Warmup,
+    #[command(flatten)]
+    Tool(Tool),
+}
+
+/// commands printing their own output instead of sections
+#[derive(Subcommand, Debug, Clone, PartialEq)]
+pub enum Tool {
+    /// Config tools
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Schedule of async sections
+    Schedule {
+        #[command(subcommand)]
+        action: ScheduleAction,
+    },
+    /// Creates a cron entry(Linux) or a Scheduled Task(Windows) refreshing async sections
+    InstallSchedule {
+        /// Interval between runs in minutes
+        #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u32).range(1..=1439))]
+        interval: u32,
+        /// Runs as the user, default: the current one(Linux) or SYSTEM(Windows)
+        #[arg(long)]
+        user: Option<String>,
+        /// Writes output of every run into the agent spool dir
+        #[arg(long, value_name = "DIR")]
+        spool_dir: Option<PathBuf>,
+        /// Removes the entry instead of creating
+        #[arg(long)]
+        remove: bool,
+        /// Prints what would be done without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Prints columns of every known section as JSON
+    Schema,
+    /// Reads the password from stdin and prints it encrypted for `password_encrypted` of the
+    /// config: DPAPI of the machine(Windows) or the key file `mk-sql.key` of the config dir(Linux)
+    EncryptPassword,
+    /// Prints randomized output of the section generated from its schema, no SQL Server needed
+    Mock {
+        /// Name of the section, e.g. `jobs`
+        #[arg(long)]
+        section: String,
+        /// Count of rows
+        #[arg(long, default_value_t = 5)]
+        rows: u32,
+        /// Seed of the random generator, the same seed gives the same output
+        #[arg(long)]
+        seed: Option<u64>,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone, PartialEq)]
+pub enum ScheduleAction {
+    /// Prints age, seconds to the next refresh, last duration and result of every cached
+    /// async section
+    Show,
+}
+
+#[derive(Subcommand, Debug, Clone, PartialEq)]
+pub enum ConfigAction {
+    /// Shows difference between the effective config and built-in defaults
+    Diff {
+        /// Compare with another config file instead of defaults
+        #[arg(long, value_name = "FILE")]
+        against: Option<PathBuf>,
+    },
 }
 
 impl Args {
@@ -54,4 +167,8 @@ impl Args {
             _ => None,
         }
     }
+
+    pub fn use_fixture(&self) -> bool {
+        self.record_fixture.is_some() || self.replay_fixture.is_some()
+    }
 }
@@ -2,7 +2,9 @@
 // This file is part of Checkmk (https://checkmk.com). It is subject to the terms and
 // conditions defined in the file COPYING, which is part of this source code package.
 
+pub mod credentials;
 pub mod defines;
+pub mod diff;
 pub mod ms_sql;
 pub mod section;
 pub mod system;
@@ -27,6 +29,12 @@ impl CheckConfig {
         })
     }
 
+    pub fn load_string(source: &str) -> Result<Self> {
+        Ok(CheckConfig {
+            ms_sql: ms_sql::Config::from_string(source)?,
+        })
+    }
+
     pub fn ms_sql(&self) -> Option<&ms_sql::Config> {
         self.ms_sql.as_ref()
     }
@@ -64,5 +72,15 @@ mod tests {
             .unwrap()
             .ms_sql()
             .is_some());
+        assert!(CheckConfig::load_string("---\nsystem:\n")
+            .unwrap()
+            .ms_sql()
+            .is_none());
+        assert!(
+            CheckConfig::load_string(&std::fs::read_to_string(&*YML_MINI_FILE).unwrap())
+                .unwrap()
+                .ms_sql()
+                .is_some()
+        );
     }
 }
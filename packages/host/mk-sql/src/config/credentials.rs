@@ -0,0 +1,383 @@
+// Copyright (C) 2024 Checkmk GmbH - License: GNU General Public License v2
+// This file is part of Checkmk (https://checkmk.com). It is subject to the terms and
+// conditions defined in the file COPYING, which is part of this source code package.
+
+//! Sources of the password: plain text of the config or a store read at runtime.
+//!
+//! `password_source` of the authentication selects the provider, the password is obtained when
+//! a connection is made and is never written back: loading of the config asks no store.
+//! Remote stores(Vault, CyberArk, feature `secret-stores`) are asked over HTTPS, their answers
+//! are reused by the process for a short time.
+//! Every secret of the config(`password`, `access_token`, `client_secret`) may be referenced
+//! instead: `<key>_env` names an environment variable, `<key>_file` a file with the secret,
+//! `<key>_encrypted` holds the output of `encrypt-password`.
+use super::defines::{keys, values};
+use super::yaml::{Get, Yaml};
+use anyhow::{anyhow, Result};
+use std::path::Path;
+
+#[cfg(feature = "secret-stores")]
+mod stores;
+
+pub trait CredentialProvider {
+    /// name for logs and errors, never the secret
+    fn describe(&self) -> String;
+    fn password(&self) -> Result<String>;
+}
+
+/// provider of `password_source`, None - the password of the config is used
+pub fn make_provider(auth: &Yaml) -> Result<Option<Box<dyn CredentialProvider>>> {
+    let source = auth
+        .get_string(keys::PASSWORD_SOURCE)
+        .unwrap_or(values::CONFIG.to_string());
+    match source.to_ascii_lowercase().as_str() {
+        values::CONFIG => Ok(None),
+        values::CREDENTIAL_MANAGER => Ok(Some(Box::new(CredentialManager {
+            target: required(auth, keys::CREDENTIAL_TARGET)?,
+        }))),
+        #[cfg(feature = "secret-stores")]
+        values::VAULT => Ok(Some(Box::new(stores::Vault::from_yaml(auth)?))),
+        #[cfg(feature = "secret-stores")]
+        values::CYBERARK => Ok(Some(Box::new(stores::CyberArk::from_yaml(auth)?))),
+        #[cfg(not(feature = "secret-stores"))]
+        values::VAULT | values::CYBERARK => Err(anyhow!(
+            "password source `{source}` is not available: built without feature `secret-stores`"
+        )),
+        _ => Err(anyhow!("unsupported password source `{source}`")),
+    }
+}
+
+fn required(auth: &Yaml, key: &str) -> Result<String> {
+    auth.get_string(key)
+        .filter(|v| !v.is_empty())
+        .ok_or_else(|| anyhow!("{key} is missing"))
+}
+
+/// password of the config or of `password_source`, read on use, the definition of the source
+/// is checked by loading
+#[derive(PartialEq, Debug, Clone)]
+pub struct Password {
+    auth: Yaml,
+}
+
+impl Password {
+    /// None - neither the password nor its source is configured
+    pub fn from_yaml(auth: &Yaml) -> Result<Option<Self>> {
+        let configured = make_provider(auth)?.is_some()
+            || [
+                "",
+                keys::ENV_SUFFIX,
+                keys::FILE_SUFFIX,
+                keys::ENCRYPTED_SUFFIX,
+            ]
+            .iter()
+            .any(|suffix| {
+                !auth
+                    .get(&format!("{}{suffix}", keys::PASSWORD))
+                    .is_badvalue()
+            });
+        Ok(configured.then(|| Self { auth: auth.clone() }))
+    }
+
+    pub fn obtain(&self) -> Result<String> {
+        obtain_password(&self.auth)?.ok_or_else(|| anyhow!("password is missing"))
+    }
+}
+
+/// the password of the provider if any, otherwise the one of the config
+pub fn obtain_password(auth: &Yaml) -> Result<Option<String>> {
+    match make_provider(auth)? {
+        Some(provider) => {
+            if auth.get_string(keys::PASSWORD).is_some() {
+                log::warn!(
+                    "Password of the config is ignored, using {}",
+                    provider.describe()
+                );
+            }
+            provider
+                .password()
+                .map(Some)
+                .map_err(|e| anyhow!("Can't read password from {}: {e}", provider.describe()))
+        }
+        None => obtain_secret(auth, keys::PASSWORD),
+    }
+}
+
+/// value of `key`, of the variable named by `<key>_env`, of the file named by `<key>_file` or
+/// decrypted `<key>_encrypted`, the reference must give non-empty secret
+pub fn obtain_secret(yaml: &Yaml, key: &str) -> Result<Option<String>> {
+    let env_key = format!("{key}{}", keys::ENV_SUFFIX);
+    let file_key = format!("{key}{}", keys::FILE_SUFFIX);
+    let encrypted_key = format!("{key}{}", keys::ENCRYPTED_SUFFIX);
+    let plain = yaml.get_string(key);
+    let env = yaml.get_string(&env_key);
+    let file = yaml.get_pathbuf(&file_key);
+    let encrypted = yaml.get_string(&encrypted_key);
+    match (plain, env, file, encrypted) {
+        (plain, None, None, None) => Ok(plain),
+        (None, Some(name), None, None) => read_env(&name)
+            .map(Some)
+            .map_err(|e| anyhow!("Bad `{env_key}`: {e}")),
+        (None, None, Some(path), None) => read_file(&path)
+            .map(Some)
+            .map_err(|e| anyhow!("Bad `{file_key}`: {e}")),
+        (None, None, None, Some(text)) => crate::crypto::decrypt_password(&text)
+            .map(Some)
+            .map_err(|e| anyhow!("Bad `{encrypted_key}`: {e}")),
+        _ => Err(anyhow!(
+            "Only one of `{key}`, `{env_key}`, `{file_key}` and `{encrypted_key}` may be set"
+        )),
+    }
+}
+
+fn read_env(name: &str) -> Result<String> {
+    match std::env::var(name) {
+        Ok(value) if !value.is_empty() => Ok(value),
+        Ok(_) => Err(anyhow!("environment variable `{name}` is empty")),
+        Err(e) => Err(anyhow!("environment variable `{name}`: {e}")),
+    }
+}
+
+/// trailing line break is not a part of the secret
+fn read_file(path: &Path) -> Result<String> {
+    let content = std::fs::read_to_string(path).map_err(|e| anyhow!("file {path:?}: {e}"))?;
+    let secret = content.trim_end_matches(['\r', '\n']);
+    if secret.is_empty() {
+        anyhow::bail!("file {path:?} is empty");
+    }
+    Ok(secret.to_string())
+}
+
+/// generic credential of the Windows Credential Manager, e.g. created with
+/// `cmdkey /generic:<target> /user:<user> /pass`
+struct CredentialManager {
+    target: String,
+}
+
+impl CredentialProvider for CredentialManager {
+    fn describe(&self) -> String {
+        format!("Credential Manager target `{}`", self.target)
+    }
+
+    #[cfg(windows)]
+    fn password(&self) -> Result<String> {
+        win::read_generic_credential(&self.target).map(|blob| decode_blob(&blob))
+    }
+
+    #[cfg(not(windows))]
+    fn password(&self) -> Result<String> {
+        anyhow::bail!("Credential Manager is available only on Windows")
+    }
+}
+
+/// the blob is UTF-16LE if stored by the system tools, other writers may use UTF-8:
+/// NUL never appears in UTF-8 text but in UTF-16 of every ASCII character
+fn decode_blob(blob: &[u8]) -> String {
+    let utf16 = || {
+        let wide = blob
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .collect::<Vec<_>>();
+        String::from_utf16_lossy(&wide)
+    };
+    if blob.contains(&0) && blob.len() % 2 == 0 {
+        return utf16();
+    }
+    match std::str::from_utf8(blob) {
+        Ok(text) if !text.chars().any(char::is_control) => text.to_string(),
+        _ if blob.len() % 2 == 0 => utf16(),
+        _ => String::from_utf8_lossy(blob).to_string(),
+    }
+}
+
+#[cfg(windows)]
+mod win {
+    use anyhow::Result;
+    use std::ffi::c_void;
+
+    const CRED_TYPE_GENERIC: u32 = 1;
+
+    #[repr(C)]
+    struct FileTime {
+        low: u32,
+        high: u32,
+    }
+
+    #[repr(C)]
+    struct CredentialW {
+        flags: u32,
+        kind: u32,
+        target_name: *mut u16,
+        comment: *mut u16,
+        last_written: FileTime,
+        credential_blob_size: u32,
+        credential_blob: *mut u8,
+        persist: u32,
+        attribute_count: u32,
+        attributes: *mut c_void,
+        target_alias: *mut u16,
+        user_name: *mut u16,
+    }
+
+    #[link(name = "advapi32")]
+    extern "system" {
+        fn CredReadW(
+            target_name: *const u16,
+            kind: u32,
+            flags: u32,
+            credential: *mut *mut CredentialW,
+        ) -> i32;
+        fn CredFree(buffer: *mut c_void);
+    }
+
+    pub fn read_generic_credential(target: &str) -> Result<Vec<u8>> {
+        let name = target.encode_utf16().chain(Some(0)).collect::<Vec<u16>>();
+        let mut credential: *mut CredentialW = std::ptr::null_mut();
+        // SAFETY: name is NUL terminated, the credential is released with CredFree
+        unsafe {
+            if CredReadW(name.as_ptr(), CRED_TYPE_GENERIC, 0, &mut credential) == 0 {
+                anyhow::bail!("{}", std::io::Error::last_os_error());
+            }
+            let size = (*credential).credential_blob_size as usize;
+            let blob = if size == 0 || (*credential).credential_blob.is_null() {
+                vec![]
+            } else {
+                std::slice::from_raw_parts((*credential).credential_blob, size).to_vec()
+            };
+            CredFree(credential as *mut c_void);
+            Ok(blob)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::yaml::test_tools::create_yaml;
+
+    fn auth(text: &str) -> Yaml {
+        create_yaml(text).get(keys::AUTHENTICATION).clone()
+    }
+
+    #[test]
+    fn test_decode_blob() {
+        let wide = "pässword"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect::<Vec<u8>>();
+        assert_eq!(decode_blob(&wide), "pässword");
+        assert_eq!(decode_blob(b"secret"), "secret");
+        assert_eq!(decode_blob(b"odd"), "odd");
+        let cyrillic = "пароль"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect::<Vec<u8>>();
+        assert_eq!(decode_blob(&cyrillic), "пароль");
+    }
+
+    #[test]
+    fn test_make_provider() {
+        assert!(make_provider(&auth("authentication:\n  password: x\n"))
+            .unwrap()
+            .is_none());
+        let provider = make_provider(&auth(
+            "authentication:\n  password_source: credential_manager\n  credential_target: mk-sql/sales\n",
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            provider.describe(),
+            "Credential Manager target `mk-sql/sales`"
+        );
+        assert!(make_provider(&auth(
+            "authentication:\n  password_source: credential_manager\n"
+        ))
+        .is_err());
+        assert!(make_provider(&auth("authentication:\n  password_source: magic\n")).is_err());
+    }
+
+    #[test]
+    fn test_obtain_secret() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("secret");
+        std::fs::write(&file, "s3cret\r\n").unwrap();
+        std::env::set_var("MK_SQL_TEST_SECRET", "from env");
+        let yaml = create_yaml(&format!(
+            "a: plain\nb_env: MK_SQL_TEST_SECRET\nc_file: '{}'\nd: x\nd_env: Y\n\
+             e_env: MK_SQL_TEST_ABSENT\nf_file: '{}'\n",
+            file.display(),
+            dir.path().join("absent").display()
+        ));
+        assert_eq!(obtain_secret(&yaml, "a").unwrap().unwrap(), "plain");
+        assert_eq!(obtain_secret(&yaml, "b").unwrap().unwrap(), "from env");
+        assert_eq!(obtain_secret(&yaml, "c").unwrap().unwrap(), "s3cret");
+        assert!(obtain_secret(&yaml, "z").unwrap().is_none());
+        assert_eq!(
+            obtain_secret(&yaml, "d").unwrap_err().to_string(),
+            "Only one of `d`, `d_env`, `d_file` and `d_encrypted` may be set"
+        );
+        assert!(obtain_secret(&yaml, "e")
+            .unwrap_err()
+            .to_string()
+            .starts_with("Bad `e_env`: environment variable `MK_SQL_TEST_ABSENT`"));
+        assert!(obtain_secret(&yaml, "f")
+            .unwrap_err()
+            .to_string()
+            .starts_with("Bad `f_file`: file"));
+        assert!(obtain_secret(&create_yaml("g_encrypted: plain\n"), "g")
+            .unwrap_err()
+            .to_string()
+            .starts_with("Bad `g_encrypted`: unknown format"));
+        std::fs::write(&file, "\n").unwrap();
+        assert!(obtain_secret(&yaml, "c")
+            .unwrap_err()
+            .to_string()
+            .ends_with("is empty"));
+    }
+
+    #[test]
+    fn test_password() {
+        assert!(
+            Password::from_yaml(&auth("authentication:\n  username: u\n"))
+                .unwrap()
+                .is_none()
+        );
+        let password = Password::from_yaml(&auth("authentication:\n  password: x\n"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(password.obtain().unwrap(), "x");
+        // the store is not asked by loading
+        let password = Password::from_yaml(&auth(
+            "authentication:\n  password_source: credential_manager\n  credential_target: t\n",
+        ))
+        .unwrap()
+        .unwrap();
+        #[cfg(unix)]
+        assert!(password.obtain().is_err());
+        let absent = Password::from_yaml(&auth(
+            "authentication:\n  password_env: MK_SQL_TEST_ABSENT_PASSWORD\n",
+        ))
+        .unwrap()
+        .unwrap();
+        assert!(absent.obtain().is_err());
+        assert!(Password::from_yaml(&auth(
+            "authentication:\n  password_source: credential_manager\n"
+        ))
+        .is_err());
+    }
+
+    #[test]
+    fn test_obtain_password() {
+        assert_eq!(
+            obtain_password(&auth("authentication:\n  password: x\n")).unwrap(),
+            Some("x".to_string())
+        );
+        #[cfg(unix)]
+        assert!(obtain_password(&auth(
+            "authentication:\n  password_source: credential_manager\n  credential_target: t\n"
+        ))
+        .unwrap_err()
+        .to_string()
+        .contains("only on Windows"));
+    }
+}
@@ -0,0 +1,333 @@
+// Copyright (C) 2024 Checkmk GmbH - License: GNU General Public License v2
+// This file is part of Checkmk (https://checkmk.com). It is subject to the terms and
+// conditions defined in the file COPYING, which is part of this source code package.
+
+//! Remote stores of the password(Vault, CyberArk) asked over HTTPS, feature `secret-stores`
+use super::{obtain_secret, required, CredentialProvider};
+use crate::config::defines::{defaults, keys};
+use crate::config::yaml::{Get, Yaml};
+use crate::http::{self, Url};
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// secrets of remote stores are requested again after this time
+const SECRET_CACHE_TTL: Duration = Duration::from_secs(300);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+lazy_static::lazy_static! {
+    /// description of the provider -> (secret, time of the request)
+    static ref SECRETS: Mutex<HashMap<String, (String, Instant)>> = Mutex::new(HashMap::new());
+}
+
+/// KV secret of the HashiCorp Vault, `vault_path` is the API path after `/v1/`,
+/// e.g. `secret/data/mssql` for the KV v2 engine mounted at `secret`
+pub struct Vault {
+    url: Url,
+    path: String,
+    field: String,
+    token: String,
+    namespace: Option<String>,
+    ca_file: Option<PathBuf>,
+}
+
+impl Vault {
+    pub fn from_yaml(auth: &Yaml) -> Result<Self> {
+        Ok(Self {
+            url: Url::parse(&required(auth, keys::VAULT_URL)?)?,
+            path: required(auth, keys::VAULT_PATH)?
+                .trim_matches('/')
+                .to_string(),
+            field: auth
+                .get_string(keys::VAULT_FIELD)
+                .unwrap_or(defaults::VAULT_FIELD.to_string()),
+            token: obtain_secret(auth, keys::VAULT_TOKEN)?
+                .filter(|t| !t.is_empty())
+                .ok_or_else(|| anyhow!("{} is missing", keys::VAULT_TOKEN))?,
+            namespace: auth.get_string(keys::VAULT_NAMESPACE),
+            ca_file: auth.get_pathbuf(keys::VAULT_CA_FILE),
+        })
+    }
+
+    fn make_request(&self) -> String {
+        let mut headers = vec![("X-Vault-Token", self.token.as_str())];
+        if let Some(namespace) = &self.namespace {
+            headers.push(("X-Vault-Namespace", namespace));
+        }
+        http::make_get_request(
+            &self.url.host_header(),
+            &format!("{}/v1/{}", self.url.path, self.path),
+            &headers,
+        )
+    }
+}
+
+impl CredentialProvider for Vault {
+    fn describe(&self) -> String {
+        format!(
+            "Vault secret `{}#{}` at {}",
+            self.path, self.field, self.url.host
+        )
+    }
+
+    fn password(&self) -> Result<String> {
+        fetch_cached(self.describe(), || {
+            let response = http::send(
+                &self.url.host,
+                self.url.port,
+                &self.make_request(),
+                self.url.tls,
+                self.ca_file.as_deref(),
+                REQUEST_TIMEOUT,
+            )?;
+            parse_vault_response(&response, &self.field)
+        })
+    }
+}
+
+/// KV v2 nests the secret in `data.data` next to `data.metadata`, KV v1 in `data`
+fn parse_vault_response(response: &str, field: &str) -> Result<String> {
+    let (status, body) = http::split_response(response)?;
+    let json = http::parse_json(body);
+    if status != "200" {
+        // {"errors":["permission denied"]}
+        let error = json
+            .as_ref()
+            .and_then(|json| json["errors"].as_vec())
+            .map(|errors| {
+                errors
+                    .iter()
+                    .filter_map(|e| e.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            })
+            .unwrap_or_default();
+        anyhow::bail!("Vault request failed with status {status}: {error}");
+    }
+    let json = json.ok_or_else(|| anyhow!("Vault response is not JSON"))?;
+    let path: &[&str] = if json["data"]["metadata"].is_badvalue() {
+        &["data", field]
+    } else {
+        &["data", "data", field]
+    };
+    http::find_json_value(&json, path)
+        .filter(|v| !v.is_empty())
+        .ok_or_else(|| anyhow!("no field `{field}` in the secret"))
+}
+
+/// account of the CyberArk Central Credential Provider, the application is authenticated by
+/// the provider itself(allowed machines, OS user), `cyberark_url` is the base of `AIMWebService`
+pub struct CyberArk {
+    url: Url,
+    app_id: String,
+    safe: String,
+    object: String,
+    ca_file: Option<PathBuf>,
+}
+
+impl CyberArk {
+    pub fn from_yaml(auth: &Yaml) -> Result<Self> {
+        Ok(Self {
+            url: Url::parse(&required(auth, keys::CYBERARK_URL)?)?,
+            app_id: required(auth, keys::CYBERARK_APP_ID)?,
+            safe: required(auth, keys::CYBERARK_SAFE)?,
+            object: required(auth, keys::CYBERARK_OBJECT)?,
+            ca_file: auth.get_pathbuf(keys::CYBERARK_CA_FILE),
+        })
+    }
+
+    fn make_request(&self) -> String {
+        http::make_get_request(
+            &self.url.host_header(),
+            &format!(
+                "{}/AIMWebService/api/Accounts?AppID={}&Safe={}&Object={}",
+                self.url.path,
+                http::encode_form(&self.app_id),
+                http::encode_form(&self.safe),
+                http::encode_form(&self.object)
+            ),
+            &[("Accept", "application/json")],
+        )
+    }
+}
+
+impl CredentialProvider for CyberArk {
+    fn describe(&self) -> String {
+        format!(
+            "CyberArk object `{}/{}` at {}",
+            self.safe, self.object, self.url.host
+        )
+    }
+
+    fn password(&self) -> Result<String> {
+        fetch_cached(self.describe(), || {
+            let response = http::send(
+                &self.url.host,
+                self.url.port,
+                &self.make_request(),
+                self.url.tls,
+                self.ca_file.as_deref(),
+                REQUEST_TIMEOUT,
+            )?;
+            parse_cyberark_response(&response)
+        })
+    }
+}
+
+fn parse_cyberark_response(response: &str) -> Result<String> {
+    let (status, body) = http::split_response(response)?;
+    let json = http::parse_json(body).unwrap_or(Yaml::Null);
+    if status != "200" {
+        anyhow::bail!(
+            "CyberArk request failed with status {status}: {} {}",
+            http::find_json_value(&json, &["ErrorCode"]).unwrap_or_default(),
+            http::find_json_value(&json, &["ErrorMsg"]).unwrap_or_default()
+        );
+    }
+    http::find_json_value(&json, &["Content"])
+        .filter(|v| !v.is_empty())
+        .ok_or_else(|| anyhow!("no Content in the response"))
+}
+
+/// secret of the remote store, requested again after `SECRET_CACHE_TTL`
+fn fetch_cached(key: String, fetch: impl FnOnce() -> Result<String>) -> Result<String> {
+    if let Some(secret) = SECRETS.lock().ok().and_then(|secrets| {
+        secrets
+            .get(&key)
+            .filter(|(_, requested)| requested.elapsed() < SECRET_CACHE_TTL)
+            .map(|(secret, _)| secret.clone())
+    }) {
+        return Ok(secret);
+    }
+    log::info!("Requesting password from {key}");
+    let secret = fetch()?;
+    if let Ok(mut secrets) = SECRETS.lock() {
+        secrets.insert(key, (secret.clone(), Instant::now()));
+    }
+    Ok(secret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::make_provider;
+    use super::*;
+    use crate::config::yaml::test_tools::create_yaml;
+
+    fn auth(text: &str) -> Yaml {
+        create_yaml(text).get(keys::AUTHENTICATION).clone()
+    }
+
+    #[test]
+    fn test_vault() {
+        std::env::set_var("MK_SQL_TEST_VAULT_TOKEN", "hvs.x");
+        let provider = Vault::from_yaml(&auth(
+            "authentication:\n  password_source: vault\n  vault_url: https://vault:8200/\n  \
+             vault_path: /secret/data/mssql\n  vault_token_env: MK_SQL_TEST_VAULT_TOKEN\n  \
+             vault_namespace: ns1\n  vault_ca_file: ca.pem\n",
+        ))
+        .unwrap();
+        assert_eq!(provider.ca_file, Some(PathBuf::from("ca.pem")));
+        assert_eq!(
+            provider.describe(),
+            "Vault secret `secret/data/mssql#password` at vault"
+        );
+        assert_eq!(
+            provider.make_request(),
+            "GET /v1/secret/data/mssql HTTP/1.0\r\nHost: vault\r\nX-Vault-Token: hvs.x\r\n\
+             X-Vault-Namespace: ns1\r\nConnection: close\r\n\r\n"
+        );
+        assert!(make_provider(&auth(
+            "authentication:\n  password_source: vault\n  vault_url: https://vault\n  vault_path: p\n"
+        ))
+        .is_err());
+        assert_eq!(
+            parse_vault_response(
+                "HTTP/1.1 200 OK\r\n\r\n{\"data\":{\"data\":{\"password\":\"p\\\"w\"},\"metadata\":{}}}",
+                "password"
+            )
+            .unwrap(),
+            "p\"w"
+        );
+        assert!(parse_vault_response("HTTP/1.1 200 OK\r\n\r\n{\"data\":{}}", "password").is_err());
+        // KV v1, the field of KV v2 metadata is not taken for the secret
+        assert_eq!(
+            parse_vault_response(
+                "HTTP/1.1 200 OK\r\n\r\n{\"data\":{\"password\":\"v1\"}}",
+                "password"
+            )
+            .unwrap(),
+            "v1"
+        );
+        assert!(parse_vault_response(
+            "HTTP/1.1 200 OK\r\n\r\n{\"data\":{\"metadata\":{\"version\":2},\"data\":{}}}",
+            "version"
+        )
+        .is_err());
+        assert_eq!(
+            parse_vault_response(
+                "HTTP/1.1 403 Forbidden\r\n\r\n{\"errors\":[\"permission denied\"]}",
+                "password"
+            )
+            .unwrap_err()
+            .to_string(),
+            "Vault request failed with status 403: permission denied"
+        );
+    }
+
+    #[test]
+    fn test_cyberark() {
+        let provider = make_provider(&auth(
+            "authentication:\n  password_source: cyberark\n  cyberark_url: https://ccp\n  \
+             cyberark_app_id: mk-sql\n  cyberark_safe: DB Safe\n  cyberark_object: sa\n",
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(provider.describe(), "CyberArk object `DB Safe/sa` at ccp");
+        let provider = CyberArk::from_yaml(&auth(
+            "authentication:\n  cyberark_url: https://ccp/base\n  \
+             cyberark_app_id: mk-sql\n  cyberark_safe: DB Safe\n  cyberark_object: sa\n",
+        ))
+        .unwrap();
+        assert!(provider.make_request().starts_with(
+            "GET /base/AIMWebService/api/Accounts?AppID=mk-sql&Safe=DB%20Safe&Object=sa HTTP/1.0\r\n"
+        ));
+        assert!(make_provider(&auth(
+            "authentication:\n  password_source: cyberark\n  cyberark_url: https://ccp\n"
+        ))
+        .is_err());
+        assert_eq!(
+            parse_cyberark_response(
+                "HTTP/1.1 200 OK\r\n\r\n{\"Content\":\"s3cret\",\"UserName\":\"sa\"}"
+            )
+            .unwrap(),
+            "s3cret"
+        );
+        assert_eq!(
+            parse_cyberark_response(
+                "HTTP/1.1 404 Not Found\r\n\r\n{\"ErrorCode\":\"APPAP004E\",\"ErrorMsg\":\"Password object matching query not found\"}"
+            )
+            .unwrap_err()
+            .to_string(),
+            "CyberArk request failed with status 404: APPAP004E Password object matching query not found"
+        );
+    }
+
+    #[test]
+    fn test_fetch_cached() {
+        assert_eq!(
+            fetch_cached("test/a".to_string(), || Ok("1".to_string())).unwrap(),
+            "1"
+        );
+        assert_eq!(
+            fetch_cached("test/a".to_string(), || Ok("2".to_string())).unwrap(),
+            "1"
+        );
+        assert!(fetch_cached("test/b".to_string(), || Err(anyhow!("down"))).is_err());
+        assert_eq!(
+            fetch_cached("test/b".to_string(), || Ok("3".to_string())).unwrap(),
+            "3"
+        );
+    }
+}
@@ -8,12 +8,44 @@ pub mod keys {
 
     pub const OPTIONS: &str = "options";
     pub const MAX_CONNECTIONS: &str = "max_connections";
+    pub const RUN_BUDGET: &str = "run_budget";
+    pub const MIN_INTERVAL: &str = "min_interval";
+    pub const CACHE_REFRESH_LEAD: &str = "cache_refresh_lead";
+    pub const CONCURRENCY_CLASSES: &str = "concurrency_classes";
+    pub const TRAILER: &str = "trailer";
+    pub const SECTION_TIMEOUT: &str = "section_timeout";
 
     pub const AUTHENTICATION: &str = "authentication";
     pub const USERNAME: &str = "username";
     pub const PASSWORD: &str = "password";
     pub const TYPE: &str = "type";
     pub const ACCESS_TOKEN: &str = "access_token";
+    pub const DATABASE: &str = "database";
+    pub const TENANT_ID: &str = "tenant_id";
+    pub const CLIENT_ID: &str = "client_id";
+    pub const CLIENT_SECRET: &str = "client_secret";
+    pub const KEYTAB: &str = "keytab";
+    pub const CREDENTIAL_CACHE: &str = "credential_cache";
+    pub const SPN: &str = "spn";
+    pub const PASSWORD_SOURCE: &str = "password_source";
+    pub const CREDENTIAL_TARGET: &str = "credential_target";
+    pub const VAULT_URL: &str = "vault_url";
+    pub const VAULT_PATH: &str = "vault_path";
+    pub const VAULT_FIELD: &str = "vault_field";
+    pub const VAULT_TOKEN: &str = "vault_token";
+    pub const VAULT_NAMESPACE: &str = "vault_namespace";
+    pub const VAULT_CA_FILE: &str = "vault_ca_file";
+    pub const CYBERARK_URL: &str = "cyberark_url";
+    pub const CYBERARK_APP_ID: &str = "cyberark_app_id";
+    pub const CYBERARK_SAFE: &str = "cyberark_safe";
+    pub const CYBERARK_OBJECT: &str = "cyberark_object";
+    pub const CYBERARK_CA_FILE: &str = "cyberark_ca_file";
+    /// `password_env`: the secret is in the environment variable
+    pub const ENV_SUFFIX: &str = "_env";
+    /// `password_file`: the secret is in the file
+    pub const FILE_SUFFIX: &str = "_file";
+    /// `password_encrypted`: the secret is encrypted by `encrypt-password`
+    pub const ENCRYPTED_SUFFIX: &str = "_encrypted";
 
     pub const CONNECTION: &str = "connection";
     pub const HOSTNAME: &str = "hostname";
@@ -25,12 +57,39 @@ pub mod keys {
     pub const CA: &str = "ca";
     pub const CLIENT_CERTIFICATE: &str = "client_certificate";
     pub const TRUST_SERVER_CERTIFICATE: &str = "trust_server_certificate";
+    pub const CA_BUNDLE: &str = "ca_bundle";
+    pub const CERTIFICATE_FINGERPRINT: &str = "certificate_fingerprint";
+    pub const PROTOCOL: &str = "protocol";
+    pub const MULTI_SUBNET_FAILOVER: &str = "multi_subnet_failover";
+    pub const APPLICATION_INTENT: &str = "application_intent";
+    pub const PIPE: &str = "pipe";
+    pub const RETRY: &str = "retry";
+    pub const ATTEMPTS: &str = "attempts";
+    pub const BASE_DELAY: &str = "base_delay";
+    pub const JITTER: &str = "jitter";
 
     pub const SECTIONS: &str = "sections";
     pub const CACHE_AGE: &str = "cache_age";
     pub const IS_ASYNC: &str = "is_async";
     pub const DISABLED: &str = "disabled";
     pub const SEP: &str = "sep";
+    pub const POST_PROCESS: &str = "post_process";
+    pub const PRIORITY: &str = "priority";
+    pub const BASELINE: &str = "baseline";
+    pub const CATALOG: &str = "catalog";
+    pub const READABLE_SECONDARY: &str = "readable_secondary";
+    pub const DEDUPLICATE: &str = "deduplicate";
+    pub const PDH_FALLBACK: &str = "pdh_fallback";
+    pub const TEST_LINKS: &str = "test_links";
+    pub const LINK_TIMEOUT: &str = "link_timeout";
+    pub const TENANT_PROPERTY: &str = "tenant_property";
+    pub const TENANT_MAP: &str = "tenant_map";
+    pub const CONCURRENCY: &str = "concurrency";
+    pub const DATABASES: &str = "databases";
+    pub const MIN_PAGE_COUNT: &str = "min_page_count";
+    pub const TOP: &str = "top";
+    pub const RENDER: &str = "render";
+    pub const MAX_LENGTH: &str = "max_length";
 
     pub const PIGGYBACK_HOST: &str = "piggyback_host";
     pub const DISCOVERY: &str = "discovery";
@@ -45,6 +104,10 @@ pub mod keys {
     pub const SID: &str = "sid";
     pub const ALIAS: &str = "alias";
     pub const PIGGYBACK: &str = "piggyback";
+    pub const ALTERNATE_ENDPOINTS: &str = "alternate_endpoints";
+    pub const OUTPUT: &str = "output";
+    pub const FILE: &str = "file";
+    pub const STDOUT: &str = "stdout";
 
     pub const CONFIGS: &str = "configs";
 }
@@ -60,12 +123,64 @@ pub mod values {
     pub const INTEGRATED: &str = "integrated";
     /// AuthType::Token
     pub const TOKEN: &str = "token";
+    /// AuthType::AzureAd
+    pub const AZURE_AD: &str = "azure_ad";
+    /// AuthType::ManagedIdentity
+    pub const MANAGED_IDENTITY: &str = "managed_identity";
+    /// AuthType::Kerberos
+    #[cfg(unix)]
+    pub const KERBEROS: &str = "kerberos";
+    /// AuthType::Gmsa
+    #[cfg(windows)]
+    pub const GMSA: &str = "gmsa";
     /// Mode::Port
     pub const PORT: &str = "port";
     /// Mode::Socket
     pub const SOCKET: &str = "socket";
     /// AuthType::Special
     pub const SPECIAL: &str = "special";
+    /// Priority::High
+    pub const HIGH: &str = "high";
+    /// Priority::Normal
+    pub const NORMAL: &str = "normal";
+    /// Priority::Low
+    pub const LOW: &str = "low";
+    /// Render::Truncate
+    pub const TRUNCATE: &str = "truncate";
+    /// Render::Hex
+    pub const HEX: &str = "hex";
+    /// Render::Base64
+    pub const BASE64: &str = "base64";
+    /// Render::Drop
+    pub const DROP: &str = "drop";
+    /// password of the config
+    pub const CONFIG: &str = "config";
+    /// password of the Windows Credential Manager
+    pub const CREDENTIAL_MANAGER: &str = "credential_manager";
+    /// password of the HashiCorp Vault
+    pub const VAULT: &str = "vault";
+    /// password of the CyberArk Central Credential Provider(AIM web service)
+    pub const CYBERARK: &str = "cyberark";
+    /// TlsMode::Disabled
+    pub const DISABLED: &str = "disabled";
+    /// TlsMode::Prefer
+    pub const PREFER: &str = "prefer";
+    /// TlsMode::Require
+    pub const REQUIRE: &str = "require";
+    /// rejected: the driver can't validate the chain without the host name
+    pub const VERIFY_CA: &str = "verify-ca";
+    /// TlsMode::VerifyFull
+    pub const VERIFY_FULL: &str = "verify-full";
+    /// Protocol::Tcp
+    pub const TCP: &str = "tcp";
+    /// Protocol::NamedPipes
+    pub const NAMED_PIPES: &str = "named_pipes";
+    /// Protocol::SharedMemory
+    pub const SHARED_MEMORY: &str = "shared_memory";
+    /// ApplicationIntent::ReadWrite
+    pub const READ_WRITE: &str = "read_write";
+    /// ApplicationIntent::ReadOnly
+    pub const READ_ONLY: &str = "read_only";
 }
 
 pub mod defaults {
@@ -81,7 +196,27 @@ pub mod defaults {
     pub const CONNECTION_HOST_NAME: &str = "localhost";
     pub const CONNECTION_PORT: u16 = 1433;
     pub const CONNECTION_TIMEOUT: u64 = 5;
+    /// including the first attempt
+    pub const RETRY_ATTEMPTS: u32 = 3;
+    /// milliseconds, doubled with every next attempt
+    pub const RETRY_BASE_DELAY: u64 = 500;
     pub const SECTIONS_CACHE_AGE: u32 = 600;
+    /// a day, sections with expensive queries
+    pub const HEAVY_SECTION_CACHE_AGE: u32 = 86400;
+    pub const MIN_PAGE_COUNT: u32 = 1000;
+    pub const QUERY_STORE_TOP: u32 = 10;
+    /// seconds, connection and `sp_testlinkedserver` of one linked server
+    pub const LINK_TIMEOUT: u32 = 5;
+    /// database extended property with the name of the tenant
+    pub const TENANT_PROPERTY: &str = "tenant";
+    /// field of the Vault secret with the password
+    pub const VAULT_FIELD: &str = "password";
+    /// concurrency class of heavy sections
+    pub const HEAVY_CONCURRENCY_CLASS: &str = "heavy";
+    /// (class, max sections of the class running at the same time per server), 0 - unlimited
+    pub const CONCURRENCY_CLASSES: &[(&str, u32)] = &[(HEAVY_CONCURRENCY_CLASS, 1)];
+    pub const RENDER_MAX_LENGTH: usize = 256;
+    pub const OUTPUT_STDOUT: bool = false;
     pub const SECTIONS_ALWAYS: &[&str] = &[
         "instance",
         "databases",
@@ -99,4 +234,5 @@ pub mod defaults {
 
     pub const TRUST_SERVER_CERTIFICATE: bool = true;
     pub const DEFAULT_SEP: char = ' ';
+    pub const POST_PROCESS_TIMEOUT: u64 = 10;
 }
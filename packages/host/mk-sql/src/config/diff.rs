@@ -0,0 +1,208 @@
+// Copyright (C) 2024 Checkmk GmbH - License: GNU General Public License v2
+// This file is part of Checkmk (https://checkmk.com). It is subject to the terms and
+// conditions defined in the file COPYING, which is part of this source code package.
+
+use super::ms_sql::Config;
+use super::section::SectionKind;
+use super::CheckConfig;
+use anyhow::Result;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+type Flat = BTreeMap<String, String>;
+
+/// Shows difference of the main config against either built-in defaults or another file
+pub fn exec(check_config: &CheckConfig, against: Option<&Path>) -> Result<String> {
+    let effective = check_config
+        .ms_sql()
+        .ok_or_else(|| anyhow::anyhow!("No Config"))?;
+    let (base, base_name) = match against {
+        Some(file) => (
+            CheckConfig::load_file(file)?
+                .ms_sql()
+                .map(describe)
+                .unwrap_or_default(),
+            file.display().to_string(),
+        ),
+        None => (describe(&Config::default()), "defaults".to_string()),
+    };
+    Ok(render(&diff(&base, &describe(effective)), &base_name))
+}
+
+/// Flattens config to `path = value` pairs, secrets are masked
+pub fn describe(config: &Config) -> Flat {
+    let mut flat = describe_one(config, "main");
+    for (num, c) in config.configs().iter().enumerate() {
+        flat.extend(describe_one(c, &format!("configs.{num}")));
+    }
+    flat
+}
+
+fn describe_one(config: &Config, prefix: &str) -> Flat {
+    let auth = config.auth();
+    let conn = config.conn();
+    let discovery = config.discovery();
+    let flat: Vec<(&str, String)> = vec![
+        (
+            "options.max_connections",
+            config.options().max_connections().0.to_string(),
+        ),
+        (
+            "options.max_queries",
+            config.options().max_queries().0.to_string(),
+        ),
+        ("authentication.username", auth.username().to_string()),
+        (
+            "authentication.password",
+            mask(auth.has_password().then_some(())),
+        ),
+        ("authentication.type", format!("{:?}", auth.auth_type())),
+        ("authentication.access_token", mask(auth.access_token())),
+        ("connection.hostname", conn.hostname().to_string()),
+        (
+            "connection.failoverpartner",
+            conn.fail_over_partner().cloned().unwrap_or_default(),
+        ),
+        ("connection.port", conn.port().0.to_string()),
+        (
+            "connection.socket",
+            conn.socket()
+                .map(|s| s.display().to_string())
+                .unwrap_or_default(),
+        ),
+        (
+            "connection.trust_server_certificate",
+            conn.trust_server_certificate().to_string(),
+        ),
+        (
+            "connection.tls.ca",
+            conn.tls()
+                .map(|t| t.ca().display().to_string())
+                .unwrap_or_default(),
+        ),
+        ("connection.timeout", conn.timeout().as_secs().to_string()),
+        ("sections.cache_age", config.cache_age().to_string()),
+        ("discovery.detect", discovery.detect().to_string()),
+        ("discovery.include", discovery.include().join(",")),
+        ("discovery.exclude", discovery.exclude().join(",")),
+        ("mode", format!("{:?}", config.mode())),
+        (
+            "piggyback_host",
+            config.piggyback_host().unwrap_or_default().to_string(),
+        ),
+        (
+            "instances",
+            config
+                .instances()
+                .iter()
+                .map(|i| i.name().to_string())
+                .collect::<Vec<_>>()
+                .join(","),
+        ),
+    ];
+    let mut flat: Flat = flat
+        .into_iter()
+        .map(|(key, value)| (format!("{prefix}.{key}"), value))
+        .collect();
+    flat.extend(config.all_sections().iter().map(|s| {
+        let kind = match s.kind() {
+            SectionKind::Sync => "sync",
+            SectionKind::Async => "async",
+            SectionKind::Disabled => "disabled",
+        };
+        (format!("{prefix}.sections.{}", s.name()), kind.to_string())
+    }));
+    flat
+}
+
+fn mask<T>(secret: Option<T>) -> String {
+    secret.map(|_| "***".to_string()).unwrap_or_default()
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Delta {
+    pub key: String,
+    pub base: Option<String>,
+    pub effective: Option<String>,
+}
+
+pub fn diff(base: &Flat, effective: &Flat) -> Vec<Delta> {
+    base.keys()
+        .chain(effective.keys())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .filter(|k| base.get(*k) != effective.get(*k))
+        .map(|k| Delta {
+            key: k.clone(),
+            base: base.get(k).cloned(),
+            effective: effective.get(k).cloned(),
+        })
+        .collect()
+}
+
+fn render(deltas: &[Delta], base_name: &str) -> String {
+    if deltas.is_empty() {
+        return format!("No difference against {base_name}\n");
+    }
+    let show = |v: &Option<String>| match v {
+        Some(v) => format!("'{v}'"),
+        None => "<absent>".to_string(),
+    };
+    format!("Difference against {base_name}:\n")
+        + &deltas
+            .iter()
+            .map(|d| format!("  {}: {} -> {}\n", d.key, show(&d.base), show(&d.effective)))
+            .collect::<String>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_config(source: &str) -> Config {
+        Config::from_string(source).unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_diff_against_defaults() {
+        let config = make_config(
+            r"---
+mssql:
+  main:
+    authentication:
+      username: foo
+      password: secret
+      type: sql_server
+    connection:
+      port: 1434
+    discovery:
+      detect: no
+",
+        );
+        let deltas = diff(&describe(&Config::default()), &describe(&config));
+        let keys = deltas.iter().map(|d| d.key.as_str()).collect::<Vec<_>>();
+        assert!(keys.contains(&"main.connection.port"));
+        assert!(keys.contains(&"main.authentication.username"));
+        assert!(keys.contains(&"main.discovery.detect"));
+        assert!(!keys.contains(&"main.connection.timeout"));
+        let password = deltas
+            .iter()
+            .find(|d| d.key == "main.authentication.password")
+            .unwrap();
+        assert_eq!(password.effective.as_deref(), Some("***"));
+    }
+
+    #[test]
+    fn test_render() {
+        assert_eq!(render(&[], "defaults"), "No difference against defaults\n");
+        let deltas = vec![Delta {
+            key: "main.mode".to_string(),
+            base: Some("Port".to_string()),
+            effective: None,
+        }];
+        assert_eq!(
+            render(&deltas, "x.yml"),
+            "Difference against x.yml:\n  main.mode: 'Port' -> <absent>\n"
+        );
+    }
+}
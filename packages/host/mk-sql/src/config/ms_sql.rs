@@ -2,12 +2,14 @@
 // This file is part of Checkmk (https://checkmk.com). It is subject to the terms and
 // conditions defined in the file COPYING, which is part of this source code package.
 
+use super::credentials;
 use super::defines::{defaults, keys, values};
 use super::section::{Section, SectionKind, Sections};
-use super::yaml::{Get, Yaml};
+use super::yaml::{trace_tools, Get, Yaml};
+use crate::crypto;
 use crate::platform;
-use crate::platform::registry::get_instances;
-use crate::platform::InstanceInfo;
+use crate::platform::get_instances;
+use crate::platform::{ClientAlias, InstanceInfo, Transport};
 use crate::types::{
     CertPath, HostName, InstanceAlias, InstanceName, MaxConnections, MaxQueries, Port,
 };
@@ -37,6 +39,12 @@ pub struct Config {
 pub struct Options {
     max_connections: MaxConnections,
     max_queries: MaxQueries,
+    run_budget: Option<Duration>,
+    min_interval: Option<Duration>,
+    cache_refresh_lead: Duration,
+    concurrency_classes: Vec<(String, u32)>,
+    trailer: bool,
+    section_timeout: Option<Duration>,
 }
 
 impl Default for Options {
@@ -44,6 +52,12 @@ impl Default for Options {
         Self {
             max_connections: defaults::MAX_CONNECTIONS.into(),
             max_queries: defaults::MAX_QUERIES.into(),
+            run_budget: None,
+            min_interval: None,
+            cache_refresh_lead: Duration::ZERO,
+            concurrency_classes: default_concurrency_classes(),
+            trailer: false,
+            section_timeout: None,
         }
     }
 }
@@ -53,6 +67,12 @@ impl Options {
         Self {
             max_connections,
             max_queries: defaults::MAX_QUERIES.into(),
+            run_budget: None,
+            min_interval: None,
+            cache_refresh_lead: Duration::ZERO,
+            concurrency_classes: default_concurrency_classes(),
+            trailer: false,
+            section_timeout: None,
         }
     }
 
@@ -64,6 +84,36 @@ impl Options {
         self.max_queries.clone()
     }
 
+    /// time for the whole run, None - unlimited
+    pub fn run_budget(&self) -> Option<Duration> {
+        self.run_budget
+    }
+
+    /// output of the last run is replayed if it is younger, None - always query
+    pub fn min_interval(&self) -> Option<Duration> {
+        self.min_interval
+    }
+
+    /// async sections are refreshed so long before their cache expires
+    pub fn cache_refresh_lead(&self) -> Duration {
+        self.cache_refresh_lead
+    }
+
+    /// (class, max sections of the class running at the same time per server), 0 - unlimited
+    pub fn concurrency_classes(&self) -> &[(String, u32)] {
+        &self.concurrency_classes
+    }
+
+    /// output ends with the trailer section: checksum and row counts of the payload
+    pub fn trailer(&self) -> bool {
+        self.trailer
+    }
+
+    /// default time for a section, None - unlimited
+    pub fn section_timeout(&self) -> Option<Duration> {
+        self.section_timeout
+    }
+
     pub fn from_yaml(yaml: &Yaml) -> Result<Option<Self>> {
         let options = yaml.get(keys::OPTIONS);
         if options.is_badvalue() {
@@ -79,10 +129,53 @@ impl Options {
                 })
                 .into(),
             max_queries: defaults::MAX_QUERIES.into(),
+            run_budget: options
+                .get_int::<u64>(keys::RUN_BUDGET)
+                .filter(|&v| v > 0)
+                .map(Duration::from_secs),
+            min_interval: options
+                .get_int::<u64>(keys::MIN_INTERVAL)
+                .filter(|&v| v > 0)
+                .map(Duration::from_secs),
+            cache_refresh_lead: Duration::from_secs(
+                options
+                    .get_int::<u64>(keys::CACHE_REFRESH_LEAD)
+                    .unwrap_or_default(),
+            ),
+            concurrency_classes: merge_concurrency_classes(
+                options.get_string_map(keys::CONCURRENCY_CLASSES),
+            ),
+            trailer: options.get_bool(keys::TRAILER, false),
+            section_timeout: options
+                .get_int::<u64>(keys::SECTION_TIMEOUT)
+                .filter(|&v| v > 0)
+                .map(Duration::from_secs),
         }))
     }
 }
 
+fn default_concurrency_classes() -> Vec<(String, u32)> {
+    defaults::CONCURRENCY_CLASSES
+        .iter()
+        .map(|(class, limit)| (class.to_string(), *limit))
+        .collect()
+}
+
+/// configured classes override the default ones
+fn merge_concurrency_classes(configured: Vec<(String, String)>) -> Vec<(String, u32)> {
+    let mut classes = default_concurrency_classes();
+    for (class, limit) in configured {
+        match limit.parse::<u32>() {
+            Ok(limit) => {
+                classes.retain(|(c, _)| c != &class);
+                classes.push((class, limit));
+            }
+            Err(_) => log::warn!("Bad limit `{limit}` of concurrency class `{class}`"),
+        }
+    }
+    classes
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
@@ -131,6 +224,7 @@ impl Config {
                     .into_iter()
                     .filter_map(|v| Config::parse_main_from_yaml(&v, &c).transpose())
                     .collect::<Result<Vec<Config>>>()?;
+                c.check_kerberos()?;
 
                 Ok(Some(c))
             }
@@ -139,17 +233,19 @@ impl Config {
     }
 
     fn parse_main_from_yaml(root: &Yaml, default: &Config) -> Result<Option<Self>> {
-        let mut hasher = DefaultHasher::new();
-        root.hash(&mut hasher);
-        let hash = format!("{:016X}", hasher.finish());
+        let hash = calc_hash(root);
         let main = root.get(keys::MAIN);
         if main.is_badvalue() {
             bail!("main key is absent");
         }
 
-        let auth = Authentication::from_yaml(main).unwrap_or_else(|_| default.auth.clone());
-        let conn =
-            Connection::from_yaml(main, Some(&auth))?.unwrap_or_else(|| default.conn().clone());
+        let auth = if main.get(keys::AUTHENTICATION).is_badvalue() {
+            default.auth.clone()
+        } else {
+            Authentication::from_yaml(main).context("Bad authentication of `main`")?
+        };
+        let conn = Connection::from_yaml(main, Some(&auth))?
+            .unwrap_or_else(|| default.conn().clone().ensure(Some(&auth)));
         let options = Options::from_yaml(main)?.unwrap_or_else(|| default.options().clone());
         let discovery = Discovery::from_yaml(main)?.unwrap_or_else(|| default.discovery().clone());
         let section_info = Sections::from_yaml(main, &default.sections)?;
@@ -235,6 +331,35 @@ impl Config {
         &self.configs
     }
 
+    /// keytab and credential cache of the process, the same for all instances and configs
+    pub fn kerberos(&self) -> Option<&Kerberos> {
+        self.all_kerberos().into_iter().next()
+    }
+
+    /// GSSAPI takes keytab and credential cache from the environment of the process, the
+    /// instances can't use different ones
+    fn check_kerberos(&self) -> Result<()> {
+        let all = self.all_kerberos();
+        if let Some(other) = all.iter().find(|k| *k != &all[0]) {
+            bail!(
+                "Kerberos keytab and credential cache are process-wide, all instances must use the same: {:?} and {:?}",
+                all[0],
+                other
+            );
+        }
+        Ok(())
+    }
+
+    /// settings with keytab or credential cache
+    fn all_kerberos(&self) -> Vec<&Kerberos> {
+        std::iter::once(&self.auth)
+            .chain(self.custom_instances.iter().map(|i| i.auth()))
+            .filter_map(Authentication::kerberos)
+            .filter(|k| k.keytab().is_some() || k.credential_cache().is_some())
+            .chain(self.configs.iter().flat_map(Config::all_kerberos))
+            .collect()
+    }
+
     pub fn config_cache_dir(&self) -> String {
         "mssql-".to_owned() + &self.hash
     }
@@ -249,6 +374,17 @@ impl Config {
     }
 }
 
+/// Hash is used as a name of cache dir, in FIPS mode only approved algorithms are allowed
+fn calc_hash(root: &Yaml) -> String {
+    if crypto::is_fips_mode() {
+        crypto::short_digest(trace_tools::dump_yaml(root).as_bytes())
+    } else {
+        let mut hasher = DefaultHasher::new();
+        root.hash(&mut hasher);
+        format!("{:016X}", hasher.finish())
+    }
+}
+
 pub fn get_registry_instance_info(name: &InstanceName) -> Option<InstanceInfo> {
     let all = get_instances();
     let a = all.iter().find(|i| &i.name == name);
@@ -274,7 +410,7 @@ fn get_additional_registry_instances(
         .map(|i| i.name().to_string().to_lowercase().clone())
         .collect();
     log::info!("localhost is defined, adding registry instances");
-    platform::registry::get_instances()
+    platform::get_instances()
         .into_iter()
         .filter_map(|i| {
             if names.contains(&i.name.to_string().to_lowercase()) {
@@ -298,9 +434,15 @@ fn get_additional_registry_instances(
 #[derive(PartialEq, Debug, Clone)]
 pub struct Authentication {
     username: String,
-    password: Option<String>,
+    password: Option<credentials::Password>,
     auth_type: AuthType,
     access_token: Option<String>,
+    /// contained database of the user, the login is impossible without it
+    database: Option<String>,
+    service_principal: Option<ServicePrincipal>,
+    /// user-assigned managed identity, None - system-assigned one
+    identity_client_id: Option<String>,
+    kerberos: Option<Kerberos>,
 }
 
 impl Default for Authentication {
@@ -310,6 +452,10 @@ impl Default for Authentication {
             password: None,
             auth_type: AuthType::default(),
             access_token: None,
+            database: None,
+            service_principal: None,
+            identity_client_id: None,
+            kerberos: None,
         }
     }
 }
@@ -319,24 +465,60 @@ impl Authentication {
         if auth.is_badvalue() {
             anyhow::bail!("authentication is missing");
         }
+        Self::parse(auth).map_err(|e| {
+            log::error!("Bad authentication: {e}");
+            e
+        })
+    }
 
+    fn parse(auth: &Yaml) -> Result<Self> {
+        let auth_type = AuthType::try_from(
+            auth.get_string(keys::TYPE)
+                .as_deref()
+                .unwrap_or(defaults::AUTH_TYPE),
+        )?;
+        if !auth.get(keys::SPN).is_badvalue() {
+            anyhow::bail!(
+                "spn is not supported: the driver makes the SPN from hostname and port of the connection, set them instead"
+            );
+        }
+        let service_principal = if auth_type == AuthType::AzureAd {
+            Some(ServicePrincipal::from_yaml(auth)?)
+        } else {
+            None
+        };
         Ok(Self {
             username: auth.get_string(keys::USERNAME).unwrap_or_default(),
-            password: auth.get_string(keys::PASSWORD),
-            auth_type: AuthType::try_from(
-                auth.get_string(keys::TYPE)
-                    .as_deref()
-                    .unwrap_or(defaults::AUTH_TYPE),
-            )?,
-            access_token: auth.get_string(keys::ACCESS_TOKEN),
+            password: credentials::Password::from_yaml(auth)?,
+            access_token: credentials::obtain_secret(auth, keys::ACCESS_TOKEN)?,
+            database: auth.get_string(keys::DATABASE).filter(|d| !d.is_empty()),
+            service_principal,
+            identity_client_id: if auth_type == AuthType::ManagedIdentity {
+                auth.get_string(keys::CLIENT_ID).filter(|s| !s.is_empty())
+            } else {
+                None
+            },
+            kerberos: if auth_type == AuthType::Kerberos {
+                Some(Kerberos::from_yaml(auth))
+            } else {
+                None
+            },
+            auth_type,
         }
         .ensure())
     }
     pub fn username(&self) -> &str {
         &self.username
     }
-    pub fn password(&self) -> Option<&String> {
-        self.password.as_ref()
+    /// read on every call: the source may be a store asked over network, blocking
+    pub fn password(&self) -> Result<Option<String>> {
+        self.password
+            .as_ref()
+            .map(credentials::Password::obtain)
+            .transpose()
+    }
+    pub fn has_password(&self) -> bool {
+        self.password.is_some()
     }
     pub fn auth_type(&self) -> &AuthType {
         &self.auth_type
@@ -344,13 +526,39 @@ impl Authentication {
     pub fn access_token(&self) -> Option<&String> {
         self.access_token.as_ref()
     }
+    pub fn database(&self) -> Option<&str> {
+        self.database.as_deref()
+    }
+    /// only for AuthType::AzureAd
+    pub fn service_principal(&self) -> Option<&ServicePrincipal> {
+        self.service_principal.as_ref()
+    }
+    /// only for AuthType::ManagedIdentity
+    pub fn identity_client_id(&self) -> Option<&str> {
+        self.identity_client_id.as_deref()
+    }
+    /// only for AuthType::Kerberos
+    pub fn kerberos(&self) -> Option<&Kerberos> {
+        self.kerberos.as_ref()
+    }
 
     pub fn defined(&self) -> bool {
-        self.auth_type() == &AuthType::Integrated || !self.username().is_empty()
+        matches!(
+            self.auth_type(),
+            AuthType::Integrated
+                | AuthType::AzureAd
+                | AuthType::ManagedIdentity
+                | AuthType::Kerberos
+                | AuthType::Gmsa
+        ) || !self.username().is_empty()
     }
 
+    /// identity of the process: no credentials are used even if configured
     fn ensure(mut self) -> Self {
-        if self.auth_type() == &AuthType::Integrated {
+        if matches!(self.auth_type(), AuthType::Integrated | AuthType::Gmsa) {
+            if self.auth_type() == &AuthType::Gmsa && self.password.is_some() {
+                log::warn!("Password is ignored: gMSA uses identity of the service");
+            }
             self.username = String::new();
             self.password = None;
             self.access_token = None;
@@ -359,12 +567,95 @@ impl Authentication {
     }
 }
 
+/// Azure AD(Entra ID) application, the token is obtained with the secret or the certificate
+#[derive(PartialEq, Debug, Clone)]
+pub struct ServicePrincipal {
+    tenant_id: String,
+    client_id: String,
+    client_secret: Option<String>,
+    /// PEM with the certificate and its private key
+    client_certificate: Option<PathBuf>,
+}
+
+impl ServicePrincipal {
+    fn from_yaml(auth: &Yaml) -> Result<Self> {
+        let tenant_id = auth
+            .get_string(keys::TENANT_ID)
+            .filter(|s| !s.is_empty())
+            .ok_or_else(|| anyhow!("{} is missing", keys::TENANT_ID))?;
+        let client_id = auth
+            .get_string(keys::CLIENT_ID)
+            .filter(|s| !s.is_empty())
+            .ok_or_else(|| anyhow!("{} is missing", keys::CLIENT_ID))?;
+        let client_secret = credentials::obtain_secret(auth, keys::CLIENT_SECRET)?;
+        let client_certificate = auth.get_pathbuf(keys::CLIENT_CERTIFICATE);
+        if client_secret.is_none() && client_certificate.is_none() {
+            bail!(
+                "either {} or {} is required",
+                keys::CLIENT_SECRET,
+                keys::CLIENT_CERTIFICATE
+            );
+        }
+        Ok(Self {
+            tenant_id,
+            client_id,
+            client_secret,
+            client_certificate,
+        })
+    }
+
+    pub fn tenant_id(&self) -> &str {
+        &self.tenant_id
+    }
+    pub fn client_id(&self) -> &str {
+        &self.client_id
+    }
+    pub fn client_secret(&self) -> Option<&str> {
+        self.client_secret.as_deref()
+    }
+    pub fn client_certificate(&self) -> Option<&Path> {
+        self.client_certificate.as_deref()
+    }
+}
+
+/// Source of the Kerberos ticket, the default ticket cache of the user if nothing is set
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Kerberos {
+    /// initial ticket is obtained by GSSAPI from the keytab
+    keytab: Option<PathBuf>,
+    /// e.g. `FILE:/tmp/krb5cc_checkmk`
+    credential_cache: Option<String>,
+}
+
+impl Kerberos {
+    fn from_yaml(auth: &Yaml) -> Self {
+        Self {
+            keytab: auth.get_pathbuf(keys::KEYTAB),
+            credential_cache: auth
+                .get_string(keys::CREDENTIAL_CACHE)
+                .filter(|s| !s.is_empty()),
+        }
+    }
+
+    pub fn keytab(&self) -> Option<&Path> {
+        self.keytab.as_deref()
+    }
+    pub fn credential_cache(&self) -> Option<&str> {
+        self.credential_cache.as_deref()
+    }
+}
+
 #[derive(PartialEq, Debug, Clone)]
 pub enum AuthType {
     SqlServer,
     Windows,
     Integrated,
     Token,
+    AzureAd,
+    ManagedIdentity,
+    Kerberos,
+    /// Group Managed Service Account of the service, Windows only
+    Gmsa,
     Undefined,
 }
 
@@ -390,11 +681,100 @@ impl TryFrom<&str> for AuthType {
             #[cfg(windows)]
             values::INTEGRATED => Ok(AuthType::Integrated),
             values::TOKEN => Ok(AuthType::Token),
+            values::AZURE_AD => Ok(AuthType::AzureAd),
+            values::MANAGED_IDENTITY => Ok(AuthType::ManagedIdentity),
+            #[cfg(unix)]
+            values::KERBEROS => Ok(AuthType::Kerberos),
+            #[cfg(windows)]
+            values::GMSA => Ok(AuthType::Gmsa),
             _ => Err(anyhow!("unsupported auth type `{val}`")),
         }
     }
 }
 
+/// encryption and validation of the server certificate, as `sslmode` of other DB clients
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum TlsMode {
+    /// no encryption at all
+    Disabled,
+    /// encryption if the server supports it, certificate is not validated
+    Prefer,
+    /// encryption, certificate is not validated
+    Require,
+    /// encryption, certificate chain and host name are validated by the driver
+    VerifyFull,
+}
+
+impl TlsMode {
+    /// the driver validates the certificate of its own TLS session
+    pub fn verifies_chain(&self) -> bool {
+        matches!(self, TlsMode::VerifyFull)
+    }
+}
+
+impl TryFrom<&str> for TlsMode {
+    type Error = anyhow::Error;
+
+    fn try_from(val: &str) -> Result<Self> {
+        match str::to_ascii_lowercase(val).as_ref() {
+            values::DISABLED => Ok(TlsMode::Disabled),
+            values::PREFER => Ok(TlsMode::Prefer),
+            values::REQUIRE => Ok(TlsMode::Require),
+            values::VERIFY_CA => Err(anyhow!(
+                "tls mode `{val}` is not supported: the driver always checks the host name, use `{}`",
+                values::VERIFY_FULL
+            )),
+            values::VERIFY_FULL => Ok(TlsMode::VerifyFull),
+            _ => Err(anyhow!("unsupported tls mode `{val}`")),
+        }
+    }
+}
+
+/// transport of TDS
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub enum Protocol {
+    #[default]
+    Tcp,
+    /// Windows only, for servers with TCP disabled by policy
+    NamedPipes,
+    /// Windows only, local instances with integrated authentication, by ODBC
+    SharedMemory,
+}
+
+impl TryFrom<&str> for Protocol {
+    type Error = anyhow::Error;
+
+    fn try_from(val: &str) -> Result<Self> {
+        match str::to_ascii_lowercase(val).as_ref() {
+            values::TCP => Ok(Protocol::Tcp),
+            values::NAMED_PIPES => Ok(Protocol::NamedPipes),
+            values::SHARED_MEMORY => Ok(Protocol::SharedMemory),
+            _ => Err(anyhow!("unsupported protocol `{val}`")),
+        }
+    }
+}
+
+/// intent of the connection to an AG listener
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub enum ApplicationIntent {
+    #[default]
+    ReadWrite,
+    /// routed to a readable secondary, the primary is used on failure
+    ReadOnly,
+}
+
+impl TryFrom<&str> for ApplicationIntent {
+    type Error = anyhow::Error;
+
+    fn try_from(val: &str) -> Result<Self> {
+        match str::to_ascii_lowercase(val).as_ref() {
+            values::READ_WRITE => Ok(ApplicationIntent::ReadWrite),
+            values::READ_ONLY => Ok(ApplicationIntent::ReadOnly),
+            _ => Err(anyhow!("unsupported application intent `{val}`")),
+        }
+    }
+}
+
 #[derive(PartialEq, Debug, Clone)]
 pub struct Connection {
     hostname: HostName,
@@ -403,6 +783,21 @@ pub struct Connection {
     socket: Option<PathBuf>,
     trust_server_certificate: bool,
     tls: Option<ConnectionTls>,
+    /// absent: encryption with fallback to plain text where allowed
+    tls_mode: Option<TlsMode>,
+    /// root trusted in addition to the system store, validated by the driver with the host name
+    ca_bundle: Option<PathBuf>,
+    protocol: Protocol,
+    /// all addresses of the host(AG listener in several subnets) are connected in parallel
+    multi_subnet_failover: bool,
+    /// default of sections, may be overridden per section
+    application_intent: ApplicationIntent,
+    /// full name of the pipe, e.g. `\\host\pipe\sql\query`, absent: derived from the instance
+    pipe: Option<String>,
+    /// named instance of the client alias without port, its port is resolved by SQL Browser
+    browse_instance: Option<InstanceName>,
+    /// absent: failures are final
+    retry: Option<Retry>,
     timeout: u64,
 }
 
@@ -412,6 +807,47 @@ impl Connection {
         if conn.is_badvalue() {
             return Ok(None);
         }
+        let explicit_port = conn.get_int::<u16>(keys::PORT).is_some();
+        let tls_mode = conn
+            .get(keys::TLS)
+            .get_string(keys::MODE)
+            .map(|m| TlsMode::try_from(m.as_str()))
+            .transpose()?;
+        if !conn.get(keys::CERTIFICATE_FINGERPRINT).is_badvalue() {
+            anyhow::bail!(
+                "certificate_fingerprint is not supported: the TDS driver can't pin the certificate of its TLS session, use ca_bundle"
+            );
+        }
+        let ca_bundle = conn
+            .get_pathbuf(keys::CA_BUNDLE)
+            .map(check_ca_bundle)
+            .transpose()?;
+        if tls_mode == Some(TlsMode::Disabled) && ca_bundle.is_some() {
+            anyhow::bail!("TLS mode `disabled` can't be used with ca_bundle");
+        }
+        let protocol = conn
+            .get_string(keys::PROTOCOL)
+            .map(|p| Protocol::try_from(p.as_str()))
+            .transpose()?
+            .unwrap_or_default();
+        // the server certificate is validated over TCP
+        if protocol != Protocol::Tcp
+            && (ca_bundle.is_some() || tls_mode.is_some_and(|m| m.verifies_chain()))
+        {
+            anyhow::bail!(
+                "Protocol {protocol:?} can't be used with ca_bundle or TLS mode {tls_mode:?}"
+            );
+        }
+        if protocol == Protocol::SharedMemory
+            && auth.is_some_and(|a| a.auth_type() != &AuthType::Integrated)
+        {
+            anyhow::bail!("Protocol `shared_memory` requires integrated authentication");
+        }
+        let application_intent = conn
+            .get_string(keys::APPLICATION_INTENT)
+            .map(|i| ApplicationIntent::try_from(i.as_str()))
+            .transpose()?
+            .unwrap_or_default();
         Ok(Some(
             Self {
                 hostname: conn
@@ -437,12 +873,21 @@ impl Connection {
                     defaults::TRUST_SERVER_CERTIFICATE,
                 ),
                 tls: ConnectionTls::from_yaml(conn)?,
+                tls_mode,
+                ca_bundle,
+                protocol,
+                multi_subnet_failover: conn.get_bool(keys::MULTI_SUBNET_FAILOVER, false),
+                application_intent,
+                pipe: conn.get_string(keys::PIPE).filter(|p| !p.is_empty()),
+                browse_instance: None,
+                retry: Retry::from_yaml(conn),
                 timeout: conn.get_int::<u64>(keys::TIMEOUT).unwrap_or_else(|| {
                     log::debug!("no timeout specified, using default");
                     defaults::CONNECTION_TIMEOUT
                 }),
             }
-            .ensure(auth),
+            .ensure(auth)
+            .resolve_client_alias(explicit_port),
         ))
     }
     pub fn hostname(&self) -> HostName {
@@ -457,6 +902,10 @@ impl Connection {
     pub fn sql_browser_port(&self) -> Option<u16> {
         None
     }
+    /// the main connection goes to the port SQL Browser reports for the instance
+    pub fn browse_instance(&self) -> Option<&InstanceName> {
+        self.browse_instance.as_ref()
+    }
     pub fn socket(&self) -> Option<&PathBuf> {
         self.socket.as_ref()
     }
@@ -466,6 +915,44 @@ impl Connection {
     pub fn tls(&self) -> Option<&ConnectionTls> {
         self.tls.as_ref()
     }
+    pub fn tls_mode(&self) -> Option<TlsMode> {
+        self.tls_mode
+    }
+    pub fn ca_bundle(&self) -> Option<&Path> {
+        self.ca_bundle.as_deref()
+    }
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+    pub fn multi_subnet_failover(&self) -> bool {
+        self.multi_subnet_failover
+    }
+    pub fn application_intent(&self) -> ApplicationIntent {
+        self.application_intent
+    }
+    pub fn retry(&self) -> Option<&Retry> {
+        self.retry.as_ref()
+    }
+    /// configured pipe or the standard one of the instance: `\\host\pipe\sql\query` of
+    /// the default instance, `\\host\pipe\MSSQL$NAME\sql\query` of a named one
+    pub fn pipe_path(&self, instance: Option<&InstanceName>) -> String {
+        if let Some(pipe) = &self.pipe {
+            return pipe.clone();
+        }
+        let host = self.hostname.to_string();
+        let host = if host == defaults::CONNECTION_HOST_NAME {
+            "."
+        } else {
+            host.as_str()
+        };
+        match instance
+            .map(|i| i.to_string().to_uppercase())
+            .filter(|i| i != "MSSQLSERVER")
+        {
+            Some(name) => format!(r"\\{host}\pipe\MSSQL${name}\sql\query"),
+            None => format!(r"\\{host}\pipe\sql\query"),
+        }
+    }
     pub fn timeout(&self) -> Duration {
         Duration::from_secs(self.timeout)
     }
@@ -480,6 +967,67 @@ impl Connection {
         }
         self
     }
+
+    /// Replaces hostname with the SQL Server client alias target as SSMS does
+    /// - port from the alias is used only if port is not set explicitly in config
+    /// - named instance without port is found by SQL Browser on connect
+    /// - named pipe alias switches TCP to `named_pipes` with the pipe of the alias
+    /// - shared memory aliases are used by ODBC only
+    fn resolve_client_alias(self, explicit_port: bool) -> Self {
+        let name = self.hostname.to_string();
+        if name == defaults::CONNECTION_HOST_NAME {
+            return self;
+        }
+        match platform::registry::get_client_alias(&name) {
+            Some(alias) => self.apply_client_alias(&name, &alias, explicit_port),
+            None => self,
+        }
+    }
+
+    fn apply_client_alias(mut self, name: &str, alias: &ClientAlias, explicit_port: bool) -> Self {
+        match alias.transport {
+            Transport::Tcp => {}
+            Transport::NamedPipe if self.protocol == Protocol::Tcp && !self.validates_tls() => {
+                self.protocol = Protocol::NamedPipes;
+                self.pipe = self.pipe.or_else(|| alias.pipe.clone());
+            }
+            _ => {
+                log::warn!(
+                    "Client alias `{name}` uses {:?} protocol, it can't be used with protocol {:?} and TLS mode {:?}",
+                    alias.transport,
+                    self.protocol,
+                    self.tls_mode
+                );
+                return self;
+            }
+        }
+        log::info!(
+            "Client alias `{name}` resolved to `{}` port {:?} instance {:?} pipe {:?}",
+            alias.host,
+            alias.port,
+            alias.instance,
+            alias.pipe
+        );
+        // `.` is the local host of the pipe
+        self.hostname = if alias.host == "." {
+            defaults::CONNECTION_HOST_NAME.to_string().into()
+        } else {
+            alias.host.to_lowercase().into()
+        };
+        if !explicit_port {
+            match (&alias.port, &alias.instance) {
+                (Some(port), _) => self.port = port.clone(),
+                (None, Some(instance)) => self.browse_instance = Some(instance.clone()),
+                (None, None) => {}
+            }
+        }
+        self
+    }
+
+    /// the server certificate is validated over TCP only
+    fn validates_tls(&self) -> bool {
+        self.ca_bundle.is_some() || self.tls_mode.is_some_and(|m| m.verifies_chain())
+    }
 }
 
 impl Default for Connection {
@@ -491,11 +1039,72 @@ impl Default for Connection {
             socket: None,
             trust_server_certificate: defaults::TRUST_SERVER_CERTIFICATE,
             tls: None,
+            tls_mode: None,
+            ca_bundle: None,
+            protocol: Protocol::default(),
+            multi_subnet_failover: false,
+            application_intent: ApplicationIntent::default(),
+            pipe: None,
+            browse_instance: None,
+            retry: None,
             timeout: defaults::CONNECTION_TIMEOUT,
         }
     }
 }
 
+/// retry of transient connection and query failures
+#[derive(PartialEq, Debug, Clone)]
+pub struct Retry {
+    /// including the first one
+    attempts: u32,
+    /// doubled with every next attempt
+    base_delay: Duration,
+    /// up to a half of the delay is random: instances don't retry in lockstep
+    jitter: bool,
+}
+
+impl Retry {
+    pub fn from_yaml(yaml: &Yaml) -> Option<Self> {
+        let retry = yaml.get(keys::RETRY);
+        if retry.is_badvalue() {
+            return None;
+        }
+        Some(Self {
+            attempts: retry
+                .get_int::<u32>(keys::ATTEMPTS)
+                .unwrap_or(defaults::RETRY_ATTEMPTS)
+                .max(1),
+            base_delay: Duration::from_millis(
+                retry
+                    .get_int::<u64>(keys::BASE_DELAY)
+                    .unwrap_or(defaults::RETRY_BASE_DELAY),
+            ),
+            jitter: retry.get_bool(keys::JITTER, true),
+        })
+    }
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+    pub fn base_delay(&self) -> Duration {
+        self.base_delay
+    }
+    pub fn jitter(&self) -> bool {
+        self.jitter
+    }
+}
+
+/// the driver loads a single certificate, PEM(`.pem`, `.crt`, the first one) or DER(`.der`)
+fn check_ca_bundle(path: PathBuf) -> Result<PathBuf> {
+    match path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .as_deref()
+    {
+        Some("pem" | "crt" | "der") => Ok(path),
+        _ => anyhow::bail!("Bad ca_bundle {path:?}, expected .pem, .crt or .der file"),
+    }
+}
+
 #[derive(PartialEq, Debug, Clone)]
 pub struct ConnectionTls {
     ca: PathBuf,
@@ -505,7 +1114,10 @@ pub struct ConnectionTls {
 impl ConnectionTls {
     pub fn from_yaml(yaml: &Yaml) -> Result<Option<Self>> {
         let tls = yaml.get(keys::TLS);
-        if tls.is_badvalue() {
+        // `mode` alone doesn't need certificates
+        if tls.is_badvalue()
+            || (tls.get(keys::CA).is_badvalue() && tls.get(keys::CLIENT_CERTIFICATE).is_badvalue())
+        {
             return Ok(None);
         }
         let ca = tls.get_pathbuf(keys::CA).context("Bad/Missing CA")?;
@@ -652,6 +1264,9 @@ pub struct CustomInstance {
     alias: Option<InstanceAlias>,
     piggyback: Option<Piggyback>,
     tcp: bool,
+    /// tried in order if the instance is not reachable using `conn`
+    alternates: Vec<Connection>,
+    output: Option<Output>,
 }
 
 impl CustomInstance {
@@ -672,6 +1287,7 @@ impl CustomInstance {
         );
         let (auth, conn) = CustomInstance::ensure_auth_and_conn(yaml, main_auth, main_conn, &name)?;
         let tcp = is_use_tcp(&name, &auth, &conn);
+        let alternates = CustomInstance::make_alternates(yaml, &conn)?;
         Ok(Self {
             name,
             auth,
@@ -679,6 +1295,8 @@ impl CustomInstance {
             alias: yaml.get_string(keys::ALIAS).map(InstanceAlias::from),
             piggyback: Piggyback::from_yaml(yaml, sections)?,
             tcp,
+            alternates,
+            output: Output::from_yaml(yaml)?,
         })
     }
 
@@ -700,9 +1318,33 @@ impl CustomInstance {
             alias: None,
             piggyback: None,
             tcp: port.is_some(),
+            alternates: vec![],
+            output: None,
         }
     }
 
+    /// Alternate endpoint inherits connection of the instance except hostname and port:
+    /// VNNs of a cluster or manual failover pairs without listener
+    fn make_alternates(yaml: &Yaml, conn: &Connection) -> Result<Vec<Connection>> {
+        yaml.get_yaml_vector(keys::ALTERNATE_ENDPOINTS)
+            .iter()
+            .map(|a| {
+                let hostname = a
+                    .get_string(keys::HOSTNAME)
+                    .filter(|h| !h.is_empty())
+                    .context("Bad/Missing hostname in alternate endpoint")?;
+                Ok(Connection {
+                    hostname: hostname.to_lowercase().into(),
+                    port: a
+                        .get_int::<u16>(keys::PORT)
+                        .map(Port)
+                        .unwrap_or_else(|| conn.port()),
+                    ..conn.clone()
+                })
+            })
+            .collect()
+    }
+
     /// Make auth and conn for custom instance using yaml
     /// - fallback on main_auth and main_conn if not defined in yaml
     /// - correct connection hostname if needed
@@ -712,7 +1354,12 @@ impl CustomInstance {
         main_conn: &Connection,
         sid: &InstanceName,
     ) -> Result<(Authentication, Connection)> {
-        let auth = Authentication::from_yaml(yaml).unwrap_or(main_auth.clone());
+        let auth = if yaml.get(keys::AUTHENTICATION).is_badvalue() {
+            main_auth.clone()
+        } else {
+            Authentication::from_yaml(yaml)
+                .with_context(|| format!("Bad authentication of instance `{sid}`"))?
+        };
         let conn = Connection::from_yaml(yaml, Some(&auth))?.unwrap_or(main_conn.clone());
 
         let instance_host = calc_real_host(&auth, &conn);
@@ -758,12 +1405,21 @@ impl CustomInstance {
     pub fn endpoint(&self) -> Endpoint {
         Endpoint::new(&self.auth, &self.conn)
     }
+    pub fn alternate_endpoints(&self) -> Vec<Endpoint> {
+        self.alternates
+            .iter()
+            .map(|c| Endpoint::new(&self.auth, c))
+            .collect()
+    }
     pub fn alias(&self) -> &Option<InstanceAlias> {
         &self.alias
     }
     pub fn piggyback(&self) -> Option<&Piggyback> {
         self.piggyback.as_ref()
     }
+    pub fn output(&self) -> Option<&Output> {
+        self.output.as_ref()
+    }
     pub fn calc_real_host(&self) -> HostName {
         calc_real_host(&self.auth, &self.conn)
     }
@@ -820,6 +1476,39 @@ impl Piggyback {
     }
 }
 
+/// Output of the instance is written into the file instead of stdout: one physical host may
+/// feed other Checkmk hosts, e.g. through the spool dir of their agents
+#[derive(PartialEq, Debug, Clone)]
+pub struct Output {
+    file: PathBuf,
+    stdout: bool,
+}
+
+impl Output {
+    pub fn from_yaml(yaml: &Yaml) -> Result<Option<Self>> {
+        let output = yaml.get(keys::OUTPUT);
+        if output.is_badvalue() {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            file: output
+                .get_pathbuf(keys::FILE)
+                .filter(|f| !f.as_os_str().is_empty())
+                .context("Bad/Missing file in output")?,
+            stdout: output.get_bool(keys::STDOUT, defaults::OUTPUT_STDOUT),
+        }))
+    }
+
+    pub fn file(&self) -> &Path {
+        &self.file
+    }
+
+    /// output is printed also to stdout
+    pub fn stdout(&self) -> bool {
+        self.stdout
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use tests::defaults::{MAX_CONNECTIONS, MAX_QUERIES};
@@ -931,6 +1620,7 @@ authentication:
   password: "bar"
   type: "sql_server"
   access_token: "baz"
+  database: "sales"
 "#;
         #[cfg(windows)]
         pub const AUTHENTICATION_INTEGRATED: &str = r#"
@@ -998,6 +1688,19 @@ piggyback:
   hostname: "piggy"
   sections:
   cache_age: 123
+"#;
+        pub const INSTANCE_ALTERNATES: &str = r#"
+sid: "INST1"
+authentication:
+  username: "u1"
+  type: "sql_server"
+connection:
+  hostname: "vnn1"
+  port: 1500
+alternate_endpoints:
+  - hostname: "VNN2"
+  - hostname: "10.0.0.3"
+    port: 1433
 "#;
         pub const PIGGYBACK_NO_HOSTNAME: &str = r#"
 piggyback:
@@ -1037,6 +1740,74 @@ piggyback:
         let s = Options::default();
         assert_eq!(s.max_connections(), MAX_CONNECTIONS.into());
         assert_eq!(s.max_queries(), MAX_QUERIES.into());
+        assert_eq!(s.run_budget(), None);
+        assert_eq!(s.min_interval(), None);
+        assert_eq!(s.cache_refresh_lead(), Duration::ZERO);
+        assert_eq!(s.concurrency_classes(), [("heavy".to_string(), 1)]);
+        assert!(!s.trailer());
+        assert_eq!(s.section_timeout(), None);
+    }
+
+    #[test]
+    fn test_options_trailer() {
+        let s = Options::from_yaml(&create_yaml("options:\n  trailer: yes\n"))
+            .unwrap()
+            .unwrap();
+        assert!(s.trailer());
+    }
+
+    #[test]
+    fn test_options_concurrency_classes() {
+        let s = Options::from_yaml(&create_yaml(
+            "options:\n  concurrency_classes:\n    heavy: 2\n    light: 0\n    bad: x\n",
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            s.concurrency_classes(),
+            [("heavy".to_string(), 2), ("light".to_string(), 0)]
+        );
+    }
+
+    #[test]
+    fn test_options_section_timeout() {
+        let s = Options::from_yaml(&create_yaml("options:\n  section_timeout: 60\n"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(s.section_timeout(), Some(Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn test_options_cache_refresh_lead() {
+        let s = Options::from_yaml(&create_yaml("options:\n  cache_refresh_lead: 30\n"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(s.cache_refresh_lead(), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn test_options_min_interval() {
+        let s = Options::from_yaml(&create_yaml("options:\n  min_interval: 30\n"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(s.min_interval(), Some(Duration::from_secs(30)));
+        assert_eq!(s.run_budget(), None);
+        let s = Options::from_yaml(&create_yaml("options:\n  min_interval: 0\n"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(s.min_interval(), None);
+    }
+
+    #[test]
+    fn test_options_run_budget() {
+        let s = Options::from_yaml(&create_yaml("options:\n  run_budget: 50\n"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(s.run_budget(), Some(Duration::from_secs(50)));
+        let s = Options::from_yaml(&create_yaml("options:\n  run_budget: 0\n"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(s.run_budget(), None);
     }
 
     #[test]
@@ -1069,9 +1840,120 @@ piggyback:
     fn test_authentication_from_yaml() {
         let a = Authentication::from_yaml(&create_yaml(data::AUTHENTICATION_FULL)).unwrap();
         assert_eq!(a.username(), "foo");
-        assert_eq!(a.password(), Some(&"bar".to_owned()));
+        assert_eq!(a.password().unwrap(), Some("bar".to_owned()));
         assert_eq!(a.auth_type(), &AuthType::SqlServer);
         assert_eq!(a.access_token(), Some(&"baz".to_owned()));
+        assert_eq!(a.database(), Some("sales"));
+    }
+
+    #[test]
+    fn test_authentication_from_yaml_azure_ad() {
+        let a = Authentication::from_yaml(&create_yaml(
+            r#"
+authentication:
+  type: "azure_ad"
+  tenant_id: "t"
+  client_id: "c"
+  client_secret: "s"
+"#,
+        ))
+        .unwrap();
+        assert_eq!(a.auth_type(), &AuthType::AzureAd);
+        assert!(a.defined());
+        let principal = a.service_principal().unwrap();
+        assert_eq!(principal.tenant_id(), "t");
+        assert_eq!(principal.client_id(), "c");
+        assert_eq!(principal.client_secret(), Some("s"));
+        assert_eq!(principal.client_certificate(), None);
+        assert!(Authentication::from_yaml(&create_yaml(
+            r#"
+authentication:
+  type: "azure_ad"
+  tenant_id: "t"
+  client_id: "c"
+"#,
+        ))
+        .is_err());
+        assert!(Authentication::from_yaml(&create_yaml(
+            r#"
+authentication:
+  type: "azure_ad"
+  client_id: "c"
+  client_certificate: "c.pem"
+"#,
+        ))
+        .is_err());
+    }
+
+    #[test]
+    fn test_authentication_from_yaml_managed_identity() {
+        let a = Authentication::from_yaml(&create_yaml(
+            "authentication:\n  type: managed_identity\n  client_id: c\n",
+        ))
+        .unwrap();
+        assert_eq!(a.auth_type(), &AuthType::ManagedIdentity);
+        assert!(a.defined());
+        assert_eq!(a.identity_client_id(), Some("c"));
+        assert_eq!(a.service_principal(), None);
+        let a =
+            Authentication::from_yaml(&create_yaml("authentication:\n  type: managed_identity\n"))
+                .unwrap();
+        assert_eq!(a.identity_client_id(), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_authentication_from_yaml_kerberos() {
+        let a = Authentication::from_yaml(&create_yaml(
+            "authentication:\n  type: kerberos\n  keytab: /etc/krb5.keytab\n",
+        ))
+        .unwrap();
+        assert_eq!(a.auth_type(), &AuthType::Kerberos);
+        assert!(a.defined());
+        let kerberos = a.kerberos().unwrap();
+        assert_eq!(kerberos.keytab(), Some(Path::new("/etc/krb5.keytab")));
+        assert_eq!(kerberos.credential_cache(), None);
+        let a =
+            Authentication::from_yaml(&create_yaml("authentication:\n  type: kerberos\n")).unwrap();
+        assert_eq!(a.kerberos(), Some(&Kerberos::default()));
+    }
+
+    #[test]
+    fn test_authentication_spn() {
+        let e = Authentication::from_yaml(&create_yaml(
+            "authentication:\n  type: gmsa\n  spn: MSSQLSvc/sql01:1433\n",
+        ))
+        .unwrap_err();
+        assert!(e.to_string().starts_with("spn is not supported"));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_endpoint_gmsa() {
+        let config = Config::from_string(
+            r"---
+mssql:
+  main:
+    authentication:
+      type: gmsa
+      username: ignored
+    connection:
+      hostname: SQL01.corp.local
+      port: 14330
+",
+        )
+        .unwrap()
+        .unwrap();
+        let endpoint = config.endpoint();
+        assert_eq!(endpoint.auth().auth_type(), &AuthType::Gmsa);
+        assert_eq!(endpoint.auth().username(), "");
+        assert!(endpoint.auth().defined());
+        assert_eq!(
+            endpoint.conn().hostname(),
+            "sql01.corp.local".to_string().into()
+        );
+        assert_eq!(endpoint.conn().port(), Port(14330));
+        assert!(!is_local_host(endpoint.auth(), endpoint.conn()));
     }
 
     #[test]
@@ -1097,12 +1979,13 @@ authentication:
         assert_eq!(a.username(), "");
         #[cfg(unix)]
         assert_eq!(a.username(), "foo");
-        assert_eq!(a.password(), None);
+        assert!(!a.has_password());
         #[cfg(windows)]
         assert_eq!(a.auth_type(), &AuthType::Integrated);
         #[cfg(unix)]
         assert_eq!(a.auth_type(), &AuthType::SqlServer);
         assert_eq!(a.access_token(), None);
+        assert_eq!(a.database(), None);
     }
 
     #[cfg(windows)]
@@ -1110,7 +1993,7 @@ authentication:
     fn test_authentication_from_yaml_integrated() {
         let a = Authentication::from_yaml(&create_yaml(data::AUTHENTICATION_INTEGRATED)).unwrap();
         assert_eq!(a.username(), "");
-        assert_eq!(a.password(), None);
+        assert!(!a.has_password());
         assert_eq!(a.auth_type(), &AuthType::Integrated);
         assert_eq!(a.access_token(), None);
     }
@@ -1134,6 +2017,144 @@ authentication:
         );
     }
 
+    #[test]
+    fn test_connection_tls_mode() {
+        let make = |mode: &str| {
+            Connection::from_yaml(
+                &create_yaml(&format!("connection:\n  tls:\n    mode: {mode}\n")),
+                None,
+            )
+        };
+        let conn = make("Verify-Full").unwrap().unwrap();
+        assert_eq!(conn.tls_mode(), Some(TlsMode::VerifyFull));
+        assert!(conn.tls().is_none());
+        let e = make("verify-ca").unwrap_err();
+        assert!(e.to_string().contains("use `verify-full`"), "{e}");
+        assert_eq!(
+            make("disabled").unwrap().unwrap().tls_mode(),
+            Some(TlsMode::Disabled)
+        );
+        assert!(make("allow").is_err());
+        assert!(TlsMode::VerifyFull.verifies_chain());
+        assert!(!TlsMode::Require.verifies_chain());
+        assert!(Connection::from_yaml(
+            &create_yaml("connection:\n  ca_bundle: a.pem\n  tls:\n    mode: disabled\n"),
+            None
+        )
+        .is_err());
+        assert_eq!(
+            Connection::from_yaml(&create_yaml("connection:\n  port: 1\n"), None)
+                .unwrap()
+                .unwrap()
+                .tls_mode(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_connection_ca_bundle() {
+        let make = |text: &str| Connection::from_yaml(&create_yaml(text), None);
+        let conn = make("connection:\n  ca_bundle: roots.PEM\n")
+            .unwrap()
+            .unwrap();
+        assert_eq!(conn.ca_bundle(), Some(Path::new("roots.PEM")));
+        assert!(make("connection:\n  ca_bundle: roots.p7b\n").is_err());
+        let e = make("connection:\n  certificate_fingerprint: \"AB:CD\"\n").unwrap_err();
+        assert!(e
+            .to_string()
+            .starts_with("certificate_fingerprint is not supported"));
+    }
+
+    #[test]
+    fn test_connection_protocol() {
+        let make = |text: &str| Connection::from_yaml(&create_yaml(text), None);
+        let conn = make("connection:\n  hostname: Sql1\n  protocol: Named_Pipes\n")
+            .unwrap()
+            .unwrap();
+        assert_eq!(conn.protocol(), Protocol::NamedPipes);
+        assert_eq!(conn.pipe_path(None), r"\\sql1\pipe\sql\query");
+        assert_eq!(
+            conn.pipe_path(Some(&InstanceName::from("MSSQLSERVER"))),
+            r"\\sql1\pipe\sql\query"
+        );
+        assert_eq!(
+            conn.pipe_path(Some(&InstanceName::from("sqlExpress"))),
+            r"\\sql1\pipe\MSSQL$SQLEXPRESS\sql\query"
+        );
+        assert_eq!(Connection::default().pipe_path(None), r"\\.\pipe\sql\query");
+        let conn = make("connection:\n  protocol: named_pipes\n  pipe: '\\\\.\\pipe\\custom'\n")
+            .unwrap()
+            .unwrap();
+        assert_eq!(conn.pipe_path(None), r"\\.\pipe\custom");
+        assert_eq!(
+            make("connection:\n  port: 1\n")
+                .unwrap()
+                .unwrap()
+                .protocol(),
+            Protocol::Tcp
+        );
+        assert!(make("connection:\n  protocol: via\n").is_err());
+        assert!(make("connection:\n  multi_subnet_failover: yes\n")
+            .unwrap()
+            .unwrap()
+            .multi_subnet_failover());
+        assert!(!Connection::default().multi_subnet_failover());
+        assert_eq!(
+            make("connection:\n  application_intent: read_only\n")
+                .unwrap()
+                .unwrap()
+                .application_intent(),
+            ApplicationIntent::ReadOnly
+        );
+        assert_eq!(
+            Connection::default().application_intent(),
+            ApplicationIntent::ReadWrite
+        );
+        assert!(make("connection:\n  application_intent: read_mostly\n").is_err());
+        assert!(Connection::default().retry().is_none());
+        assert_eq!(
+            make("connection:\n  retry:\n    attempts: 5\n    base_delay: 100\n    jitter: no\n")
+                .unwrap()
+                .unwrap()
+                .retry()
+                .unwrap(),
+            &Retry {
+                attempts: 5,
+                base_delay: Duration::from_millis(100),
+                jitter: false
+            }
+        );
+        let c = make("connection:\n  retry:\n    attempts: 0\n")
+            .unwrap()
+            .unwrap();
+        let retry = c.retry().unwrap();
+        assert_eq!(retry.attempts(), 1);
+        assert_eq!(
+            retry.base_delay(),
+            Duration::from_millis(defaults::RETRY_BASE_DELAY)
+        );
+        assert!(retry.jitter());
+        assert_eq!(
+            make("connection:\n  protocol: shared_memory\n")
+                .unwrap()
+                .unwrap()
+                .protocol(),
+            Protocol::SharedMemory
+        );
+        let sql_server = Authentication::from_yaml(&create_yaml(
+            "authentication:\n  username: u\n  password: p\n  type: sql_server\n",
+        ))
+        .unwrap();
+        assert!(Connection::from_yaml(
+            &create_yaml("connection:\n  protocol: shared_memory\n"),
+            Some(&sql_server)
+        )
+        .is_err());
+        assert!(
+            make("connection:\n  protocol: named_pipes\n  tls:\n    mode: verify-full\n").is_err()
+        );
+    }
+
     #[cfg(windows)]
     #[test]
     fn test_connection_from_yaml_auth_integrated() {
@@ -1154,6 +2175,52 @@ authentication:
         );
     }
 
+    #[test]
+    fn test_connection_apply_client_alias() {
+        let alias = ClientAlias::parse("DBMSSOCN,Real-Host,1444").unwrap();
+        let c = Connection::default().apply_client_alias("alias", &alias, false);
+        assert_eq!(c.hostname(), "real-host".to_string().into());
+        assert_eq!(c.port(), Port(1444));
+        let c = Connection::default().apply_client_alias("alias", &alias, true);
+        assert_eq!(c.hostname(), "real-host".to_string().into());
+        assert_eq!(c.port(), Port(defaults::CONNECTION_PORT));
+        let alias = ClientAlias::parse("DBMSSOCN,host").unwrap();
+        let c = Connection::default().apply_client_alias("alias", &alias, false);
+        assert_eq!(c.port(), Port(defaults::CONNECTION_PORT));
+        assert!(c.browse_instance().is_none());
+        let alias = ClientAlias::parse(r"DBMSSOCN,host\inst").unwrap();
+        let c = Connection::default().apply_client_alias("alias", &alias, false);
+        assert_eq!(c.browse_instance(), Some(&InstanceName::from("INST")));
+        let c = Connection::default().apply_client_alias("alias", &alias, true);
+        assert!(c.browse_instance().is_none());
+        let alias = ClientAlias::parse(r"DBMSSOCN,host\inst,1445").unwrap();
+        let c = Connection::default().apply_client_alias("alias", &alias, false);
+        assert_eq!(c.port(), Port(1445));
+        assert!(c.browse_instance().is_none());
+    }
+
+    #[test]
+    fn test_connection_apply_client_alias_pipe() {
+        let alias = ClientAlias::parse(r"DBNMPNTW,\\Sql1\pipe\sql\query").unwrap();
+        let c = Connection::default().apply_client_alias("alias", &alias, false);
+        assert_eq!(c.protocol(), Protocol::NamedPipes);
+        assert_eq!(c.hostname(), "sql1".to_string().into());
+        assert_eq!(c.pipe_path(None), r"\\Sql1\pipe\sql\query");
+        let c = Connection {
+            ca_bundle: Some(PathBuf::from("ca.pem")),
+            ..Default::default()
+        }
+        .apply_client_alias("alias", &alias, false);
+        assert_eq!(c.protocol(), Protocol::Tcp);
+        assert_eq!(
+            c.hostname(),
+            defaults::CONNECTION_HOST_NAME.to_string().into()
+        );
+        let alias = ClientAlias::parse("DBMSLPCN,host").unwrap();
+        let c = Connection::default().apply_client_alias("alias", &alias, false);
+        assert_eq!(c.protocol(), Protocol::Tcp);
+    }
+
     #[test]
     fn test_connection_from_yaml_default() {
         assert_eq!(
@@ -1339,6 +2406,58 @@ discovery:
         assert_eq!(instance.piggyback().unwrap().sections().cache_age(), 123);
     }
 
+    #[test]
+    fn test_custom_instance_alternates() {
+        let make = |source: &str| {
+            CustomInstance::from_yaml(
+                &create_yaml(source),
+                &Authentication::default(),
+                &Connection::default(),
+                &Sections::default(),
+            )
+        };
+        let instance = make(data::INSTANCE_ALTERNATES).unwrap();
+        let alternates = instance.alternate_endpoints();
+        assert_eq!(
+            alternates
+                .iter()
+                .map(|e| (e.hostname().to_string(), e.port()))
+                .collect::<Vec<_>>(),
+            [
+                ("vnn2".to_string(), Port(1500)),
+                ("10.0.0.3".to_string(), Port(1433))
+            ]
+        );
+        assert_eq!(alternates[0].auth().username(), "u1");
+        assert!(make(data::INSTANCE)
+            .unwrap()
+            .alternate_endpoints()
+            .is_empty());
+        assert!(
+            make(&(data::INSTANCE.to_string() + "alternate_endpoints:\n  - port: 1\n")).is_err()
+        );
+    }
+
+    #[test]
+    fn test_custom_instance_output() {
+        let make = |source: &str| {
+            CustomInstance::from_yaml(
+                &create_yaml(&(data::INSTANCE.to_string() + source)),
+                &Authentication::default(),
+                &Connection::default(),
+                &Sections::default(),
+            )
+        };
+        assert!(make("").unwrap().output().is_none());
+        let instance = make("output:\n  file: \"/spool/inst1.txt\"\n").unwrap();
+        let output = instance.output().unwrap();
+        assert_eq!(output.file(), Path::new("/spool/inst1.txt"));
+        assert!(!output.stdout());
+        let instance = make("output:\n  file: \"inst1.txt\"\n  stdout: yes\n").unwrap();
+        assert!(instance.output().unwrap().stdout());
+        assert!(make("output:\n  stdout: yes\n").is_err());
+    }
+
     fn expected_count_in_registry() -> usize {
         #[cfg(windows)]
         return 3;
@@ -1415,7 +2534,7 @@ connection:
         .unwrap();
         assert_eq!(instance.name().to_string(), "INST1");
         assert_eq!(instance.auth().username(), "u1");
-        assert_eq!(instance.auth().password().unwrap(), "pwd");
+        assert_eq!(instance.auth().password().unwrap().unwrap(), "pwd");
         assert_eq!(instance.auth().auth_type(), &AuthType::SqlServer);
         assert_eq!(instance.conn().hostname(), "localhost".to_string().into());
         assert_eq!(instance.calc_real_host(), "localhost".to_string().into());
@@ -1442,7 +2561,7 @@ connection:
         let inst2 = &c.instances()[1];
 
         assert_eq!(inst2.name().to_string(), "INST2");
-        assert_eq!(inst2.auth().password, Some("p".to_string()));
+        assert_eq!(inst2.auth().password().unwrap(), Some("p".to_string()));
         assert_eq!(&inst2.auth().username, "u");
         assert_eq!(inst2.auth().auth_type, AuthType::SqlServer);
         assert_eq!(inst2.conn().hostname, HostName::from("local".to_string()));
@@ -1455,7 +2574,7 @@ connection:
         assert_eq!(c.discovery().exclude(), &vec!["baz".to_string()]);
         assert!(c.discovery().detect());
         assert_eq!(c.auth().username(), "foo");
-        assert_eq!(c.auth().password().unwrap(), "bar");
+        assert_eq!(c.auth().password().unwrap().unwrap(), "bar");
         assert_eq!(c.auth().auth_type(), &AuthType::SqlServer);
         assert_eq!(c.auth().access_token().unwrap(), "baz");
         assert_eq!(c.conn().hostname(), "localhost".to_string().into());
@@ -1498,6 +2617,68 @@ connection:
         assert_eq!(c.cache_age(), defaults::SECTIONS_CACHE_AGE);
     }
 
+    #[test]
+    fn test_config_bad_authentication() {
+        let make = |instance_auth: &str| {
+            Config::from_string(&format!(
+                "---
+mssql:
+  main:
+    authentication:
+      username: u
+      password_source: credential_manager
+      credential_target: t
+    discovery:
+      detect: no
+    instances:
+      - sid: INST1
+        authentication:
+          username: u
+          {instance_auth}
+"
+            ))
+        };
+        // the store isn't asked by loading
+        assert!(make("password: p").unwrap().unwrap().auth().has_password());
+        let e = make("password_source: credential_manager").unwrap_err();
+        assert!(e.to_string().contains("instance `INST1`"), "{e}");
+        let e = Config::from_string(
+            "---\nmssql:\n  main:\n    authentication:\n      username: u\n      password_source: magic\n",
+        )
+        .unwrap_err();
+        assert_eq!(e.to_string(), "Bad authentication of `main`");
+    }
+
+    #[test]
+    fn test_config_kerberos() {
+        let make = |instance_auth: &str| {
+            Config::from_string(&format!(
+                "---
+mssql:
+  main:
+    authentication:
+      type: kerberos
+      keytab: /etc/a.keytab
+    discovery:
+      detect: no
+    instances:
+      - sid: INST1
+        authentication:
+          type: kerberos
+          {instance_auth}
+"
+            ))
+        };
+        let c = make("keytab: /etc/a.keytab").unwrap().unwrap();
+        assert_eq!(
+            c.kerberos().unwrap().keytab(),
+            Some(Path::new("/etc/a.keytab"))
+        );
+        assert!(make("credential_cache: ''").is_ok());
+        assert!(make("keytab: /etc/b.keytab").is_err());
+        assert!(make("credential_cache: FILE:/tmp/cc").is_err());
+    }
+
     #[test]
     fn test_config_discovery() {
         let c = make_detect_config(&[], &[]);
@@ -3,9 +3,12 @@
 // conditions defined in the file COPYING, which is part of this source code package.
 
 use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 
-use super::defines::{defaults, keys};
+use super::defines::{defaults, keys, values};
+use super::ms_sql::ApplicationIntent;
 use super::yaml::{Get, Yaml};
+use crate::ms_sql::registry;
 use anyhow::Result;
 
 pub mod names {
@@ -25,55 +28,46 @@ pub mod names {
     pub const JOBS: &str = "jobs";
     pub const MIRRORING: &str = "mirroring";
     pub const AVAILABILITY_GROUPS: &str = "availability_groups";
+
+    // optional sections, must be listed in config
+    pub const AGENT: &str = "agent";
+    pub const UPTIME: &str = "uptime";
+    pub const DATABASE_OPTIONS: &str = "database_options";
+    pub const CHECKPOINTS: &str = "checkpoints";
+    pub const BACKUP_CHAIN: &str = "backup_chain";
+    pub const CHECKDB: &str = "checkdb";
+    pub const WORKERS: &str = "workers";
+    pub const LOCKS: &str = "locks";
+    pub const FORCED_PLANS: &str = "forced_plans";
+    pub const SERVER_ROLES: &str = "server_roles";
+    pub const BUFFER_POOL_EXTENSION: &str = "buffer_pool_extension";
+    pub const VERSION_STORE: &str = "version_store";
+    pub const ADR: &str = "adr";
+    pub const INSTANCE_SETTINGS: &str = "instance_settings";
+    pub const ELASTIC_POOL: &str = "elastic_pool";
+    pub const DEADLOCKS: &str = "deadlocks";
+    pub const CONNECTION_SOURCES: &str = "connection_sources";
+    pub const WAITSTATS: &str = "waitstats";
+    pub const CONNECTIVITY: &str = "connectivity";
+    pub const INDEX_FRAGMENTATION: &str = "index_fragmentation";
+    pub const QUERY_STORE: &str = "query_store";
+    pub const MEMORY: &str = "memory";
+    pub const LOG_SHIPPING: &str = "logshipping";
+    pub const REPLICATION: &str = "replication";
+    pub const AGENT_ALERTS: &str = "agent_alerts";
+    pub const ERRORLOG: &str = "errorlog";
+    pub const FAILED_LOGINS: &str = "failed_logins";
+    pub const ENCRYPTION: &str = "encryption";
+    pub const BLOCKING_CHAINS: &str = "blocking_chains";
+    pub const IO_STATS: &str = "io_stats";
+    pub const PLAN_CACHE: &str = "plan_cache";
+    pub const LINKED_SERVERS: &str = "linked_servers";
+    pub const TENANTS: &str = "tenants";
+    pub const CDC: &str = "cdc";
+    pub const ORPHANED_USERS: &str = "orphaned_users";
+    pub const DATABASES_V2: &str = "databases_v2";
 }
 
-/// TODO(sk): convert into HashSet
-const PIPE_SEP_SECTIONS: [&str; 8] = [
-    names::INSTANCE,
-    names::COUNTERS,
-    names::BLOCKED_SESSIONS,
-    names::BACKUP,
-    names::TRANSACTION_LOG,
-    names::DATAFILES,
-    names::DATABASES,
-    names::CLUSTERS,
-];
-
-const SPACE_SEP_SECTIONS: [&str; 2] = [names::TABLE_SPACES, names::CONNECTIONS];
-
-const QUERY_BASED_SECTIONS: [&str; 3] = [names::JOBS, names::MIRRORING, names::AVAILABILITY_GROUPS];
-const PREDEFINED_SECTIONS: [&str; 13] = [
-    names::INSTANCE,
-    names::DATABASES,
-    names::COUNTERS,
-    names::BLOCKED_SESSIONS,
-    names::TRANSACTION_LOG,
-    names::CLUSTERS,
-    names::MIRRORING,
-    names::AVAILABILITY_GROUPS,
-    names::CONNECTIONS,
-    names::TABLE_SPACES,
-    names::DATAFILES,
-    names::BACKUP,
-    names::JOBS,
-];
-
-const ASYNC_SECTIONS: [&str; 4] = [
-    names::TABLE_SPACES,
-    names::DATAFILES,
-    names::BACKUP,
-    names::JOBS,
-];
-
-const PER_DATABASE_SECTIONS: [&str; 5] = [
-    names::DATABASES,
-    names::TRANSACTION_LOG,
-    names::TABLE_SPACES,
-    names::DATAFILES,
-    names::CLUSTERS,
-];
-
-const FIRST_LINE_SECTIONS: [&str; 2] = [names::MIRRORING, names::JOBS];
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub enum SectionKind {
     Sync,
@@ -81,25 +75,133 @@ pub enum SectionKind {
     Disabled,
 }
 
+/// Defines share of the run budget and order of deferring when the budget is tight
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub enum Priority {
+    /// never deferred
+    High,
+    #[default]
+    Normal,
+    /// deferred first
+    Low,
+}
+
+impl Priority {
+    pub fn weight(&self) -> u32 {
+        match self {
+            Priority::High => 4,
+            Priority::Normal => 2,
+            Priority::Low => 1,
+        }
+    }
+}
+
+impl TryFrom<&str> for Priority {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self> {
+        match value.to_lowercase().as_str() {
+            values::HIGH => Ok(Priority::High),
+            values::NORMAL => Ok(Priority::Normal),
+            values::LOW => Ok(Priority::Low),
+            _ => anyhow::bail!("unknown priority `{value}`"),
+        }
+    }
+}
+
+/// Rendering of values breaking the flat section format in custom sections:
+/// binary, XML and text with line breaks or the separator, e.g. JSON
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Render {
+    /// line breaks and separators are replaced with space, binary is hex, max length in chars
+    Truncate(usize),
+    Hex,
+    Base64,
+    /// empty value
+    Drop,
+}
+
+impl Render {
+    pub fn from_yaml(yaml: &Yaml) -> Result<Option<Self>> {
+        let max_length = yaml
+            .get_int::<usize>(keys::MAX_LENGTH)
+            .unwrap_or(defaults::RENDER_MAX_LENGTH);
+        yaml.get_string(keys::RENDER)
+            .map(|r| match r.to_lowercase().as_str() {
+                values::TRUNCATE => Ok(Render::Truncate(max_length)),
+                values::HEX => Ok(Render::Hex),
+                values::BASE64 => Ok(Render::Base64),
+                values::DROP => Ok(Render::Drop),
+                _ => anyhow::bail!("unknown render `{r}`"),
+            })
+            .transpose()
+    }
+}
+
 pub struct SectionBuilder {
     name: String,
     sep: char,
     is_async: bool,
     is_disabled: bool,
     sql: Option<String>,
+    post_process: Option<Vec<String>>,
+    priority: Priority,
+    baseline: Vec<(String, String)>,
+    catalog: Option<PathBuf>,
+    application_intent: Option<ApplicationIntent>,
+    deduplicate: bool,
+    pdh_fallback: bool,
+    test_links: bool,
+    link_timeout: Option<u32>,
+    tenant_property: Option<String>,
+    tenant_map: Vec<(String, String)>,
+    concurrency: Option<String>,
+    cache_age: Option<u32>,
+    databases: Vec<String>,
+    min_page_count: Option<u32>,
+    top: Option<u32>,
+    render: Option<Render>,
+    timeout: Option<u32>,
 }
 
 impl SectionBuilder {
     pub fn new<S: Into<String>>(name: S) -> Self {
         let name = name.into();
         let sep = get_default_separator(&name);
-        let is_async = ASYNC_SECTIONS.contains(&name.as_str());
+        let is_async = registry::find(&name).map_or(false, |d| d.is_async());
+        let is_heavy = registry::find(&name).map_or(false, |d| d.is_heavy());
+        // instance section signals state of the instance and must be always present
+        let priority = if name == names::INSTANCE {
+            Priority::High
+        } else if is_heavy {
+            Priority::Low
+        } else {
+            Priority::default()
+        };
         Self {
             name,
             sep,
             is_async,
             is_disabled: false,
             sql: None,
+            post_process: None,
+            priority,
+            baseline: vec![],
+            catalog: None,
+            application_intent: None,
+            deduplicate: false,
+            pdh_fallback: false,
+            test_links: false,
+            link_timeout: None,
+            timeout: None,
+            tenant_property: None,
+            tenant_map: vec![],
+            concurrency: is_heavy.then(|| defaults::HEAVY_CONCURRENCY_CLASS.to_string()),
+            cache_age: is_heavy.then_some(defaults::HEAVY_SECTION_CACHE_AGE),
+            databases: vec![],
+            min_page_count: None,
+            top: None,
+            render: None,
         }
     }
     pub fn sep(mut self, sep: Option<char>) -> Self {
@@ -123,6 +225,103 @@ impl SectionBuilder {
         self
     }
 
+    /// program and its arguments
+    pub fn post_process(mut self, command: Option<Vec<String>>) -> Self {
+        self.post_process = command;
+        self
+    }
+
+    pub fn priority(mut self, priority: Option<Priority>) -> Self {
+        if let Some(p) = priority {
+            self.priority = p;
+        }
+        self
+    }
+
+    pub fn baseline(mut self, baseline: Vec<(String, String)>) -> Self {
+        self.baseline = baseline;
+        self
+    }
+
+    pub fn catalog(mut self, catalog: Option<PathBuf>) -> Self {
+        self.catalog = catalog;
+        self
+    }
+
+    pub fn application_intent(mut self, value: Option<ApplicationIntent>) -> Self {
+        self.application_intent = value;
+        self
+    }
+
+    pub fn deduplicate(mut self, value: bool) -> Self {
+        self.deduplicate = value;
+        self
+    }
+
+    pub fn pdh_fallback(mut self, value: bool) -> Self {
+        self.pdh_fallback = value;
+        self
+    }
+
+    pub fn test_links(mut self, value: bool) -> Self {
+        self.test_links = value;
+        self
+    }
+
+    pub fn link_timeout(mut self, link_timeout: Option<u32>) -> Self {
+        self.link_timeout = link_timeout;
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Option<u32>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn tenant_property(mut self, property: Option<String>) -> Self {
+        self.tenant_property = property;
+        self
+    }
+
+    pub fn tenant_map(mut self, tenant_map: Vec<(String, String)>) -> Self {
+        self.tenant_map = tenant_map;
+        self
+    }
+
+    pub fn concurrency(mut self, class: Option<String>) -> Self {
+        if let Some(c) = class {
+            self.concurrency = Some(c);
+        }
+        self
+    }
+
+    pub fn cache_age(mut self, cache_age: Option<u32>) -> Self {
+        if let Some(age) = cache_age {
+            self.cache_age = Some(age);
+        }
+        self
+    }
+
+    pub fn databases(mut self, databases: Vec<String>) -> Self {
+        self.databases = databases;
+        self
+    }
+
+    pub fn min_page_count(mut self, min_page_count: Option<u32>) -> Self {
+        self.min_page_count = min_page_count;
+        self
+    }
+
+    pub fn top(mut self, top: Option<u32>) -> Self {
+        self.top = top;
+        self
+    }
+
+    pub fn render(mut self, render: Option<Render>) -> Self {
+        self.render = render;
+        self
+    }
+
     pub fn build(self) -> Section {
         Section {
             name: self.name,
@@ -135,6 +334,24 @@ impl SectionBuilder {
                 SectionKind::Sync
             },
             sql: self.sql,
+            post_process: self.post_process,
+            priority: self.priority,
+            baseline: self.baseline,
+            catalog: self.catalog,
+            application_intent: self.application_intent,
+            deduplicate: self.deduplicate,
+            pdh_fallback: self.pdh_fallback,
+            test_links: self.test_links,
+            link_timeout: self.link_timeout,
+            timeout: self.timeout,
+            tenant_property: self.tenant_property,
+            tenant_map: self.tenant_map,
+            concurrency: self.concurrency,
+            cache_age: self.cache_age,
+            databases: self.databases,
+            min_page_count: self.min_page_count,
+            top: self.top,
+            render: self.render,
         }
     }
 }
@@ -145,6 +362,42 @@ pub struct Section {
     sep: char,
     kind: SectionKind,
     sql: Option<String>,
+    /// external command to filter section body: stdin -> stdout, program and its arguments
+    post_process: Option<Vec<String>>,
+    priority: Priority,
+    /// expected values of columns, deviations are reported by the section
+    baseline: Vec<(String, String)>,
+    /// file with the latest known builds, used by the instance section
+    catalog: Option<PathBuf>,
+    /// read-only intent routes the queries to a readable secondary replica, None - intent of
+    /// the connection is used
+    application_intent: Option<ApplicationIntent>,
+    /// availability groups are reported only by their primary replica
+    deduplicate: bool,
+    /// Windows performance counters of the local instance are used if the query fails
+    pdh_fallback: bool,
+    /// linked servers are tested with `sp_testlinkedserver`
+    test_links: bool,
+    /// seconds to test one linked server
+    link_timeout: Option<u32>,
+    /// database extended property with the tenant of the database
+    tenant_property: Option<String>,
+    /// database -> tenant, takes precedence over the extended property
+    tenant_map: Vec<(String, String)>,
+    /// concurrency class limiting sections running at the same time against a server
+    concurrency: Option<String>,
+    /// overrides cache age of the config, async sections only
+    cache_age: Option<u32>,
+    /// restricts per database sections, empty - all databases
+    databases: Vec<String>,
+    /// smaller indexes are skipped by the index fragmentation section
+    min_page_count: Option<u32>,
+    /// number of rows reported by top-N sections, e.g. query store
+    top: Option<u32>,
+    /// None - values are used as is
+    render: Option<Render>,
+    /// seconds for the whole section, the connection of the query is closed when exceeded
+    timeout: Option<u32>,
 }
 
 impl Section {
@@ -167,6 +420,80 @@ impl Section {
     pub fn sql(&self) -> Option<&str> {
         self.sql.as_deref()
     }
+
+    pub fn post_process(&self) -> Option<&[String]> {
+        self.post_process.as_deref()
+    }
+
+    pub fn priority(&self) -> Priority {
+        self.priority
+    }
+
+    pub fn baseline(&self) -> &[(String, String)] {
+        &self.baseline
+    }
+
+    pub fn catalog(&self) -> Option<&Path> {
+        self.catalog.as_deref()
+    }
+
+    /// absent: intent of the connection is used
+    pub fn application_intent(&self) -> Option<ApplicationIntent> {
+        self.application_intent
+    }
+
+    pub fn deduplicate(&self) -> bool {
+        self.deduplicate
+    }
+
+    pub fn pdh_fallback(&self) -> bool {
+        self.pdh_fallback
+    }
+
+    pub fn test_links(&self) -> bool {
+        self.test_links
+    }
+
+    pub fn link_timeout(&self) -> Option<u32> {
+        self.link_timeout
+    }
+
+    /// seconds, None - `options.section_timeout` is used
+    pub fn timeout(&self) -> Option<u32> {
+        self.timeout
+    }
+
+    pub fn tenant_property(&self) -> Option<&str> {
+        self.tenant_property.as_deref()
+    }
+
+    pub fn tenant_map(&self) -> &[(String, String)] {
+        &self.tenant_map
+    }
+
+    pub fn concurrency(&self) -> Option<&str> {
+        self.concurrency.as_deref()
+    }
+
+    pub fn cache_age(&self) -> Option<u32> {
+        self.cache_age
+    }
+
+    pub fn databases(&self) -> &[String] {
+        &self.databases
+    }
+
+    pub fn min_page_count(&self) -> Option<u32> {
+        self.min_page_count
+    }
+
+    pub fn top(&self) -> Option<u32> {
+        self.top
+    }
+
+    pub fn render(&self) -> Option<Render> {
+        self.render
+    }
 }
 
 #[derive(PartialEq, Debug, Clone)]
@@ -185,36 +512,30 @@ impl Default for Sections {
 }
 
 fn get_predefined_sections() -> Vec<Section> {
-    PREDEFINED_SECTIONS
+    registry::all()
         .iter()
-        .map(|&s| Section::new(s))
+        .filter(|d| d.is_predefined())
+        .map(|d| Section::new(d.name()))
         .collect()
 }
 
 pub fn get_per_database_sections() -> Vec<String> {
-    PER_DATABASE_SECTIONS
+    registry::all()
         .iter()
-        .map(|&s| s.to_string())
+        .filter(|d| d.is_per_database())
+        .map(|d| d.name().to_string())
         .collect()
 }
 
-fn get_predefined_section_names() -> Vec<String> {
-    get_predefined_sections()
+/// known sections without the instance name as first line
+pub fn get_plain_section_names() -> HashSet<String> {
+    registry::all()
         .iter()
-        .map(|s| s.name().to_owned())
+        .filter(|d| !d.is_decorated())
+        .map(|d| d.name().to_string())
         .collect()
 }
 
-fn get_decorated_section_names() -> Vec<String> {
-    FIRST_LINE_SECTIONS.iter().map(|&s| s.to_owned()).collect()
-}
-
-pub fn get_plain_section_names() -> HashSet<String> {
-    let all = hash_set(&get_predefined_section_names());
-    let decorated = hash_set(&get_decorated_section_names());
-    (&all - &decorated).into_iter().collect()
-}
-
 impl Section {
     /// Converts entry to Section
     /// - databases:     # name
@@ -228,32 +549,127 @@ impl Section {
             .iter()
             .filter_map(|(n, yaml)| {
                 if let Some(name) = n.as_str() {
-                    Some(Section::from_yaml_entry(name, yaml))
+                    Some(Section::from_yaml_entry(name, yaml).map_err(|e| {
+                        log::error!("Section `{name}` is skipped: {e}");
+                        e
+                    }))
                 } else {
                     log::error!("Empty or malformed section name");
                     None
                 }
             })
-            .collect::<Vec<Section>>();
+            .collect::<Result<Vec<Section>>>()?;
         // for some not clear reason the entry is a LinkedHashMap:
         // we take first entry only and ignore the rest
         section
             .pop()
             .ok_or_else(|| anyhow::anyhow!("Empty section"))
     }
-    fn from_yaml_entry(name: &str, yaml: &Yaml) -> Self {
+    /// bad post process is an error: the section is skipped instead of the unfiltered output
+    fn from_yaml_entry(name: &str, yaml: &Yaml) -> Result<Self> {
         let c = yaml.get_string(keys::SEP).and_then(|s| s.chars().next());
-        let builder = SectionBuilder::new(name).sep(c);
-
-        if yaml.get_optional_bool(keys::DISABLED) == Some(true) {
+        let priority = yaml.get_string(keys::PRIORITY).and_then(|p| {
+            Priority::try_from(p.as_str())
+                .map_err(|e| log::warn!("Section `{name}`: {e}"))
+                .ok()
+        });
+        let render = Render::from_yaml(yaml)
+            .map_err(|e| log::warn!("Section `{name}`: {e}"))
+            .ok()
+            .flatten();
+        // `readable_secondary: yes` is the older form of `application_intent: read_only`
+        let application_intent = match yaml.get_string(keys::APPLICATION_INTENT) {
+            Some(i) => ApplicationIntent::try_from(i.as_str())
+                .map_err(|e| log::warn!("Section `{name}`: {e}"))
+                .ok(),
+            None => yaml.get_optional_bool(keys::READABLE_SECONDARY).map(|v| {
+                if v {
+                    ApplicationIntent::ReadOnly
+                } else {
+                    ApplicationIntent::ReadWrite
+                }
+            }),
+        };
+        let builder = SectionBuilder::new(name)
+            .sep(c)
+            .post_process(parse_post_process(yaml.get(keys::POST_PROCESS))?)
+            .priority(priority)
+            .baseline(yaml.get_string_map(keys::BASELINE))
+            .catalog(yaml.get_pathbuf(keys::CATALOG))
+            .application_intent(application_intent)
+            .deduplicate(yaml.get_bool(keys::DEDUPLICATE, false))
+            .pdh_fallback(yaml.get_bool(keys::PDH_FALLBACK, false))
+            .test_links(yaml.get_bool(keys::TEST_LINKS, false))
+            .link_timeout(yaml.get_int::<u32>(keys::LINK_TIMEOUT))
+            .timeout(yaml.get_int::<u32>(keys::TIMEOUT))
+            .tenant_property(yaml.get_string(keys::TENANT_PROPERTY))
+            .tenant_map(yaml.get_string_map(keys::TENANT_MAP))
+            .concurrency(yaml.get_string(keys::CONCURRENCY))
+            .cache_age(yaml.get_int::<u32>(keys::CACHE_AGE))
+            .databases(yaml.get_string_vector(keys::DATABASES, &[]))
+            .min_page_count(yaml.get_int::<u32>(keys::MIN_PAGE_COUNT))
+            .top(yaml.get_int::<u32>(keys::TOP))
+            .render(render);
+
+        Ok(if yaml.get_optional_bool(keys::DISABLED) == Some(true) {
             builder.set_disabled()
         } else if let Some(v) = yaml.get_optional_bool(keys::IS_ASYNC) {
             builder.set_async(v)
         } else {
             builder
         }
-        .build()
+        .build())
+    }
+}
+
+/// the command line or the list of program and arguments, WASM filters are not supported:
+/// the agent has no WASM runtime
+fn parse_post_process(yaml: &Yaml) -> Result<Option<Vec<String>>> {
+    let command = match yaml {
+        Yaml::BadValue | Yaml::Null => return Ok(None),
+        Yaml::String(line) => split_command(line)?,
+        Yaml::Array(parts) => parts
+            .iter()
+            .map(|p| {
+                p.as_str()
+                    .map(str::to_string)
+                    .ok_or_else(|| anyhow::anyhow!("post_process: {p:?} is not a string"))
+            })
+            .collect::<Result<Vec<String>>>()?,
+        _ => anyhow::bail!("post_process: expected command line or list"),
+    };
+    match command.first() {
+        None => anyhow::bail!("post_process: empty command"),
+        Some(program) if program.to_lowercase().ends_with(".wasm") => anyhow::bail!(
+            "post_process: WASM filters are not supported, the agent has no WASM runtime"
+        ),
+        Some(_) => Ok(Some(command)),
+    }
+}
+
+/// parts are separated by whitespaces, double or single quotes group them, backslashes are
+/// kept as is: `"C:\Program Files\mask.exe" --names`
+fn split_command(line: &str) -> Result<Vec<String>> {
+    let mut parts = Vec::new();
+    let mut current: Option<String> = None;
+    let mut quote: Option<char> = None;
+    for c in line.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => current.get_or_insert_with(String::new).push(c),
+            None if c == '"' || c == '\'' => {
+                quote = Some(c);
+                current.get_or_insert_with(String::new);
+            }
+            None if c.is_whitespace() => parts.extend(current.take()),
+            None => current.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quote.is_some() {
+        anyhow::bail!("post_process: unterminated quote in `{line}`");
     }
+    parts.extend(current);
+    Ok(parts)
 }
 
 impl Sections {
@@ -297,20 +713,10 @@ impl Sections {
 }
 
 fn get_default_separator(name: &str) -> char {
-    if PIPE_SEP_SECTIONS.contains(&name) {
-        '|'
-    } else if SPACE_SEP_SECTIONS.contains(&name) {
-        ' '
-    } else if QUERY_BASED_SECTIONS.contains(&name) {
-        '\t'
-    } else {
+    registry::find(name).map(|d| d.sep()).unwrap_or_else(|| {
         log::warn!("Unknown section: {}", name);
         ' '
-    }
-}
-
-fn hash_set<T: AsRef<str>>(v: &[T]) -> HashSet<String> {
-    HashSet::from_iter(v.iter().map(|s| s.as_ref().to_string()))
+    })
 }
 
 #[cfg(test)]
@@ -318,6 +724,10 @@ mod tests {
     use super::*;
     use crate::config::yaml::test_tools::create_yaml;
 
+    fn hash_set<T: AsRef<str>>(v: &[T]) -> HashSet<String> {
+        HashSet::from_iter(v.iter().map(|s| s.as_ref().to_string()))
+    }
+
     pub const SECTIONS_FULL: &str = r#"
 sections:
 - aaa:
@@ -327,11 +737,31 @@ sections:
 - ccc:
     is_async: yes
     sep: |
+    cache_age: 300
 - ddd:
     is_async: yes
+    post_process: "mask.sh --names"
+    priority: low
+    readable_secondary: yes
+    databases: [sales, crm]
+    min_page_count: 10
+    top: 5
+    baseline:
+      recovery_model: FULL
 - "eee":
     sep: "|ss"
     disabled: yes
+    application_intent: read_write
+    catalog: builds.yml
+    deduplicate: yes
+    pdh_fallback: yes
+    test_links: yes
+    link_timeout: 2
+    timeout: 30
+    tenant_property: owner
+    tenant_map:
+      sales: acme
+    concurrency: heavy
 "#;
 
     #[test]
@@ -358,6 +788,112 @@ sections:
                 .collect::<Vec<(&str, char)>>(),
             [("eee", '|')]
         );
+        assert_eq!(
+            s.sections()
+                .iter()
+                .map(|s| s.post_process())
+                .collect::<Vec<Option<&[String]>>>(),
+            [
+                None,
+                None,
+                None,
+                Some(&["mask.sh".to_string(), "--names".to_string()][..]),
+                None
+            ]
+        );
+        assert_eq!(
+            s.sections()[3].baseline(),
+            [("recovery_model".to_string(), "FULL".to_string())]
+        );
+        assert!(s.sections()[0].baseline().is_empty());
+        assert_eq!(s.sections()[4].catalog(), Some(Path::new("builds.yml")));
+        assert!(s.sections()[0].catalog().is_none());
+        assert_eq!(s.sections()[2].cache_age(), Some(300));
+        assert!(s.sections()[3].cache_age().is_none());
+        assert_eq!(s.sections()[3].databases(), ["sales", "crm"]);
+        assert_eq!(s.sections()[3].min_page_count(), Some(10));
+        assert_eq!(s.sections()[3].top(), Some(5));
+        assert!(s.sections()[0].top().is_none());
+        assert!(s.sections()[0].databases().is_empty());
+        assert_eq!(
+            s.sections()
+                .iter()
+                .map(|s| s.application_intent())
+                .collect::<Vec<Option<ApplicationIntent>>>(),
+            [
+                None,
+                None,
+                None,
+                Some(ApplicationIntent::ReadOnly),
+                Some(ApplicationIntent::ReadWrite)
+            ]
+        );
+        assert_eq!(
+            s.sections()
+                .iter()
+                .map(|s| s.deduplicate())
+                .collect::<Vec<bool>>(),
+            [false, false, false, false, true]
+        );
+        assert!(s.sections()[4].pdh_fallback());
+        assert!(!s.sections()[0].pdh_fallback());
+        assert!(s.sections()[4].test_links());
+        assert!(!s.sections()[0].test_links());
+        assert_eq!(s.sections()[4].link_timeout(), Some(2));
+        assert!(s.sections()[0].link_timeout().is_none());
+        assert_eq!(s.sections()[4].timeout(), Some(30));
+        assert!(s.sections()[0].timeout().is_none());
+        assert_eq!(s.sections()[4].tenant_property(), Some("owner"));
+        assert_eq!(
+            s.sections()[4].tenant_map(),
+            [("sales".to_string(), "acme".to_string())]
+        );
+        assert!(s.sections()[0].tenant_map().is_empty());
+        assert_eq!(s.sections()[4].concurrency(), Some("heavy"));
+        assert!(s.sections()[0].concurrency().is_none());
+        assert_eq!(
+            s.sections()
+                .iter()
+                .map(|s| s.priority())
+                .collect::<Vec<Priority>>(),
+            [
+                Priority::Normal,
+                Priority::Normal,
+                Priority::Normal,
+                Priority::Low,
+                Priority::Normal
+            ]
+        );
+    }
+
+    #[test]
+    fn test_render_from_yaml() {
+        let render = |source: &str| Render::from_yaml(&create_yaml(source));
+        assert_eq!(render("sep: '|'").unwrap(), None);
+        assert_eq!(
+            render("render: truncate").unwrap(),
+            Some(Render::Truncate(defaults::RENDER_MAX_LENGTH))
+        );
+        assert_eq!(
+            render("render: Truncate\nmax_length: 10").unwrap(),
+            Some(Render::Truncate(10))
+        );
+        assert_eq!(render("render: hex").unwrap(), Some(Render::Hex));
+        assert_eq!(render("render: base64").unwrap(), Some(Render::Base64));
+        assert_eq!(render("render: drop").unwrap(), Some(Render::Drop));
+        assert!(render("render: zip").is_err());
+    }
+
+    #[test]
+    fn test_heavy_section_defaults() {
+        let s = Section::new(names::INDEX_FRAGMENTATION);
+        assert_eq!(s.kind(), SectionKind::Async);
+        assert_eq!(s.priority(), Priority::Low);
+        assert_eq!(s.cache_age(), Some(defaults::HEAVY_SECTION_CACHE_AGE));
+        let s = SectionBuilder::new(names::INDEX_FRAGMENTATION)
+            .cache_age(Some(3600))
+            .build();
+        assert_eq!(s.cache_age(), Some(3600));
     }
 
     #[test]
@@ -369,14 +905,29 @@ sections:
                     .iter()
                     .map(|s| s.name().to_string())
             ),
-            (&hash_set(&PREDEFINED_SECTIONS) - &hash_set(&ASYNC_SECTIONS))
+            hash_set(&[
+                names::INSTANCE,
+                names::DATABASES,
+                names::COUNTERS,
+                names::BLOCKED_SESSIONS,
+                names::TRANSACTION_LOG,
+                names::CLUSTERS,
+                names::MIRRORING,
+                names::AVAILABILITY_GROUPS,
+                names::CONNECTIONS,
+            ])
         );
         assert_eq!(
             s.select(&[SectionKind::Async])
                 .iter()
                 .map(|s| s.name())
                 .collect::<Vec<&str>>(),
-            ASYNC_SECTIONS
+            [
+                names::TABLE_SPACES,
+                names::DATAFILES,
+                names::BACKUP,
+                names::JOBS
+            ]
         );
         assert_eq!(s.cache_age(), defaults::SECTIONS_CACHE_AGE);
         assert_eq!(
@@ -408,10 +959,39 @@ _nothing: "nothing"
     }
     #[test]
     fn test_get_no_first_line() {
+        let plain = get_plain_section_names();
+        assert!(plain.contains(names::INSTANCE));
+        assert!(plain.contains(names::AVAILABILITY_GROUPS));
+        assert!(!plain.contains(names::JOBS));
+        assert!(!plain.contains(names::MIRRORING));
+        assert!(!plain.contains(names::AGENT));
+        assert!(!plain.contains("custom"));
+    }
+
+    #[test]
+    fn test_parse_post_process() {
+        let parse = |text: &str| parse_post_process(&create_yaml(text)[keys::POST_PROCESS]);
         assert_eq!(
-            get_plain_section_names(),
-            (&hash_set(&get_predefined_section_names())
-                - &hash_set(&get_decorated_section_names()))
+            parse(r#"post_process: '"C:\Program Files\mask.exe" --names  "a b"'"#)
+                .unwrap()
+                .unwrap(),
+            [r"C:\Program Files\mask.exe", "--names", "a b"]
         );
+        assert_eq!(
+            parse("post_process: [/opt/my tools/mask, --names]")
+                .unwrap()
+                .unwrap(),
+            ["/opt/my tools/mask", "--names"]
+        );
+        assert_eq!(parse("post_process: \"x ''\"").unwrap().unwrap(), ["x", ""]);
+        assert!(parse("sep: '|'").unwrap().is_none());
+        assert!(parse("post_process: 'mask \"a b'").is_err());
+        assert!(parse("post_process: ' '").is_err());
+        assert!(parse("post_process: filter.WASM").is_err());
+    }
+
+    #[test]
+    fn test_section_bad_post_process() {
+        assert!(Section::from_yaml(&create_yaml("jobs:\n  post_process: filter.wasm\n")).is_err());
     }
 }
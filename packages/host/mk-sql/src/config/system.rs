@@ -11,6 +11,7 @@ use yaml_rust2::YamlLoader;
 mod keys {
     pub const SYSTEM: &str = "system";
     pub const LOGGING: &str = "logging";
+    pub const FIPS: &str = "fips";
 
     pub const LEVEL: &str = "level";
     pub const MAX_SIZE: &str = "max_size";
@@ -27,6 +28,7 @@ mod defaults {
 #[derive(PartialEq, Debug, Default)]
 pub struct SystemConfig {
     logging: Logging,
+    fips: bool,
 }
 
 #[derive(PartialEq, Debug, Default, Clone)]
@@ -107,12 +109,18 @@ impl SystemConfig {
         }
 
         let logging = Logging::from_yaml(system)?.unwrap_or_default();
+        let fips = system.get_bool(keys::FIPS, false);
 
-        Ok(Self { logging })
+        Ok(Self { logging, fips })
     }
     pub fn logging(&self) -> &Logging {
         &self.logging
     }
+
+    /// FIPS mode requested in config, see also `fips` cargo feature
+    pub fn fips(&self) -> bool {
+        self.fips
+    }
 }
 
 #[cfg(test)]
@@ -125,6 +133,7 @@ system: # optional
     level: "trace" # mandatory
     max_size: 1
     max_count: 3
+  fips: yes
 mssql:
   no_matter: ""
 "#;
@@ -134,6 +143,7 @@ mssql:
         assert_eq!(sys_config.logging().level(), log::Level::Trace);
         assert_eq!(sys_config.logging().max_size(), 1);
         assert_eq!(sys_config.logging().max_count(), 3);
+        assert!(sys_config.fips());
     }
 
     #[test]
@@ -142,5 +152,6 @@ mssql:
         assert_eq!(sys_config.logging().level(), defaults::LOG_LEVEL);
         assert_eq!(sys_config.logging().max_size(), defaults::LOG_MAX_SIZE);
         assert_eq!(sys_config.logging().max_count(), defaults::LOG_MAX_COUNT);
+        assert!(!sys_config.fips());
     }
 }
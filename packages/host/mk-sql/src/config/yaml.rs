@@ -48,6 +48,9 @@ pub trait Get {
 
     fn get_yaml_vector(&self, key: &str) -> Vec<Yaml>;
 
+    /// scalar values of the map as strings, order is kept
+    fn get_string_map(&self, key: &str) -> Vec<(String, String)>;
+
     fn get_bool(&self, key: &str, default: bool) -> bool {
         self.get_optional_bool(key).unwrap_or(default)
     }
@@ -115,6 +118,32 @@ impl Get for Yaml {
         self[key].as_vec().unwrap_or(&vec![]).to_vec()
     }
 
+    fn get_string_map(&self, key: &str) -> Vec<(String, String)> {
+        let to_string = |y: &Yaml| match y {
+            Yaml::String(s) | Yaml::Real(s) => Some(s.clone()),
+            Yaml::Integer(i) => Some(i.to_string()),
+            Yaml::Boolean(b) => Some(b.to_string()),
+            _ => None,
+        };
+        match &self[key] {
+            Yaml::Hash(hash) => hash
+                .iter()
+                .filter_map(|(k, v)| match (to_string(k), to_string(v)) {
+                    (Some(k), Some(v)) => Some((k, v)),
+                    _ => {
+                        log::error!("Bad entry in {key} {:?} (expected scalar)", k);
+                        None
+                    }
+                })
+                .collect(),
+            Yaml::BadValue | Yaml::Null => vec![],
+            value => {
+                log::error!("Bad value in {key} {:?} (expected map)", value);
+                vec![]
+            }
+        }
+    }
+
     fn get_optional_bool(&self, key: &str) -> Option<bool> {
         let result = &self[key];
         if result.is_badvalue() {
@@ -212,6 +241,21 @@ vector:
         );
     }
 
+    #[test]
+    fn test_get_string_map() {
+        let yaml = load_from_str("map:\n  b: x\n  a: 1\n  c: true\n  d: [1]\nbad: 1").unwrap();
+        assert_eq!(
+            yaml[0].get_string_map("map"),
+            [
+                ("b".to_string(), "x".to_string()),
+                ("a".to_string(), "1".to_string()),
+                ("c".to_string(), "true".to_string())
+            ]
+        );
+        assert!(yaml[0].get_string_map("bad").is_empty());
+        assert!(yaml[0].get_string_map("absent").is_empty());
+    }
+
     #[test]
     fn test_get_string_vector() {
         let yaml = load_from_str("some: 1").unwrap();
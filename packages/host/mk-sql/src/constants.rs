@@ -15,14 +15,20 @@ pub mod log {
 }
 
 pub const SQL_QUERY_EXTENSION: &str = "sql";
+/// sqlcmd variables for the query file with the same name: `NAME=value` per line
+pub const SQL_VARS_EXTENSION: &str = "vars";
 
 pub mod environment {
     pub const CONFIG_NAME: &str = "mk-sql.yml";
+    /// key of passwords encrypted by `encrypt-password`, Linux
+    pub const KEY_FILE_NAME: &str = "mk-sql.key";
     pub const CONFIG_DIR_ENV_VAR: &str = "MK_CONFDIR";
     pub const LOG_DIR_ENV_VAR: &str = "MK_LOGDIR";
     pub const TEMP_DIR_ENV_VAR: &str = "MK_TEMPDIR";
     pub const STATE_DIR_ENV_VAR: &str = "MK_STATEDIR";
     pub const VAR_DIR_ENV_VAR: &str = "MK_VARDIR";
+    /// bearer token required by the REST API, mandatory if not bound to loopback
+    pub const REST_TOKEN_ENV_VAR: &str = "MK_SQL_REST_TOKEN";
 }
 
 pub const ODBC_CONNECTION_TIMEOUT: u32 = 2;
@@ -33,6 +39,7 @@ lazy_static! {
         Path::new(&get_env_value(environment::CONFIG_DIR_ENV_VAR, "."))
             .join(environment::CONFIG_NAME);
     pub static ref CONFIG_DIR: PathBuf = Path::new(&get_conf_dir()).to_owned();
+    pub static ref KEY_FILE: PathBuf = CONFIG_DIR.join(environment::KEY_FILE_NAME);
     pub static ref ENV_LOG_DIR: Option<PathBuf> = std::env::var(environment::LOG_DIR_ENV_VAR)
         .ok()
         .map(PathBuf::from);
@@ -0,0 +1,380 @@
+// Copyright (C) 2024 Checkmk GmbH - License: GNU General Public License v2
+// This file is part of Checkmk (https://checkmk.com). It is subject to the terms and
+// conditions defined in the file COPYING, which is part of this source code package.
+
+/// FIPS mode and crypto primitives used by the plugin
+/// - Linux: TLS is done by OpenSSL, FIPS mode loads the OpenSSL `fips` provider and thus
+///   disables all non-approved algorithms. The built-in(vendored) OpenSSL ships no `fips`
+///   provider: the module `fips.so` of a FIPS-validated OpenSSL 3 release, installed with
+///   `openssl fipsinstall`, must be supplied by `OPENSSL_MODULES` and its `fipsmodule.cnf`
+///   included by `OPENSSL_CONF`, otherwise FIPS mode fails
+/// - Windows: TLS is done by SChannel, FIPS mode requires OS level FIPS policy
+use anyhow::{anyhow, Result};
+use std::io::Read;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static FIPS_MODE: AtomicBool = AtomicBool::new(cfg!(feature = "fips"));
+
+/// true if FIPS mode is either built in or requested in config
+pub fn is_fips_mode() -> bool {
+    FIPS_MODE.load(Ordering::Relaxed)
+}
+
+/// Activates FIPS mode if requested, must be called before any crypto usage
+/// Error means that FIPS mode is required but impossible to activate
+pub fn init(fips_requested: bool) -> Result<()> {
+    if !fips_requested && !is_fips_mode() {
+        return Ok(());
+    }
+    activate_fips()?;
+    FIPS_MODE.store(true, Ordering::Relaxed);
+    log::info!("FIPS mode is active");
+    Ok(())
+}
+
+#[cfg(unix)]
+fn activate_fips() -> Result<()> {
+    use openssl::provider::Provider;
+    // explicitly loaded providers disable auto loading of the `default` provider,
+    // `base` is required for encoders/decoders, i.e. for certificate processing
+    let fips = Provider::load(None, "fips").map_err(|e| {
+        anyhow::anyhow!(
+            "FIPS mode: OpenSSL fips provider is not available: {e}. {} has no built-in fips \
+             provider, set OPENSSL_MODULES to the directory of `fips.so` of a FIPS-validated \
+             OpenSSL 3 and OPENSSL_CONF to a config including its `fipsmodule.cnf`",
+            openssl::version::version()
+        )
+    })?;
+    let base = Provider::load(None, "base")
+        .map_err(|e| anyhow::anyhow!("FIPS mode: OpenSSL base provider is not available: {e}"))?;
+    // providers must stay loaded for the whole life of the process
+    std::mem::forget(fips);
+    std::mem::forget(base);
+    Ok(())
+}
+
+#[cfg(windows)]
+fn activate_fips() -> Result<()> {
+    use winreg::{enums::HKEY_LOCAL_MACHINE, RegKey};
+    const FIPS_POLICY_KEY: &str = r"SYSTEM\CurrentControlSet\Control\Lsa\FipsAlgorithmPolicy";
+    let enabled: u32 = RegKey::predef(HKEY_LOCAL_MACHINE)
+        .open_subkey_with_flags(FIPS_POLICY_KEY, winreg::enums::KEY_READ)
+        .and_then(|key| key.get_value("Enabled"))
+        .unwrap_or_default();
+    if enabled == 0 {
+        anyhow::bail!("FIPS mode: Windows FIPS algorithm policy is not enabled");
+    }
+    Ok(())
+}
+
+/// SHA-256 based replacement for `DefaultHasher`, returns first 64 bits as hex
+pub fn short_digest(data: &[u8]) -> String {
+    openssl::sha::sha256(data)
+        .iter()
+        .take(8)
+        .map(|b| format!("{:02X}", b))
+        .collect()
+}
+
+/// password encrypted with AES-256-GCM by the key file: `aes:` + base64(nonce, text, tag)
+const KEY_FILE_PREFIX: &str = "aes:";
+/// password encrypted by DPAPI with the machine key: `dpapi:` + base64(blob)
+const DPAPI_PREFIX: &str = "dpapi:";
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+/// `encrypt-password`: the first line of the input, printed encrypted
+pub fn exec_encrypt_password(mut input: impl Read) -> Result<String> {
+    let mut text = String::new();
+    input.read_to_string(&mut text)?;
+    let password = text.lines().next().unwrap_or_default();
+    if password.is_empty() {
+        anyhow::bail!("Password is expected on stdin");
+    }
+    Ok(encrypt_password(password)? + "\n")
+}
+
+/// DPAPI on Windows, otherwise the key file of the config dir, created if absent
+pub fn encrypt_password(password: &str) -> Result<String> {
+    #[cfg(windows)]
+    {
+        Ok(DPAPI_PREFIX.to_string()
+            + &openssl::base64::encode_block(&dpapi::protect(password.as_bytes())?))
+    }
+    #[cfg(not(windows))]
+    {
+        encrypt_with_key(&load_key(&crate::constants::KEY_FILE, true)?, password)
+    }
+}
+
+/// value written by `encrypt-password`, both formats are accepted on every platform where
+/// available
+pub fn decrypt_password(text: &str) -> Result<String> {
+    decrypt_password_with(text, &crate::constants::KEY_FILE)
+}
+
+fn decrypt_password_with(text: &str, key_file: &Path) -> Result<String> {
+    if let Some(data) = text.strip_prefix(KEY_FILE_PREFIX) {
+        decrypt_with_key(&load_key(key_file, false)?, data)
+    } else if let Some(data) = text.strip_prefix(DPAPI_PREFIX) {
+        decrypt_dpapi(data)
+    } else {
+        Err(anyhow!(
+            "unknown format, `{KEY_FILE_PREFIX}` or `{DPAPI_PREFIX}` is expected"
+        ))
+    }
+}
+
+#[cfg(windows)]
+fn decrypt_dpapi(data: &str) -> Result<String> {
+    let blob = dpapi::unprotect(&openssl::base64::decode_block(data.trim())?)?;
+    String::from_utf8(blob).map_err(|_| anyhow!("decrypted password is not UTF-8"))
+}
+
+#[cfg(not(windows))]
+fn decrypt_dpapi(_data: &str) -> Result<String> {
+    anyhow::bail!("DPAPI is available only on Windows")
+}
+
+/// random key, readable only by the owner
+fn load_key(path: &Path, create: bool) -> Result<Vec<u8>> {
+    if create && !path.exists() {
+        let mut key = vec![0u8; KEY_LEN];
+        openssl::rand::rand_bytes(&mut key)?;
+        write_key(path, &key).map_err(|e| anyhow!("Can't create key file {path:?}: {e}"))?;
+        log::info!("Key file {path:?} is created");
+    }
+    let key = std::fs::read(path).map_err(|e| anyhow!("Can't read key file {path:?}: {e}"))?;
+    if key.len() != KEY_LEN {
+        anyhow::bail!("Key file {path:?} must contain {KEY_LEN} bytes");
+    }
+    Ok(key)
+}
+
+fn write_key(path: &Path, key: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(key)
+}
+
+#[cfg_attr(windows, allow(dead_code))]
+fn encrypt_with_key(key: &[u8], password: &str) -> Result<String> {
+    let mut nonce = [0u8; NONCE_LEN];
+    openssl::rand::rand_bytes(&mut nonce)?;
+    let mut tag = [0u8; TAG_LEN];
+    let encrypted = openssl::symm::encrypt_aead(
+        openssl::symm::Cipher::aes_256_gcm(),
+        key,
+        Some(&nonce),
+        &[],
+        password.as_bytes(),
+        &mut tag,
+    )?;
+    Ok(KEY_FILE_PREFIX.to_string()
+        + &openssl::base64::encode_block(&[&nonce[..], &encrypted, &tag].concat()))
+}
+
+fn decrypt_with_key(key: &[u8], data: &str) -> Result<String> {
+    let data = openssl::base64::decode_block(data.trim()).map_err(|_| anyhow!("bad base64"))?;
+    if data.len() < NONCE_LEN + TAG_LEN {
+        anyhow::bail!("encrypted password is too short");
+    }
+    let (nonce, rest) = data.split_at(NONCE_LEN);
+    let (encrypted, tag) = rest.split_at(rest.len() - TAG_LEN);
+    let plain = openssl::symm::decrypt_aead(
+        openssl::symm::Cipher::aes_256_gcm(),
+        key,
+        Some(nonce),
+        &[],
+        encrypted,
+        tag,
+    )
+    .map_err(|_| anyhow!("can't decrypt, the key file doesn't match"))?;
+    String::from_utf8(plain).map_err(|_| anyhow!("decrypted password is not UTF-8"))
+}
+
+/// machine scope: the password is encrypted by an administrator and decrypted by the agent
+/// running as another account
+#[cfg(windows)]
+mod dpapi {
+    use anyhow::Result;
+    use std::ffi::c_void;
+
+    const CRYPTPROTECT_UI_FORBIDDEN: u32 = 0x1;
+    const CRYPTPROTECT_LOCAL_MACHINE: u32 = 0x4;
+    /// other applications using DPAPI of the machine can't decrypt without it
+    const ENTROPY: &[u8] = b"mk-sql password";
+
+    #[repr(C)]
+    struct DataBlob {
+        size: u32,
+        data: *mut u8,
+    }
+
+    impl DataBlob {
+        fn from(data: &[u8]) -> Self {
+            Self {
+                size: data.len() as u32,
+                data: data.as_ptr() as *mut u8,
+            }
+        }
+    }
+
+    #[link(name = "crypt32")]
+    extern "system" {
+        fn CryptProtectData(
+            data_in: *const DataBlob,
+            description: *const u16,
+            entropy: *const DataBlob,
+            reserved: *mut c_void,
+            prompt: *mut c_void,
+            flags: u32,
+            data_out: *mut DataBlob,
+        ) -> i32;
+        fn CryptUnprotectData(
+            data_in: *const DataBlob,
+            description: *mut *mut u16,
+            entropy: *const DataBlob,
+            reserved: *mut c_void,
+            prompt: *mut c_void,
+            flags: u32,
+            data_out: *mut DataBlob,
+        ) -> i32;
+    }
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn LocalFree(memory: *mut c_void) -> *mut c_void;
+    }
+
+    pub fn protect(data: &[u8]) -> Result<Vec<u8>> {
+        let input = DataBlob::from(data);
+        let entropy = DataBlob::from(ENTROPY);
+        let mut output = DataBlob {
+            size: 0,
+            data: std::ptr::null_mut(),
+        };
+        // SAFETY: input blobs outlive the call, the output is released by `take`
+        let ok = unsafe {
+            CryptProtectData(
+                &input,
+                std::ptr::null(),
+                &entropy,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                CRYPTPROTECT_UI_FORBIDDEN | CRYPTPROTECT_LOCAL_MACHINE,
+                &mut output,
+            )
+        };
+        take(ok, output)
+    }
+
+    pub fn unprotect(data: &[u8]) -> Result<Vec<u8>> {
+        let input = DataBlob::from(data);
+        let entropy = DataBlob::from(ENTROPY);
+        let mut output = DataBlob {
+            size: 0,
+            data: std::ptr::null_mut(),
+        };
+        // SAFETY: input blobs outlive the call, the output is released by `take`
+        let ok = unsafe {
+            CryptUnprotectData(
+                &input,
+                std::ptr::null_mut(),
+                &entropy,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                CRYPTPROTECT_UI_FORBIDDEN,
+                &mut output,
+            )
+        };
+        take(ok, output)
+    }
+
+    fn take(ok: i32, output: DataBlob) -> Result<Vec<u8>> {
+        if ok == 0 {
+            anyhow::bail!("DPAPI: {}", std::io::Error::last_os_error());
+        }
+        // SAFETY: the blob is allocated by DPAPI and released with LocalFree
+        unsafe {
+            let data = std::slice::from_raw_parts(output.data, output.size as usize).to_vec();
+            LocalFree(output.data as *mut c_void);
+            Ok(data)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_with_key() {
+        let key = [7u8; KEY_LEN];
+        let encrypted = encrypt_with_key(&key, "pässword").unwrap();
+        assert!(encrypted.starts_with(KEY_FILE_PREFIX));
+        assert_ne!(encrypted, encrypt_with_key(&key, "pässword").unwrap());
+        let data = encrypted.strip_prefix(KEY_FILE_PREFIX).unwrap();
+        assert_eq!(decrypt_with_key(&key, data).unwrap(), "pässword");
+        assert!(decrypt_with_key(&[8u8; KEY_LEN], data).is_err());
+        assert!(decrypt_with_key(&key, "AAAA").is_err());
+    }
+
+    #[test]
+    fn test_decrypt_password_with_key_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let key_file = dir.path().join("mk-sql.key");
+        assert!(decrypt_password_with("aes:AAAA", &key_file).is_err());
+        let key = load_key(&key_file, true).unwrap();
+        assert_eq!(load_key(&key_file, true).unwrap(), key);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&key_file).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        let encrypted = encrypt_with_key(&key, "secret").unwrap();
+        assert_eq!(
+            decrypt_password_with(&encrypted, &key_file).unwrap(),
+            "secret"
+        );
+        assert!(decrypt_password_with("secret", &key_file)
+            .unwrap_err()
+            .to_string()
+            .starts_with("unknown format"));
+        std::fs::write(&key_file, b"short").unwrap();
+        assert!(decrypt_password_with(&encrypted, &key_file).is_err());
+    }
+
+    #[test]
+    fn test_exec_encrypt_password_empty() {
+        assert!(exec_encrypt_password(&b""[..]).is_err());
+        assert!(exec_encrypt_password(&b"\nsecret\n"[..]).is_err());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_dpapi() {
+        let encrypted = encrypt_password("secret").unwrap();
+        assert!(encrypted.starts_with(DPAPI_PREFIX));
+        assert_eq!(decrypt_password(&encrypted).unwrap(), "secret");
+    }
+
+    #[test]
+    fn test_short_digest() {
+        assert_eq!(short_digest(b"abc"), "BA7816BF8F01CFEA");
+        assert_eq!(short_digest(b""), "E3B0C44298FC1C14");
+    }
+
+    #[cfg(not(feature = "fips"))]
+    #[test]
+    fn test_init_not_requested() {
+        assert!(init(false).is_ok());
+        assert!(!is_fips_mode());
+    }
+}
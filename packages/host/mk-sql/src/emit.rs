@@ -3,10 +3,23 @@
 // conditions defined in the file COPYING, which is part of this source code package.
 
 use crate::types::PiggybackHostName;
+use anyhow::Result;
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use super::config::defines::defaults;
 const PREFIX: &str = "mssql";
 
+/// bytes queued for stdout, the producer waits when exceeded
+pub const STDOUT_BUFFER_CAP: usize = 16 * 1024 * 1024;
+/// the consumer(agent transport) is considered stalled if nothing is accepted so long
+pub const STDOUT_TIMEOUT: Duration = Duration::from_secs(60);
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
 pub fn header(name: &str, separator: char) -> String {
     if separator == defaults::DEFAULT_SEP {
         format!("<<<{PREFIX}_{name}>>>\n")
@@ -24,6 +37,197 @@ pub fn piggyback_footer() -> String {
     piggyback_header(&"".to_string().into())
 }
 
+pub const TRAILER_SECTION_NAME: &str = "trailer";
+
+/// Accumulates emitted payload to let the check detect truncated output:
+/// sha256 of all bytes before the trailer, total count of bytes and lines, rows per section
+pub struct Trailer {
+    hasher: openssl::sha::Sha256,
+    bytes: usize,
+    lines: usize,
+    /// (section header, rows), in order of appearance
+    sections: Vec<(String, usize)>,
+    current: Option<usize>,
+}
+
+impl Default for Trailer {
+    fn default() -> Self {
+        Self {
+            hasher: openssl::sha::Sha256::new(),
+            bytes: 0,
+            lines: 0,
+            sections: vec![],
+            current: None,
+        }
+    }
+}
+
+impl Trailer {
+    pub fn update(&mut self, data: &str) {
+        self.hasher.update(data.as_bytes());
+        self.bytes += data.len();
+        for line in data.lines() {
+            self.lines += 1;
+            if line.starts_with("<<<<") {
+                self.current = None;
+            } else if let Some(name) = line.strip_prefix("<<<").and_then(|l| l.strip_suffix(">>>"))
+            {
+                let name = name.split(':').next().unwrap_or_default().to_string();
+                self.current = Some(
+                    self.sections
+                        .iter()
+                        .position(|(n, _)| *n == name)
+                        .unwrap_or_else(|| {
+                            self.sections.push((name, 0));
+                            self.sections.len() - 1
+                        }),
+                );
+            } else if let Some(idx) = self.current {
+                self.sections[idx].1 += 1;
+            }
+        }
+    }
+
+    /// the trailer section, must be the last one in the output
+    pub fn finish(self, separator: char) -> String {
+        let digest = self
+            .hasher
+            .finish()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect::<String>();
+        let sep = separator;
+        let mut text = header(TRAILER_SECTION_NAME, separator);
+        text += &format!("sha256{sep}{digest}\n");
+        text += &format!("bytes{sep}{}\n", self.bytes);
+        text += &format!("lines{sep}{}\n", self.lines);
+        for (name, rows) in self.sections {
+            text += &format!("rows{sep}{name}{sep}{rows}\n");
+        }
+        text
+    }
+}
+
+/// the file is replaced atomically using a hidden temporary file: the agent doesn't pick up
+/// hidden files from the spool dir
+pub fn write_file_atomically(file: &Path, data: &str) -> std::io::Result<()> {
+    let temp = file.with_file_name(format!(
+        ".{}.new",
+        file.file_name().unwrap_or_default().to_string_lossy()
+    ));
+    std::fs::write(&temp, data).and_then(|_| std::fs::rename(&temp, file))
+}
+
+/// Writes in a worker thread: a blocked consumer can't hang the collector.
+/// Queued data is capped, `write` and `finish` fail if the consumer accepts nothing for `timeout`
+pub struct GuardedWriter {
+    sender: Option<SyncSender<String>>,
+    done: Receiver<std::io::Result<()>>,
+    pending: Arc<AtomicUsize>,
+    written: Arc<AtomicUsize>,
+    cap: usize,
+    timeout: Duration,
+}
+
+impl GuardedWriter {
+    pub fn stdout() -> Self {
+        Self::new(std::io::stdout(), STDOUT_BUFFER_CAP, STDOUT_TIMEOUT)
+    }
+
+    pub fn new<W: Write + Send + 'static>(mut writer: W, cap: usize, timeout: Duration) -> Self {
+        let (sender, receiver) = mpsc::sync_channel::<String>(1024);
+        let (done_sender, done) = mpsc::channel();
+        let pending = Arc::new(AtomicUsize::new(0));
+        let written = Arc::new(AtomicUsize::new(0));
+        let (worker_pending, worker_written) = (pending.clone(), written.clone());
+        std::thread::spawn(move || {
+            let result = receiver.iter().try_for_each(|data| {
+                writer.write_all(data.as_bytes())?;
+                writer.flush()?;
+                worker_pending.fetch_sub(data.len(), Ordering::SeqCst);
+                worker_written.fetch_add(data.len(), Ordering::SeqCst);
+                Ok(())
+            });
+            done_sender.send(result).ok();
+        });
+        Self {
+            sender: Some(sender),
+            done,
+            pending,
+            written,
+            cap,
+            timeout,
+        }
+    }
+
+    /// waits while the queue is full, data bigger than the cap waits for the empty queue
+    pub fn write(&mut self, data: &str) -> Result<()> {
+        let start = Instant::now();
+        loop {
+            let pending = self.pending.load(Ordering::SeqCst);
+            if pending == 0 || pending + data.len() <= self.cap {
+                break;
+            }
+            if let Ok(result) = self.done.try_recv() {
+                return Err(self.to_error(result));
+            }
+            if start.elapsed() >= self.timeout {
+                anyhow::bail!(self.stall_message());
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+        self.pending.fetch_add(data.len(), Ordering::SeqCst);
+        match &self.sender {
+            Some(sender) => sender
+                .send(data.to_string())
+                .map_err(|_| anyhow::anyhow!("Output writer is stopped")),
+            None => anyhow::bail!("Output writer is finished"),
+        }
+    }
+
+    /// waits until everything is written
+    pub fn finish(mut self) -> Result<()> {
+        drop(self.sender.take());
+        let mut written = self.written.load(Ordering::SeqCst);
+        let mut last_progress = Instant::now();
+        loop {
+            match self.done.recv_timeout(POLL_INTERVAL) {
+                Ok(Ok(())) => return Ok(()),
+                Ok(result) => return Err(self.to_error(result)),
+                Err(RecvTimeoutError::Disconnected) => anyhow::bail!("Output writer is lost"),
+                Err(RecvTimeoutError::Timeout) => {
+                    let now_written = self.written.load(Ordering::SeqCst);
+                    if now_written != written {
+                        written = now_written;
+                        last_progress = Instant::now();
+                    } else if last_progress.elapsed() >= self.timeout {
+                        anyhow::bail!(self.stall_message());
+                    }
+                }
+            }
+        }
+    }
+
+    fn stall_message(&self) -> String {
+        format!(
+            "Output consumer is stalled for {:?}: {} bytes written, {} bytes pending",
+            self.timeout,
+            self.written.load(Ordering::SeqCst),
+            self.pending.load(Ordering::SeqCst)
+        )
+    }
+
+    fn to_error(&self, result: std::io::Result<()>) -> anyhow::Error {
+        match result {
+            Err(e) => anyhow::anyhow!(
+                "Output write failed after {} bytes: {e}",
+                self.written.load(Ordering::SeqCst)
+            ),
+            Ok(()) => anyhow::anyhow!("Output writer is stopped"),
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -43,4 +247,88 @@ mod test {
         );
         assert_eq!(piggyback_footer(), "<<<<>>>>\n");
     }
+
+    /// accepts only what is allowed by the test
+    struct Gate {
+        allowed: std::sync::mpsc::Receiver<()>,
+        data: Arc<std::sync::Mutex<Vec<u8>>>,
+    }
+
+    impl Write for Gate {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.allowed
+                .recv()
+                .map_err(|_| std::io::Error::from(std::io::ErrorKind::BrokenPipe))?;
+            self.data.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_guarded_writer() {
+        let data = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let (allow, allowed) = std::sync::mpsc::channel();
+        let gate = Gate {
+            allowed,
+            data: data.clone(),
+        };
+        let mut writer = GuardedWriter::new(gate, 4, Duration::from_millis(50));
+        allow.send(()).unwrap();
+        writer.write("abc").unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(data.lock().unwrap().as_slice(), b"abc");
+        // consumer is stalled: the first chunk is queued, the second one exceeds the cap
+        writer.write("def").unwrap();
+        let e = writer.write("ghi").unwrap_err();
+        assert!(
+            e.to_string().contains("3 bytes written, 3 bytes pending"),
+            "{e}"
+        );
+        allow.send(()).unwrap();
+        writer.finish().unwrap();
+        assert_eq!(data.lock().unwrap().as_slice(), b"abcdef");
+    }
+
+    #[test]
+    fn test_guarded_writer_stalled_finish() {
+        let (allow, allowed) = std::sync::mpsc::channel::<()>();
+        let gate = Gate {
+            allowed,
+            data: Default::default(),
+        };
+        let mut writer = GuardedWriter::new(gate, 100, Duration::from_millis(30));
+        writer.write("abc").unwrap();
+        assert!(writer.finish().unwrap_err().to_string().contains("stalled"));
+        drop(allow);
+    }
+
+    #[test]
+    fn test_trailer() {
+        let mut trailer = Trailer::default();
+        trailer.update("<<<mssql_instance:sep(124)>>>\na\nb\n");
+        trailer.update("<<<<host>>>>\n<<<mssql_jobs:sep(09)>>>\nc\n<<<<>>>>\nd\n");
+        trailer.update("<<<mssql_instance:sep(124)>>>\ne\n");
+        assert_eq!(
+            trailer.finish('|'),
+            "<<<mssql_trailer:sep(124)>>>\n\
+             sha256|e3a83029ba6a43c849e353cfe3ea6a6bb190b919a823ed07a95881af8e99d68d\n\
+             bytes|117\n\
+             lines|10\n\
+             rows|mssql_instance|3\n\
+             rows|mssql_jobs|1\n"
+        );
+    }
+
+    #[test]
+    fn test_write_file_atomically() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("600_mk-sql");
+        write_file_atomically(&file, "a\n").unwrap();
+        write_file_atomically(&file, "b\n").unwrap();
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "b\n");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
 }
@@ -0,0 +1,86 @@
+// Copyright (C) 2024 Checkmk GmbH - License: GNU General Public License v2
+// This file is part of Checkmk (https://checkmk.com). It is subject to the terms and
+// conditions defined in the file COPYING, which is part of this source code package.
+
+//! C ABI to use the collector from other languages, see `python/mk_sql_ffi.py`
+//!
+//! ```c
+//! int mk_sql_collect(const char *config_yaml, char **output);
+//! void mk_sql_free(char *output);
+//! ```
+//! `output` contains agent output on success(0) or error message on failure(non zero)
+//! and must be released with `mk_sql_free`.
+
+use crate::api::{prepare_kerberos, CheckConfig, Env, MsSqlRunner};
+use anyhow::Result;
+use std::ffi::{c_char, c_int, CStr, CString};
+
+pub const MK_SQL_OK: c_int = 0;
+pub const MK_SQL_ERROR: c_int = 1;
+
+/// # Safety
+/// `config_yaml` must be a valid zero terminated string, `output` must be a valid pointer
+#[no_mangle]
+pub unsafe extern "C" fn mk_sql_collect(
+    config_yaml: *const c_char,
+    output: *mut *mut c_char,
+) -> c_int {
+    if output.is_null() {
+        return MK_SQL_ERROR;
+    }
+    let (code, text) = if config_yaml.is_null() {
+        (MK_SQL_ERROR, "No config".to_string())
+    } else {
+        match collect(&CStr::from_ptr(config_yaml).to_string_lossy()) {
+            Ok(text) => (MK_SQL_OK, text),
+            Err(e) => (MK_SQL_ERROR, e.to_string()),
+        }
+    };
+    *output = CString::new(text.replace('\0', ""))
+        .unwrap_or_default()
+        .into_raw();
+    code
+}
+
+/// # Safety
+/// `output` must be either null or returned by `mk_sql_collect`
+#[no_mangle]
+pub unsafe extern "C" fn mk_sql_free(output: *mut c_char) {
+    if !output.is_null() {
+        drop(CString::from_raw(output));
+    }
+}
+
+fn collect(config_yaml: &str) -> Result<String> {
+    let config = CheckConfig::load_string(config_yaml)?;
+    let mut output = String::new();
+    prepare_kerberos(config.ms_sql().and_then(|c| c.kerberos()));
+    tokio::runtime::Runtime::new()?.block_on(config.exec_with(
+        &Env::default(),
+        &MsSqlRunner,
+        &mut output,
+    ))?;
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collect_error() {
+        let config = CString::new("---\nsystem:\n").unwrap();
+        let mut output: *mut c_char = std::ptr::null_mut();
+        unsafe {
+            assert_eq!(mk_sql_collect(config.as_ptr(), &mut output), MK_SQL_ERROR);
+            assert_eq!(CStr::from_ptr(output).to_str().unwrap(), "No Config");
+            mk_sql_free(output);
+            assert_eq!(mk_sql_collect(std::ptr::null(), &mut output), MK_SQL_ERROR);
+            mk_sql_free(output);
+            assert_eq!(
+                mk_sql_collect(config.as_ptr(), std::ptr::null_mut()),
+                MK_SQL_ERROR
+            );
+        }
+    }
+}
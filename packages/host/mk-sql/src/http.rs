@@ -0,0 +1,361 @@
+// Copyright (C) 2024 Checkmk GmbH - License: GNU General Public License v2
+// This file is part of Checkmk (https://checkmk.com). It is subject to the terms and
+// conditions defined in the file COPYING, which is part of this source code package.
+
+//! Minimal blocking HTTP/1.0 client for token and secret services: one request per connection,
+//! TLS by OpenSSL with the system trust store and optional CA file
+use anyhow::{Context, Result};
+use openssl::ssl::{SslConnector, SslMethod};
+use openssl::x509::X509;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::time::Duration;
+use yaml_rust2::{Yaml, YamlLoader};
+
+const HTTP_PORT: u16 = 80;
+const HTTPS_PORT: u16 = 443;
+/// answers of token and secret services are small
+const MAX_RESPONSE_SIZE: usize = 1024 * 1024;
+
+#[derive(Debug, PartialEq)]
+pub struct Url {
+    pub tls: bool,
+    pub host: String,
+    pub port: u16,
+    /// without trailing `/`, may be empty
+    pub path: String,
+}
+
+impl Url {
+    /// `http(s)://host[:port][/path]`, IPv6 address in brackets: `https://[::1]:8200`
+    pub fn parse(url: &str) -> Result<Self> {
+        let (tls, rest) = if let Some(rest) = url.strip_prefix("https://") {
+            (true, rest)
+        } else if let Some(rest) = url.strip_prefix("http://") {
+            (false, rest)
+        } else {
+            anyhow::bail!("Bad url `{url}`: scheme must be http or https");
+        };
+        let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+        let (host, port) =
+            match authority.strip_prefix('[') {
+                Some(bracketed) => match bracketed.split_once(']') {
+                    Some((host, "")) => (host, None),
+                    Some((host, port)) => (
+                        host,
+                        Some(port.strip_prefix(':').with_context(|| {
+                            format!("Bad url `{url}`: unexpected text after `]`")
+                        })?),
+                    ),
+                    None => anyhow::bail!("Bad url `{url}`: `]` is missing"),
+                },
+                None => match authority.rsplit_once(':') {
+                    Some((host, port)) => (host, Some(port)),
+                    None => (authority, None),
+                },
+            };
+        let port = match port {
+            Some(port) => port
+                .parse::<u16>()
+                .with_context(|| format!("Bad url `{url}`: invalid port"))?,
+            None if tls => HTTPS_PORT,
+            None => HTTP_PORT,
+        };
+        if host.is_empty() {
+            anyhow::bail!("Bad url `{url}`: host is missing");
+        }
+        Ok(Self {
+            tls,
+            host: host.to_string(),
+            port,
+            path: path.trim_end_matches('/').to_string(),
+        })
+    }
+
+    /// value of the `Host` header, IPv6 address is bracketed
+    pub fn host_header(&self) -> String {
+        if self.host.contains(':') {
+            format!("[{}]", self.host)
+        } else {
+            self.host.clone()
+        }
+    }
+}
+
+/// application/x-www-form-urlencoded, unreserved characters are kept
+pub fn encode_form(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+/// HTTP/1.0 to avoid chunked responses, the server closes the connection
+pub fn make_post_request(host: &str, path: &str, body: &str) -> String {
+    format!(
+        "POST {path} HTTP/1.0\r\nHost: {host}\r\nContent-Type: application/x-www-form-urlencoded\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+pub fn make_get_request(host: &str, path: &str, headers: &[(&str, &str)]) -> String {
+    let headers = headers
+        .iter()
+        .map(|(name, value)| format!("{name}: {value}\r\n"))
+        .collect::<String>();
+    format!("GET {path} HTTP/1.0\r\nHost: {host}\r\n{headers}Connection: close\r\n\r\n")
+}
+
+/// `ca_file`: PEM roots trusted in addition to the default store, e.g. private CA of the service
+pub fn send(
+    host: &str,
+    port: u16,
+    request: &str,
+    tls: bool,
+    ca_file: Option<&Path>,
+    timeout: Duration,
+) -> Result<String> {
+    let address = (host, port)
+        .to_socket_addrs()?
+        .next()
+        .with_context(|| format!("Can't resolve {host}"))?;
+    let stream = TcpStream::connect_timeout(&address, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    if tls {
+        exchange(make_connector(ca_file)?.connect(host, stream)?, request)
+    } else {
+        exchange(stream, request)
+    }
+}
+
+/// vendored OpenSSL knows no trust store on Windows: roots of the machine are added
+fn make_connector(ca_file: Option<&Path>) -> Result<SslConnector> {
+    let mut builder = SslConnector::builder(SslMethod::tls_client())?;
+    let store = builder.cert_store_mut();
+    for der in crate::platform::registry::get_root_certificates() {
+        // duplicates and certificates unknown to OpenSSL are skipped
+        if let Ok(certificate) = X509::from_der(&der) {
+            store.add_cert(certificate).ok();
+        }
+    }
+    if let Some(ca_file) = ca_file {
+        builder
+            .set_ca_file(ca_file)
+            .with_context(|| format!("Can't load CA file {ca_file:?}"))?;
+    }
+    Ok(builder.build())
+}
+
+fn exchange<S: Read + Write>(mut stream: S, request: &str) -> Result<String> {
+    stream.write_all(request.as_bytes())?;
+    stream.flush()?;
+    let mut response = Vec::new();
+    let mut buffer = [0u8; 4096];
+    loop {
+        match stream.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => {
+                response.extend_from_slice(&buffer[..n]);
+                if response.len() > MAX_RESPONSE_SIZE {
+                    anyhow::bail!("Response is larger than {MAX_RESPONSE_SIZE} bytes");
+                }
+                if is_complete(&response) {
+                    break;
+                }
+            }
+            // servers often skip close_notify
+            Err(_) if is_complete(&response) => break,
+            Err(e) => return Err(anyhow::Error::from(e).context("Response is incomplete")),
+        }
+    }
+    if let Some(expected) = expected_size(&response).filter(|&size| response.len() < size) {
+        anyhow::bail!(
+            "Response is truncated: {} of {expected} bytes",
+            response.len()
+        );
+    }
+    Ok(String::from_utf8_lossy(&response).to_string())
+}
+
+/// size of head and body by `Content-Length`, None - not known yet or not given
+fn expected_size(response: &[u8]) -> Option<usize> {
+    let head_end = response.windows(4).position(|w| w == b"\r\n\r\n")? + 4;
+    String::from_utf8_lossy(&response[..head_end])
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+        .map(|length| head_end + length)
+}
+
+fn is_complete(response: &[u8]) -> bool {
+    expected_size(response).is_some_and(|size| response.len() >= size)
+}
+
+/// status code and body of the raw response
+pub fn split_response(response: &str) -> Result<(&str, &str)> {
+    let (head, body) = response
+        .split_once("\r\n\r\n")
+        .context("Malformed HTTP response")?;
+    Ok((head.split_whitespace().nth(1).unwrap_or_default(), body))
+}
+
+/// JSON is parsed as YAML(superset of JSON), None - not a document
+pub fn parse_json(body: &str) -> Option<Yaml> {
+    YamlLoader::load_from_str(body)
+        .ok()?
+        .into_iter()
+        .next()
+        .filter(|json| json.as_hash().is_some())
+}
+
+/// string or number at the path of object fields, e.g. `["data", "data", "password"]`
+/// IMDS gives numbers as strings
+pub fn find_json_value(json: &Yaml, path: &[&str]) -> Option<String> {
+    let value = path.iter().try_fold(json, |node, name| {
+        node.as_hash()?.get(&Yaml::String(name.to_string()))
+    })?;
+    match value {
+        Yaml::String(s) => Some(s.clone()),
+        Yaml::Integer(i) => Some(i.to_string()),
+        Yaml::Real(r) => Some(r.clone()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_url() {
+        assert_eq!(
+            Url::parse("https://vault.local:8200/").unwrap(),
+            Url {
+                tls: true,
+                host: "vault.local".to_string(),
+                port: 8200,
+                path: String::new()
+            }
+        );
+        let url = Url::parse("http://ccp/base").unwrap();
+        assert_eq!((url.tls, url.port, url.path.as_str()), (false, 80, "/base"));
+        assert!(Url::parse("ftp://x").is_err());
+        assert!(Url::parse("https://:1").is_err());
+        assert!(Url::parse("https://x:port").is_err());
+        let url = Url::parse("https://[fd00::1]:8200/v").unwrap();
+        assert_eq!(
+            (url.host.as_str(), url.port, url.path.as_str()),
+            ("fd00::1", 8200, "/v")
+        );
+        assert_eq!(url.host_header(), "[fd00::1]");
+        let url = Url::parse("http://[::1]").unwrap();
+        assert_eq!((url.host.as_str(), url.port), ("::1", 80));
+        assert_eq!(Url::parse("http://h").unwrap().host_header(), "h");
+        assert!(Url::parse("https://[::1").is_err());
+        assert!(Url::parse("https://[::1]8200").is_err());
+        assert!(Url::parse("https://[]:1").is_err());
+    }
+
+    #[test]
+    fn test_make_connector() {
+        assert!(make_connector(None).is_ok());
+        let dir = tempfile::tempdir().unwrap();
+        let e = make_connector(Some(&dir.path().join("absent.pem")))
+            .err()
+            .unwrap();
+        assert!(e.to_string().starts_with("Can't load CA file"), "{e}");
+    }
+
+    #[test]
+    fn test_make_get_request() {
+        assert_eq!(
+            make_get_request("h", "/p", &[("X-A", "1")]),
+            "GET /p HTTP/1.0\r\nHost: h\r\nX-A: 1\r\nConnection: close\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn test_find_json_value() {
+        let json = parse_json(
+            r#"{"a": "x\"y\\zä\n", "n":42, "data": {"data": {"a": "nested"}, "l": [1]}}"#,
+        )
+        .unwrap();
+        assert_eq!(find_json_value(&json, &["a"]).unwrap(), "x\"y\\zä\n");
+        assert_eq!(find_json_value(&json, &["n"]).unwrap(), "42");
+        assert_eq!(
+            find_json_value(&json, &["data", "data", "a"]).unwrap(),
+            "nested"
+        );
+        assert_eq!(find_json_value(&json, &["data", "a"]), None);
+        assert_eq!(find_json_value(&json, &["data", "l"]), None);
+        assert_eq!(find_json_value(&json, &["absent"]), None);
+        assert!(parse_json(r#"{"s":"unterminated"#).is_none());
+        assert!(parse_json("<html></html>").is_none());
+    }
+
+    /// replies with `data`, then fails as TLS without close_notify
+    struct Replay {
+        data: std::io::Cursor<Vec<u8>>,
+    }
+
+    impl Read for Replay {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            match self.data.read(buf)? {
+                0 => Err(std::io::ErrorKind::UnexpectedEof.into()),
+                n => Ok(n),
+            }
+        }
+    }
+
+    impl Write for Replay {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn replay(data: &str) -> Replay {
+        Replay {
+            data: std::io::Cursor::new(data.as_bytes().to_vec()),
+        }
+    }
+
+    #[test]
+    fn test_exchange() {
+        let complete = "HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\n{}";
+        assert_eq!(exchange(replay(complete), "GET").unwrap(), complete);
+        let e = exchange(
+            replay("HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\n{}"),
+            "GET",
+        )
+        .unwrap_err();
+        assert_eq!(e.to_string(), "Response is incomplete");
+        let e = exchange(replay("HTTP/1.1 200 OK\r\n\r\n{}"), "GET").unwrap_err();
+        assert_eq!(e.to_string(), "Response is incomplete");
+        let large = format!("HTTP/1.1 200 OK\r\n\r\n{}", "x".repeat(MAX_RESPONSE_SIZE));
+        assert!(exchange(replay(&large), "GET").is_err());
+        let short = b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\n{}".as_slice();
+        assert_eq!(expected_size(short), Some(short.len() + 8));
+        assert!(!is_complete(short));
+        assert_eq!(expected_size(b"HTTP/1.1 200 OK\r\n"), None);
+    }
+
+    #[test]
+    fn test_split_response() {
+        assert_eq!(
+            split_response("HTTP/1.1 404 Not Found\r\nA: b\r\n\r\n{}").unwrap(),
+            ("404", "{}")
+        );
+        assert!(split_response("garbage").is_err());
+    }
+}
@@ -2,12 +2,22 @@
 // This file is part of Checkmk (https://checkmk.com). It is subject to the terms and
 // conditions defined in the file COPYING, which is part of this source code package.
 
+pub mod api;
 pub mod args;
 pub mod config;
 pub mod constants;
+pub mod crypto;
 pub mod emit;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "http")]
+pub mod http;
 pub mod ms_sql;
 pub mod platform;
+#[cfg(feature = "rest-api")]
+pub mod rest;
+pub mod run_lock;
+pub mod schedule;
 pub mod setup;
 pub mod types;
 pub mod utils;
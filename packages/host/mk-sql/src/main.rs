@@ -1,29 +1,174 @@
 // Copyright (C) 2023 Checkmk GmbH - License: GNU General Public License v2
 // This file is part of Checkmk (https://checkmk.com). It is subject to the terms and
 // conditions defined in the file COPYING, which is part of this source code package.
-use mk_sql::setup;
+use mk_sql::args::{ConfigAction, ScheduleAction, Tool};
+use mk_sql::config::{diff, CheckConfig};
+use mk_sql::crypto;
+use mk_sql::emit::{self, GuardedWriter};
+use mk_sql::ms_sql::{client, mock, schema, trace};
+#[cfg(feature = "rest-api")]
+use mk_sql::rest;
+use mk_sql::run_lock::{self, Lock};
+use mk_sql::schedule;
+use mk_sql::setup::{self, Env};
 
-#[tokio::main]
-async fn main() {
+fn main() {
     let result = setup::init(std::env::args_os());
+    if let Ok((config, _)) = &result {
+        // the environment is shared by all threads: set before the runtime starts them
+        client::prepare_kerberos(config.ms_sql().and_then(|c| c.kerberos()));
+    }
+    match tokio::runtime::Runtime::new() {
+        Ok(runtime) => runtime.block_on(run(result)),
+        Err(e) => {
+            display_and_log(e);
+            std::process::exit(1);
+        }
+    }
+}
+
+async fn run(result: anyhow::Result<(CheckConfig, Env)>) {
     if let Ok((config, environment)) = result {
-        match config.exec(&environment).await {
-            Ok(output) => {
-                print!("{output}");
-                log::info!("Success");
-                std::process::exit(0);
+        if let Some(tool) = environment.tool() {
+            match run_tool(tool, &config, &environment) {
+                Ok(output) => {
+                    print!("{output}");
+                    std::process::exit(0);
+                }
+                Err(e) => {
+                    display_and_log(e);
+                    std::process::exit(1);
+                }
             }
-            Err(e) => {
+        }
+        if let Some(address) = environment.rest_api() {
+            if let Err(e) = serve_rest_api(address, &environment).await {
                 display_and_log(e);
                 std::process::exit(1);
             }
+            std::process::exit(0);
         }
+        let lock = match run_lock::acquire(&environment, run_lock::WAIT_FOR_LOCK) {
+            Lock::Busy => {
+                log::warn!("Another run is active, reusing last output");
+                std::process::exit(write_output(
+                    &run_lock::load_output(&environment).unwrap_or_default(),
+                    &environment,
+                ));
+            }
+            lock => lock,
+        };
+        if let Some(output) = find_replay(&config, &environment) {
+            log::info!("Last run is too recent, replaying its output");
+            let code = write_output(&output, &environment);
+            drop(lock);
+            std::process::exit(code);
+        }
+        let code = match config.exec(&environment).await {
+            Ok(_) if environment.is_warmup() => {
+                log::info!("Caches of async sections are refreshed");
+                0
+            }
+            Ok(output) => {
+                run_lock::store_output(&environment, &output);
+                match write_output(&output, &environment) {
+                    0 => {
+                        log::info!("Success");
+                        0
+                    }
+                    code => code,
+                }
+            }
+            Err(e) => {
+                display_and_log(e);
+                1
+            }
+        };
+        drop(lock); // exit doesn't run destructors
+        std::process::exit(code);
     } else {
         display_and_log(&result.err().unwrap());
         std::process::exit(1);
     }
 }
 
+/// into the spool file if requested, otherwise to stdout: a stalled consumer of stdout ends
+/// the run with error instead of hanging forever, the blocked writer thread is dropped by exit
+fn write_output(output: &str, environment: &Env) -> i32 {
+    if let Some(file) = environment.spool_file() {
+        return match emit::write_file_atomically(file, output) {
+            Ok(()) => 0,
+            Err(e) => {
+                display_and_log(format!("Error {e} writing output into {file:?}"));
+                1
+            }
+        };
+    }
+    let mut writer = GuardedWriter::stdout();
+    match writer.write(output).and_then(|_| writer.finish()) {
+        Ok(()) => 0,
+        Err(e) => {
+            display_and_log(e);
+            1
+        }
+    }
+}
+
+/// output of the last run if it is younger than `options.min_interval` of the main config
+fn find_replay(config: &CheckConfig, environment: &Env) -> Option<String> {
+    if environment.is_warmup() {
+        return None;
+    }
+    config
+        .ms_sql()
+        .and_then(|ms_sql| ms_sql.options().min_interval())
+        .and_then(|interval| run_lock::load_recent_output(environment, interval))
+}
+
+fn run_tool(tool: &Tool, config: &CheckConfig, environment: &Env) -> anyhow::Result<String> {
+    match tool {
+        Tool::Config {
+            action: ConfigAction::Diff { against },
+        } => diff::exec(config, against.as_deref()),
+        Tool::Schedule {
+            action: ScheduleAction::Show,
+        } => Ok(trace::show(environment)),
+        Tool::InstallSchedule {
+            interval,
+            user,
+            spool_dir,
+            remove,
+            dry_run,
+        } => schedule::exec(
+            environment,
+            &schedule::Schedule {
+                interval: *interval,
+                user: user.clone(),
+                spool_dir: spool_dir.clone(),
+            },
+            *remove,
+            *dry_run,
+        ),
+        Tool::Schema => Ok(schema::to_json() + "\n"),
+        Tool::EncryptPassword => crypto::exec_encrypt_password(std::io::stdin().lock()),
+        Tool::Mock {
+            section,
+            rows,
+            seed,
+        } => mock::exec(section, *rows, *seed),
+    }
+}
+
+#[cfg(feature = "rest-api")]
+async fn serve_rest_api(address: &str, environment: &Env) -> anyhow::Result<()> {
+    rest::serve(address, &environment.base_cache_dir().unwrap_or_default()).await
+}
+
+#[cfg(not(feature = "rest-api"))]
+async fn serve_rest_api(address: &str, _environment: &Env) -> anyhow::Result<()> {
+    anyhow::bail!("Can't serve `{address}`: built without feature `rest-api`")
+}
+
 fn display_and_log(e: impl std::fmt::Display) {
     log::error!("{e}",);
     eprintln!("Stop on error: `{e}`",);
@@ -2,10 +2,25 @@
 // This file is part of Checkmk (https://checkmk.com). It is subject to the terms and
 // conditions defined in the file COPYING, which is part of this source code package.
 
+#[cfg(feature = "azure-ad")]
+pub mod aad;
+pub mod anonymize;
 pub mod client;
 pub mod custom;
 pub mod defaults;
+pub mod fixture;
 pub mod instance;
+pub mod mock;
+pub mod pool;
 pub mod query;
+pub mod registry;
+pub mod retry;
+pub mod scheduler;
+pub mod schema;
 pub mod section;
+pub mod settings;
+pub mod setup_errors;
 pub mod sqls;
+pub mod state_gc;
+pub mod trace;
+pub mod value;
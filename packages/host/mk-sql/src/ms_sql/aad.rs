@@ -0,0 +1,291 @@
+// Copyright (C) 2024 Checkmk GmbH - License: GNU General Public License v2
+// This file is part of Checkmk (https://checkmk.com). It is subject to the terms and
+// conditions defined in the file COPYING, which is part of this source code package.
+
+//! Azure AD(Entra ID) token for Azure SQL:
+//! - service principal: OAuth2 client credentials flow, the application authenticates with the
+//!   client secret or with the certificate(signed JWT assertion)
+//! - managed identity: the token is issued by the instance metadata service(IMDS) of the VM
+//!
+//! Tokens are reused by the process until shortly before their expiration.
+use crate::config::ms_sql::ServicePrincipal;
+use crate::http::{
+    encode_form, find_json_value, make_post_request, parse_json, send, split_response,
+};
+use anyhow::{Context, Result};
+use openssl::hash::MessageDigest;
+use openssl::pkey::{PKey, Private};
+use openssl::x509::X509;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use yaml_rust2::Yaml;
+
+const AUTHORITY_HOST: &str = "login.microsoftonline.com";
+const HTTPS_PORT: u16 = 443;
+const IMDS_HOST: &str = "169.254.169.254";
+const IMDS_PORT: u16 = 80;
+const IMDS_PATH: &str = "/metadata/identity/oauth2/token?api-version=2018-02-01";
+const RESOURCE: &str = "https://database.windows.net/";
+const SCOPE: &str = "https://database.windows.net/.default";
+const ASSERTION_TYPE: &str = "urn:ietf:params:oauth:client-assertion-type:jwt-bearer";
+/// validity of the certificate assertion
+const ASSERTION_LIFETIME: u64 = 600;
+/// cached token is renewed so long before its expiration
+const EXPIRATION_MARGIN: Duration = Duration::from_secs(300);
+
+lazy_static::lazy_static! {
+    /// `tenant/client` -> (token, expiration)
+    static ref TOKENS: Mutex<HashMap<String, (String, Instant)>> = Mutex::new(HashMap::new());
+}
+
+pub async fn obtain_token(principal: &ServicePrincipal, timeout: Duration) -> Result<String> {
+    let key = format!("{}/{}", principal.tenant_id(), principal.client_id());
+    if let Some(token) = find_cached(&key) {
+        return Ok(token);
+    }
+    let request = make_post_request(
+        AUTHORITY_HOST,
+        &token_path(principal.tenant_id()),
+        &make_request_body(principal)?,
+    );
+    log::info!(
+        "Requesting Azure AD token for client {}",
+        principal.client_id()
+    );
+    let response = tokio::task::spawn_blocking(move || {
+        send(AUTHORITY_HOST, HTTPS_PORT, &request, true, None, timeout)
+    })
+    .await??;
+    store(key, parse_response(&response)?)
+}
+
+/// client_id: user-assigned identity, None - system-assigned one
+pub async fn obtain_managed_identity_token(
+    client_id: Option<&str>,
+    timeout: Duration,
+) -> Result<String> {
+    let key = format!("managed_identity/{}", client_id.unwrap_or_default());
+    if let Some(token) = find_cached(&key) {
+        return Ok(token);
+    }
+    let request = make_imds_request(client_id);
+    log::info!("Requesting Azure AD token from IMDS");
+    let response = tokio::task::spawn_blocking(move || {
+        send(IMDS_HOST, IMDS_PORT, &request, false, None, timeout)
+    })
+    .await??;
+    store(key, parse_response(&response)?)
+}
+
+fn store(key: String, (token, expires_in): (String, Duration)) -> Result<String> {
+    if let Ok(mut tokens) = TOKENS.lock() {
+        tokens.insert(key, (token.clone(), Instant::now() + expires_in));
+    }
+    Ok(token)
+}
+
+fn find_cached(key: &str) -> Option<String> {
+    let tokens = TOKENS.lock().ok()?;
+    tokens
+        .get(key)
+        .filter(|(_, expiration)| Instant::now() + EXPIRATION_MARGIN < *expiration)
+        .map(|(token, _)| token.clone())
+}
+
+fn token_path(tenant_id: &str) -> String {
+    format!("/{}/oauth2/v2.0/token", encode_form(tenant_id))
+}
+
+fn make_request_body(principal: &ServicePrincipal) -> Result<String> {
+    let mut params = vec![
+        ("grant_type", "client_credentials".to_string()),
+        ("client_id", principal.client_id().to_string()),
+        ("scope", SCOPE.to_string()),
+    ];
+    if let Some(secret) = principal.client_secret() {
+        params.push(("client_secret", secret.to_string()));
+    } else if let Some(certificate) = principal.client_certificate() {
+        let pem = std::fs::read(certificate)
+            .with_context(|| format!("Can't read certificate {certificate:?}"))?;
+        let x509 = X509::from_pem(&pem)?;
+        let key = PKey::private_key_from_pem(&pem)?;
+        let now = crate::utils::get_utc_now()?;
+        params.push(("client_assertion_type", ASSERTION_TYPE.to_string()));
+        params.push((
+            "client_assertion",
+            make_assertion(principal, &x509, &key, now)?,
+        ));
+    } else {
+        anyhow::bail!("Neither client secret nor certificate is provided");
+    }
+    Ok(params
+        .iter()
+        .map(|(name, value)| format!("{name}={}", encode_form(value)))
+        .collect::<Vec<_>>()
+        .join("&"))
+}
+
+/// JWT signed by the private key of the certificate, `x5t` identifies the certificate
+fn make_assertion(
+    principal: &ServicePrincipal,
+    x509: &X509,
+    key: &PKey<Private>,
+    now: u64,
+) -> Result<String> {
+    let thumbprint = encode_base64_url(&x509.digest(MessageDigest::sha1())?);
+    let header = format!(r#"{{"alg":"RS256","typ":"JWT","x5t":"{thumbprint}"}}"#);
+    let mut jti = [0u8; 16];
+    openssl::rand::rand_bytes(&mut jti)?;
+    let jti = jti.iter().map(|b| format!("{b:02x}")).collect::<String>();
+    let client_id = crate::utils::escape_json(principal.client_id());
+    let claims = format!(
+        r#"{{"aud":"https://{AUTHORITY_HOST}{}","iss":"{client_id}","sub":"{client_id}","jti":"{jti}","nbf":{now},"exp":{}}}"#,
+        token_path(principal.tenant_id()),
+        now + ASSERTION_LIFETIME
+    );
+    let content = format!(
+        "{}.{}",
+        encode_base64_url(header.as_bytes()),
+        encode_base64_url(claims.as_bytes())
+    );
+    let mut signer = openssl::sign::Signer::new(MessageDigest::sha256(), key)?;
+    signer.update(content.as_bytes())?;
+    Ok(format!(
+        "{content}.{}",
+        encode_base64_url(&signer.sign_to_vec()?)
+    ))
+}
+
+fn encode_base64_url(data: &[u8]) -> String {
+    openssl::base64::encode_block(data)
+        .trim_end_matches('=')
+        .replace('+', "-")
+        .replace('/', "_")
+}
+
+/// IMDS rejects requests without `Metadata` header
+fn make_imds_request(client_id: Option<&str>) -> String {
+    let client_id = client_id
+        .map(|c| format!("&client_id={}", encode_form(c)))
+        .unwrap_or_default();
+    format!(
+        "GET {IMDS_PATH}&resource={}{client_id} HTTP/1.0\r\nHost: {IMDS_HOST}\r\nMetadata: true\r\nConnection: close\r\n\r\n",
+        encode_form(RESOURCE)
+    )
+}
+
+/// token and its lifetime from the raw HTTP response
+fn parse_response(response: &str) -> Result<(String, Duration)> {
+    let (status, body) = split_response(response).context("Malformed token response")?;
+    let json = parse_json(body).unwrap_or(Yaml::Null);
+    if status != "200" {
+        anyhow::bail!(
+            "Token request failed with status {status}: {}",
+            find_json_value(&json, &["error_description"])
+                .or_else(|| find_json_value(&json, &["error"]))
+                .unwrap_or_default()
+        );
+    }
+    let token = find_json_value(&json, &["access_token"]).context("No access_token in response")?;
+    let expires_in = find_json_value(&json, &["expires_in"])
+        .and_then(|e| e.parse::<u64>().ok())
+        .unwrap_or_default();
+    Ok((token, Duration::from_secs(expires_in)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ms_sql::Authentication;
+    use yaml_rust2::YamlLoader;
+
+    fn make_principal(extra: &str) -> ServicePrincipal {
+        let text = format!(
+            "authentication:\n  type: azure_ad\n  tenant_id: contoso\n  client_id: app\n{extra}"
+        );
+        Authentication::from_yaml(&YamlLoader::load_from_str(&text).unwrap()[0])
+            .unwrap()
+            .service_principal()
+            .unwrap()
+            .clone()
+    }
+
+    #[test]
+    fn test_make_request_body_secret() {
+        assert_eq!(
+            make_request_body(&make_principal("  client_secret: 'a+b=/c'\n")).unwrap(),
+            "grant_type=client_credentials&client_id=app\
+             &scope=https%3A%2F%2Fdatabase.windows.net%2F.default&client_secret=a%2Bb%3D%2Fc"
+        );
+        assert_eq!(token_path("contoso"), "/contoso/oauth2/v2.0/token");
+    }
+
+    #[test]
+    fn test_make_assertion() {
+        let rsa = openssl::rsa::Rsa::generate(2048).unwrap();
+        let key = PKey::from_rsa(rsa).unwrap();
+        let mut builder = X509::builder().unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder.sign(&key, MessageDigest::sha256()).unwrap();
+        let x509 = builder.build();
+        let principal = make_principal("  client_certificate: 'c.pem'\n");
+        let assertion = make_assertion(&principal, &x509, &key, 1000).unwrap();
+        let parts = assertion.split('.').collect::<Vec<_>>();
+        assert_eq!(parts.len(), 3);
+        let decode = |s: &str| {
+            let padded = format!("{s}{}", "=".repeat((4 - s.len() % 4) % 4));
+            openssl::base64::decode_block(&padded.replace('-', "+").replace('_', "/")).unwrap()
+        };
+        let claims = String::from_utf8(decode(parts[1])).unwrap();
+        assert!(claims.contains(
+            r#""aud":"https://login.microsoftonline.com/contoso/oauth2/v2.0/token","iss":"app","sub":"app""#
+        ));
+        assert!(claims.contains(r#""nbf":1000,"exp":1600}"#));
+        let mut verifier = openssl::sign::Verifier::new(MessageDigest::sha256(), &key).unwrap();
+        verifier
+            .update(format!("{}.{}", parts[0], parts[1]).as_bytes())
+            .unwrap();
+        assert!(verifier.verify(&decode(parts[2])).unwrap());
+    }
+
+    #[test]
+    fn test_make_imds_request() {
+        assert_eq!(
+            make_imds_request(None),
+            "GET /metadata/identity/oauth2/token?api-version=2018-02-01\
+             &resource=https%3A%2F%2Fdatabase.windows.net%2F HTTP/1.0\r\n\
+             Host: 169.254.169.254\r\nMetadata: true\r\nConnection: close\r\n\r\n"
+        );
+        assert!(make_imds_request(Some("id")).contains("%2F&client_id=id HTTP/1.0"));
+        let (token, lifetime) = parse_response(
+            "HTTP/1.1 200 OK\r\n\r\n{\"access_token\":\"eyJ0\",\"expires_in\":\"86399\"}",
+        )
+        .unwrap();
+        assert_eq!(token, "eyJ0");
+        assert_eq!(lifetime, Duration::from_secs(86399));
+    }
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(encode_form("a b&c~"), "a%20b%26c~");
+        assert_eq!(encode_base64_url(&[0xfb, 0xff]), "-_8");
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let (token, lifetime) = parse_response(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\r\n\
+             {\"token_type\":\"Bearer\",\"expires_in\": 3599,\"access_token\":\"eyJ0\"}",
+        )
+        .unwrap();
+        assert_eq!(token, "eyJ0");
+        assert_eq!(lifetime, Duration::from_secs(3599));
+        let e = parse_response(
+            "HTTP/1.1 401 Unauthorized\r\n\r\n{\"error\":\"invalid_client\",\"error_description\":\"AADSTS7000215\"}",
+        )
+        .unwrap_err();
+        assert!(e.to_string().contains("401: AADSTS7000215"));
+        assert!(parse_response("garbage").is_err());
+    }
+}
@@ -0,0 +1,281 @@
+// Copyright (C) 2024 Checkmk GmbH - License: GNU General Public License v2
+// This file is part of Checkmk (https://checkmk.com). It is subject to the terms and
+// conditions defined in the file COPYING, which is part of this source code package.
+
+//! Pseudonymization of recorded query results for support bundles.
+//!
+//! Values of identifier columns(instances, databases, logins, jobs, hosts) are replaced with
+//! `<kind>_<hash>` where hash is derived from the value only: the same name gets the same
+//! pseudonym in every block, in every query text and in every run. Generic columns like `name`
+//! are identifiers only in some queries, so they are mapped per query. Known names are
+//! replaced in free text as whole tokens only, in query text only inside of quotes.
+use super::sqls::query;
+use crate::platform::Block;
+use std::collections::HashMap;
+
+/// lower case column name -> kind of the pseudonym, valid for every query
+const NAME_COLUMNS: [(&str, &str); 21] = [
+    ("instancename", "inst"),
+    ("instance_name", "inst"),
+    ("instancenames", "inst"),
+    ("database_name", "db"),
+    ("databasename", "db"),
+    ("databasenames", "db"),
+    ("db_name", "db"),
+    ("physical_name", "file"),
+    ("job_name", "job"),
+    ("login_name", "login"),
+    ("loginame", "login"),
+    ("machinename", "host"),
+    ("machine_name", "host"),
+    ("computername", "host"),
+    ("computernamephysicalnetbios", "host"),
+    ("servername", "host"),
+    ("server_name", "host"),
+    ("host_name", "host"),
+    ("nodename", "host"),
+    ("clustername", "host"),
+    ("mirroring_partner_name", "host"),
+];
+
+/// query -> lower case column name -> kind of the pseudonym, valid only for the query
+const QUERY_NAME_COLUMNS: [(&str, &str, &str); 20] = [
+    (query::DATABASE_NAMES, "name", "db"),
+    (query::DATABASES, "name", "db"),
+    (query::TRANSACTION_LOGS, "name", "file"),
+    (query::DATAFILES, "name", "file"),
+    (query::CONNECTIONS, "dbname", "db"),
+    (query::DATABASE_OPTIONS, "name", "db"),
+    (query::DATABASE_OPTIONS, "owner", "login"),
+    (query::DATABASES_V2, "owner", "login"),
+    (query::CHECKPOINTS, "name", "db"),
+    (query::CHECKPOINTS_REDUCED, "name", "db"),
+    (query::BACKUP_CHAIN, "name", "db"),
+    (query::CHECKDB, "name", "db"),
+    (query::VERSION_STORE, "name", "db"),
+    (query::VERSION_STORE_USAGE, "name", "db"),
+    (query::ADR, "name", "db"),
+    (query::ADR_REDUCED, "name", "db"),
+    (query::ENCRYPTION, "name", "db"),
+    (query::ENCRYPTION_REDUCED, "name", "db"),
+    (query::LINKED_SERVERS, "name", "host"),
+    (query::LINKED_SERVERS, "data_source", "host"),
+];
+
+/// not sensitive and used literally by queries
+const KEPT_VALUES: [&str; 5] = ["master", "model", "msdb", "tempdb", "MSSQLSERVER"];
+
+/// values shorter are not replaced in free text to avoid damaging of the text
+const MIN_TEXT_VALUE_LEN: usize = 3;
+
+#[derive(Debug, Default)]
+pub struct Anonymizer {
+    pseudonyms: HashMap<String, String>,
+}
+
+impl Anonymizer {
+    /// collects values of identifier columns of the block returned by the query
+    pub fn learn(&mut self, sql: &str, block: &Block) {
+        for (idx, column) in block.headline.iter().enumerate() {
+            if let Some(kind) = find_kind(sql, column) {
+                for value in block.rows.iter().filter_map(|r| r.get(idx)) {
+                    if !value.is_empty() && !KEPT_VALUES.contains(&value.as_str()) {
+                        self.pseudonyms
+                            .entry(value.clone())
+                            .or_insert_with(|| make_pseudonym(kind, value));
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn block(&self, block: &Block) -> Block {
+        Block {
+            headline: block.headline.clone(),
+            rows: block
+                .rows
+                .iter()
+                .map(|r| r.iter().map(|v| self.value(v)).collect())
+                .collect(),
+        }
+    }
+
+    fn value(&self, value: &str) -> String {
+        self.pseudonyms
+            .get(value)
+            .cloned()
+            .unwrap_or_else(|| self.text(value))
+    }
+
+    /// replaces known names found as whole tokens in the text, e.g. in error message
+    pub fn text(&self, text: &str) -> String {
+        let known = self.known();
+        replace_tokens(text, &known)
+    }
+
+    /// replaces known names only inside of `[]`, `''` and `""` of the query text: the agent
+    /// quotes every name it puts into a query, the rest is the query itself
+    pub fn sql(&self, sql: &str) -> String {
+        let known = self.known();
+        let mut result = String::with_capacity(sql.len());
+        let mut rest = sql;
+        while let Some(start) = rest.find(['[', '\'', '"']) {
+            let close = match rest.as_bytes()[start] {
+                b'[' => ']',
+                quote => quote as char,
+            };
+            result.push_str(&rest[..=start]);
+            rest = &rest[start + 1..];
+            let end = rest.find(close).unwrap_or(rest.len());
+            result.push_str(&replace_tokens(&rest[..end], &known));
+            rest = &rest[end..];
+            if !rest.is_empty() {
+                result.push(close);
+                rest = &rest[1..];
+            }
+        }
+        result.push_str(rest);
+        result
+    }
+
+    /// names to replace in text, longest first
+    fn known(&self) -> Vec<(&str, &str)> {
+        let mut known = self
+            .pseudonyms
+            .iter()
+            .filter(|(name, _)| name.len() >= MIN_TEXT_VALUE_LEN)
+            .map(|(name, pseudonym)| (name.as_str(), pseudonym.as_str()))
+            .collect::<Vec<_>>();
+        known.sort_by(|a, b| b.0.len().cmp(&a.0.len()).then(a.0.cmp(b.0)));
+        known
+    }
+}
+
+fn find_kind(sql: &str, column: &str) -> Option<&'static str> {
+    let column = column.to_lowercase();
+    NAME_COLUMNS
+        .iter()
+        .find(|(name, _)| *name == column)
+        .map(|(_, kind)| *kind)
+        .or_else(|| {
+            QUERY_NAME_COLUMNS
+                .iter()
+                .find(|(query, name, _)| *query == sql && *name == column)
+                .map(|(_, _, kind)| *kind)
+        })
+}
+
+fn is_token_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// one pass over the text: a name is replaced only if no token char is adjacent to it
+fn replace_tokens(text: &str, known: &[(&str, &str)]) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut pos = 0;
+    while pos < text.len() {
+        let rest = &text[pos..];
+        let at_start = !text[..pos].ends_with(is_token_char);
+        let found = known.iter().find(|(name, _)| {
+            at_start && rest.starts_with(name) && !rest[name.len()..].starts_with(is_token_char)
+        });
+        match found {
+            Some((name, pseudonym)) => {
+                result.push_str(pseudonym);
+                pos += name.len();
+            }
+            None => {
+                let c = rest.chars().next().unwrap_or_default();
+                result.push(c);
+                pos += c.len_utf8();
+            }
+        }
+    }
+    result
+}
+
+fn make_pseudonym(kind: &str, value: &str) -> String {
+    let digest = openssl::sha::sha256(value.as_bytes());
+    let hash = digest[..5]
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<String>();
+    format!("{kind}_{hash}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_strings(v: &[&str]) -> Vec<String> {
+        v.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_anonymizer() {
+        let block = Block {
+            headline: to_strings(&["database_name", "size", "Job_Name"]),
+            rows: vec![
+                to_strings(&["Payroll", "10", "Nightly Payroll"]),
+                to_strings(&["master", "1", ""]),
+            ],
+        };
+        let mut anonymizer = Anonymizer::default();
+        anonymizer.learn("SELECT x", &block);
+        let result = anonymizer.block(&block);
+        let payroll = make_pseudonym("db", "Payroll");
+        assert!(payroll.starts_with("db_"));
+        assert_eq!(payroll.len(), "db_".len() + 10);
+        assert_eq!(result.headline, block.headline);
+        assert_eq!(
+            result.rows,
+            vec![
+                vec![
+                    payroll.clone(),
+                    "10".to_string(),
+                    make_pseudonym("job", "Nightly Payroll")
+                ],
+                to_strings(&["master", "1", ""]),
+            ]
+        );
+        assert_eq!(
+            anonymizer.sql("USE [Payroll]; SELECT 'Nightly Payroll' FROM master"),
+            format!(
+                "USE [{payroll}]; SELECT '{}' FROM master",
+                make_pseudonym("job", "Nightly Payroll")
+            )
+        );
+    }
+
+    #[test]
+    fn test_anonymizer_query_columns() {
+        let block = Block {
+            headline: to_strings(&["name", "owner"]),
+            rows: vec![to_strings(&["name", "sa"]), to_strings(&["Pay", "dbo"])],
+        };
+        let mut anonymizer = Anonymizer::default();
+        anonymizer.learn("SELECT name, owner FROM unknown", &block);
+        assert_eq!(anonymizer.block(&block).rows, block.rows);
+
+        anonymizer.learn(query::DATABASE_OPTIONS, &block);
+        let pay = make_pseudonym("db", "Pay");
+        assert_eq!(
+            anonymizer.block(&block).rows,
+            vec![
+                vec![make_pseudonym("db", "name"), make_pseudonym("login", "sa")],
+                vec![pay.clone(), make_pseudonym("login", "dbo")],
+            ]
+        );
+        assert_eq!(
+            anonymizer.sql("SELECT name FROM [name] WHERE x = 'Pay' AND y = 'Payroll'"),
+            format!(
+                "SELECT name FROM [{}] WHERE x = '{pay}' AND y = 'Payroll'",
+                make_pseudonym("db", "name")
+            )
+        );
+        assert_eq!(
+            anonymizer.text("no access to Pay, xPay, Pay_1, Pay2 and Pay"),
+            format!("no access to {pay}, xPay, Pay_1, Pay2 and {pay}")
+        );
+    }
+}
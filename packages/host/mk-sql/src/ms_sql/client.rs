@@ -2,20 +2,24 @@
 // This file is part of Checkmk (https://checkmk.com). It is subject to the terms and
 // conditions defined in the file COPYING, which is part of this source code package.
 
-use crate::config::{self, ms_sql::AuthType, ms_sql::Endpoint};
-use crate::types::{CertPath, HostName, Port};
+use crate::config::{self, ms_sql::AuthType, ms_sql::Endpoint, ms_sql::Protocol, ms_sql::TlsMode};
+use crate::crypto;
+use crate::types::{CertPath, HostName, InstanceName, Port};
 use anyhow::Result;
+use std::path::{Path, PathBuf};
 
-#[cfg(windows)]
-use crate::types::InstanceName; // only on windows possible to connect by name
-#[cfg(windows)]
 use tiberius::SqlBrowser;
 use tiberius::{AuthMethod, Config};
 use tokio::net::TcpStream;
 use tokio_util::compat::{Compat, TokioAsyncWriteCompatExt};
 
+#[cfg(feature = "azure-ad")]
+use super::aad;
 use super::defaults;
+use super::fixture::{self, FixtureClient};
 pub type StdClient = tiberius::Client<Compat<TcpStream>>;
+#[cfg(windows)]
+pub type PipeClient = tiberius::Client<Compat<tokio::net::windows::named_pipe::NamedPipeClient>>;
 #[derive(Debug)]
 pub struct OdbcClient {
     conn_string: String,
@@ -23,6 +27,9 @@ pub struct OdbcClient {
 
 #[cfg(windows)]
 const LOCAL_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(1);
+/// all instances of the pipe are in use: the server creates a new one shortly
+#[cfg(windows)]
+const PIPE_BUSY_DELAY: std::time::Duration = std::time::Duration::from_millis(50);
 
 impl OdbcClient {
     pub fn new(conn_string: impl ToString) -> Self {
@@ -38,7 +45,10 @@ impl OdbcClient {
 #[derive(Debug)]
 pub enum UniClient {
     Std(StdClient),
+    #[cfg(windows)]
+    Pipe(PipeClient),
     Odbc(OdbcClient),
+    Fixture(FixtureClient),
 }
 
 pub struct RemoteConnection<'a> {
@@ -47,6 +57,12 @@ pub struct RemoteConnection<'a> {
     pub credentials: Credentials<'a>,
 }
 
+/// TDS over the named pipe, the host names the server for TLS and SPN
+pub struct PipeConnection<'a> {
+    pub remote: RemoteConnection<'a>,
+    pub path: String,
+}
+
 #[cfg(windows)]
 #[derive(Default)]
 pub struct LocalConnection {
@@ -70,6 +86,7 @@ pub struct NamedConnection {
 
 enum ClientConnection<'a> {
     Remote(RemoteConnection<'a>),
+    Pipe(PipeConnection<'a>),
     #[cfg(windows)]
     Named(NamedConnection),
     #[cfg(windows)]
@@ -82,6 +99,11 @@ pub struct ClientBuilder<'a> {
     database: Option<String>,
     certificate: Option<CertPath>,
     trust_server_certificate: bool,
+    tls_mode: Option<TlsMode>,
+    ca_bundle: Option<PathBuf>,
+    read_only: bool,
+    multi_subnet_failover: bool,
+    browse_instance: Option<InstanceName>,
 }
 
 impl<'a> Default for ClientBuilder<'a> {
@@ -91,6 +113,11 @@ impl<'a> Default for ClientBuilder<'a> {
             database: None,
             certificate: None,
             trust_server_certificate: config::defines::defaults::TRUST_SERVER_CERTIFICATE,
+            tls_mode: None,
+            ca_bundle: None,
+            read_only: false,
+            multi_subnet_failover: false,
+            browse_instance: None,
         }
     }
 }
@@ -123,6 +150,24 @@ impl<'a> ClientBuilder<'a> {
         self
     }
 
+    pub fn logon_on_pipe(
+        mut self,
+        host: &HostName,
+        path: &str,
+        credentials: Credentials<'a>,
+    ) -> Self {
+        log::info!("Logon at pipe `{path}`");
+        self.client_connection = Some(ClientConnection::Pipe(PipeConnection {
+            remote: RemoteConnection {
+                host: host.to_owned(),
+                port: None,
+                credentials,
+            },
+            path: path.to_string(),
+        }));
+        self
+    }
+
     #[cfg(windows)]
     pub fn browse<P: Into<Port>>(
         mut self,
@@ -179,6 +224,39 @@ impl<'a> ClientBuilder<'a> {
         self
     }
 
+    /// replaces `trust_server_certificate`, `verify-full` validates by `certificate` if set
+    pub fn tls_mode(mut self, mode: Option<TlsMode>) -> Self {
+        self.tls_mode = mode;
+        self
+    }
+
+    /// the driver validates the certificate and the host name of its own TLS session by the root
+    /// from the file, the connection is never made without encryption
+    /// the file is read on every connection: a renewed root is used without restart
+    pub fn ca_bundle(mut self, ca_bundle: Option<&Path>) -> Self {
+        self.ca_bundle = ca_bundle.map(Path::to_path_buf);
+        self
+    }
+
+    /// ApplicationIntent=ReadOnly: an AG listener routes the connection to a readable secondary
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// MultiSubnetFailover: all addresses of the host are connected at once, the first wins
+    pub fn multi_subnet_failover(mut self, enabled: bool) -> Self {
+        self.multi_subnet_failover = enabled;
+        self
+    }
+
+    /// the port of the named instance is asked from SQL Browser of the host, as SSMS does for
+    /// a client alias without port
+    pub fn browse_instance(mut self, instance: Option<&InstanceName>) -> Self {
+        self.browse_instance = instance.cloned();
+        self
+    }
+
     pub fn make_config(&self) -> Result<Config> {
         let mut config = Config::new();
         if let Some(db) = &self.database {
@@ -186,11 +264,14 @@ impl<'a> ClientBuilder<'a> {
         }
 
         match &self.client_connection {
-            Some(ClientConnection::Remote(connection)) => {
+            Some(ClientConnection::Remote(connection))
+            | Some(ClientConnection::Pipe(PipeConnection {
+                remote: connection, ..
+            })) => {
                 let port = connection.port.as_ref().map(|p| p.value());
                 config.host(&connection.host);
                 config.port(port.unwrap_or(defaults::STANDARD_PORT));
-                config.authentication(match connection.credentials {
+                config.authentication(match &connection.credentials {
                     Credentials::SqlServer { user, password } => {
                         log::trace!(
                             "Remote connection to {} with user {}",
@@ -200,12 +281,27 @@ impl<'a> ClientBuilder<'a> {
                         AuthMethod::sql_server(user, password)
                     }
                     #[cfg(windows)]
-                    Credentials::Windows { user, password } => AuthMethod::windows(user, password),
+                    Credentials::Windows { user, password } => {
+                        if crypto::is_fips_mode() {
+                            anyhow::bail!(
+                                "Windows(NTLM) authentication is not allowed in FIPS mode"
+                            )
+                        }
+                        AuthMethod::windows(user, password)
+                    }
                     #[cfg(unix)]
                     Credentials::Windows {
                         user: _,
                         password: _,
                     } => anyhow::bail!("not supported"),
+                    Credentials::Kerberos => {
+                        log::trace!("Remote connection to {} with Kerberos", config.get_addr());
+                        kerberos_auth_method()?
+                    }
+                    Credentials::AadToken { token } => {
+                        log::trace!("Remote connection to {} with AAD token", config.get_addr());
+                        AuthMethod::aad_token(token)
+                    }
                 });
             }
             #[cfg(windows)]
@@ -230,54 +326,152 @@ impl<'a> ClientBuilder<'a> {
             }
             _ => anyhow::bail!("No client connection provided"),
         }
-        if let Some(certificate) = &self.certificate {
-            config.trust_cert_ca(certificate);
-        } else if self.trust_server_certificate {
-            config.trust_cert();
+        if let Some(instance) = self.browsed_instance() {
+            config.port(defaults::SQL_BROWSER_PORT);
+            config.instance_name(instance);
+        }
+        match (&self.ca_bundle, self.tls_mode) {
+            (Some(ca_bundle), _) => {
+                config.encryption(tiberius::EncryptionLevel::Required);
+                config.trust_cert_ca(ca_bundle.display());
+            }
+            (None, Some(mode)) => self.apply_tls_mode(&mut config, mode),
+            (None, None) => {
+                if let Some(certificate) = &self.certificate {
+                    config.trust_cert_ca(certificate);
+                } else if self.trust_server_certificate {
+                    config.trust_cert();
+                }
+            }
+        }
+        if self.read_only {
+            config.readonly(true);
         }
         Ok(config)
     }
 
+    /// named instance of a TCP connection, other connections don't use SQL Browser
+    fn browsed_instance(&self) -> Option<&InstanceName> {
+        match &self.client_connection {
+            Some(ClientConnection::Remote(_)) => self.browse_instance.as_ref(),
+            #[cfg(windows)]
+            Some(ClientConnection::Local(_)) => self.browse_instance.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// `verify-full` is done by the driver on the TLS session of the login: the chain by the
+    /// system store or by `certificate`, the host name by the name of the connection
+    fn apply_tls_mode(&self, config: &mut Config, mode: TlsMode) {
+        config.encryption(match mode {
+            TlsMode::Disabled => tiberius::EncryptionLevel::NotSupported,
+            TlsMode::Prefer => tiberius::EncryptionLevel::On,
+            TlsMode::Require | TlsMode::VerifyFull => tiberius::EncryptionLevel::Required,
+        });
+        match (mode, &self.certificate) {
+            (TlsMode::VerifyFull, Some(certificate)) => config.trust_cert_ca(certificate),
+            (TlsMode::VerifyFull, None) => {}
+            _ => config.trust_cert(),
+        }
+    }
+
     pub async fn build(self) -> Result<UniClient> {
         let tiberius_config = self.make_config()?;
-        match self.client_connection {
-            Some(ClientConnection::Remote(_)) => create_remote_client(tiberius_config).await,
+        let address = match &self.client_connection {
+            Some(ClientConnection::Pipe(pipe)) => pipe.path.clone(),
+            _ => tiberius_config.get_addr(),
+        };
+        let target = fixture::make_target(&address, self.database.as_deref());
+        if fixture::is_replay() {
+            return fixture::replay_client(target);
+        }
+        if self.browsed_instance().is_some() {
+            let client = create_named_instance_client(tiberius_config).await;
+            return fixture::wrap_client(target, client);
+        }
+        let client = match self.client_connection {
+            Some(ClientConnection::Pipe(pipe)) => {
+                connect_via_pipe(tiberius_config, &pipe.path).await
+            }
+            Some(ClientConnection::Remote(_))
+                if self.tls_mode.is_some() || self.ca_bundle.is_some() =>
+            {
+                connect_via_tcp(tiberius_config, self.multi_subnet_failover).await
+            }
+            Some(ClientConnection::Remote(_)) => {
+                create_remote_client(tiberius_config, self.multi_subnet_failover).await
+            }
             #[cfg(windows)]
             Some(ClientConnection::Named(_)) => create_named_instance_client(tiberius_config).await,
             #[cfg(windows)]
-            Some(ClientConnection::Local(_)) => connect_via_tcp(tiberius_config).await,
+            Some(ClientConnection::Local(_)) => {
+                connect_via_tcp(tiberius_config, self.multi_subnet_failover).await
+            }
             _ => anyhow::bail!("No client connection provided"),
-        }
+        };
+        fixture::wrap_client(target, client)
     }
 }
 
 pub enum Credentials<'a> {
-    SqlServer { user: &'a str, password: &'a str },
-    Windows { user: &'a str, password: &'a str },
+    SqlServer {
+        user: &'a str,
+        password: String,
+    },
+    Windows {
+        user: &'a str,
+        password: String,
+    },
+    AadToken {
+        token: String,
+    },
+    /// ticket of the current process, see `prepare_kerberos`, on Windows also gMSA of the service
+    Kerberos,
 }
 
 pub const SQL_LOGIN_ERROR_TAG: &str = "[SQL LOGIN ERROR]";
 pub const SQL_TCP_ERROR_TAG: &str = "[SQL TCP ERROR]";
+pub const SQL_PIPE_ERROR_TAG: &str = "[SQL PIPE ERROR]";
 
 pub async fn connect_main_endpoint(endpoint: &Endpoint) -> Result<UniClient> {
-    connect_custom_endpoint(endpoint, endpoint.port()).await
+    connect_endpoint(endpoint, endpoint.port(), endpoint.conn().browse_instance()).await
 }
 
+/// the default instance if the protocol is `named_pipes` or `shared_memory`
 pub async fn connect_custom_endpoint(endpoint: &Endpoint, port: Port) -> Result<UniClient> {
+    connect_endpoint(endpoint, port, None).await
+}
+
+/// browse_instance: the port is asked from SQL Browser, `port` is not used
+async fn connect_endpoint(
+    endpoint: &Endpoint,
+    port: Port,
+    browse_instance: Option<&InstanceName>,
+) -> Result<UniClient> {
     let (auth, conn) = endpoint.split();
+    match conn.protocol() {
+        Protocol::NamedPipes => return connect_pipe(endpoint, None, None).await,
+        Protocol::SharedMemory => return connect_shared_memory(None, None),
+        Protocol::Tcp => {}
+    }
     let map_elapsed_to_anyhow = |e: tokio::time::error::Elapsed| {
         log::warn!("Timeout: {e} when creating client from config");
         anyhow::anyhow!("Timeout: {e} when creating client from config")
     };
     let client = match auth.auth_type() {
         AuthType::SqlServer | AuthType::Windows => {
-            if let Some(credentials) = obtain_config_credentials(auth) {
+            if let Some(credentials) = obtain_config_credentials(auth).await? {
                 tokio::time::timeout(
                     conn.timeout(),
                     ClientBuilder::new()
                         .logon_on_port(&conn.hostname(), Some(port), credentials)
+                        .database(auth.database())
                         .certificate(conn.tls().map(|t| t.client_certificate().to_owned()))
                         .trust_server_certificate(conn.trust_server_certificate())
+                        .tls_mode(conn.tls_mode())
+                        .ca_bundle(conn.ca_bundle())
+                        .multi_subnet_failover(conn.multi_subnet_failover())
+                        .browse_instance(browse_instance)
                         .build(),
                 )
                 .await
@@ -287,13 +481,56 @@ pub async fn connect_custom_endpoint(endpoint: &Endpoint, port: Port) -> Result<
             }
         }
 
+        AuthType::Kerberos | AuthType::Gmsa => {
+            log_gmsa_identity(auth);
+            tokio::time::timeout(
+                conn.timeout(),
+                ClientBuilder::new()
+                    .logon_on_port(&conn.hostname(), Some(port), Credentials::Kerberos)
+                    .database(auth.database())
+                    .certificate(conn.tls().map(|t| t.client_certificate().to_owned()))
+                    .trust_server_certificate(conn.trust_server_certificate())
+                    .tls_mode(conn.tls_mode())
+                    .ca_bundle(conn.ca_bundle())
+                    .multi_subnet_failover(conn.multi_subnet_failover())
+                    .browse_instance(browse_instance)
+                    .build(),
+            )
+            .await
+            .map_err(map_elapsed_to_anyhow)?
+        }
+
+        AuthType::AzureAd | AuthType::ManagedIdentity => {
+            let credentials = obtain_token_credentials(auth, conn.timeout()).await?;
+            tokio::time::timeout(
+                conn.timeout(),
+                ClientBuilder::new()
+                    .logon_on_port(&conn.hostname(), Some(port), credentials)
+                    .database(auth.database())
+                    .certificate(conn.tls().map(|t| t.client_certificate().to_owned()))
+                    .trust_server_certificate(conn.trust_server_certificate())
+                    .tls_mode(conn.tls_mode())
+                    .ca_bundle(conn.ca_bundle())
+                    .multi_subnet_failover(conn.multi_subnet_failover())
+                    .browse_instance(browse_instance)
+                    .build(),
+            )
+            .await
+            .map_err(map_elapsed_to_anyhow)?
+        }
+
         #[cfg(windows)]
         AuthType::Integrated => tokio::time::timeout(
             LOCAL_TIMEOUT,
             ClientBuilder::new()
                 .local_by_port(Some(port), Some(conn.hostname()))
+                .database(auth.database())
                 .certificate(conn.tls().map(|t| t.client_certificate().to_owned()))
                 .trust_server_certificate(conn.trust_server_certificate())
+                .tls_mode(conn.tls_mode())
+                .ca_bundle(conn.ca_bundle())
+                .multi_subnet_failover(conn.multi_subnet_failover())
+                .browse_instance(browse_instance)
                 .build(),
         )
         .await
@@ -321,13 +558,16 @@ pub async fn connect_custom_instance(
     };
     let client = match auth.auth_type() {
         AuthType::SqlServer | AuthType::Windows => {
-            if let Some(_credentials) = obtain_config_credentials(auth) {
+            if let Some(_credentials) = obtain_config_credentials(auth).await? {
                 tokio::time::timeout(
                     conn.timeout(),
                     ClientBuilder::new()
                         .browse(&conn.hostname(), instance, conn.sql_browser_port())
+                        .database(auth.database())
                         .certificate(conn.tls().map(|t| t.client_certificate().to_owned()))
                         .trust_server_certificate(conn.trust_server_certificate())
+                        .tls_mode(conn.tls_mode())
+                        .ca_bundle(conn.ca_bundle())
                         .build(),
                 )
                 .await
@@ -343,6 +583,8 @@ pub async fn connect_custom_instance(
                 .browse(&constants::LOCAL_HOST, instance, conn.sql_browser_port())
                 .certificate(conn.tls().map(|t| t.client_certificate().to_owned()))
                 .trust_server_certificate(conn.trust_server_certificate())
+                .tls_mode(conn.tls_mode())
+                .ca_bundle(conn.ca_bundle())
                 .build(),
         )
         .await
@@ -354,28 +596,174 @@ pub async fn connect_custom_instance(
     client
 }
 
-pub fn obtain_config_credentials(auth: &config::ms_sql::Authentication) -> Option<Credentials> {
-    match auth.auth_type() {
+/// the pipe of the instance, None - the default one; authentication is the same as over TCP
+pub async fn connect_pipe(
+    endpoint: &Endpoint,
+    instance: Option<&InstanceName>,
+    database: Option<String>,
+) -> Result<UniClient> {
+    let (auth, conn) = endpoint.split();
+    let credentials = match auth.auth_type() {
+        AuthType::SqlServer | AuthType::Windows => obtain_config_credentials(auth)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Not provided credentials"))?,
+        AuthType::Kerberos | AuthType::Gmsa | AuthType::Integrated => {
+            log_gmsa_identity(auth);
+            Credentials::Kerberos
+        }
+        AuthType::AzureAd | AuthType::ManagedIdentity => {
+            obtain_token_credentials(auth, conn.timeout()).await?
+        }
+        _ => anyhow::bail!("Not supported authorization type"),
+    };
+    tokio::time::timeout(
+        conn.timeout(),
+        ClientBuilder::new()
+            .logon_on_pipe(&conn.hostname(), &conn.pipe_path(instance), credentials)
+            .database(database.or_else(|| auth.database().map(str::to_string)))
+            .certificate(conn.tls().map(|t| t.client_certificate().to_owned()))
+            .trust_server_certificate(conn.trust_server_certificate())
+            .tls_mode(conn.tls_mode())
+            .ca_bundle(conn.ca_bundle())
+            .build(),
+    )
+    .await
+    .map_err(|e| anyhow::anyhow!("Timeout: {e} when connecting to pipe"))?
+}
+
+/// the local instance by ODBC forced to shared memory, None - the default one
+pub fn connect_shared_memory(
+    instance: Option<&InstanceName>,
+    database: Option<String>,
+) -> Result<UniClient> {
+    #[cfg(unix)]
+    anyhow::bail!(
+        "Shared memory of `{}` db:`{database:?}` can be used only on Windows",
+        instance.map(|i| i.to_string()).unwrap_or_default()
+    );
+    #[cfg(windows)]
+    {
+        let default = InstanceName::from("MSSQLSERVER");
+        let connection_string = crate::platform::odbc::make_shared_memory_connection_string(
+            instance.unwrap_or(&default),
+            database.as_deref(),
+            None,
+        );
+        if fixture::is_replay() {
+            return fixture::replay_client(connection_string);
+        }
+        fixture::wrap_client(
+            connection_string.clone(),
+            Ok(UniClient::Odbc(OdbcClient::new(connection_string))),
+        )
+    }
+}
+
+/// the password is read now, a store is asked out of the runtime
+pub async fn obtain_config_credentials(
+    auth: &config::ms_sql::Authentication,
+) -> Result<Option<Credentials<'_>>> {
+    Ok(match auth.auth_type() {
         AuthType::SqlServer => Some(Credentials::SqlServer {
             user: auth.username(),
-            password: auth.password().map(|s| s.as_str()).unwrap_or(""),
+            password: obtain_password(auth).await?,
         }),
         #[cfg(windows)]
         AuthType::Windows => Some(Credentials::Windows {
             user: auth.username(),
-            password: auth.password().map(|s| s.as_str()).unwrap_or(""),
+            password: obtain_password(auth).await?,
         }),
         _ => None,
+    })
+}
+
+async fn obtain_password(auth: &config::ms_sql::Authentication) -> Result<String> {
+    let auth = auth.clone();
+    Ok(tokio::task::spawn_blocking(move || auth.password())
+        .await??
+        .unwrap_or_default())
+}
+
+/// Azure AD token of the service principal or of the managed identity of the host
+#[cfg(feature = "azure-ad")]
+pub async fn obtain_token_credentials(
+    auth: &config::ms_sql::Authentication,
+    timeout: std::time::Duration,
+) -> Result<Credentials<'static>> {
+    let token = match auth.auth_type() {
+        AuthType::ManagedIdentity => {
+            aad::obtain_managed_identity_token(auth.identity_client_id(), timeout).await?
+        }
+        _ => {
+            let principal = auth
+                .service_principal()
+                .ok_or_else(|| anyhow::anyhow!("Not provided service principal"))?;
+            aad::obtain_token(principal, timeout).await?
+        }
+    };
+    Ok(Credentials::AadToken { token })
+}
+
+#[cfg(not(feature = "azure-ad"))]
+pub async fn obtain_token_credentials(
+    _auth: &config::ms_sql::Authentication,
+    _timeout: std::time::Duration,
+) -> Result<Credentials<'static>> {
+    anyhow::bail!("Azure AD authentication is not available: built without feature `azure-ad`")
+}
+
+/// GSSAPI picks up the ticket source from the environment: keytab for the initial ticket and
+/// the credential cache, both are process-wide and must be set before the runtime starts
+/// threads, see `Config::kerberos`
+pub fn prepare_kerberos(kerberos: Option<&config::ms_sql::Kerberos>) {
+    if let Some(kerberos) = kerberos {
+        if let Some(keytab) = kerberos.keytab() {
+            std::env::set_var("KRB5_CLIENT_KTNAME", keytab);
+        }
+        if let Some(cache) = kerberos.credential_cache() {
+            std::env::set_var("KRB5CCNAME", cache);
+        }
+    }
+}
+
+/// gMSA needs nothing: SSPI uses the account the service runs under, the password is managed by AD
+/// the driver makes the SPN `MSSQLSvc/<host>:<port>` from the connection
+pub fn log_gmsa_identity(auth: &config::ms_sql::Authentication) {
+    if auth.auth_type() == &AuthType::Gmsa {
+        log::info!(
+            "Using identity of the service `{}\\{}`",
+            std::env::var("USERDOMAIN").unwrap_or_default(),
+            std::env::var("USERNAME").unwrap_or_default(),
+        );
     }
 }
 
+/// SSPI on Windows, GSSAPI on Linux
+#[cfg(any(windows, feature = "kerberos"))]
+fn kerberos_auth_method() -> Result<AuthMethod> {
+    Ok(AuthMethod::Integrated)
+}
+
+#[cfg(not(any(windows, feature = "kerberos")))]
+fn kerberos_auth_method() -> Result<AuthMethod> {
+    anyhow::bail!("Kerberos authentication requires build with feature `kerberos`")
+}
+
 /// Create client for remote MS SQL
-async fn create_remote_client(tiberius_config: Config) -> Result<UniClient> {
+async fn create_remote_client(tiberius_config: Config, parallel: bool) -> Result<UniClient> {
     let mut config = tiberius_config.clone();
     config.encryption(tiberius::EncryptionLevel::Required);
-    match connect_via_tcp(config).await {
+    match connect_via_tcp(config, parallel).await {
         Ok(client) => Ok(client),
         #[cfg(unix)]
+        Err(err) if crypto::is_fips_mode() => {
+            log::warn!(
+                "Encryption is not supported by the host, err is {}. Unencrypted connection is not allowed in FIPS mode",
+                err
+            );
+            Err(err)
+        }
+        #[cfg(unix)]
         Err(err) => {
             log::warn!(
                 "Encryption is not supported by the host, err is {}. Trying without encryption...",
@@ -383,7 +771,7 @@ async fn create_remote_client(tiberius_config: Config) -> Result<UniClient> {
             );
             let mut config = tiberius_config.clone();
             config.encryption(tiberius::EncryptionLevel::NotSupported);
-            Ok(connect_via_tcp(config).await?)
+            Ok(connect_via_tcp(config, parallel).await?)
         }
         #[cfg(windows)]
         Err(err) => {
@@ -397,7 +785,6 @@ async fn create_remote_client(tiberius_config: Config) -> Result<UniClient> {
 }
 
 /// Create client for `named` MS SQL `instance`
-#[cfg(windows)]
 async fn create_named_instance_client(config: Config) -> anyhow::Result<UniClient> {
     log::info!("Named connection to addr {}", config.get_addr());
 
@@ -415,9 +802,15 @@ async fn create_named_instance_client(config: Config) -> anyhow::Result<UniClien
         .map(UniClient::Std)
 }
 
-async fn connect_via_tcp(config: Config) -> Result<UniClient> {
+/// parallel: every address of the host is connected at once, see `connect_any`
+async fn connect_via_tcp(config: Config, parallel: bool) -> Result<UniClient> {
     log::info!("Connecting to addr '{}'...", config.get_addr());
-    let tcp = TcpStream::connect(config.get_addr()).await.map_err(|e| {
+    let tcp = if parallel {
+        connect_any(&config.get_addr()).await
+    } else {
+        TcpStream::connect(config.get_addr()).await
+    }
+    .map_err(|e| {
         anyhow::anyhow!(
             "{} address:{} error:`{}`",
             SQL_TCP_ERROR_TAG,
@@ -442,6 +835,56 @@ async fn connect_via_tcp(config: Config) -> Result<UniClient> {
     result.map(UniClient::Std)
 }
 
+/// as MultiSubnetFailover of ADO.NET: after failover of the AG the listener name resolves to
+/// addresses in several subnets, only one of them is online
+async fn connect_any(address: &str) -> std::io::Result<TcpStream> {
+    let addresses = tokio::net::lookup_host(address).await?.collect::<Vec<_>>();
+    if addresses.is_empty() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("{address} has no addresses"),
+        ));
+    }
+    log::info!(
+        "Connecting to {} addresses of '{address}' in parallel",
+        addresses.len()
+    );
+    // the losers are dropped, i.e. their connection attempts are cancelled
+    let (tcp, _) = futures::future::select_ok(
+        addresses
+            .into_iter()
+            .map(|a| Box::pin(TcpStream::connect(a))),
+    )
+    .await?;
+    Ok(tcp)
+}
+
+#[cfg(windows)]
+async fn connect_via_pipe(config: Config, path: &str) -> Result<UniClient> {
+    use tokio::net::windows::named_pipe::ClientOptions;
+    const ERROR_PIPE_BUSY: i32 = 231;
+    log::info!("Connecting to pipe '{path}'...");
+    let pipe = loop {
+        match ClientOptions::new().open(path) {
+            Ok(pipe) => break pipe,
+            Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY) => {
+                tokio::time::sleep(PIPE_BUSY_DELAY).await
+            }
+            Err(e) => anyhow::bail!("{} pipe:{} error:`{}`", SQL_PIPE_ERROR_TAG, path, e),
+        }
+    };
+    log::info!("Connected to pipe '{path}'");
+    PipeClient::connect(config, pipe.compat_write())
+        .await
+        .map_err(|e| anyhow::anyhow!("{} {}", SQL_LOGIN_ERROR_TAG, e))
+        .map(UniClient::Pipe)
+}
+
+#[cfg(unix)]
+async fn connect_via_pipe(_config: Config, path: &str) -> Result<UniClient> {
+    anyhow::bail!("Named pipe `{path}` can be used only on Windows")
+}
+
 /// Create `local` connection to MS SQL `instance`
 ///
 /// # Arguments
@@ -507,12 +950,20 @@ mssql:
         assert!(s.contains("Connection refused"), "{s}");
     }
 
-    #[test]
-    fn test_obtain_credentials_from_config() {
+    #[tokio::test]
+    async fn test_obtain_credentials_from_config() {
         #[cfg(windows)]
-        assert!(obtain_config_credentials(make_config_with_auth_type("windows").auth()).is_some());
         assert!(
-            obtain_config_credentials(make_config_with_auth_type("sql_server").auth()).is_some()
+            obtain_config_credentials(make_config_with_auth_type("windows").auth())
+                .await
+                .unwrap()
+                .is_some()
+        );
+        assert!(
+            obtain_config_credentials(make_config_with_auth_type("sql_server").auth())
+                .await
+                .unwrap()
+                .is_some()
         );
     }
 
@@ -552,7 +1003,7 @@ mssql:
     fn test_client_builder_remote() {
         let credentials = Credentials::SqlServer {
             user: "u",
-            password: "p",
+            password: "p".to_string(),
         };
         let remote = ClientBuilder::new();
         assert!(remote.client_connection.is_none());
@@ -568,6 +1019,47 @@ mssql:
             }))
         ));
     }
+
+    #[test]
+    fn test_client_builder_pipe() {
+        let credentials = Credentials::SqlServer {
+            user: "u",
+            password: "p".to_string(),
+        };
+        let host: HostName = "host".to_owned().into();
+        let builder =
+            ClientBuilder::new().logon_on_pipe(&host, r"\\host\pipe\sql\query", credentials);
+        assert!(matches!(
+            &builder.client_connection,
+            Some(ClientConnection::Pipe(PipeConnection { path, .. })) if path == r"\\host\pipe\sql\query"
+        ));
+        assert_eq!(builder.make_config().unwrap().get_addr(), "host:1433");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_connect_any() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let tcp = connect_any(&format!("localhost:{port}")).await.unwrap();
+        assert_eq!(tcp.peer_addr().unwrap().port(), port);
+        drop(listener);
+        assert!(connect_any(&format!("127.0.0.1:{port}")).await.is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_connect_shared_memory_unix() {
+        let e = connect_shared_memory(Some(&InstanceName::from("SQLEXPRESS")), None).unwrap_err();
+        assert!(e.to_string().contains("only on Windows"), "{e}");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_connect_pipe_unix() {
+        let endpoint = Endpoint::default();
+        let e = connect_pipe(&endpoint, None, None).await.unwrap_err();
+        assert!(e.to_string().contains("only on Windows"), "{e}");
+    }
     #[cfg(windows)]
     #[test]
     fn test_client_builder_local_instance() {
@@ -2,8 +2,10 @@
 // This file is part of Checkmk (https://checkmk.com). It is subject to the terms and
 // conditions defined in the file COPYING, which is part of this source code package.
 
-use crate::constants::CONFIG_DIR;
-use std::path::PathBuf;
+use crate::constants::{CONFIG_DIR, SQL_VARS_EXTENSION};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
 pub const MSSQL_CUSTOM_SQL_SUB_DIR: &str = "mssql";
 
@@ -15,3 +17,132 @@ pub fn get_sql_dir() -> Option<PathBuf> {
         None
     }
 }
+
+/// Reads the query file converting sqlcmd script into a single batch:
+/// - `:setvar NAME value` defines a variable, other `:` commands are ignored
+/// - `$(NAME)` is replaced, precedence as in sqlcmd: `:setvar`, vars file, environment
+/// - `GO` separators are removed
+pub fn read_sql_file(path: &Path) -> Result<String> {
+    let text = std::fs::read_to_string(path)?;
+    let vars_file = path.with_extension(SQL_VARS_EXTENSION);
+    let vars = if vars_file.is_file() {
+        parse_vars(&std::fs::read_to_string(&vars_file)?)
+    } else {
+        HashMap::new()
+    };
+    expand_sqlcmd(&text, &vars, |name| std::env::var(name).ok())
+}
+
+/// `NAME=value` per line, `#` starts a comment line
+fn parse_vars(text: &str) -> HashMap<String, String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .filter_map(|l| l.split_once('='))
+        .map(|(name, value)| (name.trim().to_uppercase(), unquote(value.trim())))
+        .collect()
+}
+
+fn unquote(value: &str) -> String {
+    value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value)
+        .to_string()
+}
+
+fn expand_sqlcmd(
+    text: &str,
+    vars: &HashMap<String, String>,
+    env: impl Fn(&str) -> Option<String>,
+) -> Result<String> {
+    let mut defined = vars.clone();
+    let mut lines: Vec<&str> = Vec::new();
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if trimmed.eq_ignore_ascii_case("go") {
+            continue;
+        }
+        if let Some(command) = trimmed.strip_prefix(':') {
+            match command.split_once(char::is_whitespace) {
+                Some((c, rest)) if c.eq_ignore_ascii_case("setvar") => {
+                    let (name, value) = rest
+                        .trim()
+                        .split_once(char::is_whitespace)
+                        .unwrap_or((rest.trim(), ""));
+                    defined.insert(name.to_uppercase(), unquote(value.trim()));
+                }
+                _ => log::warn!("sqlcmd command `{trimmed}` is not supported, ignored"),
+            }
+            continue;
+        }
+        lines.push(line);
+    }
+    let mut result = String::new();
+    for line in lines {
+        let mut rest = line;
+        while let Some(start) = rest.find("$(") {
+            let end = rest[start..]
+                .find(')')
+                .ok_or_else(|| anyhow::anyhow!("Unterminated variable in `{line}`"))?;
+            let name = &rest[start + 2..start + end];
+            let value = defined
+                .get(&name.to_uppercase())
+                .cloned()
+                .or_else(|| env(name))
+                .ok_or_else(|| anyhow::anyhow!("Variable `{name}` is not defined"))?;
+            result += &rest[..start];
+            result += &value;
+            rest = &rest[start + end + 1..];
+        }
+        result += rest;
+        result.push('\n');
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCRIPT: &str = r#":setvar Threshold 80
+:on error exit
+SELECT name FROM $(DbName).sys.tables
+WHERE size > $(threshold) AND owner = '$(OWNER)'
+GO
+"#;
+
+    #[test]
+    fn test_parse_vars() {
+        let vars = parse_vars("# comment\ndbname = \"master\"\nbroken\n\nx=a=b\n");
+        assert_eq!(vars.len(), 2);
+        assert_eq!(vars["DBNAME"], "master");
+        assert_eq!(vars["X"], "a=b");
+    }
+
+    #[test]
+    fn test_expand_sqlcmd() {
+        let vars = parse_vars("DbName=msdb\nThreshold=10");
+        let env = |name: &str| (name == "OWNER").then(|| "dbo".to_string());
+        assert_eq!(
+            expand_sqlcmd(SCRIPT, &vars, env).unwrap(),
+            "SELECT name FROM msdb.sys.tables\nWHERE size > 80 AND owner = 'dbo'\n"
+        );
+        assert!(expand_sqlcmd(SCRIPT, &vars, |_| None).is_err());
+        assert!(expand_sqlcmd("SELECT $(x", &vars, |_| None).is_err());
+        assert_eq!(
+            expand_sqlcmd("SELECT 1", &HashMap::new(), |_| None).unwrap(),
+            "SELECT 1\n"
+        );
+    }
+
+    #[test]
+    fn test_read_sql_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let sql = dir.path().join("check.sql");
+        std::fs::write(&sql, "SELECT '$(A)'").unwrap();
+        assert!(read_sql_file(&sql).is_err());
+        std::fs::write(dir.path().join("check.vars"), "A=1").unwrap();
+        assert_eq!(read_sql_file(&sql).unwrap(), "SELECT '1'\n");
+    }
+}
@@ -0,0 +1,389 @@
+// Copyright (C) 2024 Checkmk GmbH - License: GNU General Public License v2
+// This file is part of Checkmk (https://checkmk.com). It is subject to the terms and
+// conditions defined in the file COPYING, which is part of this source code package.
+
+//! Record/replay of query results
+//! - record: every query result of a real run is stored in a fixture file
+//! - replay: no database connection is made, all results are taken from the fixture file
+//!
+//! Results are stored as text blocks(the same as ODBC delivers), in record mode the text blocks
+//! are used for output too, thus recorded and replayed output are identical.
+//! With anonymization names in the fixture file are pseudonymized, see `anonymize`: the run
+//! itself uses real data, replay of the file gives the pseudonymized output.
+
+use super::anonymize::Anonymizer;
+use super::client::UniClient;
+use super::query::{self, UniAnswer};
+use super::value::{self, Value};
+use crate::platform::Block;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tiberius::Row;
+use yaml_rust2::yaml::Hash;
+use yaml_rust2::{Yaml, YamlEmitter, YamlLoader};
+
+mod keys {
+    pub const TARGET: &str = "target";
+    pub const QUERY: &str = "query";
+    pub const ERROR: &str = "error";
+    pub const ANSWERS: &str = "answers";
+    pub const HEADLINE: &str = "headline";
+    pub const ROWS: &str = "rows";
+}
+
+/// Query text used to store connection errors
+const CONNECT: &str = "";
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Mode {
+    Record,
+    Replay,
+}
+
+type Entry = std::result::Result<Vec<Block>, String>;
+
+struct Fixture {
+    mode: Mode,
+    file: PathBuf,
+    anonymize: bool,
+    entries: HashMap<(String, String), Entry>,
+}
+
+lazy_static::lazy_static! {
+    static ref FIXTURE: Mutex<Option<Fixture>> = Mutex::new(None);
+}
+
+#[derive(Debug)]
+pub struct FixtureClient {
+    target: String,
+    inner: Option<Box<UniClient>>,
+}
+
+pub fn init(record: Option<&Path>, replay: Option<&Path>, anonymize: bool) -> Result<()> {
+    let fixture = match (record, replay) {
+        (Some(_), Some(_)) => anyhow::bail!("Record and replay can't be used together"),
+        (Some(file), None) => Fixture {
+            mode: Mode::Record,
+            file: file.to_owned(),
+            anonymize,
+            entries: HashMap::new(),
+        },
+        (None, Some(_)) if anonymize => anyhow::bail!("Anonymization is possible only in record"),
+        (None, Some(file)) => Fixture {
+            mode: Mode::Replay,
+            file: file.to_owned(),
+            anonymize,
+            entries: load(file)?,
+        },
+        (None, None) if anonymize => anyhow::bail!("Anonymization requires record of fixture"),
+        (None, None) => return Ok(()),
+    };
+    log::info!("Fixture {:?} mode, file {:?}", fixture.mode, fixture.file);
+    *FIXTURE.lock().unwrap() = Some(fixture);
+    Ok(())
+}
+
+fn mode() -> Option<Mode> {
+    FIXTURE.lock().unwrap().as_ref().map(|f| f.mode)
+}
+
+pub fn is_replay() -> bool {
+    mode() == Some(Mode::Replay)
+}
+
+pub fn make_target(address: &str, database: Option<&str>) -> String {
+    format!(
+        "{}/{}",
+        address.to_lowercase(),
+        database.unwrap_or_default()
+    )
+}
+
+/// returns client without any database connection
+pub fn replay_client(target: String) -> Result<UniClient> {
+    match find(&target, CONNECT) {
+        Some(Err(e)) => anyhow::bail!(e),
+        _ => Ok(UniClient::Fixture(FixtureClient {
+            target,
+            inner: None,
+        })),
+    }
+}
+
+/// in record mode wraps the real client to intercept all queries
+pub fn wrap_client(target: String, client: Result<UniClient>) -> Result<UniClient> {
+    if mode() != Some(Mode::Record) {
+        return client;
+    }
+    match client {
+        Ok(c) => Ok(UniClient::Fixture(FixtureClient {
+            target,
+            inner: Some(Box::new(c)),
+        })),
+        Err(e) => {
+            store(&target, CONNECT, Err(e.to_string()));
+            Err(e)
+        }
+    }
+}
+
+pub async fn exec(client: &mut FixtureClient, sql: &str) -> Result<Vec<UniAnswer>> {
+    match client.inner.as_mut() {
+        Some(inner) => {
+            let entry = query::exec_native(inner, sql)
+                .await
+                .map(|answers| answers.into_iter().map(to_block).collect::<Vec<Block>>())
+                .map_err(|e| e.to_string());
+            store(&client.target, sql, entry.clone());
+            to_answers(entry)
+        }
+        None => match find(&client.target, sql) {
+            Some(entry) => to_answers(entry),
+            None => anyhow::bail!("Query not found in fixture for `{}`", client.target),
+        },
+    }
+}
+
+/// writes recorded results in file, does nothing in other modes
+pub fn save() {
+    if let Some(fixture) = FIXTURE.lock().unwrap().as_ref() {
+        if fixture.mode == Mode::Record {
+            let text = if fixture.anonymize {
+                to_yaml_text(&anonymize(&fixture.entries))
+            } else {
+                to_yaml_text(&fixture.entries)
+            };
+            match std::fs::write(&fixture.file, text) {
+                Ok(_) => log::info!("Fixture saved in {:?}", fixture.file),
+                Err(e) => log::error!("Failed to save fixture {:?}: {e}", fixture.file),
+            }
+        }
+    }
+}
+
+fn anonymize(entries: &HashMap<(String, String), Entry>) -> HashMap<(String, String), Entry> {
+    let mut anonymizer = Anonymizer::default();
+    entries
+        .iter()
+        .flat_map(|((_, sql), e)| e.iter().flatten().map(move |b| (sql, b)))
+        .for_each(|(sql, b)| anonymizer.learn(sql, b));
+    entries
+        .iter()
+        .map(|((target, sql), entry)| {
+            let key = (anonymizer.text(target), anonymizer.sql(sql));
+            let entry = match entry {
+                Ok(blocks) => Ok(blocks.iter().map(|b| anonymizer.block(b)).collect()),
+                Err(e) => Err(anonymizer.text(e)),
+            };
+            (key, entry)
+        })
+        .collect()
+}
+
+fn store(target: &str, sql: &str, entry: Entry) {
+    if let Some(fixture) = FIXTURE.lock().unwrap().as_mut() {
+        fixture
+            .entries
+            .insert((target.to_owned(), sql.to_owned()), entry);
+    }
+}
+
+fn find(target: &str, sql: &str) -> Option<Entry> {
+    FIXTURE
+        .lock()
+        .unwrap()
+        .as_ref()
+        .and_then(|f| f.entries.get(&(target.to_owned(), sql.to_owned())).cloned())
+}
+
+fn to_answers(entry: Entry) -> Result<Vec<UniAnswer>> {
+    entry
+        .map(|blocks| blocks.into_iter().map(UniAnswer::Block).collect())
+        .map_err(|e| anyhow::anyhow!(e))
+}
+
+fn to_block(answer: UniAnswer) -> Block {
+    match answer {
+        UniAnswer::Block(block) => block,
+        UniAnswer::Rows(rows) => Block {
+            headline: rows
+                .first()
+                .map(|r| r.columns().iter().map(|c| c.name().to_string()).collect())
+                .unwrap_or_default(),
+            rows: rows.into_iter().map(row_to_strings).collect(),
+        },
+    }
+}
+
+fn row_to_strings(row: Row) -> Vec<String> {
+    value::to_values(row).iter().map(Value::to_text).collect()
+}
+
+fn to_yaml_text(entries: &HashMap<(String, String), Entry>) -> String {
+    let mut sorted = entries.iter().collect::<Vec<_>>();
+    sorted.sort_by(|a, b| a.0.cmp(b.0));
+    let yaml = Yaml::Array(
+        sorted
+            .into_iter()
+            .map(|((target, sql), entry)| to_yaml_entry(target, sql, entry))
+            .collect(),
+    );
+    let mut text = String::new();
+    YamlEmitter::new(&mut text)
+        .dump(&yaml)
+        .unwrap_or_else(|e| log::error!("Failed to dump fixture: {e}"));
+    text + "\n"
+}
+
+fn to_yaml_entry(target: &str, sql: &str, entry: &Entry) -> Yaml {
+    let string = |s: &str| Yaml::String(s.to_owned());
+    let strings = |v: &[String]| Yaml::Array(v.iter().map(|s| string(s)).collect());
+    let mut hash = Hash::new();
+    hash.insert(string(keys::TARGET), string(target));
+    hash.insert(string(keys::QUERY), string(sql));
+    match entry {
+        Ok(blocks) => {
+            let answers = blocks
+                .iter()
+                .map(|b| {
+                    let mut block = Hash::new();
+                    block.insert(string(keys::HEADLINE), strings(&b.headline));
+                    block.insert(
+                        string(keys::ROWS),
+                        Yaml::Array(b.rows.iter().map(|r| strings(r)).collect()),
+                    );
+                    Yaml::Hash(block)
+                })
+                .collect();
+            hash.insert(string(keys::ANSWERS), Yaml::Array(answers));
+        }
+        Err(e) => {
+            hash.insert(string(keys::ERROR), string(e));
+        }
+    }
+    Yaml::Hash(hash)
+}
+
+fn load(file: &Path) -> Result<HashMap<(String, String), Entry>> {
+    let text = std::fs::read_to_string(file)
+        .map_err(|e| anyhow::anyhow!("Can't read fixture file {:?}: {e}", file))?;
+    from_yaml_text(&text)
+}
+
+fn from_yaml_text(text: &str) -> Result<HashMap<(String, String), Entry>> {
+    let docs = YamlLoader::load_from_str(text)?;
+    let entries = docs
+        .first()
+        .and_then(|d| d.as_vec())
+        .ok_or_else(|| anyhow::anyhow!("Fixture is not a list"))?;
+    Ok(entries
+        .iter()
+        .map(|e| {
+            let key = (to_string(&e[keys::TARGET]), to_string(&e[keys::QUERY]));
+            let entry = if e[keys::ERROR].is_badvalue() {
+                Ok(to_vec(&e[keys::ANSWERS])
+                    .iter()
+                    .map(|b| Block {
+                        headline: to_strings(&b[keys::HEADLINE]),
+                        rows: to_vec(&b[keys::ROWS]).iter().map(to_strings).collect(),
+                    })
+                    .collect())
+            } else {
+                Err(to_string(&e[keys::ERROR]))
+            };
+            (key, entry)
+        })
+        .collect())
+}
+
+fn to_vec(yaml: &Yaml) -> Vec<Yaml> {
+    yaml.as_vec().cloned().unwrap_or_default()
+}
+
+fn to_strings(yaml: &Yaml) -> Vec<String> {
+    to_vec(yaml).iter().map(to_string).collect()
+}
+
+/// emitter may store numbers-like values without quotes
+fn to_string(yaml: &Yaml) -> String {
+    match yaml {
+        Yaml::String(s) | Yaml::Real(s) => s.clone(),
+        Yaml::Integer(i) => i.to_string(),
+        Yaml::Boolean(b) => b.to_string(),
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ms_sql::sqls;
+
+    #[test]
+    fn test_yaml_round_trip() {
+        let mut entries: HashMap<(String, String), Entry> = HashMap::new();
+        entries.insert(
+            ("host:1433/".to_string(), "SELECT 1\nFROM x".to_string()),
+            Ok(vec![Block {
+                headline: vec!["a".to_string(), "b".to_string()],
+                rows: vec![
+                    vec!["1".to_string(), "true".to_string()],
+                    vec!["".to_string(), "x y".to_string()],
+                ],
+            }]),
+        );
+        entries.insert(
+            ("host:1433/db".to_string(), CONNECT.to_string()),
+            Err("login failed".to_string()),
+        );
+        let restored = from_yaml_text(&to_yaml_text(&entries)).unwrap();
+        assert_eq!(restored.len(), 2);
+        let blocks = restored
+            .get(&("host:1433/".to_string(), "SELECT 1\nFROM x".to_string()))
+            .unwrap()
+            .as_ref()
+            .unwrap();
+        assert_eq!(blocks[0].headline, ["a", "b"]);
+        assert_eq!(blocks[0].rows, [["1", "true"], ["", "x y"]]);
+        assert_eq!(
+            restored
+                .get(&("host:1433/db".to_string(), CONNECT.to_string()))
+                .unwrap()
+                .as_ref()
+                .unwrap_err(),
+            "login failed"
+        );
+    }
+
+    #[test]
+    fn test_anonymize() {
+        let mut entries: HashMap<(String, String), Entry> = HashMap::new();
+        entries.insert(
+            (
+                "host:1433/".to_string(),
+                sqls::query::DATABASE_NAMES.to_string(),
+            ),
+            Ok(vec![Block {
+                headline: vec!["name".to_string()],
+                rows: vec![vec!["Secret".to_string()], vec!["msdb".to_string()]],
+            }]),
+        );
+        entries.insert(
+            ("host:1433/Secret".to_string(), "USE [Secret]".to_string()),
+            Err("no access to Secret".to_string()),
+        );
+        let text = to_yaml_text(&anonymize(&entries));
+        assert!(!text.contains("Secret"));
+        assert!(text.contains("msdb"));
+        assert!(text.contains(sqls::query::DATABASE_NAMES));
+        assert_eq!(from_yaml_text(&text).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_make_target() {
+        assert_eq!(make_target("Host:1433", Some("db")), "host:1433/db");
+        assert_eq!(make_target("host:1433", None), "host:1433/");
+    }
+}
@@ -6,22 +6,32 @@
 use super::client::OdbcClient;
 use super::client::{self, UniClient};
 use super::custom::get_sql_dir;
+use super::fixture;
+use super::pool::ClientPool;
+use super::registry;
+use super::retry::{self, Backoff};
+use super::scheduler::Scheduler;
 use super::section::{Section, SectionKind};
+use super::settings::generate_effective_settings_section;
+use super::setup_errors::generate_setup_errors_section;
+use super::state_gc;
+use super::trace;
+use crate::api::{Emitter, MsSqlRunner, SectionRunner};
 use crate::config::defines::defaults::MAX_CONNECTIONS;
 use crate::config::ms_sql::{is_local_host, is_use_tcp, Discovery};
 use crate::config::section;
 use crate::config::{
     self,
-    ms_sql::{AuthType, CustomInstance, Endpoint},
+    ms_sql::{ApplicationIntent, AuthType, CustomInstance, Endpoint, Output, Protocol},
     section::names,
     CheckConfig,
 };
-use crate::emit;
+use crate::emit::{self, Trailer};
 use crate::ms_sql::query::{
-    obtain_computer_name, obtain_instance_name, obtain_system_user, run_custom_query,
+    obtain_computer_name, obtain_instance_name, obtain_system_user, render_text, run_custom_query,
     run_known_query, Column, UniAnswer,
 };
-use crate::ms_sql::sqls;
+use crate::ms_sql::sqls::{self, Permission};
 #[cfg(windows)]
 use crate::platform::odbc;
 use crate::setup::Env;
@@ -35,14 +45,24 @@ use std::thread;
 
 use anyhow::Result;
 use futures::stream::{self, StreamExt};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::Path;
+use std::sync::Mutex;
+use std::time::Instant;
 
-use crate::platform::{get_row_value_by_idx, Block};
+use crate::constants;
+use crate::platform::{self, get_row_value_by_idx, Block};
 use tiberius::Row;
 
 pub const SQL_LOGIN_ERROR_TAG: &str = "[SQL LOGIN ERROR]";
 pub const SQL_TCP_ERROR_TAG: &str = "[SQL TCP ERROR]";
+/// sections of the instance generated with reduced queries because of missing permissions
+pub const DEGRADED_SECTION_NAME: &str = "degraded";
+pub const AG_REPORTING_SECTION_NAME: &str = "ag_reporting";
+/// value of the `source` entry of counters collected from Windows performance counters
+pub const PDH_SOURCE: &str = "pdh";
+/// piggyback section of a tenant
+pub const TENANT_USAGE_SECTION_NAME: &str = "tenant_usage";
 
 #[derive(Clone, Debug, Default)]
 pub struct SqlInstanceBuilder {
@@ -59,6 +79,7 @@ pub struct SqlInstanceBuilder {
     environment: Option<Env>,
     cache_dir: Option<String>,
     piggyback: Option<PiggybackHostName>,
+    output: Option<Output>,
 }
 
 impl SqlInstanceBuilder {
@@ -119,6 +140,10 @@ impl SqlInstanceBuilder {
         self.piggyback = piggyback.map(|s| s.to_string().to_lowercase().into());
         self
     }
+    pub fn output(mut self, output: Option<Output>) -> Self {
+        self.output = output;
+        self
+    }
 
     pub fn from_row(self, row: &Row) -> Self {
         self.name(row.get_value_by_idx(0))
@@ -184,6 +209,7 @@ impl SqlInstanceBuilder {
             environment: self.environment.unwrap_or_default(),
             cache_dir: self.cache_dir.unwrap_or_default(),
             piggyback: self.piggyback,
+            output: self.output,
             version_table,
             tcp,
         }
@@ -219,6 +245,7 @@ pub struct SqlInstance {
     environment: Env,
     cache_dir: String,
     piggyback: Option<PiggybackHostName>,
+    output: Option<Output>,
     version_table: [u32; 3],
     pub tcp: bool,
 }
@@ -280,6 +307,19 @@ impl SqlInstance {
         &self.piggyback
     }
 
+    pub fn output(&self) -> Option<&Output> {
+        self.output.as_ref()
+    }
+
+    /// output of the instance is printed to stdout, it may be routed to a file only
+    pub fn is_printed(&self) -> bool {
+        self.output.as_ref().map_or(true, |o| o.stdout())
+    }
+
+    pub fn endpoint(&self) -> &Endpoint {
+        &self.endpoint
+    }
+
     pub fn hostname(&self) -> HostName {
         self.endpoint.hostname()
     }
@@ -336,13 +376,14 @@ impl SqlInstance {
             .to_owned()
     }
 
-    pub async fn generate_sections(&self, sections: &[Section]) -> String {
+    pub async fn generate_sections(&self, sections: &[Section], scheduler: &Scheduler) -> String {
         let header = self.generate_header();
 
         // if yes - call generate_section with database parameter
         // else - call generate_section without database parameter
         log::trace!("{:?} @ {:?}", self, self.endpoint);
-        let body = match self.create_client(&self.endpoint, None).await {
+        let backoff = Backoff::new(self.endpoint.conn().retry(), scheduler.deadline());
+        let body = match retry::run(backoff, || self.create_client(&self.endpoint, None)).await {
             Ok(mut client) => {
                 let real_name = obtain_instance_name(&mut client)
                     .await
@@ -369,7 +410,7 @@ impl SqlInstance {
                     instance_section.to_plain_header()
                         + &self.generate_bad_state_entry(instance_section.sep(), &error_text)
                 } else {
-                    self._generate_sections(&mut client, &self.endpoint, sections)
+                    self._generate_sections(&mut client, &self.endpoint, sections, scheduler)
                         .await
                 }
             }
@@ -387,12 +428,8 @@ impl SqlInstance {
     /// Gather databases based on sections content: only if any of sections is database based
     async fn gather_databases(&self, client: &mut UniClient, sections: &[Section]) -> Vec<String> {
         let database_based_sections = section::get_per_database_sections();
-        let need = database_based_sections.iter().any(|s| {
-            sections
-                .iter()
-                .map(|s| s.name().to_string())
-                .collect::<Vec<String>>()
-                .contains(s)
+        let need = sections.iter().any(|s| {
+            database_based_sections.contains(&s.name().to_string()) || s.name() == names::BACKUP
         });
         if need {
             self.generate_databases(client).await
@@ -401,21 +438,109 @@ impl SqlInstance {
         }
     }
 
+    /// Gather data shared by sections, every query is made once per instance
+    async fn gather_context(
+        &self,
+        client: &mut UniClient,
+        endpoint: &Endpoint,
+        sections: &[Section],
+    ) -> InstanceContext {
+        let properties = SqlInstanceProperties::obtain_by_query(client)
+            .await
+            .map_err(|e| log::error!("Failed to get sql instance properties: {e}"))
+            .ok();
+        let version_major = properties
+            .as_ref()
+            .map(|p| parse_version(&Some(p.version.clone()))[0])
+            .unwrap_or_else(|| self.version_major());
+        let contained_ags = if version_major >= 16 {
+            self.count_contained_ags(client)
+                .await
+                .map_err(|e| log::warn!("Failed to get contained availability groups: {e}"))
+                .ok()
+        } else {
+            None
+        };
+        let mut databases = self.gather_databases(client, sections).await;
+        if properties.as_ref().map_or(false, |p| p.is_contained_user()) {
+            log::info!(
+                "Instance {}: contained database user, only own database is monitored",
+                self.name
+            );
+            databases.retain(|d| Some(d.as_str()) == endpoint.auth().database());
+        }
+        let is_clustered = if sections.iter().any(|s| s.name() == names::CLUSTERS) {
+            self.is_instance_clustered(client)
+                .await
+                .map_err(|e| log::warn!("Failed to get cluster state: {e}"))
+                .ok()
+        } else {
+            None
+        };
+        InstanceContext {
+            properties,
+            is_clustered,
+            contained_ags,
+            databases,
+            degraded: Mutex::new(vec![]),
+            pool: ClientPool::default(),
+            deadline: None,
+        }
+    }
+
     async fn _generate_sections(
         &self,
         client: &mut UniClient,
         endpoint: &Endpoint,
         sections: &[Section],
+        scheduler: &Scheduler,
     ) -> String {
-        let mut data: Vec<String> = Vec::new();
-        let databases = self.gather_databases(client, sections).await;
-        for section in sections.iter() {
-            data.push(
-                self.generate_section(client, endpoint, section, &databases)
+        let mut data: Vec<Option<String>> = vec![None; sections.len()];
+        let mut context = self.gather_context(client, endpoint, sections).await;
+        context.deadline = scheduler.deadline();
+        let order = scheduler.order(sections);
+        for (pos, &idx) in order.iter().enumerate() {
+            let section = &sections[idx];
+            let version_major = context.version_major().unwrap_or(self.version_major());
+            if !section.is_supported(version_major) {
+                log::info!(
+                    "Section `{}` of `{}` is skipped: not supported by version {version_major}",
+                    section.name(),
+                    self.name
+                );
+                continue;
+            }
+            if !context.is_accessible(section) {
+                log::info!(
+                    "Section `{}` of `{}` is skipped: not accessible for contained database user",
+                    section.name(),
+                    self.name
+                );
+                continue;
+            }
+            let pending = order[pos..]
+                .iter()
+                .map(|&i| &sections[i])
+                .collect::<Vec<&Section>>();
+            if scheduler.must_defer(section, &pending) {
+                log::warn!(
+                    "Section `{}` of `{}` is deferred to the next run: run budget is exhausted",
+                    section.name(),
+                    self.name
+                );
+                continue;
+            }
+            let _permit = scheduler
+                .acquire(&endpoint.hostname().to_string(), section)
+                .await;
+            data[idx] = Some(
+                self.generate_section(client, endpoint, section, &context)
                     .await,
             );
         }
-        data.join("")
+        // output keeps the configured order
+        data.into_iter().flatten().collect::<Vec<String>>().join("")
+            + &context.generate_degraded_section(&self.name)
     }
 
     /// Create a client for an Instance based on Config
@@ -431,13 +556,74 @@ impl SqlInstance {
             endpoint.auth().username(),
             endpoint.conn().hostname()
         );
-        if self.tcp {
-            create_tcp_client(endpoint, database, self.port()).await
+        if endpoint.conn().protocol() == Protocol::NamedPipes {
+            client::connect_pipe(endpoint, Some(&self.name), database).await
+        } else if endpoint.conn().protocol() == Protocol::SharedMemory {
+            client::connect_shared_memory(Some(&self.name), database)
+        } else if self.tcp {
+            create_tcp_client(endpoint, database, self.port(), false).await
         } else {
             create_odbc_client(&self.name, database)
         }
     }
 
+    /// intent of the section or, if not set, of the connection
+    fn section_intent(&self, endpoint: &Endpoint, section: &Section) -> ApplicationIntent {
+        section
+            .application_intent()
+            .unwrap_or_else(|| endpoint.conn().application_intent())
+    }
+
+    /// idle connection of the pool is used if available
+    /// read-only intent is tried first: AG listener routes such connection to a readable
+    /// secondary, on failure the primary is used
+    async fn create_section_client(
+        &self,
+        endpoint: &Endpoint,
+        section: &Section,
+        context: &InstanceContext,
+        database: Option<String>,
+    ) -> Result<UniClient> {
+        let intent = self.section_intent(endpoint, section);
+        if let Some(client) = context.pool.take(database.as_deref(), intent) {
+            return Ok(client);
+        }
+        if intent == ApplicationIntent::ReadOnly
+            && self.tcp
+            && endpoint.conn().protocol() == Protocol::Tcp
+        {
+            match create_tcp_client(endpoint, database.clone(), self.port(), true).await {
+                Ok(client) => return Ok(client),
+                Err(e) => log::warn!(
+                    "Section `{}` of `{}`: readable secondary is not available, using primary: {e}",
+                    section.name(),
+                    self.name
+                ),
+            }
+        }
+        let backoff = Backoff::new(endpoint.conn().retry(), context.deadline);
+        retry::run(backoff, || self.create_client(endpoint, database.clone())).await
+    }
+
+    /// transient failures are retried as configured by `connection.retry`
+    async fn run_query_with_retry(
+        &self,
+        client: &mut UniClient,
+        context: &InstanceContext,
+        query: &str,
+    ) -> Result<Vec<UniAnswer>> {
+        let mut backoff = Backoff::new(self.endpoint.conn().retry(), context.deadline);
+        loop {
+            match run_custom_query(&mut *client, query).await {
+                Err(e) => match backoff.next_delay(&e) {
+                    Some(delay) => tokio::time::sleep(delay).await,
+                    None => return Err(e),
+                },
+                answers => return answers,
+            }
+        }
+    }
+
     pub async fn generate_details_entry(&self, client: &mut UniClient, sep: char) -> String {
         let r = SqlInstanceProperties::obtain_by_query(client).await;
         match r {
@@ -449,6 +635,19 @@ impl SqlInstance {
         }
     }
 
+    /// uses already gathered properties, queries server only if absent
+    async fn generate_details_entry_from_context(
+        &self,
+        client: &mut UniClient,
+        context: &InstanceContext,
+        sep: char,
+    ) -> String {
+        match &context.properties {
+            Some(properties) => self.process_details_rows(properties, sep),
+            None => self.generate_details_entry(client, sep).await,
+        }
+    }
+
     pub fn generate_good_state_entry(&self, sep: char) -> String {
         format!("{}{sep}state{sep}1{sep}\n", self.mssql_name(),)
     }
@@ -462,21 +661,86 @@ impl SqlInstance {
         client: &mut UniClient,
         endpoint: &Endpoint,
         section: &Section,
-        databases: &[String],
+        context: &InstanceContext,
     ) -> String {
-        let body = match self.read_data_from_cache(section.name(), section.cache_age() as u64) {
-            Some(from_cache) => from_cache,
+        let (body, age) =
+            match self.read_data_from_cache(section.name(), section.fresh_age() as u64) {
+                Some(from_cache) => from_cache,
+                None => {
+                    let start = std::time::Instant::now();
+                    match self
+                        .generate_section_body_in_time(client, endpoint, section, context)
+                        .await
+                    {
+                        Some(from_sql) => {
+                            if section.kind() == &SectionKind::Async {
+                                self.write_data_in_cache(section.name(), &from_sql);
+                                self.write_trace_entry(section, start.elapsed(), &from_sql);
+                            };
+                            (from_sql, 0)
+                        }
+                        None => (self.generate_timeout_entry(section), 0),
+                    }
+                }
+            };
+        section.to_aged_work_header(age) + section.post_process(body).await.as_str()
+    }
+
+    /// None if the section exceeds its timeout: the connection of the running query is dropped
+    /// NOTE: the driver can't send TDS attention, the query may keep running on the server until
+    /// the server notices the closed connection, usually when it sends the results
+    /// the shared client may be dropped in the middle of a query and is reconnected
+    async fn generate_section_body_in_time(
+        &self,
+        client: &mut UniClient,
+        endpoint: &Endpoint,
+        section: &Section,
+        context: &InstanceContext,
+    ) -> Option<String> {
+        let timeout = match section.timeout() {
+            Some(timeout) => timeout,
             None => {
-                let from_sql = self
-                    .generate_section_body(client, endpoint, section, databases)
-                    .await;
-                if section.kind() == &SectionKind::Async {
-                    self.write_data_in_cache(section.name(), &from_sql);
-                };
-                from_sql
+                return Some(
+                    self.generate_section_body(client, endpoint, section, context)
+                        .await,
+                )
             }
         };
-        section.to_work_header() + body.as_str()
+        let result = tokio::time::timeout(
+            timeout,
+            self.generate_section_body(&mut *client, endpoint, section, context),
+        )
+        .await;
+        match result {
+            Ok(body) => Some(body),
+            Err(_) => {
+                log::warn!(
+                    "Section `{}` of `{}` is cancelled: timeout of {}s is exceeded",
+                    section.name(),
+                    self.name,
+                    timeout.as_secs()
+                );
+                let backoff = Backoff::new(endpoint.conn().retry(), context.deadline);
+                match retry::run(backoff, || self.create_client(endpoint, None)).await {
+                    Ok(c) => *client = c,
+                    Err(e) => log::error!("Failed to reconnect `{}`: {e}", self.name),
+                }
+                None
+            }
+        }
+    }
+
+    /// the timed out section is not cached: the next run queries it again
+    fn generate_timeout_entry(&self, section: &Section) -> String {
+        let timeout = section.timeout().unwrap_or_default().as_secs();
+        format!(
+            "{}{}{}\n",
+            self.name,
+            section.sep(),
+            prepare_error(&anyhow::anyhow!(
+                "Timeout of {timeout}s is exceeded, the connection of the query is closed"
+            ))
+        )
     }
 
     async fn generate_section_body(
@@ -484,17 +748,33 @@ impl SqlInstance {
         client: &mut UniClient,
         endpoint: &Endpoint,
         section: &Section,
-        databases: &[String],
+        context: &InstanceContext,
     ) -> String {
-        if let Some(query) = section.select_query(get_sql_dir(), self.version_major()) {
+        let databases = &context.databases;
+        let version_major = context.version_major().unwrap_or(self.version_major());
+        if let Some(query) = section.select_query(get_sql_dir(), version_major) {
             let sep = section.sep();
             match section.name() {
                 names::INSTANCE => {
                     self.generate_good_state_entry(sep)
-                        + &self.generate_details_entry(client, sep).await
+                        + &self
+                            .generate_details_entry_from_context(client, context, sep)
+                            .await
+                        + &context
+                            .properties
+                            .as_ref()
+                            .map(|p| self.process_patch_rows(p, section, sep))
+                            .unwrap_or_default()
+                        + &self.process_containment_rows(context, sep)
+                }
+                names::COUNTERS => {
+                    self.generate_counters_section(client, section, &query)
+                        .await
+                }
+                names::BACKUP => {
+                    self.generate_backup_section_for_databases(client, databases, &query, sep)
+                        .await
                 }
-                names::COUNTERS => self.generate_counters_section(client, &query, sep).await,
-                names::BACKUP => self.generate_backup_section(client, &query, sep).await,
                 names::BLOCKED_SESSIONS => {
                     self.generate_sessions_section(client, &query, sep).await
                 }
@@ -503,17 +783,75 @@ impl SqlInstance {
                         .await
                 }
                 names::CONNECTIONS => self.generate_connections_section(client, &query, sep).await,
+                names::CLUSTERS if context.is_clustered == Some(false) => String::new(),
+                names::UPTIME => {
+                    self.generate_uptime_section(client, section, context, &query)
+                        .await
+                }
+                names::ERRORLOG => {
+                    self.generate_errorlog_section(client, section, context, &query)
+                        .await
+                }
+                names::FAILED_LOGINS => {
+                    self.generate_failed_logins_section(client, section, context, &query)
+                        .await
+                }
+                names::SERVER_ROLES => {
+                    self.generate_server_roles_section(client, &query, sep)
+                        .await
+                }
+                names::INDEX_FRAGMENTATION | names::QUERY_STORE | names::ORPHANED_USERS => {
+                    self.generate_sequential_database_section(
+                        endpoint, section, context, databases, &query,
+                    )
+                    .await
+                }
+                names::DATABASE_OPTIONS => {
+                    self.generate_database_options_section(client, section, &query, sep)
+                        .await
+                }
+                // threads block the runtime and can't be cancelled by the timeout
+                names::TRANSACTION_LOG
+                | names::TABLE_SPACES
+                | names::DATAFILES
+                | names::CLUSTERS
+                    if section.timeout().is_some() =>
+                {
+                    self.generate_database_indexed_section_async(
+                        databases, endpoint, section, &query, sep,
+                    )
+                    .await
+                }
                 names::TRANSACTION_LOG
                 | names::TABLE_SPACES
                 | names::DATAFILES
                 | names::CLUSTERS => self.generate_database_indexed_section_threading(
                     databases, endpoint, section, &query, sep,
                 ),
+                names::TENANTS => {
+                    self.generate_tenants_section(client, section, context, &query)
+                        .await
+                }
+                names::LINKED_SERVERS => {
+                    self.generate_linked_servers_section(client, endpoint, section, &query)
+                        .await
+                }
+                names::AVAILABILITY_GROUPS if section.deduplicate() => {
+                    self.generate_deduplicated_ag_section(
+                        client, endpoint, section, context, &query,
+                    )
+                    .await
+                }
                 names::MIRRORING | names::JOBS | names::AVAILABILITY_GROUPS => {
-                    self.generate_unified_section(endpoint, section, None).await
+                    self.generate_unified_section(endpoint, section, context, Some(&query))
+                        .await
+                }
+                name if registry::find(name).is_some() => {
+                    self.generate_unified_section(endpoint, section, context, Some(&query))
+                        .await
                 }
                 _ => self
-                    .generate_custom_section(endpoint, section)
+                    .generate_custom_section(endpoint, section, context)
                     .await
                     .unwrap_or_else(|| {
                         format!(
@@ -529,8 +867,9 @@ impl SqlInstance {
         }
     }
 
-    fn read_data_from_cache(&self, name: &str, cache_age: u64) -> Option<String> {
-        if cache_age == 0 {
+    /// returns data with its age
+    fn read_data_from_cache(&self, name: &str, cache_age: u64) -> Option<(String, u64)> {
+        if cache_age == 0 || self.environment.is_warmup() {
             return None;
         }
         if let Some(path) = self
@@ -538,8 +877,8 @@ impl SqlInstance {
             .obtain_cache_sub_dir(self.cache_dir())
             .map(|d| d.join(self.make_cache_entry_name(name)))
         {
-            match utils::get_modified_age(&path) {
-                Ok(file_age) if file_age <= cache_age => {
+            match utils::get_stamped_age(&path) {
+                Some(file_age) if file_age <= cache_age => {
                     log::info!("Cache file {path:?} is new enough for {cache_age} cache_age",);
                     std::fs::read_to_string(&path)
                         .map_err(|e| {
@@ -547,6 +886,7 @@ impl SqlInstance {
                             e
                         })
                         .ok()
+                        .map(|data| (data, file_age))
                 }
                 _ => None,
             }
@@ -555,33 +895,84 @@ impl SqlInstance {
         }
     }
 
+    /// the time stamp is stored next to the data: the modification time of the file follows
+    /// jumps of the system clock
     fn write_data_in_cache(&self, name: &str, body: &str) {
         if let Some(dir) = self.environment.obtain_cache_sub_dir(self.cache_dir()) {
-            let file_name = self.make_cache_entry_name(name);
-            std::fs::write(dir.join(file_name), body)
+            let path = dir.join(self.make_cache_entry_name(name));
+            std::fs::write(&path, body)
+                .and_then(|_| {
+                    std::fs::write(
+                        path.with_extension(utils::STAMP_EXTENSION),
+                        utils::TimeStamp::now().to_string(),
+                    )
+                })
                 .unwrap_or_else(|e| log::error!("Error {e} writing cache"));
         }
     }
 
+    fn write_trace_entry(&self, section: &Section, duration: std::time::Duration, body: &str) {
+        if let Some(dir) = self.environment.obtain_cache_sub_dir(self.cache_dir()) {
+            trace::write(
+                &dir.join(self.make_cache_entry_name(section.name())),
+                &trace::Trace {
+                    duration,
+                    result: trace::TraceResult::from_body(body),
+                    cache_age: section.cache_age(),
+                    refresh_lead: section.cache_age() - section.fresh_age(),
+                },
+            );
+        }
+    }
+
     fn make_cache_entry_name(&self, name: &str) -> String {
         format!("{};{};{}.mssql", self.hostname(), self.name, name)
     }
 
+    /// state survives between runs, unlike cache it is never expired
+    fn read_state_entry(&self, name: &str) -> Option<String> {
+        self.environment
+            .obtain_cache_sub_dir(self.cache_dir())
+            .and_then(|d| std::fs::read_to_string(d.join(self.make_state_entry_name(name))).ok())
+    }
+
+    fn write_state_entry(&self, name: &str, value: &str) {
+        if let Some(dir) = self.environment.obtain_cache_sub_dir(self.cache_dir()) {
+            std::fs::write(dir.join(self.make_state_entry_name(name)), value)
+                .unwrap_or_else(|e| log::error!("Error {e} writing state"));
+        }
+    }
+
+    fn make_state_entry_name(&self, name: &str) -> String {
+        format!("{};{};{}.state", self.hostname(), self.name, name)
+    }
+
     pub async fn generate_counters_section(
         &self,
         client: &mut UniClient,
+        section: &Section,
         query: &str,
-        sep: char,
     ) -> String {
+        let sep = section.sep();
         let x = run_custom_query(client, query)
             .await
             .and_then(validate_rows_has_two_blocks)
             .and_then(|answers| {
-                Ok(self.process_utc_rows(&answers[0], sep)?
-                    + &self.process_counters_rows(&answers[1], sep)?)
+                let counters = self.process_counters_rows(&answers[1], sep)?;
+                if section.pdh_fallback() && !is_counters_complete(&counters, sep) {
+                    anyhow::bail!("Counters are incomplete");
+                }
+                Ok(self.process_utc_rows(&answers[0], sep)? + &counters)
             });
         match x {
             Ok(result) => result,
+            Err(err) if section.pdh_fallback() => {
+                log::warn!("Failed to get counters: {err}, trying performance counters");
+                self.generate_pdh_counters(sep).unwrap_or_else(|e| {
+                    log::error!("Failed to get performance counters: {e}");
+                    format!("{sep}{sep}{}{sep}{}\n", self.name, err).to_string()
+                })
+            }
             Err(err) => {
                 log::error!("Failed to get counters: {}", err);
                 format!("{sep}{sep}{}{sep}{}\n", self.name, err).to_string()
@@ -589,6 +980,22 @@ impl SqlInstance {
         }
     }
 
+    /// Windows performance counters of the local instance in format of the counters section,
+    /// flagged with the `source` entry
+    fn generate_pdh_counters(&self, sep: char) -> Result<String> {
+        if self.hostname() != *constants::LOCAL_HOST {
+            anyhow::bail!("Instance `{}` is not local", self.name);
+        }
+        let rows = platform::pdh::get_counters(&self.name)?;
+        let utc = utils::format_utc(utils::get_utc_now()?);
+        Ok(format!("None{sep}utc_time{sep}None{sep}{utc}\n")
+            + &format!("None{sep}source{sep}None{sep}{PDH_SOURCE}\n")
+            + &rows
+                .iter()
+                .map(|row| Counter::from_block(row).into_string(sep))
+                .collect::<String>())
+    }
+
     pub async fn generate_counters_entry(&self, client: &mut UniClient, sep: char) -> String {
         let x = run_known_query(client, sqls::Id::CounterEntries)
             .await
@@ -706,10 +1113,20 @@ impl SqlInstance {
         sep: char,
     ) -> String {
         let databases = self.generate_databases(client).await;
+        self.generate_backup_section_for_databases(client, &databases, query, sep)
+            .await
+    }
 
+    async fn generate_backup_section_for_databases(
+        &self,
+        client: &mut UniClient,
+        databases: &[String],
+        query: &str,
+        sep: char,
+    ) -> String {
         let result = run_custom_query(client, query)
             .await
-            .map(|rows| self.process_backup_rows(&rows, &databases, sep));
+            .map(|rows| self.process_backup_rows(&rows, databases, sep));
         match result {
             Ok(output) => output,
             Err(err) => {
@@ -996,7 +1413,7 @@ impl SqlInstance {
         query: &str,
         sep: char,
     ) -> Result<Option<String>> {
-        if !self.is_database_clustered(client).await? {
+        if !self.is_instance_clustered(client).await? {
             return Ok(None);
         }
         let (nodes, active_node) = self.get_cluster_nodes(client, query).await?;
@@ -1009,7 +1426,17 @@ impl SqlInstance {
         )))
     }
 
-    async fn is_database_clustered(&self, client: &mut UniClient) -> Result<bool> {
+    async fn count_contained_ags(&self, client: &mut UniClient) -> Result<u32> {
+        let answers = &run_known_query(client, sqls::Id::ContainedAvailabilityGroups)
+            .await
+            .and_then(validate_rows)?;
+        Ok(answers[0]
+            .get_first_value_by_name("contained_ags")
+            .and_then(|v| v.parse::<u32>().ok())
+            .unwrap_or_default())
+    }
+
+    async fn is_instance_clustered(&self, client: &mut UniClient) -> Result<bool> {
         let answers = &run_known_query(client, sqls::Id::IsClustered)
             .await
             .and_then(validate_rows)?;
@@ -1028,6 +1455,212 @@ impl SqlInstance {
         Ok((String::default(), String::default()))
     }
 
+    /// restart flag is set if start time differs from the one seen in the previous run
+    pub async fn generate_uptime_section(
+        &self,
+        client: &mut UniClient,
+        section: &Section,
+        context: &InstanceContext,
+        query: &str,
+    ) -> String {
+        let sep = section.sep();
+        let answers = match self
+            .run_section_query(client, section, context, "", query)
+            .await
+        {
+            Ok(answers) => answers,
+            Err(e) => return format!("{}{sep}{}\n", self.name, prepare_error(&e)),
+        };
+        let Some((start_time, uptime)) = answers.first().and_then(|a| {
+            a.get_first_value_by_name("sqlserver_start_time")
+                .zip(a.get_first_value_by_name("uptime"))
+        }) else {
+            return format!("{}{sep}{sep}{sep}{sep}\n", self.name);
+        };
+        let last_known = self.read_state_entry(names::UPTIME).unwrap_or_default();
+        let restarted = !last_known.is_empty() && last_known != start_time;
+        if restarted {
+            log::warn!(
+                "Instance {} restarted: was started {last_known}, now {start_time}",
+                self.name
+            );
+        }
+        self.write_state_entry(names::UPTIME, &start_time);
+        format!(
+            "{}{sep}{start_time}{sep}{uptime}{sep}{last_known}{sep}{}\n",
+            self.name, restarted as u8
+        )
+    }
+
+    /// rows of the query newer than the latest `date_column` value of the previous run, the
+    /// first run gets the last 24 hours, the value is kept in the state of the section
+    async fn run_since_query(
+        &self,
+        client: &mut UniClient,
+        section: &Section,
+        context: &InstanceContext,
+        query: &str,
+        columns: &[&str],
+        date_column: usize,
+    ) -> Result<Vec<Vec<String>>> {
+        let last_known = self.read_state_entry(section.name()).unwrap_or_default();
+        let rows = self
+            .run_section_query(client, section, context, &declare_since(&last_known), query)
+            .await?
+            .first()
+            .map(|a| a.get_rows_by_names(columns))
+            .unwrap_or_default();
+        if let Some(latest) = rows.iter().map(|r| &r[date_column]).max() {
+            self.write_state_entry(section.name(), latest);
+        }
+        Ok(rows)
+    }
+
+    pub async fn generate_errorlog_section(
+        &self,
+        client: &mut UniClient,
+        section: &Section,
+        context: &InstanceContext,
+        query: &str,
+    ) -> String {
+        const COLUMNS: [&str; 5] = ["log_date", "error", "severity", "state", "message"];
+        let sep = section.sep();
+        match self
+            .run_since_query(client, section, context, query, &COLUMNS, 0)
+            .await
+        {
+            Ok(rows) => rows
+                .iter()
+                .map(|r| {
+                    let message = r[4].replace(['\r', '\n', sep], " ");
+                    format!(
+                        "{}{sep}{}{sep}{}{sep}{}{sep}{}{sep}{}\n",
+                        self.name,
+                        r[0],
+                        r[1],
+                        r[2],
+                        r[3],
+                        message.trim()
+                    )
+                })
+                .collect(),
+            Err(e) => format!("{}{sep}{}\n", self.name, prepare_error(&e)),
+        }
+    }
+
+    /// failed logins since the previous run per login and client
+    pub async fn generate_failed_logins_section(
+        &self,
+        client: &mut UniClient,
+        section: &Section,
+        context: &InstanceContext,
+        query: &str,
+    ) -> String {
+        const COLUMNS: [&str; 4] = ["login_name", "client", "failed", "last_failed"];
+        let sep = section.sep();
+        match self
+            .run_since_query(client, section, context, query, &COLUMNS, 3)
+            .await
+        {
+            Ok(rows) => rows
+                .iter()
+                .map(|r| {
+                    format!(
+                        "{}{sep}{}\n",
+                        self.name,
+                        r.iter()
+                            .map(|v| v.replace(sep, " "))
+                            .collect::<Vec<String>>()
+                            .join(&sep.to_string())
+                    )
+                })
+                .collect(),
+            Err(e) => format!("{}{sep}{}\n", self.name, prepare_error(&e)),
+        }
+    }
+
+    /// members of watched roles are always reported, members of other roles only if the
+    /// membership has changed since the previous run
+    pub async fn generate_server_roles_section(
+        &self,
+        client: &mut UniClient,
+        query: &str,
+        sep: char,
+    ) -> String {
+        const COLUMNS: [&str; 5] = [
+            "role_name",
+            "member_name",
+            "member_type",
+            "is_disabled",
+            "modify_date",
+        ];
+        let answers = match run_custom_query(client, query).await {
+            Ok(answers) => answers,
+            Err(e) => return format!("{}{sep}{}\n", self.name, prepare_error(&e)),
+        };
+        let rows = answers
+            .first()
+            .map(|a| a.get_rows_by_names(&COLUMNS))
+            .unwrap_or_default();
+        let last_known = self.read_state_entry(names::SERVER_ROLES);
+        self.write_state_entry(
+            names::SERVER_ROLES,
+            &rows
+                .iter()
+                .map(|r| format!("{}\t{}", r[0], r[1]))
+                .collect::<Vec<String>>()
+                .join("\n"),
+        );
+        to_server_roles_entries(&self.name, &rows, last_known.as_deref(), sep)
+    }
+
+    /// baseline from config is applied to user databases only
+    pub async fn generate_database_options_section(
+        &self,
+        client: &mut UniClient,
+        section: &Section,
+        query: &str,
+        sep: char,
+    ) -> String {
+        const COLUMNS: [&str; 6] = [
+            "name",
+            "recovery_model",
+            "auto_close",
+            "auto_shrink",
+            "page_verify",
+            "owner",
+        ];
+        let answers = match run_custom_query(client, query).await {
+            Ok(answers) => answers,
+            Err(e) => return format!("{}{sep}{}\n", self.name, prepare_error(&e)),
+        };
+        let columns = [COLUMNS.as_slice(), &["is_system"]].concat();
+        answers
+            .first()
+            .map(|a| a.get_rows_by_names(&columns))
+            .unwrap_or_default()
+            .iter()
+            .map(|row| {
+                let drift = if row[COLUMNS.len()] == "1" {
+                    String::new()
+                } else {
+                    section.find_drift(
+                        &COLUMNS
+                            .iter()
+                            .zip(row.iter())
+                            .map(|(c, v)| (*c, v.as_str()))
+                            .collect::<Vec<(&str, &str)>>(),
+                    )
+                };
+                format!(
+                    "{}{sep}{}{sep}{drift}\n",
+                    self.name,
+                    row[..COLUMNS.len()].join(&sep.to_string())
+                )
+            })
+            .collect()
+    }
+
     pub async fn generate_connections_section(
         &self,
         client: &mut UniClient,
@@ -1068,28 +1701,243 @@ impl SqlInstance {
                             .unwrap_or_default()
                     )
                 })
-                .collect::<Vec<String>>()
-                .join(""),
-            None => String::new(),
+                .collect::<Vec<String>>()
+                .join(""),
+            None => String::new(),
+        }
+    }
+
+    /// NOTE: uses ' ' instead of '\t' in error messages
+    /// on missing permission the reduced variant of the built-in query is used if defined, the
+    /// section is marked as degraded then
+    /// `declarations` are prepended to the query and to its reduced variant
+    async fn run_section_query(
+        &self,
+        client: &mut UniClient,
+        section: &Section,
+        context: &InstanceContext,
+        declarations: &str,
+        query: &str,
+    ) -> Result<Vec<UniAnswer>> {
+        let error = match self
+            .run_query_with_retry(&mut *client, context, &(declarations.to_string() + query))
+            .await
+        {
+            Err(e) => e,
+            answers => return answers,
+        };
+        let version_major = context.version_major().unwrap_or(self.version_major());
+        match section.select_fallback_query(get_sql_dir(), version_major, &error.to_string()) {
+            Some((fallback, permission)) => {
+                log::warn!(
+                    "Section `{}` of `{}` is degraded: no permission `{}`, reduced query is used",
+                    section.name(),
+                    self.name,
+                    permission.as_str()
+                );
+                context.mark_degraded(section, permission);
+                run_custom_query(client, declarations.to_string() + &fallback).await
+            }
+            None => Err(error),
+        }
+    }
+
+    /// an availability group is reported only by its primary replica: the monitoring gets
+    /// health of the group once and not from every replica, `ag_reporting` section with the
+    /// groups of the instance is appended
+    async fn generate_deduplicated_ag_section(
+        &self,
+        client: &mut UniClient,
+        endpoint: &Endpoint,
+        section: &Section,
+        context: &InstanceContext,
+        query: &str,
+    ) -> String {
+        let body = self
+            .generate_unified_section(endpoint, section, context, Some(query))
+            .await;
+        let replicas = match run_known_query(client, sqls::Id::AvailabilityGroupReplicas).await {
+            Ok(answers) => answers
+                .first()
+                .map(|a| a.get_rows_by_names(&["ag_name", "primary_replica", "is_reporting"]))
+                .unwrap_or_default(),
+            Err(e) => {
+                log::warn!(
+                    "Availability groups of {} are not deduplicated: {e}",
+                    self.name
+                );
+                return body;
+            }
+        };
+        let skipped = replicas
+            .iter()
+            .filter(|r| r[2] != "1")
+            .map(|r| r[0].as_str())
+            .collect::<Vec<&str>>();
+        let reporting = if replicas.is_empty() {
+            String::new()
+        } else {
+            emit::header(AG_REPORTING_SECTION_NAME, '|')
+                + &replicas
+                    .iter()
+                    .map(|r| format!("{}|{}|{}|{}\n", self.name, r[0], r[1], r[2]))
+                    .collect::<String>()
+        };
+        skip_ag_rows(&body, section.sep(), &skipped) + &reporting
+    }
+
+    /// tenants of the instance and, as piggyback data of every tenant, usage aggregated over
+    /// its databases
+    async fn generate_tenants_section(
+        &self,
+        client: &mut UniClient,
+        section: &Section,
+        context: &InstanceContext,
+        query: &str,
+    ) -> String {
+        let sep = section.sep();
+        let rows = match self
+            .run_section_query(
+                client,
+                section,
+                context,
+                &section.declare_tenant_property(),
+                query,
+            )
+            .await
+        {
+            Ok(answers) => answers
+                .last()
+                .map(|a| {
+                    a.get_rows_by_names(&[
+                        "database_name",
+                        "tenant",
+                        "data_kb",
+                        "log_kb",
+                        "backup_age",
+                    ])
+                })
+                .unwrap_or_default(),
+            Err(e) => return format!("{} {}\n", self.name, prepare_error(&e)),
+        };
+        let tenants = to_tenant_usages(&rows, section.tenant_map());
+        let body = section.first_line(Some(&self.name))
+            + &tenants
+                .iter()
+                .map(|(tenant, usage)| format!("{tenant}{sep}{}\n", usage.databases.join(",")))
+                .collect::<String>();
+        if tenants.is_empty() {
+            return body;
+        }
+        let piggyback = tenants
+            .iter()
+            .map(|(tenant, usage)| {
+                emit::piggyback_header(&tenant.to_lowercase().into())
+                    + &emit::header(TENANT_USAGE_SECTION_NAME, sep)
+                    + &usage.to_entry(&self.name, tenant, sep)
+                    + &emit::piggyback_footer()
+            })
+            .collect::<String>();
+        // piggyback data of the instance continues after data of tenants
+        body + &piggyback + &self.generate_header()
+    }
+
+    /// linked servers are tested concurrently, each one with own connection and timeout: a
+    /// dead link doesn't stall the section
+    async fn generate_linked_servers_section(
+        &self,
+        client: &mut UniClient,
+        endpoint: &Endpoint,
+        section: &Section,
+        query: &str,
+    ) -> String {
+        let sep = section.sep();
+        let servers = match run_custom_query(client, query).await {
+            Ok(answers) => answers
+                .first()
+                .map(|a| {
+                    a.get_rows_by_names(&[
+                        "name",
+                        "product",
+                        "provider",
+                        "data_source",
+                        "rpc_out",
+                        "data_access",
+                    ])
+                })
+                .unwrap_or_default(),
+            Err(e) => return format!("{} {}\n", self.name, prepare_error(&e)),
+        };
+        let timeout = std::time::Duration::from_secs(section.link_timeout() as u64);
+        let tasks = servers.iter().map(|server| async move {
+            let (status, detail) = if section.test_links() {
+                self.test_linked_server(endpoint, &server[0], timeout).await
+            } else {
+                ("not_tested", String::new())
+            };
+            format!(
+                "{}{sep}{status}{sep}{}\n",
+                server.join(&sep.to_string()),
+                detail.replace(sep, " ")
+            )
+        });
+        let rows = stream::iter(tasks)
+            .buffered(MAX_CONNECTIONS as usize)
+            .collect::<Vec<_>>()
+            .await;
+        section.first_line(Some(&self.name)) + &rows.join("")
+    }
+
+    async fn test_linked_server(
+        &self,
+        endpoint: &Endpoint,
+        name: &str,
+        timeout: std::time::Duration,
+    ) -> (&'static str, String) {
+        let test = async {
+            let mut c = self.create_client(endpoint, None).await?;
+            run_custom_query(
+                &mut c,
+                format!("EXEC sp_testlinkedserver N'{}'", name.replace('\'', "''")),
+            )
+            .await
+        };
+        match tokio::time::timeout(timeout, test).await {
+            Ok(Ok(_)) => ("reachable", String::new()),
+            Ok(Err(e)) => ("unreachable", prepare_error(&e)),
+            Err(_) => {
+                log::warn!("Test of linked server `{name}` timed out");
+                ("timeout", String::new())
+            }
         }
     }
 
-    /// NOTE: uses ' ' instead of '\t' in error messages
     pub async fn generate_unified_section(
         &self,
         endpoint: &Endpoint,
         section: &Section,
+        context: &InstanceContext,
         query: Option<&str>,
     ) -> String {
-        match self.create_client(endpoint, section.main_db()).await {
+        let database = section.main_db();
+        match self
+            .create_section_client(endpoint, section, context, database.clone())
+            .await
+        {
             Ok(mut c) => {
                 let q = query.map(|q| q.to_owned()).unwrap_or_else(|| {
                     section
                         .select_query(get_sql_dir(), self.version_major())
                         .unwrap_or_default()
                 });
-                run_custom_query(&mut c, q)
-                    .await
+                let answers = self
+                    .run_section_query(&mut c, section, context, "", &q)
+                    .await;
+                if answers.is_ok() {
+                    let intent = self.section_intent(endpoint, section);
+                    context.pool.give_back(database.as_deref(), intent, c);
+                }
+                answers
                     .and_then(|r| section.validate_rows(r))
                     .map(|rows| {
                         format!(
@@ -1108,15 +1956,23 @@ impl SqlInstance {
         &self,
         endpoint: &Endpoint,
         section: &Section,
+        context: &InstanceContext,
     ) -> Option<String> {
-        match self.create_client(endpoint, None).await {
+        match self
+            .create_section_client(endpoint, section, context, None)
+            .await
+        {
             Ok(mut c) => {
                 if let Some(query) =
                     section.find_provided_query(get_sql_dir(), self.version_major())
                 {
+                    let answers = self.run_query_with_retry(&mut c, context, &query).await;
+                    if answers.is_ok() {
+                        let intent = self.section_intent(endpoint, section);
+                        context.pool.give_back(None, intent, c);
+                    }
                     Some(
-                        run_custom_query(&mut c, query)
-                            .await
+                        answers
                             .and_then(|r| section.validate_rows(r))
                             .map(|rows| {
                                 format!(
@@ -1135,6 +1991,43 @@ impl SqlInstance {
         }
     }
 
+    /// query is executed in every selected database one after another: the query is heavy
+    /// parameters of the section are available in the query as variables
+    async fn generate_sequential_database_section(
+        &self,
+        endpoint: &Endpoint,
+        section: &Section,
+        context: &InstanceContext,
+        databases: &[String],
+        query: &str,
+    ) -> String {
+        let query = section.declare_variables() + query;
+        let mut result = section.first_line(Some(&self.name));
+        let intent = ApplicationIntent::ReadWrite;
+        for database in section.select_databases(databases) {
+            let client = match context.pool.take(Some(database.as_str()), intent) {
+                Some(client) => Ok(client),
+                None => self.create_client(endpoint, Some(database.clone())).await,
+            };
+            let entries = match client {
+                Ok(mut c) => {
+                    let answers = run_custom_query(&mut c, &query).await;
+                    if answers.is_ok() {
+                        context.pool.give_back(Some(database.as_str()), intent, c);
+                    }
+                    answers
+                        .map(|rows| self.to_entries(rows, section.sep(), section))
+                        .unwrap_or_else(|e| {
+                            format!("{} {} {}\n", self.name, database, prepare_error(&e))
+                        })
+                }
+                Err(err) => format!("{} {} {}\n", self.name, database, err),
+            };
+            result += &entries;
+        }
+        result
+    }
+
     /// rows must be not empty
     fn to_entries(&self, answers: Vec<UniAnswer>, sep: char, section: &Section) -> String {
         // just a safety guard, the function should not get empty rows
@@ -1154,12 +2047,19 @@ impl SqlInstance {
         let result = match answer {
             UniAnswer::Rows(rows) => rows
                 .into_iter()
-                .map(|r| r.get_all(sep) + additional_row)
+                .map(|r| r.get_all(sep, section.render()) + additional_row)
                 .collect::<Vec<String>>()
                 .join("\n"),
             UniAnswer::Block(block) => block
                 .rows
                 .iter()
+                .map(|r| match section.render() {
+                    Some(render) => r
+                        .iter()
+                        .map(|v| render_text(v, sep, render))
+                        .collect::<Vec<String>>(),
+                    None => r.clone(),
+                })
                 .map(|r| r.join(&sep.to_string()) + additional_row)
                 .collect::<Vec<String>>()
                 .join("\n"),
@@ -1231,6 +2131,41 @@ impl SqlInstance {
         )
     }
 
+    /// update level of the instance compared with the latest build from the catalog if any
+    fn process_patch_rows(
+        &self,
+        properties: &SqlInstanceProperties,
+        section: &Section,
+        sep: char,
+    ) -> String {
+        let version = properties.version.to_string();
+        let latest = version
+            .split('.')
+            .next()
+            .and_then(|major| section.find_latest_build(major));
+        format!(
+            "{}{sep}patch{sep}{}{sep}{}{sep}{}{sep}{}{sep}{}\n",
+            self.mssql_name(),
+            properties.update_level,
+            properties.update_reference,
+            properties.update_date,
+            latest.as_deref().unwrap_or_default(),
+            to_patch_status(&version, latest.as_deref()),
+        )
+    }
+
+    fn process_containment_rows(&self, context: &InstanceContext, sep: char) -> String {
+        format!(
+            "{}{sep}containment{sep}{}{sep}{}\n",
+            self.mssql_name(),
+            context.is_contained_user() as u8,
+            context
+                .contained_ags
+                .map(|c| c.to_string())
+                .unwrap_or_default()
+        )
+    }
+
     fn process_backup_rows(&self, rows: &[UniAnswer], databases: &[String], sep: char) -> String {
         let (mut ready, missing_data) = self.process_backup_rows_partly(rows, databases, sep);
         let missing: Vec<String> = self.process_missing_backup_rows(&missing_data, sep);
@@ -1308,27 +2243,53 @@ pub async fn create_tcp_client(
     endpoint: &Endpoint,
     database: Option<String>,
     port: Option<Port>,
+    read_only: bool,
 ) -> Result<UniClient> {
     let (auth, conn) = endpoint.split();
+    let database = database.or_else(|| auth.database().map(str::to_string));
     let client = match auth.auth_type() {
         AuthType::SqlServer | AuthType::Windows => {
-            if let Some(credentials) = client::obtain_config_credentials(auth) {
+            if let Some(credentials) = client::obtain_config_credentials(auth).await? {
                 client::ClientBuilder::new()
                     .logon_on_port(&conn.hostname(), port, credentials)
                     .database(database)
+                    .read_only(read_only)
             } else {
                 anyhow::bail!("Not provided credentials")
             }
         }
 
+        AuthType::Kerberos | AuthType::Gmsa => {
+            client::log_gmsa_identity(auth);
+            client::ClientBuilder::new()
+                .logon_on_port(&conn.hostname(), port, client::Credentials::Kerberos)
+                .database(database)
+                .read_only(read_only)
+        }
+
+        AuthType::AzureAd | AuthType::ManagedIdentity => client::ClientBuilder::new()
+            .logon_on_port(
+                &conn.hostname(),
+                port,
+                client::obtain_token_credentials(auth, conn.timeout()).await?,
+            )
+            .database(database)
+            .read_only(read_only),
+
         #[cfg(windows)]
         AuthType::Integrated => client::ClientBuilder::new()
             .local_by_port(port, Some(conn.hostname()))
-            .database(database),
+            .database(database)
+            .read_only(read_only),
 
         _ => anyhow::bail!("Not supported authorization type"),
     };
-    client.build().await
+    client
+        .tls_mode(conn.tls_mode())
+        .ca_bundle(conn.ca_bundle())
+        .multi_subnet_failover(conn.multi_subnet_failover())
+        .build()
+        .await
 }
 
 pub fn create_odbc_client(
@@ -1341,7 +2302,73 @@ pub fn create_odbc_client(
     {
         let connection_string =
             odbc::make_connection_string(instance_name, database.as_deref(), None);
-        Ok(UniClient::Odbc(OdbcClient::new(connection_string)))
+        if fixture::is_replay() {
+            return fixture::replay_client(connection_string);
+        }
+        fixture::wrap_client(
+            connection_string.clone(),
+            Ok(UniClient::Odbc(OdbcClient::new(connection_string))),
+        )
+    }
+}
+
+/// Data gathered once per instance and shared by all sections of the instance
+#[derive(Debug, Default)]
+pub struct InstanceContext {
+    pub properties: Option<SqlInstanceProperties>,
+    /// `None` if not required by sections or not available
+    pub is_clustered: Option<bool>,
+    /// SQL Server 2022+, `None` if not available
+    pub contained_ags: Option<u32>,
+    pub databases: Vec<String>,
+    /// sections generated using reduced queries because of missing permissions
+    pub degraded: Mutex<Vec<(String, Permission)>>,
+    /// connections reused by the sections instead of a login per section
+    pub pool: ClientPool,
+    /// end of the run budget, transient failures are not retried after it
+    pub deadline: Option<Instant>,
+}
+
+impl InstanceContext {
+    /// real version reported by the server, may differ from the discovered one
+    pub fn version_major(&self) -> Option<u32> {
+        self.properties
+            .as_ref()
+            .map(|p| parse_version(&Some(p.version.clone()))[0])
+            .filter(|&v| v != 0)
+    }
+
+    pub fn is_contained_user(&self) -> bool {
+        self.properties
+            .as_ref()
+            .map_or(false, |p| p.is_contained_user())
+    }
+
+    /// contained database user may access only own database: sections routed to master/msdb
+    /// are skipped, in contained AG routing is done by the server(msdb is mapped to the AG one)
+    pub fn is_accessible(&self, section: &Section) -> bool {
+        !self.is_contained_user() || section.main_db().is_none()
+    }
+
+    pub fn mark_degraded(&self, section: &Section, permission: Permission) {
+        if let Ok(mut degraded) = self.degraded.lock() {
+            degraded.push((section.name().to_string(), permission));
+        }
+    }
+
+    /// row: instance, section, missing permission; absent if nothing is degraded
+    pub fn generate_degraded_section(&self, instance_name: &InstanceName) -> String {
+        let degraded = self.degraded.lock().map(|d| d.clone()).unwrap_or_default();
+        if degraded.is_empty() {
+            return String::new();
+        }
+        emit::header(DEGRADED_SECTION_NAME, '|')
+            + &degraded
+                .iter()
+                .map(|(section, permission)| {
+                    format!("{instance_name}|{section}|{}\n", permission.as_str())
+                })
+                .collect::<String>()
     }
 }
 
@@ -1353,6 +2380,21 @@ pub struct SqlInstanceProperties {
     pub edition: InstanceEdition,
     pub product_level: String,
     pub net_bios: String,
+    /// CU number, e.g. `CU12`, empty for RTM/GDR builds
+    pub update_level: String,
+    /// KB article of the update
+    pub update_reference: String,
+    /// build date of the installed update
+    pub update_date: String,
+    /// 0 for logins, id of the contained database for contained database users
+    pub authenticating_database_id: String,
+}
+
+impl SqlInstanceProperties {
+    /// contained database user has no access to master, msdb and other databases
+    pub fn is_contained_user(&self) -> bool {
+        !self.authenticating_database_id.is_empty() && self.authenticating_database_id != "0"
+    }
 }
 
 impl From<&UniAnswer> for SqlInstanceProperties {
@@ -1366,6 +2408,10 @@ impl From<&UniAnswer> for SqlInstanceProperties {
                 let edition: InstanceEdition = row.get_value_by_name("Edition").into();
                 let product_level = row.get_value_by_name("ProductLevel");
                 let net_bios = row.get_value_by_name("NetBios");
+                let update_level = row.get_value_by_name("ProductUpdateLevel");
+                let update_reference = row.get_value_by_name("ProductUpdateReference");
+                let update_date = row.get_value_by_name("ResourceLastUpdateDateTime");
+                let authenticating_database_id = row.get_value_by_name("AuthenticatingDatabaseId");
                 Self {
                     name: (if name.is_empty() {
                         "MSSQLSERVER".to_string()
@@ -1378,6 +2424,10 @@ impl From<&UniAnswer> for SqlInstanceProperties {
                     edition,
                     product_level,
                     net_bios,
+                    update_level,
+                    update_reference,
+                    update_date,
+                    authenticating_database_id,
                 }
             }
             UniAnswer::Block(block) => {
@@ -1390,6 +2440,11 @@ impl From<&UniAnswer> for SqlInstanceProperties {
                 let edition: InstanceEdition = block.get_value_by_name(row, "Edition").into();
                 let product_level = block.get_value_by_name(row, "ProductLevel");
                 let net_bios = block.get_value_by_name(row, "NetBios");
+                let update_level = block.get_value_by_name(row, "ProductUpdateLevel");
+                let update_reference = block.get_value_by_name(row, "ProductUpdateReference");
+                let update_date = block.get_value_by_name(row, "ResourceLastUpdateDateTime");
+                let authenticating_database_id =
+                    block.get_value_by_name(row, "AuthenticatingDatabaseId");
                 Self {
                     name: (if name.is_empty() {
                         "MSSQLSERVER".to_string()
@@ -1402,6 +2457,10 @@ impl From<&UniAnswer> for SqlInstanceProperties {
                     edition,
                     product_level,
                     net_bios,
+                    update_level,
+                    update_reference,
+                    update_date,
+                    authenticating_database_id,
                 }
             }
         }
@@ -1426,6 +2485,67 @@ fn validate_rows(rows: Vec<UniAnswer>) -> Result<Vec<UniAnswer>> {
     }
 }
 
+#[derive(Debug, Default, PartialEq)]
+struct TenantUsage {
+    databases: Vec<String>,
+    data_kb: i64,
+    log_kb: i64,
+    /// seconds, oldest last full backup among backed up databases
+    max_backup_age: i64,
+    not_backed_up: u32,
+}
+
+impl TenantUsage {
+    fn to_entry(&self, instance: &InstanceName, tenant: &str, sep: char) -> String {
+        format!(
+            "{instance}{sep}{tenant}{sep}{}{sep}{}{sep}{}{sep}{}{sep}{}\n",
+            self.databases.len(),
+            self.data_kb,
+            self.log_kb,
+            self.max_backup_age,
+            self.not_backed_up
+        )
+    }
+}
+
+/// rows: database, tenant from the property, data KB, log KB, backup age(-1 - never),
+/// databases without tenant are skipped
+fn to_tenant_usages(
+    rows: &[Vec<String>],
+    tenant_map: &[(String, String)],
+) -> BTreeMap<String, TenantUsage> {
+    let mut tenants: BTreeMap<String, TenantUsage> = BTreeMap::new();
+    for row in rows.iter().filter(|r| r.len() == 5) {
+        let tenant = tenant_map
+            .iter()
+            .find(|(d, _)| d.eq_ignore_ascii_case(&row[0]))
+            .map_or(row[1].as_str(), |(_, t)| t.as_str());
+        if tenant.is_empty() {
+            continue;
+        }
+        let value = |i: usize| row[i].parse::<i64>().unwrap_or_default();
+        let usage = tenants.entry(tenant.to_string()).or_default();
+        usage.databases.push(row[0].clone());
+        usage.data_kb += value(2);
+        usage.log_kb += value(3);
+        if value(4) < 0 {
+            usage.not_backed_up += 1;
+        } else {
+            usage.max_backup_age = usage.max_backup_age.max(value(4));
+        }
+    }
+    tenants
+}
+
+/// corrupted counters give an empty or partial set, buffer manager is always present
+fn is_counters_complete(counters: &str, sep: char) -> bool {
+    counters.lines().any(|l| {
+        l.split(sep)
+            .next()
+            .map_or(false, |o| o.ends_with(":Buffer_Manager"))
+    })
+}
+
 fn validate_rows_has_two_blocks(rows: Vec<UniAnswer>) -> Result<Vec<UniAnswer>> {
     if rows.len() != 2 || rows[0].is_empty() || rows[1].is_empty() {
         Err(anyhow::anyhow!("Output from query is invalid"))
@@ -1855,6 +2975,90 @@ impl Counter {
     }
 }
 
+/// `updates_available` if the build is older than the latest known one
+fn to_patch_status(build: &str, latest: Option<&str>) -> &'static str {
+    let parse = |b: &str| {
+        b.split('.')
+            .map(|n| n.trim().parse::<u32>().unwrap_or_default())
+            .collect::<Vec<u32>>()
+    };
+    match latest {
+        Some(latest) if parse(build) < parse(latest) => "updates_available",
+        Some(_) => "up_to_date",
+        None => "unknown",
+    }
+}
+
+/// rows of the groups are removed with the empty line following every row
+fn skip_ag_rows(body: &str, sep: char, skipped: &[&str]) -> String {
+    let mut result = String::new();
+    let mut after_skipped = false;
+    for line in body.split_inclusive('\n') {
+        let name = line.split(sep).next().unwrap_or_default().trim_end();
+        if after_skipped && line.trim().is_empty() {
+            after_skipped = false;
+        } else if skipped.contains(&name) {
+            after_skipped = true;
+        } else {
+            after_skipped = false;
+            result.push_str(line);
+        }
+    }
+    result
+}
+
+/// `@since` of the error log queries, the state is the latest reported log date(style 121)
+fn declare_since(last_known: &str) -> String {
+    let since = last_known
+        .chars()
+        .filter(|c| c.is_ascii_digit() || matches!(c, '-' | ':' | ' ' | '.'))
+        .collect::<String>();
+    format!(
+        "DECLARE @since DATETIME = ISNULL(TRY_CONVERT(DATETIME, N'{}', 121), DATEADD(HOUR, -24, GETDATE()));\n",
+        since.trim()
+    )
+}
+
+/// row: role, member, member type, disabled, modify date, change(empty, added or removed)
+/// no changes are reported without previous state
+fn to_server_roles_entries(
+    instance_name: &InstanceName,
+    rows: &[Vec<String>],
+    last_known: Option<&str>,
+    sep: char,
+) -> String {
+    const WATCHED_ROLES: [&str; 2] = ["sysadmin", "securityadmin"];
+    let previous = last_known
+        .map(|s| {
+            s.lines()
+                .filter_map(|l| l.split_once('\t'))
+                .collect::<Vec<(&str, &str)>>()
+        })
+        .unwrap_or_default();
+    let current = rows
+        .iter()
+        .map(|r| (r[0].as_str(), r[1].as_str()))
+        .collect::<Vec<(&str, &str)>>();
+    let added =
+        |role: &str, member: &str| last_known.is_some() && !previous.contains(&(role, member));
+    let present = rows.iter().filter_map(|r| {
+        let change = if added(&r[0], &r[1]) { "added" } else { "" };
+        (WATCHED_ROLES.contains(&r[0].as_str()) || !change.is_empty()).then(|| {
+            format!(
+                "{instance_name}{sep}{}{sep}{change}\n",
+                r.join(&sep.to_string())
+            )
+        })
+    });
+    let removed = previous
+        .iter()
+        .filter(|p| !current.contains(p))
+        .map(|(role, member)| {
+            format!("{instance_name}{sep}{role}{sep}{member}{sep}{sep}{sep}{sep}removed\n")
+        });
+    present.chain(removed).collect()
+}
+
 fn to_blocked_session_entry(instance_name: &InstanceName, row: &Row, sep: char) -> String {
     let session_id = row.get_value_by_idx(0).trim().to_string();
     let wait_duration_ms = row.get_bigint_by_idx(1).to_string();
@@ -1880,42 +3084,55 @@ fn to_blocked_session_entry_odbc(
 
 impl CheckConfig {
     pub async fn exec(&self, environment: &Env) -> Result<String> {
+        let mut output = String::new();
+        self.exec_with(environment, &MsSqlRunner, &mut output)
+            .await?;
+        Ok(output)
+    }
+
+    /// Output of every config is passed to the emitter as soon as ready
+    pub async fn exec_with<R: SectionRunner, E: Emitter>(
+        &self,
+        environment: &Env,
+        runner: &R,
+        emitter: &mut E,
+    ) -> Result<()> {
         if let Some(ms_sql) = self.ms_sql() {
-            CheckConfig::prepare_cache_sub_dir(environment, &ms_sql.config_cache_dir());
+            let mut trailer = ms_sql.options().trailer().then(Trailer::default);
             log::info!("Generating main data");
-            let mut output: Vec<String> = Vec::new();
-            output.push(
-                generate_data(ms_sql, environment)
-                    .await
-                    .unwrap_or_else(|e| {
-                        log::error!("Error generating data at main config: {e}");
-                        format!("{e}\n")
-                    }),
-            );
+            let data = runner.run(ms_sql, environment).await.unwrap_or_else(|e| {
+                log::error!("Error generating data at main config: {e}");
+                format!("{e}\n")
+            });
+            trailer.iter_mut().for_each(|t| t.update(&data));
+            emitter.emit(&data)?;
             for (num, config) in std::iter::zip(0.., ms_sql.configs()) {
                 log::info!("Generating configs data");
-                CheckConfig::prepare_cache_sub_dir(environment, &config.config_cache_dir());
-                let configs_data = generate_data(config, environment)
-                    .await
-                    .unwrap_or_else(|e| {
-                        log::error!("Error generating data at config {num}: {e}");
-                        format!("{e}\n")
-                    });
-                output.push(configs_data);
+                let configs_data = runner.run(config, environment).await.unwrap_or_else(|e| {
+                    log::error!("Error generating data at config {num}: {e}");
+                    format!("{e}\n")
+                });
+                trailer.iter_mut().for_each(|t| t.update(&configs_data));
+                emitter.emit(&configs_data)?;
+            }
+            if let Some(trailer) = trailer {
+                emitter.emit(&trailer.finish('|'))?;
             }
-            Ok(output.join(""))
+            state_gc::exec(environment, ms_sql);
+            fixture::save();
+            Ok(())
         } else {
             log::error!("No config");
             anyhow::bail!("No Config")
         }
     }
+}
 
-    fn prepare_cache_sub_dir(environment: &Env, hash: &str) {
-        match environment.obtain_cache_sub_dir(hash).map(utils::touch_dir) {
-            Some(Err(e)) => log::error!("Error touching dir: {e}, caching may be not possible"),
-            Some(Ok(p)) => log::info!("Using cache dir {p:?}"),
-            None => log::warn!("No cache dir defined, caching is not possible"),
-        }
+pub(crate) fn prepare_cache_sub_dir(environment: &Env, hash: &str) {
+    match environment.obtain_cache_sub_dir(hash).map(utils::touch_dir) {
+        Some(Err(e)) => log::error!("Error touching dir: {e}, caching may be not possible"),
+        Some(Ok(p)) => log::info!("Using cache dir {p:?}"),
+        None => log::warn!("No cache dir defined, caching is not possible"),
     }
 }
 
@@ -1929,10 +3146,13 @@ fn generate_dumb_header(ms_sql: &config::ms_sql::Config) -> String {
         .join("")
 }
 
-fn generate_signaling_blocks(ms_sql: &config::ms_sql::Config, instances: &[SqlInstance]) -> String {
+fn generate_signaling_blocks<P: AsRef<SqlInstance>>(
+    ms_sql: &config::ms_sql::Config,
+    instances: &[P],
+) -> String {
     instances
         .iter()
-        .map(|i| i.piggyback().as_ref().cloned())
+        .map(|i| i.as_ref().piggyback().as_ref().cloned())
         .collect::<Vec<Option<PiggybackHostName>>>()
         .into_iter()
         .map(|h| generate_signaling_block(ms_sql, &h))
@@ -1954,11 +3174,18 @@ fn generate_signaling_block(
 
 /// Generate data as defined by config
 /// Consists from two parts: instance entries + sections for every instance
-async fn generate_data(ms_sql: &config::ms_sql::Config, environment: &Env) -> Result<String> {
+pub(crate) async fn generate_data(
+    ms_sql: &config::ms_sql::Config,
+    environment: &Env,
+) -> Result<String> {
+    let scheduler = Scheduler::new(ms_sql.options().run_budget())
+        .concurrency_classes(ms_sql.options().concurrency_classes());
+    let setup_errors = generate_setup_errors_section(ms_sql, environment);
     let instances = find_working_instances(ms_sql, environment).await?;
     if instances.is_empty() {
         return Ok(generate_signaling_block(ms_sql, &None)
-            + "ERROR: Failed to gather SQL server instances\n");
+            + "ERROR: Failed to gather SQL server instances\n"
+            + &setup_errors);
     }
     log::info!(
         "Found {} SQL server instances: [ {} ]",
@@ -1970,9 +3197,20 @@ async fn generate_data(ms_sql: &config::ms_sql::Config, environment: &Env) -> Re
             .join(", ")
     );
 
+    if environment.is_warmup() && environment.disable_caching() {
+        log::warn!("Caching is disabled, warmup has no effect");
+    }
     let sections = ms_sql
         .valid_sections()
         .into_iter()
+        .filter(|s| !environment.is_warmup() || s.kind() == config::section::SectionKind::Async)
+        .filter(|s| {
+            let used = environment.is_section_used(s.name());
+            if !used {
+                log::info!("Section {} is not used by the site, skipped", s.name());
+            }
+            used
+        })
         .map(|s| {
             Section::new(
                 s,
@@ -1982,12 +3220,52 @@ async fn generate_data(ms_sql: &config::ms_sql::Config, environment: &Env) -> Re
                     Some(ms_sql.cache_age())
                 },
             )
+            .refresh_lead(ms_sql.options().cache_refresh_lead().as_secs() as u32)
+            .default_timeout(ms_sql.options().section_timeout())
         })
         .collect::<Vec<_>>();
 
-    Ok(generate_signaling_blocks(ms_sql, &instances)
-        + &generate_instance_entries(&instances)
-        + &generate_result(&instances, &sections, ms_sql).await?)
+    let results = generate_result(&instances, &sections, ms_sql, &scheduler).await;
+    write_routed_outputs(ms_sql, &results);
+    let printed = instances
+        .iter()
+        .filter(|i| i.is_printed())
+        .collect::<Vec<&SqlInstance>>();
+    Ok(generate_signaling_blocks(ms_sql, &printed)
+        + &generate_instance_entries(&printed)
+        + &results
+            .iter()
+            .filter(|(i, _)| i.is_printed())
+            .map(|(_, body)| body.as_str())
+            .collect::<String>()
+        + &generate_effective_settings_section(
+            ms_sql,
+            environment,
+            &printed
+                .iter()
+                .map(|i| (&i.name, i.endpoint()))
+                .collect::<Vec<_>>(),
+            &sections,
+        )
+        + &setup_errors)
+}
+
+/// every file gets complete output of its instances, the file is replaced atomically
+fn write_routed_outputs(ms_sql: &config::ms_sql::Config, results: &[(&SqlInstance, String)]) {
+    let mut files: BTreeMap<&Path, String> = BTreeMap::new();
+    for (instance, body) in results {
+        if let Some(output) = instance.output() {
+            files.entry(output.file()).or_default().push_str(
+                &(generate_signaling_block(ms_sql, instance.piggyback())
+                    + &generate_instance_entry(instance)
+                    + body),
+            );
+        }
+    }
+    for (file, data) in files {
+        emit::write_file_atomically(file, &data)
+            .unwrap_or_else(|e| log::error!("Error {e} writing output into {file:?}"));
+    }
 }
 
 fn generate_instance_entries<P: AsRef<SqlInstance>>(instances: &[P]) -> String {
@@ -2108,7 +3386,11 @@ async fn add_custom_instance_builders(
     let mut builders: Vec<SqlInstanceBuilder> = Vec::new();
     for (builder, endpoint) in reconnects.into_iter() {
         if let Some(endpoint) = endpoint {
-            if let Some(b) = get_custom_instance_builder(&builder, &endpoint).await {
+            let alternates = customizations
+                .get(&builder.get_name())
+                .map(|c| c.alternate_endpoints())
+                .unwrap_or_default();
+            if let Some(b) = find_custom_instance_builder(&builder, &endpoint, &alternates).await {
                 builders.push(b);
             }
         } else {
@@ -2118,6 +3400,28 @@ async fn add_custom_instance_builders(
     Ok(builders)
 }
 
+/// endpoints are tried in order, the first reachable one is used
+async fn find_custom_instance_builder(
+    builder: &SqlInstanceBuilder,
+    endpoint: &Endpoint,
+    alternates: &[Endpoint],
+) -> Option<SqlInstanceBuilder> {
+    for e in std::iter::once(endpoint).chain(alternates) {
+        if let Some(b) = get_custom_instance_builder(builder, e).await {
+            return Some(b);
+        }
+        if !alternates.is_empty() {
+            log::warn!(
+                "Instance `{}` is not reachable at {}:{}, trying next endpoint",
+                builder.get_name(),
+                e.hostname(),
+                e.port()
+            );
+        }
+    }
+    None
+}
+
 async fn get_custom_instance_builder(
     builder: &SqlInstanceBuilder,
     endpoint: &Endpoint,
@@ -2125,6 +3429,25 @@ async fn get_custom_instance_builder(
     let instance_name = &builder.get_name();
     let auth = endpoint.auth();
     let conn = endpoint.conn();
+    if conn.protocol() != Protocol::Tcp {
+        log::debug!(
+            "Trying to connect to `{instance_name}` using {:?}",
+            conn.protocol()
+        );
+        let client = match conn.protocol() {
+            Protocol::SharedMemory => client::connect_shared_memory(Some(instance_name), None),
+            _ => client::connect_pipe(endpoint, Some(instance_name), None).await,
+        };
+        return match client {
+            Ok(mut client) => obtain_properties(&mut client, instance_name)
+                .await
+                .map(|p| to_instance_builder(endpoint, &p)),
+            Err(e) => {
+                log::error!("Error creating client for `{instance_name}`: {e}");
+                None
+            }
+        };
+    }
     if is_local_host(auth, conn) && !is_use_tcp(instance_name, auth, conn) {
         if let Ok(mut client) = create_odbc_client(instance_name, None) {
             log::debug!("Trying to connect to `{instance_name}` using ODBC");
@@ -2347,27 +3670,30 @@ fn apply_customization(
                 .map(|h| h.clone().into()),
         )
         .alias(customization.alias())
+        .output(customization.output().cloned())
 }
 
 /// Intelligent async processing of the data
-async fn generate_result(
-    instances: &[SqlInstance],
+async fn generate_result<'a>(
+    instances: &'a [SqlInstance],
     sections: &[Section],
     ms_sql: &config::ms_sql::Config,
-) -> Result<String> {
+    scheduler: &Scheduler,
+) -> Vec<(&'a SqlInstance, String)> {
     // place all futures now in vector for future asynchronous processing
-    let tasks = instances
-        .iter()
-        .map(move |instance| instance.generate_sections(sections));
+    let tasks = instances.iter().map(move |instance| async move {
+        (
+            instance,
+            instance.generate_sections(sections, scheduler).await,
+        )
+    });
 
     // processing here
     let s: u32 = ms_sql.options().max_connections().into();
-    let results = stream::iter(tasks)
+    stream::iter(tasks)
         .buffer_unordered(s as usize)
         .collect::<Vec<_>>()
-        .await;
-
-    Ok(results.join(""))
+        .await
 }
 
 // TODO(sk):probably normal SQL query  is better than registry reading SQL query
@@ -2537,13 +3863,69 @@ fn to_sql_instance(answers: &UniAnswer) -> Vec<SqlInstanceBuilder> {
 #[cfg(test)]
 mod tests {
     use super::{
-        generate_instance_entries, generate_signaling_blocks, SqlInstance, SqlInstanceBuilder,
+        declare_since, generate_instance_entries, generate_signaling_blocks, is_counters_complete,
+        skip_ag_rows, to_patch_status, to_server_roles_entries, to_tenant_usages,
+        write_routed_outputs, InstanceContext, SqlInstance, SqlInstanceBuilder,
+        SqlInstanceProperties,
     };
     use crate::args::Args;
+    use crate::config::section::{names, SectionBuilder};
+    use crate::ms_sql::section::Section;
+    use crate::ms_sql::sqls::Permission;
     use crate::setup::Env;
-    use crate::types::Port;
+    use crate::types::{InstanceName, Port};
     use std::path::Path;
 
+    #[test]
+    fn test_to_patch_status() {
+        assert_eq!(to_patch_status("16.0.4135.4", None), "unknown");
+        assert_eq!(
+            to_patch_status("16.0.4135.4", Some("16.0.4135.4")),
+            "up_to_date"
+        );
+        assert_eq!(
+            to_patch_status("16.0.4135.4", Some("16.0.4125.3")),
+            "up_to_date"
+        );
+        assert_eq!(
+            to_patch_status("16.0.1000.6", Some("16.0.4135.4")),
+            "updates_available"
+        );
+    }
+
+    #[test]
+    fn test_to_server_roles_entries() {
+        let name = InstanceName::from("I");
+        let row = |r: &[&str]| r.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+        let rows = vec![
+            row(&["sysadmin", "sa", "SQL_LOGIN", "0", "2024-01-01 10:00:00"]),
+            row(&[
+                "sysadmin",
+                "dom\\adm",
+                "WINDOWS_LOGIN",
+                "0",
+                "2024-02-01 10:00:00",
+            ]),
+            row(&["dbcreator", "app", "SQL_LOGIN", "0", "2024-03-01 10:00:00"]),
+        ];
+        assert_eq!(
+            to_server_roles_entries(&name, &rows, None, '|'),
+            "I|sysadmin|sa|SQL_LOGIN|0|2024-01-01 10:00:00|\n\
+             I|sysadmin|dom\\adm|WINDOWS_LOGIN|0|2024-02-01 10:00:00|\n"
+        );
+        assert_eq!(
+            to_server_roles_entries(
+                &name,
+                &rows,
+                Some("sysadmin\tsa\ndbcreator\tapp\nsecurityadmin\told"),
+                '|'
+            ),
+            "I|sysadmin|sa|SQL_LOGIN|0|2024-01-01 10:00:00|\n\
+             I|sysadmin|dom\\adm|WINDOWS_LOGIN|0|2024-02-01 10:00:00|added\n\
+             I|securityadmin|old|||||removed\n"
+        );
+    }
+
     #[test]
     fn test_generate_state_entry() {
         let i = SqlInstanceBuilder::new().name("test_name").build();
@@ -2558,6 +3940,88 @@ mod tests {
         );
     }
 
+    fn make_properties(version: &str, authenticating_database_id: &str) -> SqlInstanceProperties {
+        SqlInstanceProperties {
+            name: "MSSQLSERVER".to_string().into(),
+            version: version.to_string().into(),
+            computer_name: "computer".to_string().into(),
+            edition: "Standard".to_string().into(),
+            product_level: "RTM".to_string(),
+            net_bios: "computer".to_string(),
+            update_level: String::new(),
+            update_reference: String::new(),
+            update_date: String::new(),
+            authenticating_database_id: authenticating_database_id.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_instance_context_version_major() {
+        assert_eq!(InstanceContext::default().version_major(), None);
+        let make_context = |version: &str| InstanceContext {
+            properties: Some(make_properties(version, "0")),
+            ..Default::default()
+        };
+        assert_eq!(make_context("16.0.1000.6").version_major(), Some(16));
+        assert_eq!(make_context("").version_major(), None);
+    }
+
+    #[test]
+    fn test_skip_ag_rows() {
+        let body = "AG1\tnode1\t2\tHEALTHY\tONLINE\n\nAG2\tnode2\t2\tHEALTHY\tONLINE\n\n";
+        assert_eq!(skip_ag_rows(body, '\t', &[]), body);
+        assert_eq!(
+            skip_ag_rows(body, '\t', &["AG1"]),
+            "AG2\tnode2\t2\tHEALTHY\tONLINE\n\n"
+        );
+        assert_eq!(skip_ag_rows(body, '\t', &["AG1", "AG2"]), "");
+        assert_eq!(
+            skip_ag_rows("INST ERROR: x\n", '\t', &["AG1"]),
+            "INST ERROR: x\n"
+        );
+    }
+
+    #[test]
+    fn test_declare_since() {
+        assert_eq!(
+            declare_since("2024-01-02 03:04:05.678\n"),
+            "DECLARE @since DATETIME = ISNULL(TRY_CONVERT(DATETIME, N'2024-01-02 03:04:05.678', 121), DATEADD(HOUR, -24, GETDATE()));\n"
+        );
+        assert!(!declare_since("x'; DROP TABLE t; --").contains("DROP"));
+    }
+
+    #[test]
+    fn test_instance_context_degraded() {
+        let context = InstanceContext::default();
+        let name = InstanceName::from("A");
+        assert_eq!(context.generate_degraded_section(&name), "");
+        let uptime = Section::new(&SectionBuilder::new(names::UPTIME).build(), None);
+        let jobs = Section::new(&SectionBuilder::new(names::JOBS).build(), None);
+        context.mark_degraded(&uptime, Permission::ViewServerState);
+        context.mark_degraded(&jobs, Permission::Msdb);
+        assert_eq!(
+            context.generate_degraded_section(&name),
+            "<<<mssql_degraded:sep(124)>>>\nA|uptime|view_server_state\nA|jobs|msdb\n"
+        );
+    }
+
+    #[test]
+    fn test_instance_context_contained_user() {
+        let make_context = |id: &str| InstanceContext {
+            properties: Some(make_properties("16.0.1000.6", id)),
+            ..Default::default()
+        };
+        let jobs = Section::new(&SectionBuilder::new(names::JOBS).build(), None);
+        let counters = Section::new(&SectionBuilder::new(names::COUNTERS).build(), None);
+        assert!(!InstanceContext::default().is_contained_user());
+        assert!(!make_context("").is_contained_user());
+        assert!(!make_context("0").is_contained_user());
+        assert!(make_context("0").is_accessible(&jobs));
+        assert!(make_context("7").is_contained_user());
+        assert!(!make_context("7").is_accessible(&jobs));
+        assert!(make_context("7").is_accessible(&counters));
+    }
+
     fn make_instances() -> Vec<SqlInstance> {
         let builders = vec![
             SqlInstanceBuilder::new().name("A"),
@@ -2640,6 +4104,52 @@ mssql:
         );
     }
 
+    #[test]
+    fn test_write_routed_outputs() {
+        use crate::config::ms_sql::Output;
+        use crate::config::yaml::test_tools::create_yaml;
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("a.txt");
+        let make_output = |stdout: &str| {
+            Output::from_yaml(&create_yaml(&format!(
+                "output:\n  file: {:?}\n  stdout: {stdout}\n",
+                file.to_string_lossy()
+            )))
+            .unwrap()
+        };
+        let ms_sql = crate::config::ms_sql::Config::from_string(
+            "---\nmssql:\n  main:\n    authentication:\n      username: u\n      type: sql_server\n    sections:\n      - uptime:\n",
+        )
+        .unwrap()
+        .unwrap();
+        let a = SqlInstanceBuilder::new()
+            .name("A")
+            .output(make_output("no"))
+            .build();
+        let b = SqlInstanceBuilder::new()
+            .name("B")
+            .output(make_output("yes"))
+            .build();
+        let c = SqlInstanceBuilder::new().name("C").build();
+        assert!(!a.is_printed());
+        assert!(b.is_printed());
+        assert!(c.is_printed());
+        write_routed_outputs(
+            &ms_sql,
+            &[
+                (&a, "A|body\n".to_string()),
+                (&b, "B|body\n".to_string()),
+                (&c, "C|body\n".to_string()),
+            ],
+        );
+        let written = std::fs::read_to_string(&file).unwrap();
+        assert!(written.contains("<<<mssql_uptime:sep(124)>>>\n"));
+        assert!(written.contains("MSSQL_A|config|"));
+        assert!(written.contains("A|body\n") && written.contains("B|body\n"));
+        assert!(!written.contains("C|body"));
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
     #[test]
     fn test_instance_header_footer() {
         let normal = SqlInstanceBuilder::new().name("test_name").build();
@@ -2653,6 +4163,42 @@ mssql:
         assert_eq!(piggyback.generate_footer(), "<<<<>>>>\n");
     }
 
+    #[test]
+    fn test_to_tenant_usages() {
+        let row = |values: [&str; 5]| values.map(str::to_string).to_vec();
+        let rows = [
+            row(["crm", "acme", "100", "10", "3600"]),
+            row(["sales", "", "200", "20", "-1"]),
+            row(["shop", "acme", "300", "30", "60"]),
+            row(["web", "", "1", "1", "1"]),
+            row(["wiki", "globex", "5", "1", "7200"]),
+        ];
+        let tenants = to_tenant_usages(&rows, &[("Sales".to_string(), "acme".to_string())]);
+        assert_eq!(tenants.keys().collect::<Vec<&String>>(), ["acme", "globex"]);
+        let acme = &tenants["acme"];
+        assert_eq!(acme.databases, ["crm", "sales", "shop"]);
+        assert_eq!(
+            acme.to_entry(&InstanceName::from("MSSQLSERVER"), "acme", '|'),
+            "MSSQLSERVER|acme|3|600|60|3600|1\n"
+        );
+        assert_eq!(tenants["globex"].max_backup_age, 7200);
+        assert!(to_tenant_usages(&rows[1..2], &[]).is_empty());
+    }
+
+    #[test]
+    fn test_is_counters_complete() {
+        assert!(is_counters_complete(
+            "SQLServer:General_Statistics|user_connections|None|3\n\
+             MSSQL_SQLEXPRESS:Buffer_Manager|page_life_expectancy|None|1234\n",
+            '|'
+        ));
+        assert!(!is_counters_complete(
+            "SQLServer:General_Statistics|user_connections|None|3\n",
+            '|'
+        ));
+        assert!(!is_counters_complete("", '|'));
+    }
+
     #[test]
     fn test_calc_unused() {
         use crate::ms_sql::instance::calc_unused;
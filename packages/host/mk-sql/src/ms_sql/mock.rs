@@ -0,0 +1,136 @@
+// Copyright (C) 2024 Checkmk GmbH - License: GNU General Public License v2
+// This file is part of Checkmk (https://checkmk.com). It is subject to the terms and
+// conditions defined in the file COPYING, which is part of this source code package.
+
+//! Randomized output of a known section generated from its schema.
+//!
+//! Helps to develop and to fuzz parsers of check plugins without SQL Server: the output has
+//! the header, the instance line of decorated sections and rows of typed random values.
+//! The same seed gives the same output.
+use super::registry;
+use super::schema::{Column, ColumnType};
+use super::section::Section;
+use crate::config::section::SectionBuilder;
+use anyhow::Result;
+
+const INSTANCE_NAME: &str = "MSSQLSERVER";
+const WORDS: [&str; 8] = [
+    "alpha", "bravo", "charlie", "delta", "echo", "foxtrot", "golf", "hotel",
+];
+
+/// xorshift64, good enough for test data, no dependency
+struct Random(u64);
+
+impl Random {
+    fn new(seed: u64) -> Self {
+        // zero state is a fixed point of xorshift
+        Self(seed.max(1))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, limit: u64) -> u64 {
+        self.next() % limit
+    }
+}
+
+pub fn exec(name: &str, rows: u32, seed: Option<u64>) -> Result<String> {
+    let definition =
+        registry::find(name).ok_or_else(|| anyhow::anyhow!("Unknown section `{name}`"))?;
+    let columns = definition.columns();
+    if columns.is_empty() {
+        anyhow::bail!("Section `{name}` has no schema");
+    }
+    let seed = seed.unwrap_or_else(|| {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default()
+    });
+    let mut random = Random::new(seed);
+    let sep = definition.sep();
+    let section = Section::new(&SectionBuilder::new(name).build(), None);
+    let mut output = section.to_plain_header();
+    if definition.is_decorated() {
+        output += &format!("{INSTANCE_NAME}\n");
+    }
+    for _ in 0..rows {
+        output += &columns
+            .iter()
+            .map(|c| make_value(c, &mut random))
+            .collect::<Vec<String>>()
+            .join(&sep.to_string());
+        output.push('\n');
+    }
+    Ok(output)
+}
+
+fn make_value(column: &Column, random: &mut Random) -> String {
+    match column.column_type {
+        ColumnType::Text if column.name == "instance" => INSTANCE_NAME.to_string(),
+        ColumnType::Text => format!(
+            "{}_{}",
+            WORDS[random.below(WORDS.len() as u64) as usize],
+            random.below(100)
+        ),
+        ColumnType::Integer => random.below(100_000).to_string(),
+        ColumnType::Float => format!("{}.{:02}", random.below(10_000), random.below(100)),
+        ColumnType::Flag => random.below(2).to_string(),
+        ColumnType::Timestamp => format!(
+            "{}-{:02}-{:02} {:02}:{:02}:{:02}",
+            2020 + random.below(6),
+            1 + random.below(12),
+            1 + random.below(28),
+            random.below(24),
+            random.below(60),
+            random.below(60)
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::section::names;
+
+    #[test]
+    fn test_exec() {
+        let output = exec(names::LOG_SHIPPING, 3, Some(42)).unwrap();
+        let lines = output.lines().collect::<Vec<&str>>();
+        assert_eq!(lines[0], "<<<mssql_logshipping:sep(124)>>>");
+        assert_eq!(lines[1], INSTANCE_NAME);
+        assert_eq!(lines.len(), 5);
+        assert!(lines[2..].iter().all(|l| l.split('|').count() == 11));
+        assert_eq!(output, exec(names::LOG_SHIPPING, 3, Some(42)).unwrap());
+        assert_ne!(output, exec(names::LOG_SHIPPING, 3, Some(43)).unwrap());
+
+        let output = exec(names::TRANSACTION_LOG, 1, Some(1)).unwrap();
+        assert!(output
+            .lines()
+            .nth(1)
+            .unwrap()
+            .starts_with(&format!("{INSTANCE_NAME}|")));
+
+        assert!(exec("unknown", 1, None).is_err());
+    }
+
+    #[test]
+    fn test_make_value() {
+        let mut random = Random::new(0);
+        let column = Column {
+            name: "t",
+            column_type: ColumnType::Timestamp,
+        };
+        assert_eq!(make_value(&column, &mut random).len(), 19);
+        let column = Column {
+            name: "f",
+            column_type: ColumnType::Flag,
+        };
+        assert!(["0", "1"].contains(&make_value(&column, &mut random).as_str()));
+    }
+}
@@ -0,0 +1,114 @@
+// Copyright (C) 2024 Checkmk GmbH - License: GNU General Public License v2
+// This file is part of Checkmk (https://checkmk.com). It is subject to the terms and
+// conditions defined in the file COPYING, which is part of this source code package.
+
+//! Connections of an instance reused by its sections during one run.
+//!
+//! A section takes an idle connection to the same database with the same intent and gives it
+//! back after a successful query: the login, which may take long with AD, is made once per
+//! database and intent instead of once per section. Failed connections are never given back.
+//! The pool lives in the runtime of the instance: threaded sections connect on their own.
+use super::client::UniClient;
+use crate::config::ms_sql::ApplicationIntent;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+
+/// idle connections kept per database and intent
+pub const MAX_IDLE: usize = 2;
+
+type Key = (Option<String>, ApplicationIntent);
+
+#[derive(Default)]
+pub struct ClientPool {
+    idle: Mutex<HashMap<Key, Vec<UniClient>>>,
+}
+
+impl ClientPool {
+    /// idle connection to the database, None - a new one must be created
+    pub fn take(&self, database: Option<&str>, intent: ApplicationIntent) -> Option<UniClient> {
+        let client = self
+            .idle
+            .lock()
+            .ok()?
+            .get_mut(&(database.map(str::to_string), intent))?
+            .pop();
+        if client.is_some() {
+            log::debug!("Pooled connection to {database:?} {intent:?} is reused");
+        }
+        client
+    }
+
+    /// the connection is dropped if the pool of the database is full
+    pub fn give_back(&self, database: Option<&str>, intent: ApplicationIntent, client: UniClient) {
+        if let Ok(mut idle) = self.idle.lock() {
+            let clients = idle
+                .entry((database.map(str::to_string), intent))
+                .or_default();
+            if clients.len() < MAX_IDLE {
+                clients.push(client);
+            }
+        }
+    }
+
+    pub fn idle_count(&self) -> usize {
+        self.idle
+            .lock()
+            .map(|idle| idle.values().map(Vec::len).sum())
+            .unwrap_or_default()
+    }
+}
+
+impl fmt::Debug for ClientPool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ClientPool {{ idle: {} }}", self.idle_count())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ms_sql::client::OdbcClient;
+
+    fn make_client(name: &str) -> UniClient {
+        UniClient::Odbc(OdbcClient::new(name))
+    }
+
+    fn name_of(client: Option<UniClient>) -> Option<String> {
+        match client? {
+            UniClient::Odbc(c) => Some(c.conn_string().to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_client_pool() {
+        let pool = ClientPool::default();
+        assert!(pool.take(None, ApplicationIntent::ReadWrite).is_none());
+        pool.give_back(None, ApplicationIntent::ReadWrite, make_client("a"));
+        pool.give_back(Some("db"), ApplicationIntent::ReadWrite, make_client("b"));
+        pool.give_back(None, ApplicationIntent::ReadOnly, make_client("c"));
+        assert_eq!(pool.idle_count(), 3);
+        assert_eq!(
+            name_of(pool.take(Some("db"), ApplicationIntent::ReadWrite)),
+            Some("b".to_string())
+        );
+        assert!(pool
+            .take(Some("db"), ApplicationIntent::ReadWrite)
+            .is_none());
+        assert_eq!(
+            name_of(pool.take(None, ApplicationIntent::ReadOnly)),
+            Some("c".to_string())
+        );
+        assert_eq!(pool.idle_count(), 1);
+    }
+
+    #[test]
+    fn test_client_pool_limit() {
+        let pool = ClientPool::default();
+        for name in ["a", "b", "c"] {
+            pool.give_back(None, ApplicationIntent::ReadWrite, make_client(name));
+        }
+        assert_eq!(pool.idle_count(), MAX_IDLE);
+    }
+}
@@ -9,16 +9,17 @@ use crate::platform::Block;
 #[cfg(windows)]
 use crate::platform::odbc;
 
+use crate::config::section::Render;
 use crate::types::{ComputerName, InstanceName};
 
 use super::sqls::find_known_query;
-use super::{client::UniClient, sqls};
+use super::{client::UniClient, fixture, sqls, value};
 use std::borrow::Borrow;
 
 use anyhow::Result;
 use std::time::Instant;
 
-use tiberius::{ColumnData, Query, Row};
+use tiberius::{Query, Row};
 
 pub type SqlRows = Vec<Row>;
 pub enum UniAnswer {
@@ -56,6 +57,29 @@ impl UniAnswer {
         .unwrap_or_default()
     }
 
+    /// value of the column in the first row, None if no rows
+    pub fn get_first_value_by_name(&self, name: &str) -> Option<String> {
+        match self {
+            UniAnswer::Rows(rows) => rows.first().map(|r| r.get_value_by_name(name)),
+            UniAnswer::Block(b) => b.first().map(|r| b.get_value_by_name(r, name)),
+        }
+    }
+
+    /// values of the columns for every row
+    pub fn get_rows_by_names(&self, names: &[&str]) -> Vec<Vec<String>> {
+        match self {
+            UniAnswer::Rows(rows) => rows
+                .iter()
+                .map(|r| names.iter().map(|n| r.get_value_by_name(n)).collect())
+                .collect(),
+            UniAnswer::Block(b) => b
+                .rows
+                .iter()
+                .map(|r| names.iter().map(|n| b.get_value_by_name(r, n)).collect())
+                .collect(),
+        }
+    }
+
     pub fn get_is_clustered(&self) -> bool {
         match self {
             UniAnswer::Rows(rows) => rows.first().map(|r| r.get_value_by_name("is_clustered")),
@@ -73,7 +97,7 @@ pub trait Column<'a> {
     fn get_optional_value_by_idx(&self, idx: usize) -> Option<String>;
     fn get_value_by_name(&self, idx: &str) -> String;
     fn get_optional_value_by_name(&self, idx: &str) -> Option<String>;
-    fn get_all(self, sep: char) -> String;
+    fn get_all(self, sep: char, render: Option<Render>) -> String;
 }
 
 impl<'a> Column<'a> for Row {
@@ -115,29 +139,59 @@ impl<'a> Column<'a> for Row {
             .map(str::to_string)
     }
 
-    /// more or less correct method to extract all data from the tiberius.Row
+    /// all columns of the tiberius.Row converted by `value::Value`
     /// unfortunately tiberius::Row implements only into_iter -> we are using `self``, not `&self``
-    fn get_all(self, sep: char) -> String {
-        self.into_iter()
-            .map(|c| match c {
-                ColumnData::Guid(v) => v
-                    .map(|v| format!("{{{}}}", v.to_string().to_uppercase()))
-                    .unwrap_or_default(),
-                ColumnData::I16(v) => v.map(|v| v.to_string()).unwrap_or_default(),
-                ColumnData::I32(v) => v.map(|v| v.to_string()).unwrap_or_default(),
-                ColumnData::F32(v) => v.map(|v| v.to_string()).unwrap_or_default(),
-                ColumnData::F64(v) => v.map(|v| v.to_string()).unwrap_or_default(),
-                ColumnData::Bit(v) => v.map(|v| v.to_string()).unwrap_or_default(),
-                ColumnData::U8(v) => v.map(|v| v.to_string()).unwrap_or_default(),
-                ColumnData::String(v) => v.map(|v| v.to_string()).unwrap_or_default(),
-                ColumnData::Numeric(v) => v.map(|v| v.to_string()).unwrap_or_default(),
-                _ => format!("Unsupported '{:?}'", c),
-            })
+    /// binary, XML and text breaking the format are rendered if `render` is set
+    fn get_all(self, sep: char, render: Option<Render>) -> String {
+        value::to_values(self)
+            .iter()
+            .map(|v| v.to_field(sep, render))
             .collect::<Vec<String>>()
             .join(&sep.to_string())
     }
 }
 
+/// text without line breaks and separators is kept as is
+pub fn render_text(text: &str, sep: char, render: Render) -> String {
+    if !text.contains(['\r', '\n', sep]) {
+        return text.to_string();
+    }
+    match render {
+        Render::Truncate(max_length) => {
+            let flat = text
+                .chars()
+                .map(|c| {
+                    if c == '\r' || c == '\n' || c == sep {
+                        ' '
+                    } else {
+                        c
+                    }
+                })
+                .collect::<String>();
+            truncate(&flat, max_length)
+        }
+        _ => render_bytes(text.as_bytes(), render),
+    }
+}
+
+pub fn render_bytes(bytes: &[u8], render: Render) -> String {
+    match render {
+        Render::Truncate(max_length) => truncate(&to_hex(bytes), max_length),
+        Render::Hex => to_hex(bytes),
+        Render::Base64 => openssl::base64::encode_block(bytes),
+        Render::Drop => String::new(),
+    }
+}
+
+fn truncate(text: &str, max_length: usize) -> String {
+    text.chars().take(max_length).collect()
+}
+
+/// SQL Server notation: `0x` prefix, upper case
+fn to_hex(bytes: &[u8]) -> String {
+    "0x".to_string() + &bytes.iter().map(|b| format!("{b:02X}")).collect::<String>()
+}
+
 /// Runs predefined query
 /// return Vec\<Vec\<Row\>\> as a Results Vec: one Vec\<Row\> per one statement in query.
 pub async fn run_known_query<T: Borrow<sqls::Id>>(
@@ -189,6 +243,14 @@ async fn _run_known_query<T: Borrow<sqls::Id>>(
 async fn exec_sql(client: &mut UniClient, query: &str) -> Result<Vec<UniAnswer>> {
     log::debug!("Query to run short: `{}`", make_short_query(query));
     log::trace!("Query to run: `{}`", query);
+    match client {
+        UniClient::Fixture(client) => fixture::exec(client, query).await,
+        _ => exec_native(client, query).await,
+    }
+}
+
+/// executes query using real connection
+pub async fn exec_native(client: &mut UniClient, query: &str) -> Result<Vec<UniAnswer>> {
     match client {
         UniClient::Std(client) => {
             let stream = Query::new(query).query(client).await?;
@@ -196,6 +258,12 @@ async fn exec_sql(client: &mut UniClient, query: &str) -> Result<Vec<UniAnswer>>
             let answers: Vec<UniAnswer> = tiberius_rows.into_iter().map(UniAnswer::Rows).collect();
             Ok(answers)
         }
+        #[cfg(windows)]
+        UniClient::Pipe(client) => {
+            let stream = Query::new(query).query(client).await?;
+            let tiberius_rows: Vec<Vec<Row>> = stream.into_results().await?;
+            Ok(tiberius_rows.into_iter().map(UniAnswer::Rows).collect())
+        }
         UniClient::Odbc(client) => {
             #[cfg(windows)]
             {
@@ -207,6 +275,7 @@ async fn exec_sql(client: &mut UniClient, query: &str) -> Result<Vec<UniAnswer>>
             #[cfg(unix)]
             anyhow::bail!("ODBC is not supported for now `{}`", client.conn_string());
         }
+        UniClient::Fixture(_) => anyhow::bail!("Fixture client can't be nested"),
     }
 }
 
@@ -265,3 +334,33 @@ pub fn get_first_row_column(rows: &[Row], column: usize) -> Option<String> {
             .map(str::to_string)
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_text() {
+        let json = "{\"a\": 1,\n \"b\": \"x|y\"}";
+        assert_eq!(render_text("plain", '|', Render::Drop), "plain");
+        assert_eq!(
+            render_text(json, '|', Render::Truncate(100)),
+            "{\"a\": 1,  \"b\": \"x y\"}"
+        );
+        assert_eq!(render_text(json, '|', Render::Truncate(5)), "{\"a\":");
+        assert_eq!(render_text("a\nb", '|', Render::Hex), "0x610A62");
+        assert_eq!(render_text("a|b", '|', Render::Drop), "");
+    }
+
+    #[test]
+    fn test_render_bytes() {
+        assert_eq!(render_bytes(&[0, 0xAB, 0x10], Render::Hex), "0x00AB10");
+        assert_eq!(render_bytes(&[0, 0xAB, 0x10], Render::Truncate(4)), "0x00");
+        assert_eq!(render_bytes(b"", Render::Base64), "");
+        assert_eq!(render_bytes(b"f", Render::Base64), "Zg==");
+        assert_eq!(render_bytes(b"fo", Render::Base64), "Zm8=");
+        assert_eq!(render_bytes(b"foo", Render::Base64), "Zm9v");
+        assert_eq!(render_bytes(b"foobar", Render::Base64), "Zm9vYmFy");
+        assert_eq!(render_bytes(&[1, 2], Render::Drop), "");
+    }
+}
@@ -0,0 +1,537 @@
+// Copyright (C) 2024 Checkmk GmbH - License: GNU General Public License v2
+// This file is part of Checkmk (https://checkmk.com). It is subject to the terms and
+// conditions defined in the file COPYING, which is part of this source code package.
+
+//! Registry of known sections.
+//!
+//! Built-in sections are listed in `BUILTIN`. A new section, also an out-of-tree one, is
+//! added by implementing [`SectionDefinition`] and calling [`register`] before the config
+//! is loaded: defaults, query lookup, setup validation and the output schema take it from
+//! here.
+use super::schema::{self, Column};
+use super::sqls::{self, Id, Permission};
+use crate::config::section::names;
+use anyhow::Result;
+use std::sync::{Arc, RwLock};
+
+pub trait SectionDefinition: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// default separator, may be overridden in config
+    fn sep(&self) -> char {
+        ' '
+    }
+
+    /// default kind, may be overridden in config
+    fn is_async(&self) -> bool {
+        false
+    }
+
+    /// included in the section list when config has none
+    fn is_predefined(&self) -> bool {
+        true
+    }
+
+    /// output starts with the instance name
+    fn is_decorated(&self) -> bool {
+        false
+    }
+
+    /// output is collected for every database of the instance
+    fn is_per_database(&self) -> bool {
+        false
+    }
+
+    /// database to connect to, None - default one
+    fn main_db(&self) -> Option<&str> {
+        None
+    }
+
+    /// minimal major version of the instance, the section is skipped on older ones
+    fn min_version(&self) -> u32 {
+        0
+    }
+
+    /// expensive query: cached for a day, low priority and generated after other sections
+    fn is_heavy(&self) -> bool {
+        false
+    }
+
+    /// query to be used if sql dir provides nothing
+    fn query(&self) -> Option<String>;
+
+    /// query for the major version of the instance, 0 - unknown version
+    fn query_for_version(&self, _version: u32) -> Option<String> {
+        self.query()
+    }
+
+    /// only built-in sections have a predefined query id
+    fn sql_id(&self) -> Option<Id> {
+        None
+    }
+
+    /// output columns for the schema, empty - not described
+    fn columns(&self) -> &[Column] {
+        &[]
+    }
+
+    /// reduced query for the major version of the instance if the query has failed with the
+    /// error because of missing permission
+    fn fallback_query(&self, _version: u32, _error: &str) -> Option<(String, Permission)> {
+        None
+    }
+}
+
+struct Builtin {
+    name: &'static str,
+    id: Id,
+    sep: char,
+    is_async: bool,
+    is_predefined: bool,
+    is_decorated: bool,
+    is_per_database: bool,
+    main_db: Option<&'static str>,
+    min_version: u32,
+    is_heavy: bool,
+    /// (min major version, query) ordered by version, replace the default query
+    variants: &'static [(u32, Id)],
+}
+
+impl Builtin {
+    const fn new(name: &'static str, id: Id, sep: char) -> Self {
+        Self {
+            name,
+            id,
+            sep,
+            is_async: false,
+            is_predefined: true,
+            is_decorated: false,
+            is_per_database: false,
+            main_db: None,
+            min_version: 0,
+            is_heavy: false,
+            variants: &[],
+        }
+    }
+
+    const fn asynchronous(mut self) -> Self {
+        self.is_async = true;
+        self
+    }
+
+    /// must be listed in config explicitly
+    const fn optional(mut self) -> Self {
+        self.is_predefined = false;
+        self
+    }
+
+    const fn decorated(mut self) -> Self {
+        self.is_decorated = true;
+        self
+    }
+
+    const fn per_database(mut self) -> Self {
+        self.is_per_database = true;
+        self
+    }
+
+    const fn main_db(mut self, db: &'static str) -> Self {
+        self.main_db = Some(db);
+        self
+    }
+
+    const fn since(mut self, min_version: u32) -> Self {
+        self.min_version = min_version;
+        self
+    }
+
+    const fn heavy(mut self) -> Self {
+        self.is_heavy = true;
+        self
+    }
+
+    const fn variants(mut self, variants: &'static [(u32, Id)]) -> Self {
+        self.variants = variants;
+        self
+    }
+
+    fn find_id(&self, version: u32) -> Id {
+        self.variants
+            .iter()
+            .rev()
+            .find(|(min_version, _)| version >= *min_version)
+            .map_or(self.id, |(_, id)| *id)
+    }
+}
+
+fn load_known_query(id: Id) -> Option<String> {
+    sqls::find_known_query(id)
+        .map_err(|e| {
+            log::error!("{e}");
+            e
+        })
+        .ok()
+        .map(str::to_string)
+}
+
+impl SectionDefinition for Builtin {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn sep(&self) -> char {
+        self.sep
+    }
+
+    fn is_async(&self) -> bool {
+        self.is_async
+    }
+
+    fn is_predefined(&self) -> bool {
+        self.is_predefined
+    }
+
+    fn is_decorated(&self) -> bool {
+        self.is_decorated
+    }
+
+    fn is_per_database(&self) -> bool {
+        self.is_per_database
+    }
+
+    fn main_db(&self) -> Option<&str> {
+        self.main_db
+    }
+
+    fn min_version(&self) -> u32 {
+        self.min_version
+    }
+
+    fn is_heavy(&self) -> bool {
+        self.is_heavy
+    }
+
+    fn query(&self) -> Option<String> {
+        load_known_query(self.id)
+    }
+
+    fn query_for_version(&self, version: u32) -> Option<String> {
+        load_known_query(self.find_id(version))
+    }
+
+    fn sql_id(&self) -> Option<Id> {
+        Some(self.id)
+    }
+
+    fn columns(&self) -> &[Column] {
+        schema::find_builtin(self.name)
+    }
+
+    fn fallback_query(&self, version: u32, error: &str) -> Option<(String, Permission)> {
+        sqls::find_fallback(self.find_id(version), error)
+            .and_then(|(id, permission)| load_known_query(id).map(|q| (q, permission)))
+    }
+}
+
+/// order defines order of the default section list
+const BUILTIN: [Builtin; 49] = [
+    Builtin::new(names::INSTANCE, Id::InstanceProperties, '|'),
+    Builtin::new(names::DATABASES, Id::Databases, '|').per_database(),
+    Builtin::new(names::COUNTERS, Id::Counters, '|'),
+    Builtin::new(names::BLOCKED_SESSIONS, Id::BlockedSessions, '|'),
+    Builtin::new(names::TRANSACTION_LOG, Id::TransactionLogs, '|').per_database(),
+    Builtin::new(names::CLUSTERS, Id::Clusters, '|').per_database(),
+    Builtin::new(names::MIRRORING, Id::Mirroring, '\t')
+        .decorated()
+        .main_db("master"),
+    Builtin::new(names::AVAILABILITY_GROUPS, Id::AvailabilityGroups, '\t'),
+    Builtin::new(names::CONNECTIONS, Id::Connections, ' '),
+    Builtin::new(names::TABLE_SPACES, Id::TableSpaces, ' ')
+        .asynchronous()
+        .per_database(),
+    Builtin::new(names::DATAFILES, Id::Datafiles, '|')
+        .asynchronous()
+        .per_database(),
+    Builtin::new(names::BACKUP, Id::Backup, '|').asynchronous(),
+    Builtin::new(names::JOBS, Id::Jobs, '\t')
+        .asynchronous()
+        .decorated()
+        .main_db("msdb"),
+    Builtin::new(names::AGENT, Id::Agent, '\t')
+        .optional()
+        .decorated(),
+    Builtin::new(names::UPTIME, Id::Uptime, '|').optional(),
+    Builtin::new(names::DATABASE_OPTIONS, Id::DatabaseOptions, '|').optional(),
+    Builtin::new(names::CHECKPOINTS, Id::Checkpoints, '|')
+        .optional()
+        .decorated(),
+    Builtin::new(names::BACKUP_CHAIN, Id::BackupChain, '|')
+        .asynchronous()
+        .optional()
+        .decorated()
+        .main_db("msdb"),
+    Builtin::new(names::CHECKDB, Id::CheckDb, '|')
+        .asynchronous()
+        .optional()
+        .decorated(),
+    Builtin::new(names::WORKERS, Id::Workers, '|')
+        .optional()
+        .decorated(),
+    Builtin::new(names::LOCKS, Id::Locks, '|')
+        .optional()
+        .decorated(),
+    Builtin::new(names::FORCED_PLANS, Id::ForcedPlans, '|')
+        .asynchronous()
+        .optional()
+        .decorated(),
+    Builtin::new(names::SERVER_ROLES, Id::ServerRoles, '|').optional(),
+    Builtin::new(names::BUFFER_POOL_EXTENSION, Id::BufferPoolExtension, '|')
+        .optional()
+        .decorated()
+        .variants(&[(15, Id::BufferPoolExtensionHybrid)]),
+    Builtin::new(names::VERSION_STORE, Id::VersionStore, '|')
+        .optional()
+        .decorated()
+        .variants(&[(14, Id::VersionStoreUsage)]),
+    Builtin::new(names::ADR, Id::Adr, '|')
+        .optional()
+        .decorated()
+        .since(15),
+    Builtin::new(names::INSTANCE_SETTINGS, Id::InstanceSettings, '|')
+        .optional()
+        .decorated()
+        .variants(&[(14, Id::InstanceSettingsPrivileges)]),
+    Builtin::new(names::ELASTIC_POOL, Id::ElasticPool, '|')
+        .optional()
+        .decorated()
+        .main_db("master"),
+    Builtin::new(names::DEADLOCKS, Id::Deadlocks, '|')
+        .asynchronous()
+        .optional()
+        .decorated(),
+    Builtin::new(names::CONNECTION_SOURCES, Id::ConnectionSources, '|')
+        .optional()
+        .decorated(),
+    Builtin::new(names::WAITSTATS, Id::WaitStats, '|')
+        .optional()
+        .decorated(),
+    Builtin::new(names::CONNECTIVITY, Id::Connectivity, '|')
+        .optional()
+        .decorated(),
+    Builtin::new(names::INDEX_FRAGMENTATION, Id::IndexFragmentation, '|')
+        .asynchronous()
+        .optional()
+        .decorated()
+        .per_database()
+        .heavy(),
+    Builtin::new(names::QUERY_STORE, Id::QueryStore, '|')
+        .asynchronous()
+        .optional()
+        .decorated()
+        .per_database()
+        .since(13),
+    Builtin::new(names::MEMORY, Id::Memory, '|')
+        .optional()
+        .decorated(),
+    Builtin::new(names::LOG_SHIPPING, Id::LogShipping, '|')
+        .optional()
+        .decorated()
+        .main_db("msdb"),
+    Builtin::new(names::REPLICATION, Id::Replication, '|')
+        .optional()
+        .decorated()
+        .main_db("master"),
+    Builtin::new(names::AGENT_ALERTS, Id::AgentAlerts, '|')
+        .optional()
+        .decorated()
+        .main_db("msdb"),
+    Builtin::new(names::ERRORLOG, Id::ErrorLog, '|')
+        .asynchronous()
+        .optional(),
+    Builtin::new(names::FAILED_LOGINS, Id::FailedLogins, '|').optional(),
+    Builtin::new(names::ENCRYPTION, Id::Encryption, '|')
+        .optional()
+        .decorated()
+        .main_db("master"),
+    Builtin::new(names::BLOCKING_CHAINS, Id::BlockingChains, '|')
+        .optional()
+        .decorated(),
+    Builtin::new(names::IO_STATS, Id::IoStats, '|')
+        .optional()
+        .decorated(),
+    Builtin::new(names::PLAN_CACHE, Id::PlanCache, '|')
+        .optional()
+        .decorated(),
+    Builtin::new(names::LINKED_SERVERS, Id::LinkedServers, '|')
+        .asynchronous()
+        .optional()
+        .decorated(),
+    Builtin::new(names::TENANTS, Id::Tenants, '|')
+        .asynchronous()
+        .optional()
+        .decorated(),
+    Builtin::new(names::CDC, Id::Cdc, '|')
+        .optional()
+        .decorated(),
+    Builtin::new(names::ORPHANED_USERS, Id::OrphanedUsers, '|')
+        .asynchronous()
+        .optional()
+        .decorated()
+        .per_database(),
+    Builtin::new(names::DATABASES_V2, Id::DatabasesV2, '|')
+        .optional()
+        .decorated(),
+];
+
+lazy_static::lazy_static! {
+    static ref REGISTRY: RwLock<Vec<Arc<dyn SectionDefinition>>> = RwLock::new(
+        BUILTIN
+            .into_iter()
+            .map(|b| Arc::new(b) as Arc<dyn SectionDefinition>)
+            .collect()
+    );
+}
+
+/// Adds section to the registry, the name must be unique
+pub fn register(definition: Arc<dyn SectionDefinition>) -> Result<()> {
+    let mut registry = REGISTRY
+        .write()
+        .map_err(|e| anyhow::anyhow!("Section registry is poisoned: {e}"))?;
+    if registry.iter().any(|d| d.name() == definition.name()) {
+        anyhow::bail!("Section `{}` is already registered", definition.name());
+    }
+    registry.push(definition);
+    Ok(())
+}
+
+pub fn find(name: &str) -> Option<Arc<dyn SectionDefinition>> {
+    REGISTRY
+        .read()
+        .ok()
+        .and_then(|r| r.iter().find(|d| d.name() == name).cloned())
+}
+
+/// all registered sections in order of registration
+pub fn all() -> Vec<Arc<dyn SectionDefinition>> {
+    REGISTRY.read().map(|r| r.clone()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Extra;
+    impl SectionDefinition for Extra {
+        fn name(&self) -> &str {
+            "extra_test_section"
+        }
+        fn is_predefined(&self) -> bool {
+            false
+        }
+        fn query(&self) -> Option<String> {
+            Some("SELECT 1".to_string())
+        }
+    }
+
+    #[test]
+    fn test_builtin() {
+        let jobs = find(names::JOBS).unwrap();
+        assert_eq!(jobs.sep(), '\t');
+        assert!(jobs.is_async());
+        assert!(jobs.is_decorated());
+        assert_eq!(jobs.main_db(), Some("msdb"));
+        assert_eq!(jobs.sql_id(), Some(Id::Jobs));
+        assert!(jobs.query().is_some());
+        assert!(find("zu").is_none());
+        assert_eq!(
+            all()[..BUILTIN.len()]
+                .iter()
+                .filter(|d| d.is_predefined())
+                .count(),
+            13
+        );
+        assert!(!find(names::AGENT).unwrap().is_predefined());
+    }
+
+    #[test]
+    fn test_variants() {
+        let bpe = find(names::BUFFER_POOL_EXTENSION).unwrap();
+        assert_eq!(bpe.query(), load_known_query(Id::BufferPoolExtension));
+        assert_eq!(bpe.query_for_version(0), bpe.query());
+        assert_eq!(bpe.query_for_version(14), bpe.query());
+        assert_eq!(
+            bpe.query_for_version(15),
+            load_known_query(Id::BufferPoolExtensionHybrid)
+        );
+        assert_eq!(
+            bpe.query_for_version(16),
+            load_known_query(Id::BufferPoolExtensionHybrid)
+        );
+        let jobs = find(names::JOBS).unwrap();
+        assert_eq!(jobs.query_for_version(16), jobs.query());
+        assert_eq!(jobs.min_version(), 0);
+        assert_eq!(find(names::ADR).unwrap().min_version(), 15);
+    }
+
+    #[test]
+    fn test_fallback_query() {
+        const NO_VSS: &str = "Token error: 'VIEW SERVER STATE permission was denied on object \
+            'server', database 'master'.' on server s executing  on line 1 (code: 300)";
+        const NO_VSPS: &str = "VIEW SERVER PERFORMANCE STATE permission was denied";
+        const NO_MSDB: &str =
+            "The SELECT permission was denied on the object 'sysjobs', database 'msdb', schema 'dbo'.";
+        assert_eq!(
+            Permission::find_missing(NO_VSS),
+            Some(Permission::ViewServerState)
+        );
+        assert_eq!(
+            Permission::find_missing(NO_VSPS),
+            Some(Permission::ViewServerState)
+        );
+        assert_eq!(Permission::find_missing(NO_MSDB), Some(Permission::Msdb));
+        assert_eq!(Permission::find_missing("Invalid object name"), None);
+        const NO_XP: &str = "The EXECUTE permission was denied on the object 'xp_readerrorlog', \
+            database 'mssqlsystemresource', schema 'sys'.";
+        assert_eq!(
+            Permission::find_missing(NO_XP),
+            Some(Permission::SecurityAdmin)
+        );
+
+        let uptime = find(names::UPTIME).unwrap();
+        assert_eq!(
+            uptime.fallback_query(16, NO_VSS),
+            load_known_query(Id::UptimeTempdb).map(|q| (q, Permission::ViewServerState))
+        );
+        assert!(uptime.fallback_query(16, NO_MSDB).is_none());
+        assert!(uptime.fallback_query(16, "timeout").is_none());
+        let version_store = find(names::VERSION_STORE).unwrap();
+        assert!(version_store.fallback_query(13, NO_VSS).is_none());
+        assert_eq!(
+            version_store.fallback_query(14, NO_VSS).unwrap().0,
+            load_known_query(Id::VersionStore).unwrap()
+        );
+        assert_eq!(
+            find(names::JOBS).unwrap().fallback_query(0, NO_MSDB),
+            load_known_query(Id::JobsView).map(|q| (q, Permission::Msdb))
+        );
+        assert_eq!(
+            find(names::ERRORLOG).unwrap().fallback_query(0, NO_XP),
+            load_known_query(Id::ErrorLogRingBuffer).map(|q| (q, Permission::SecurityAdmin))
+        );
+        assert!(find(names::COUNTERS)
+            .unwrap()
+            .fallback_query(0, NO_VSS)
+            .is_none());
+    }
+
+    #[test]
+    fn test_register() {
+        register(Arc::new(Extra)).unwrap();
+        assert!(register(Arc::new(Extra)).is_err());
+        let extra = find("extra_test_section").unwrap();
+        assert_eq!(extra.sep(), ' ');
+        assert_eq!(extra.query().as_deref(), Some("SELECT 1"));
+        assert!(extra.sql_id().is_none());
+        assert!(extra.columns().is_empty());
+    }
+}
@@ -0,0 +1,192 @@
+// Copyright (C) 2024 Checkmk GmbH - License: GNU General Public License v2
+// This file is part of Checkmk (https://checkmk.com). It is subject to the terms and
+// conditions defined in the file COPYING, which is part of this source code package.
+
+//! Retry of transient failures(`connection.retry`): timeouts and SQL Server errors which pass by
+//! themselves, e.g. a database of Azure SQL being moved. The delay is doubled with every attempt,
+//! a retry which would pass the deadline of the run(`options.run_budget`) is not made.
+use crate::config::ms_sql::Retry;
+use anyhow::Result;
+use std::future::Future;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// 4060 - database can't be opened, e.g. it is still recovering
+/// 40197, 40501, 40613 - Azure SQL: service error, service is busy, database is unavailable
+const TRANSIENT_CODES: &[u32] = &[4060, 40197, 40501, 40613];
+
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+pub fn is_transient(error: &anyhow::Error) -> bool {
+    let by_type = error.chain().any(|cause| {
+        if cause.is::<tokio::time::error::Elapsed>() {
+            return true;
+        }
+        match cause.downcast_ref::<tiberius::error::Error>() {
+            Some(tiberius::error::Error::Server(e)) => TRANSIENT_CODES.contains(&e.code()),
+            Some(tiberius::error::Error::Io { kind, .. }) => *kind == std::io::ErrorKind::TimedOut,
+            _ => false,
+        }
+    });
+    // errors converted to text, e.g. by ODBC or fixtures
+    let text = error.to_string().to_lowercase();
+    by_type
+        || text.contains("timeout")
+        || text.contains("timed out")
+        || TRANSIENT_CODES
+            .iter()
+            .any(|code| text.contains(&format!("code: {code},")))
+}
+
+/// `attempt` - retries made before, 0 for the first one
+/// jitter subtracts `seed` modulo the half of the delay
+fn make_delay(retry: &Retry, attempt: u32, seed: u64) -> Duration {
+    let delay = retry
+        .base_delay()
+        .saturating_mul(1 << attempt.min(16))
+        .min(MAX_DELAY);
+    if retry.jitter() {
+        let half = delay.as_millis() as u64 / 2;
+        delay - Duration::from_millis(seed % (half + 1))
+    } else {
+        delay
+    }
+}
+
+fn make_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| u64::from(d.subsec_nanos()))
+        .unwrap_or_default()
+}
+
+pub struct Backoff<'a> {
+    retry: Option<&'a Retry>,
+    deadline: Option<Instant>,
+    /// retries made
+    attempt: u32,
+}
+
+impl<'a> Backoff<'a> {
+    /// None retry - every failure is final
+    pub fn new(retry: Option<&'a Retry>, deadline: Option<Instant>) -> Self {
+        Self {
+            retry,
+            deadline,
+            attempt: 0,
+        }
+    }
+
+    /// delay before the next attempt, None - the failure is final: it is not transient,
+    /// attempts are exhausted or the deadline is too close
+    pub fn next_delay(&mut self, error: &anyhow::Error) -> Option<Duration> {
+        let retry = self.retry?;
+        if self.attempt + 1 >= retry.attempts() || !is_transient(error) {
+            return None;
+        }
+        let delay = make_delay(retry, self.attempt, make_seed());
+        if self.deadline.is_some_and(|d| Instant::now() + delay >= d) {
+            log::warn!("No retry of transient failure, the run budget is exhausted: {error}");
+            return None;
+        }
+        self.attempt += 1;
+        log::warn!(
+            "Transient failure, attempt {} of {} in {} ms: {error}",
+            self.attempt + 1,
+            retry.attempts(),
+            delay.as_millis()
+        );
+        Some(delay)
+    }
+}
+
+/// `f` is called again while it fails transiently
+pub async fn run<T, F, Fut>(mut backoff: Backoff<'_>, f: F) -> Result<T>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    loop {
+        match f().await {
+            Err(e) => match backoff.next_delay(&e) {
+                Some(delay) => tokio::time::sleep(delay).await,
+                None => return Err(e),
+            },
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::yaml::test_tools::create_yaml;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn make_retry(source: &str) -> Retry {
+        Retry::from_yaml(&create_yaml(source)).unwrap()
+    }
+
+    #[test]
+    fn test_is_transient() {
+        assert!(is_transient(&anyhow::anyhow!(
+            "Timeout: deadline has elapsed when creating client from config"
+        )));
+        assert!(is_transient(&anyhow::anyhow!(
+            "Token error: 'Database is not currently available' on server x executing  on line 1 (code: 40613, state: 1, class: 16)"
+        )));
+        assert!(!is_transient(&anyhow::anyhow!(
+            "Token error: 'Login failed' on server x executing  on line 1 (code: 18456, state: 1, class: 14)"
+        )));
+        assert!(!is_transient(&anyhow::anyhow!("code: 406130, state: 1")));
+    }
+
+    #[test]
+    fn test_make_delay() {
+        let retry = make_retry("retry:\n  base_delay: 100\n  jitter: no\n");
+        assert_eq!(make_delay(&retry, 0, 7), Duration::from_millis(100));
+        assert_eq!(make_delay(&retry, 2, 7), Duration::from_millis(400));
+        assert_eq!(make_delay(&retry, 31, 7), MAX_DELAY);
+        let retry = make_retry("retry:\n  base_delay: 100\n");
+        assert_eq!(make_delay(&retry, 0, 7), Duration::from_millis(93));
+        assert_eq!(make_delay(&retry, 0, 50), Duration::from_millis(50));
+        assert_eq!(make_delay(&retry, 0, 51), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_backoff() {
+        let retry = make_retry("retry:\n  attempts: 3\n  base_delay: 1\n");
+        let timeout = anyhow::anyhow!("Timeout");
+        let mut backoff = Backoff::new(Some(&retry), None);
+        assert!(backoff
+            .next_delay(&anyhow::anyhow!("Login failed"))
+            .is_none());
+        assert!(backoff.next_delay(&timeout).is_some());
+        assert!(backoff.next_delay(&timeout).is_some());
+        assert!(backoff.next_delay(&timeout).is_none());
+        assert!(Backoff::new(None, None).next_delay(&timeout).is_none());
+        let mut backoff = Backoff::new(Some(&retry), Some(Instant::now()));
+        assert!(backoff.next_delay(&timeout).is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_run() {
+        let retry = make_retry("retry:\n  attempts: 3\n  base_delay: 1\n");
+        let calls = AtomicU32::new(0);
+        let result = run(Backoff::new(Some(&retry), None), || async {
+            match calls.fetch_add(1, Ordering::SeqCst) {
+                0 => anyhow::bail!("Timeout"),
+                n => Ok(n),
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap(), 1);
+        calls.store(0, Ordering::SeqCst);
+        let result: Result<u32> = run(Backoff::new(Some(&retry), None), || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            anyhow::bail!("Timeout")
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+}
@@ -0,0 +1,179 @@
+// Copyright (C) 2024 Checkmk GmbH - License: GNU General Public License v2
+// This file is part of Checkmk (https://checkmk.com). It is subject to the terms and
+// conditions defined in the file COPYING, which is part of this source code package.
+
+//! Distribution of the run budget(`options.run_budget`) among sections of an instance.
+//!
+//! Sync sections go before async ones, higher priority first, heavy sections are the last. Every section gets a share of the
+//! remaining budget proportional to its priority weight among pending sections. A section whose
+//! share is too small is deferred: it is not produced in this run and is retried in the next one.
+//!
+//! Sections of a concurrency class(`options.concurrency_classes`) are limited per server: all
+//! instances of the server share the limit, e.g. heavy sections never run simultaneously.
+use super::section::{Section, SectionKind};
+use crate::config::section::Priority;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// low priority sections with smaller share are deferred
+const MIN_LOW_PRIORITY_SLOT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Default)]
+pub struct Scheduler {
+    deadline: Option<Instant>,
+    /// class -> max running sections per server, 0 - unlimited
+    limits: HashMap<String, u32>,
+    /// (server, class) -> semaphore
+    semaphores: Arc<Mutex<HashMap<(String, String), Arc<Semaphore>>>>,
+}
+
+impl Scheduler {
+    /// None - no limits, nothing is deferred
+    pub fn new(budget: Option<Duration>) -> Self {
+        Self {
+            deadline: budget.map(|b| Instant::now() + b),
+            ..Default::default()
+        }
+    }
+
+    /// end of the run budget, None - unlimited
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    pub fn concurrency_classes(mut self, classes: &[(String, u32)]) -> Self {
+        self.limits = classes.iter().cloned().collect();
+        self
+    }
+
+    /// waits for a free slot of the section's class on the server, None - not limited
+    pub async fn acquire(&self, server: &str, section: &Section) -> Option<OwnedSemaphorePermit> {
+        let class = section.concurrency()?;
+        let limit = *self.limits.get(class).filter(|&&l| l > 0)?;
+        let semaphore = self
+            .semaphores
+            .lock()
+            .ok()?
+            .entry((server.to_string(), class.to_string()))
+            .or_insert_with(|| Arc::new(Semaphore::new(limit as usize)))
+            .clone();
+        semaphore.acquire_owned().await.ok()
+    }
+
+    /// indices of sections in the order of execution
+    pub fn order(&self, sections: &[Section]) -> Vec<usize> {
+        let mut order = (0..sections.len()).collect::<Vec<usize>>();
+        order.sort_by_key(|&i| {
+            (
+                sections[i].is_heavy(),
+                sections[i].kind() == &SectionKind::Async,
+                Reverse(sections[i].priority().weight()),
+            )
+        });
+        order
+    }
+
+    /// share of the remaining budget, `pending` includes the section itself
+    pub fn slot(&self, section: &Section, pending: &[&Section]) -> Option<Duration> {
+        let remaining = self.deadline?.saturating_duration_since(Instant::now());
+        let total = pending
+            .iter()
+            .map(|s| s.priority().weight())
+            .sum::<u32>()
+            .max(1);
+        Some(remaining * section.priority().weight() / total)
+    }
+
+    pub fn must_defer(&self, section: &Section, pending: &[&Section]) -> bool {
+        match (section.priority(), self.slot(section, pending)) {
+            (_, None) | (Priority::High, _) => false,
+            (Priority::Normal, Some(slot)) => slot.is_zero(),
+            (Priority::Low, Some(slot)) => slot < MIN_LOW_PRIORITY_SLOT,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::section::SectionBuilder;
+
+    fn make_section(name: &str, is_async: bool, priority: Priority) -> Section {
+        Section::new(
+            &SectionBuilder::new(name)
+                .set_async(is_async)
+                .priority(Some(priority))
+                .build(),
+            Some(100),
+        )
+    }
+
+    fn make_sections() -> Vec<Section> {
+        vec![
+            make_section("a", true, Priority::High),
+            make_section("b", false, Priority::Low),
+            make_section("c", false, Priority::High),
+            make_section("d", true, Priority::Normal),
+        ]
+    }
+
+    #[test]
+    fn test_order() {
+        assert_eq!(Scheduler::default().order(&make_sections()), [2, 1, 0, 3]);
+        let mut sections = make_sections();
+        sections.insert(0, make_section("index_fragmentation", true, Priority::High));
+        assert_eq!(Scheduler::default().order(&sections), [3, 2, 1, 4, 0]);
+    }
+
+    #[tokio::test]
+    async fn test_acquire() {
+        let heavy = Section::new(
+            &SectionBuilder::new("index_fragmentation").build(),
+            Some(100),
+        );
+        let light = make_section("a", true, Priority::High);
+        let scheduler = Scheduler::default().concurrency_classes(&[("heavy".to_string(), 1)]);
+        assert!(scheduler.acquire("host", &light).await.is_none());
+        let permit = scheduler.acquire("host", &heavy).await;
+        assert!(permit.is_some());
+        let clone = scheduler.clone();
+        assert!(
+            tokio::time::timeout(Duration::from_millis(10), clone.acquire("host", &heavy))
+                .await
+                .is_err()
+        );
+        assert!(scheduler.acquire("other", &heavy).await.is_some());
+        drop(permit);
+        assert!(clone.acquire("host", &heavy).await.is_some());
+        let unlimited = Scheduler::default().concurrency_classes(&[("heavy".to_string(), 0)]);
+        assert!(unlimited.acquire("host", &heavy).await.is_none());
+    }
+
+    #[test]
+    fn test_defer() {
+        let sections = make_sections();
+        let pending = sections.iter().collect::<Vec<&Section>>();
+        let unlimited = Scheduler::new(None);
+        assert!(unlimited.slot(&sections[0], &pending).is_none());
+        assert!(sections.iter().all(|s| !unlimited.must_defer(s, &pending)));
+
+        let exhausted = Scheduler::new(Some(Duration::ZERO));
+        assert_eq!(
+            sections
+                .iter()
+                .map(|s| exhausted.must_defer(s, &pending))
+                .collect::<Vec<bool>>(),
+            [false, true, false, true]
+        );
+
+        let plenty = Scheduler::new(Some(Duration::from_secs(3600)));
+        assert!(sections.iter().all(|s| !plenty.must_defer(s, &pending)));
+        // weights 4 + 1 + 4 + 2
+        let slot = plenty.slot(&sections[1], &pending).unwrap();
+        assert!(slot <= Duration::from_secs(3600) / 11);
+        assert!(slot > Duration::from_secs(300));
+    }
+}
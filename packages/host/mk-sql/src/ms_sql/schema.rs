@@ -0,0 +1,725 @@
+// Copyright (C) 2024 Checkmk GmbH - License: GNU General Public License v2
+// This file is part of Checkmk (https://checkmk.com). It is subject to the terms and
+// conditions defined in the file COPYING, which is part of this source code package.
+
+//! Machine-readable contract of the output of known sections.
+//!
+//! Columns are listed in output order. Output of decorated sections starts with a line
+//! containing the instance name only, this line is not described by the columns.
+//! `VERSION` must be increased on any incompatible change: removed, renamed, reordered or
+//! retyped column. A column appended to the end is compatible.
+use super::registry::{self, SectionDefinition};
+use crate::config::section::names;
+use crate::utils;
+
+pub const VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColumnType {
+    Text,
+    Integer,
+    Float,
+    /// 0 or 1
+    Flag,
+    /// `YYYY-MM-DD hh:mm:ss`
+    Timestamp,
+}
+
+impl ColumnType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Text => "text",
+            Self::Integer => "integer",
+            Self::Float => "float",
+            Self::Flag => "flag",
+            Self::Timestamp => "timestamp",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Column {
+    pub name: &'static str,
+    pub column_type: ColumnType,
+}
+
+const fn text(name: &'static str) -> Column {
+    Column {
+        name,
+        column_type: ColumnType::Text,
+    }
+}
+
+const fn int(name: &'static str) -> Column {
+    Column {
+        name,
+        column_type: ColumnType::Integer,
+    }
+}
+
+const fn float(name: &'static str) -> Column {
+    Column {
+        name,
+        column_type: ColumnType::Float,
+    }
+}
+
+const fn flag(name: &'static str) -> Column {
+    Column {
+        name,
+        column_type: ColumnType::Flag,
+    }
+}
+
+const fn time(name: &'static str) -> Column {
+    Column {
+        name,
+        column_type: ColumnType::Timestamp,
+    }
+}
+
+/// transaction logs and datafiles share the format
+const FILES: [Column; 8] = [
+    text("instance"),
+    text("database"),
+    text("name"),
+    text("physical_name"),
+    int("max_size"),
+    int("allocated_size"),
+    int("used_size"),
+    flag("unlimited"),
+];
+
+const BUILTIN: [(&str, &[Column]); 49] = [
+    (
+        names::INSTANCE,
+        // values depend on kind: config, state, details, patch or containment
+        &[text("instance"), text("kind"), text("values")],
+    ),
+    (
+        names::DATABASES,
+        &[
+            text("instance"),
+            text("name"),
+            text("status"),
+            text("recovery"),
+            flag("auto_close"),
+            flag("auto_shrink"),
+        ],
+    ),
+    (
+        names::COUNTERS,
+        &[
+            text("object"),
+            text("counter"),
+            text("instance"),
+            text("value"),
+        ],
+    ),
+    (
+        names::BLOCKED_SESSIONS,
+        &[
+            text("instance"),
+            text("session_id"),
+            int("wait_duration_ms"),
+            text("wait_type"),
+            text("blocking_session_id"),
+        ],
+    ),
+    (names::TRANSACTION_LOG, &FILES),
+    (
+        names::CLUSTERS,
+        &[
+            text("instance"),
+            text("database"),
+            text("active_node"),
+            text("nodes"),
+        ],
+    ),
+    (
+        names::MIRRORING,
+        &[
+            text("server_name"),
+            text("database_name"),
+            int("mirroring_state"),
+            text("mirroring_state_desc"),
+            int("mirroring_role"),
+            text("mirroring_role_desc"),
+            int("mirroring_safety_level"),
+            text("mirroring_safety_level_desc"),
+            text("mirroring_partner_name"),
+            text("mirroring_partner_instance"),
+            text("mirroring_witness_name"),
+            int("mirroring_witness_state"),
+            text("mirroring_witness_state_desc"),
+        ],
+    ),
+    (
+        names::AVAILABILITY_GROUPS,
+        &[
+            text("name"),
+            text("primary_replica"),
+            int("synchronization_health"),
+            text("synchronization_health_desc"),
+            text("primary_recovery_health_desc"),
+            text("replica_server_name"),
+            int("log_send_queue_kb"),
+            int("redo_queue_kb"),
+            int("estimated_data_loss_s"),
+        ],
+    ),
+    (
+        names::CONNECTIONS,
+        &[text("instance"), text("database"), int("connections")],
+    ),
+    (
+        names::TABLE_SPACES,
+        // sizes are reported with units, e.g. `12.5 MB`
+        &[
+            text("instance"),
+            text("database"),
+            text("database_size"),
+            text("unallocated"),
+            text("reserved"),
+            text("data"),
+            text("index_size"),
+            text("unused"),
+        ],
+    ),
+    (names::DATAFILES, &FILES),
+    (
+        names::BACKUP,
+        // date and time of the backup are separated by `|`, time has UTC offset
+        &[
+            text("instance"),
+            text("database"),
+            text("last_backup_date"),
+            text("last_backup_time"),
+            text("type"),
+        ],
+    ),
+    (
+        names::JOBS,
+        &[
+            text("job_id"),
+            text("job_name"),
+            flag("job_enabled"),
+            text("next_run_date"),
+            text("next_run_time"),
+            int("last_run_outcome"),
+            text("last_outcome_message"),
+            text("last_run_date"),
+            text("last_run_time"),
+            int("last_run_duration"),
+            flag("schedule_enabled"),
+            time("server_current_time"),
+        ],
+    ),
+    (
+        names::AGENT,
+        &[
+            text("service_name"),
+            text("status"),
+            text("start_mode"),
+            time("last_startup_time"),
+            int("seconds_since_start"),
+            time("server_current_time"),
+        ],
+    ),
+    (
+        names::UPTIME,
+        &[
+            text("instance"),
+            time("sqlserver_start_time"),
+            int("uptime"),
+            time("last_known_start_time"),
+            flag("restarted"),
+        ],
+    ),
+    (
+        names::DATABASE_OPTIONS,
+        &[
+            text("instance"),
+            text("name"),
+            text("recovery_model"),
+            flag("auto_close"),
+            flag("auto_shrink"),
+            text("page_verify"),
+            text("owner"),
+            text("drift"),
+        ],
+    ),
+    (
+        names::CHECKPOINTS,
+        &[
+            text("name"),
+            int("target_recovery_time"),
+            flag("indirect_checkpoint"),
+            float("log_since_last_checkpoint_mb"),
+            float("log_recovery_size_mb"),
+            int("recovery_vlf_count"),
+        ],
+    ),
+    (
+        names::BACKUP_CHAIN,
+        &[
+            text("name"),
+            time("last_base_backup"),
+            time("last_log_backup"),
+            text("chain_status"),
+        ],
+    ),
+    (
+        names::CHECKDB,
+        &[text("name"), text("last_known_good"), int("age_days")],
+    ),
+    (
+        names::WORKERS,
+        &[
+            int("max_workers"),
+            int("current_workers"),
+            int("active_workers"),
+            int("runnable_tasks"),
+            int("work_queue"),
+            int("online_schedulers"),
+            int("offline_schedulers"),
+        ],
+    ),
+    (
+        names::LOCKS,
+        // meaning of values depends on kind: summary or wait
+        &[text("kind"), text("c1"), text("c2"), text("c3"), text("c4")],
+    ),
+    (
+        names::FORCED_PLANS,
+        &[
+            text("database_name"),
+            int("query_id"),
+            int("plan_id"),
+            int("force_failure_count"),
+            text("last_force_failure_reason"),
+            text("status"),
+        ],
+    ),
+    (
+        names::SERVER_ROLES,
+        &[
+            text("instance"),
+            text("role_name"),
+            text("member_name"),
+            text("member_type"),
+            flag("is_disabled"),
+            time("modify_date"),
+            text("change"),
+        ],
+    ),
+    (
+        names::BUFFER_POOL_EXTENSION,
+        &[
+            text("path"),
+            text("state"),
+            int("size_kb"),
+            int("used_kb"),
+            flag("hybrid_configured"),
+            flag("hybrid_enabled"),
+        ],
+    ),
+    (
+        names::VERSION_STORE,
+        &[
+            text("name"),
+            text("snapshot_isolation"),
+            flag("read_committed_snapshot"),
+            int("version_store_kb"),
+        ],
+    ),
+    (
+        names::ADR,
+        &[
+            text("name"),
+            flag("adr_enabled"),
+            int("pvs_size_kb"),
+            int("aborted_transactions"),
+        ],
+    ),
+    (
+        names::INSTANCE_SETTINGS,
+        &[
+            text("collation"),
+            text("default_language"),
+            text("authentication_mode"),
+            text("memory_model"),
+            flag("instant_file_initialization"),
+        ],
+    ),
+    (
+        names::ELASTIC_POOL,
+        &[
+            text("kind"),
+            text("pool_name"),
+            text("database_name"),
+            float("cpu_percent"),
+            float("data_io_percent"),
+            float("log_write_percent"),
+            float("worker_percent"),
+            float("session_percent"),
+            int("storage_mb"),
+            int("dtu_limit"),
+            int("storage_limit_mb"),
+        ],
+    ),
+    (
+        names::DEADLOCKS,
+        &[
+            time("event_time"),
+            text("victim"),
+            text("victim_login"),
+            text("victim_database"),
+            int("processes"),
+            text("resources"),
+        ],
+    ),
+    (
+        names::CONNECTION_SOURCES,
+        &[
+            text("login_name"),
+            text("client_host"),
+            text("client_address"),
+            text("program_name"),
+            int("connections"),
+            int("new_1m"),
+            int("new_5m"),
+            int("new_15m"),
+            int("min_age"),
+            int("max_age"),
+            int("avg_age"),
+        ],
+    ),
+    (
+        names::WAITSTATS,
+        &[
+            text("wait_type"),
+            int("waiting_tasks"),
+            int("wait_time_ms"),
+            int("max_wait_time_ms"),
+            int("signal_wait_time_ms"),
+        ],
+    ),
+    (
+        names::CONNECTIVITY,
+        &[
+            time("event_time"),
+            text("record_type"),
+            text("reason"),
+            text("remote_host"),
+            int("remote_port"),
+            int("spid"),
+            int("sni_error"),
+            int("os_error"),
+            int("state"),
+        ],
+    ),
+    (
+        names::INDEX_FRAGMENTATION,
+        &[
+            text("database_name"),
+            text("table_name"),
+            text("index_name"),
+            text("index_type"),
+            int("partition_number"),
+            int("page_count"),
+            float("fragmentation_percent"),
+        ],
+    ),
+    (
+        names::QUERY_STORE,
+        &[
+            text("database_name"),
+            text("rank_by"),
+            int("query_id"),
+            text("object_name"),
+            int("executions"),
+            int("duration_ms"),
+            int("cpu_ms"),
+            int("max_duration_ms"),
+            text("query_text"),
+        ],
+    ),
+    (
+        names::MEMORY,
+        // value is in KB for clerks, meaning of counter values depends on name
+        &[text("kind"), text("name"), int("value")],
+    ),
+    (
+        names::LOG_SHIPPING,
+        // threshold is the backup threshold for primaries and the restore one for
+        // secondaries, both in minutes
+        &[
+            text("role"),
+            text("primary_server"),
+            text("primary_database"),
+            text("secondary_server"),
+            text("secondary_database"),
+            time("last_backup_date"),
+            time("last_copied_date"),
+            time("last_restored_date"),
+            int("last_restored_latency"),
+            int("threshold"),
+            time("server_current_time"),
+        ],
+    ),
+    (
+        names::REPLICATION,
+        &[
+            text("distribution_db"),
+            text("publisher"),
+            text("publisher_db"),
+            text("publication"),
+            text("subscriber"),
+            text("subscriber_db"),
+            int("publication_type"),
+            int("subscription_status"),
+            int("runstatus"),
+            time("last_sync"),
+            int("delivery_latency_ms"),
+            time("server_current_time"),
+        ],
+    ),
+    (
+        names::AGENT_ALERTS,
+        &[
+            text("alert_name"),
+            flag("enabled"),
+            int("severity"),
+            int("message_id"),
+            text("database_name"),
+            int("occurrence_count"),
+            time("last_occurrence"),
+            int("notifications"),
+            int("enabled_operators"),
+            time("server_current_time"),
+        ],
+    ),
+    (
+        names::ERRORLOG,
+        &[
+            text("instance"),
+            time("log_date"),
+            int("error"),
+            int("severity"),
+            int("state"),
+            text("message"),
+        ],
+    ),
+    (
+        names::FAILED_LOGINS,
+        &[
+            text("instance"),
+            text("login_name"),
+            text("client"),
+            int("failed"),
+            time("last_failed"),
+        ],
+    ),
+    (
+        names::ENCRYPTION,
+        &[
+            text("kind"),
+            text("name"),
+            text("state"),
+            text("key_algorithm"),
+            text("detail"),
+            time("expiry_date"),
+            int("days_left"),
+        ],
+    ),
+    (
+        names::BLOCKING_CHAINS,
+        &[
+            int("head_blocker"),
+            int("session_id"),
+            int("blocking_session_id"),
+            int("depth"),
+            text("wait_type"),
+            int("wait_time_ms"),
+            text("wait_resource"),
+            text("login_name"),
+            text("database_name"),
+            text("statement"),
+        ],
+    ),
+    (
+        names::IO_STATS,
+        // counters are cumulative since start, rates are computed by the check
+        &[
+            text("database_name"),
+            int("file_id"),
+            text("file_name"),
+            text("file_type"),
+            int("num_of_reads"),
+            int("num_of_bytes_read"),
+            int("io_stall_read_ms"),
+            int("num_of_writes"),
+            int("num_of_bytes_written"),
+            int("io_stall_write_ms"),
+            int("sample_ms"),
+            time("sample_time"),
+        ],
+    ),
+    (
+        names::PLAN_CACHE,
+        // compilations, recompilations and batch requests are cumulative: per second rates
+        // are computed by the check
+        &[
+            int("plans"),
+            int("cache_kb"),
+            int("single_use_plans"),
+            int("single_use_kb"),
+            float("single_use_percent"),
+            int("compilations"),
+            int("recompilations"),
+            int("batch_requests"),
+            time("sample_time"),
+        ],
+    ),
+    (
+        names::LINKED_SERVERS,
+        // detail is the error of the test
+        &[
+            text("name"),
+            text("product"),
+            text("provider"),
+            text("data_source"),
+            flag("rpc_out"),
+            flag("data_access"),
+            text("status"),
+            text("detail"),
+        ],
+    ),
+    (
+        names::TENANTS,
+        // databases of the tenant separated by comma, tenant data is in piggyback
+        // `tenant_usage` sections
+        &[text("tenant"), text("databases")],
+    ),
+    (
+        names::CDC,
+        // -1 - not applicable: latency and capture job for change tracking
+        &[
+            text("database_name"),
+            text("feature"),
+            int("latency_s"),
+            time("last_activity"),
+            int("retention_minutes"),
+            int("retained_minutes"),
+            int("capture_enabled"),
+            int("cleanup_enabled"),
+        ],
+    ),
+    (
+        names::ORPHANED_USERS,
+        &[
+            text("database_name"),
+            text("user_name"),
+            text("user_type"),
+            time("create_date"),
+        ],
+    ),
+    (
+        names::DATABASES_V2,
+        &[
+            text("database_name"),
+            text("state"),
+            text("recovery_model"),
+            flag("is_read_only"),
+            int("data_size_kb"),
+            int("log_size_kb"),
+            text("log_reuse_wait"),
+            text("owner"),
+        ],
+    ),
+];
+
+/// columns of a built-in section, empty for unknown ones
+pub fn find_builtin(name: &str) -> &'static [Column] {
+    BUILTIN
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map_or(&[], |(_, columns)| columns)
+}
+
+/// schema of all registered sections with `version` of the schema
+pub fn to_json() -> String {
+    format!(
+        "{{\"version\":{VERSION},\"sections\":[{}]}}",
+        registry::all()
+            .iter()
+            .map(|d| section_to_json(d.as_ref()))
+            .collect::<Vec<String>>()
+            .join(",")
+    )
+}
+
+/// schema of the registered section, None if the section is unknown
+pub fn find_json(name: &str) -> Option<String> {
+    registry::find(name).map(|d| section_to_json(d.as_ref()))
+}
+
+fn section_to_json(definition: &dyn SectionDefinition) -> String {
+    format!(
+        "{{\"name\":\"{}\",\"version\":{VERSION},\"sep\":\"{}\",\"decorated\":{},\"per_database\":{},\"columns\":[{}]}}",
+        utils::escape_json(definition.name()),
+        utils::escape_json(&definition.sep().to_string()),
+        definition.is_decorated(),
+        definition.is_per_database(),
+        definition
+            .columns()
+            .iter()
+            .map(|c| format!(
+                "{{\"name\":\"{}\",\"type\":\"{}\"}}",
+                utils::escape_json(c.name),
+                c.column_type.as_str()
+            ))
+            .collect::<Vec<String>>()
+            .join(",")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_complete() {
+        for d in registry::all().iter().filter(|d| d.sql_id().is_some()) {
+            assert!(!d.columns().is_empty(), "{}", d.name());
+        }
+        assert!(find_builtin("zu").is_empty());
+    }
+
+    #[test]
+    fn test_find_json() {
+        assert_eq!(
+            find_json(names::CHECKDB).unwrap(),
+            format!(
+                "{{\"name\":\"checkdb\",\"version\":{VERSION},\"sep\":\"|\",\"decorated\":true,\"per_database\":false,\"columns\":[\
+                {{\"name\":\"name\",\"type\":\"text\"}},\
+                {{\"name\":\"last_known_good\",\"type\":\"text\"}},\
+                {{\"name\":\"age_days\",\"type\":\"integer\"}}]}}"
+            )
+        );
+        assert!(find_json(names::JOBS)
+            .unwrap()
+            .contains(r#""sep":"\t","decorated":true"#));
+        assert!(find_json("zu").is_none());
+    }
+
+    #[test]
+    fn test_to_json() {
+        let json = to_json();
+        assert!(json.starts_with(&format!(
+            "{{\"version\":{VERSION},\"sections\":[{{\"name\":\"instance\""
+        )));
+        assert!(json.ends_with("]}]}"));
+    }
+}
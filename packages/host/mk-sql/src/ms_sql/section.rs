@@ -2,16 +2,20 @@
 // This file is part of Checkmk (https://checkmk.com). It is subject to the terms and
 // conditions defined in the file COPYING, which is part of this source code package.
 
+use super::custom;
 use super::query::UniAnswer;
-use super::sqls::{self, find_known_query};
-use crate::config::section::get_plain_section_names;
+use super::registry;
+use super::sqls::{self, find_known_query, Permission};
+use crate::config::defines::{defaults, keys};
+use crate::config::ms_sql::ApplicationIntent;
+use crate::config::section::{get_plain_section_names, Priority, Render};
+use crate::config::yaml::Get;
 use crate::config::{self, section, section::names};
 use crate::emit::header;
 use crate::{constants, types::InstanceName, utils};
 use anyhow::Result;
-use std::collections::HashMap;
-use std::fs::read_to_string;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 #[derive(Debug, PartialEq)]
 pub enum SectionKind {
@@ -24,8 +28,28 @@ pub struct Section {
     name: String,
     sep: char,
     cache_age: Option<u32>,
+    /// seconds before expiration of the cache to refresh it
+    refresh_lead: u32,
     decorated: bool,
     header_name: String,
+    /// program and its arguments
+    post_process: Option<Vec<String>>,
+    priority: Priority,
+    baseline: Vec<(String, String)>,
+    catalog: Option<PathBuf>,
+    application_intent: Option<ApplicationIntent>,
+    deduplicate: bool,
+    pdh_fallback: bool,
+    test_links: bool,
+    link_timeout: Option<u32>,
+    tenant_property: Option<String>,
+    tenant_map: Vec<(String, String)>,
+    concurrency: Option<String>,
+    databases: Vec<String>,
+    min_page_count: Option<u32>,
+    top: Option<u32>,
+    render: Option<Render>,
+    timeout: Option<Duration>,
 }
 
 fn to_header_name(name: &str) -> &str {
@@ -43,7 +67,7 @@ impl Section {
 
     pub fn new(section: &config::section::Section, global_cache_age: Option<u32>) -> Self {
         let cache_age = if section.kind() == config::section::SectionKind::Async {
-            global_cache_age
+            global_cache_age.map(|age| section.cache_age().unwrap_or(age))
         } else {
             None
         };
@@ -51,8 +75,26 @@ impl Section {
             name: section.name().into(),
             sep: section.sep(),
             cache_age,
+            refresh_lead: 0,
             decorated: !get_plain_section_names().contains(section.name()),
             header_name: to_header_name(section.name()).into(),
+            post_process: section.post_process().map(<[String]>::to_vec),
+            priority: section.priority(),
+            baseline: section.baseline().to_vec(),
+            catalog: section.catalog().map(Path::to_path_buf),
+            application_intent: section.application_intent(),
+            deduplicate: section.deduplicate(),
+            pdh_fallback: section.pdh_fallback(),
+            test_links: section.test_links(),
+            link_timeout: section.link_timeout(),
+            tenant_property: section.tenant_property().map(str::to_string),
+            tenant_map: section.tenant_map().to_vec(),
+            concurrency: section.concurrency().map(str::to_string),
+            databases: section.databases().to_vec(),
+            min_page_count: section.min_page_count(),
+            top: section.top(),
+            render: section.render(),
+            timeout: section.timeout().map(|t| Duration::from_secs(t.into())),
         }
     }
 
@@ -61,19 +103,24 @@ impl Section {
     }
 
     pub fn to_work_header(&self) -> String {
+        self.to_aged_work_header(0)
+    }
+
+    /// `age` - seconds since the data was collected, the data is from cache if not 0
+    pub fn to_aged_work_header(&self, age: u64) -> String {
         header(
-            &(self.header_name.clone() + &self.cached_header()),
+            &(self.header_name.clone() + &self.cached_header(age)),
             self.sep,
         )
     }
 
-    fn cached_header(&self) -> String {
+    fn cached_header(&self, age: u64) -> String {
         self.cache_age
-            .map(|age| {
+            .map(|cache_age| {
                 format!(
                     ":cached({},{})",
-                    utils::get_utc_now().unwrap_or_default(),
-                    age
+                    utils::get_utc_now().unwrap_or_default().saturating_sub(age),
+                    cache_age
                 )
             })
             .unwrap_or_default()
@@ -99,6 +146,112 @@ impl Section {
         }
     }
 
+    pub fn priority(&self) -> Priority {
+        self.priority
+    }
+
+    pub fn application_intent(&self) -> Option<ApplicationIntent> {
+        self.application_intent
+    }
+
+    pub fn deduplicate(&self) -> bool {
+        self.deduplicate
+    }
+
+    pub fn pdh_fallback(&self) -> bool {
+        self.pdh_fallback
+    }
+
+    pub fn test_links(&self) -> bool {
+        self.test_links
+    }
+
+    /// seconds
+    pub fn link_timeout(&self) -> u32 {
+        self.link_timeout.unwrap_or(defaults::LINK_TIMEOUT)
+    }
+
+    /// databases of the instance selected by the section config, all if nothing is configured
+    pub fn select_databases<'a>(&self, databases: &'a [String]) -> Vec<&'a String> {
+        databases
+            .iter()
+            .filter(|d| {
+                self.databases.is_empty()
+                    || self.databases.iter().any(|s| s.eq_ignore_ascii_case(d))
+            })
+            .collect()
+    }
+
+    pub fn render(&self) -> Option<Render> {
+        self.render
+    }
+
+    pub fn min_page_count(&self) -> u32 {
+        self.min_page_count.unwrap_or(defaults::MIN_PAGE_COUNT)
+    }
+
+    pub fn top(&self) -> u32 {
+        self.top.unwrap_or(defaults::QUERY_STORE_TOP)
+    }
+
+    /// parameters of per database queries as T-SQL variables
+    pub fn declare_variables(&self) -> String {
+        format!(
+            "DECLARE @min_page_count BIGINT = {};\nDECLARE @top INT = {};\n",
+            self.min_page_count(),
+            self.top()
+        )
+    }
+
+    /// name of the extended property as T-SQL variable `@property`
+    pub fn declare_tenant_property(&self) -> String {
+        format!(
+            "DECLARE @property NVARCHAR(128) = N'{}';\n",
+            self.tenant_property
+                .as_deref()
+                .unwrap_or(defaults::TENANT_PROPERTY)
+                .replace('\'', "''")
+        )
+    }
+
+    /// database -> tenant from config
+    pub fn tenant_map(&self) -> &[(String, String)] {
+        &self.tenant_map
+    }
+
+    /// None - not limited
+    pub fn concurrency(&self) -> Option<&str> {
+        self.concurrency.as_deref()
+    }
+
+    /// deviations from the baseline as `column=value` separated by comma, empty if none
+    pub fn find_drift(&self, row: &[(&str, &str)]) -> String {
+        self.baseline
+            .iter()
+            .filter_map(|(column, expected)| {
+                row.iter()
+                    .find(|(c, _)| c == column)
+                    .filter(|(_, actual)| normalize_value(actual) != normalize_value(expected))
+                    .map(|(c, actual)| format!("{c}={actual}"))
+            })
+            .collect::<Vec<String>>()
+            .join(",")
+    }
+
+    /// latest known build for the major version from the catalog file, format is
+    /// `catalog: {<major>: <build>}`, e.g. `16: 16.0.4135.4`
+    pub fn find_latest_build(&self, major: &str) -> Option<String> {
+        let catalog = self.catalog.as_deref()?;
+        let yaml = config::yaml::load_from_file(catalog)
+            .map_err(|e| log::error!("Failed to load catalog {:?}: {e}", catalog))
+            .ok()?;
+        yaml.first()?
+            .get_string_map(keys::CATALOG)
+            .into_iter()
+            .find(|(m, _)| m == major)
+            .map(|(_, build)| build)
+    }
+
     pub fn cache_age(&self) -> u32 {
         if let Some(v) = self.cache_age {
             v
@@ -107,6 +260,40 @@ impl Section {
         }
     }
 
+    pub fn refresh_lead(mut self, lead: u32) -> Self {
+        self.refresh_lead = lead;
+        self
+    }
+
+    /// used if the section has no own timeout
+    pub fn default_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = self.timeout.or(timeout);
+        self
+    }
+
+    /// time for the whole section, None - unlimited
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// max age of the cache to be used: the cache is refreshed before it expires and the
+    /// server never gets data with expired `cached` header
+    pub fn fresh_age(&self) -> u32 {
+        self.cache_age().saturating_sub(self.refresh_lead)
+    }
+
+    /// Passes body through the external command configured for the section
+    /// On any error the body is suppressed: the command is usually used to hide sensitive data
+    pub async fn post_process(&self, body: String) -> String {
+        match &self.post_process {
+            Some(command) => run_post_process(command, &body).await.unwrap_or_else(|e| {
+                log::error!("Post process {command:?} of `{}` failed: {e}", self.name);
+                String::new()
+            }),
+            None => body,
+        }
+    }
+
     pub fn first_line(&self, value: Option<&InstanceName>) -> String {
         if self.decorated {
             value.map(|v| format!("{}\n", v)).unwrap_or_default()
@@ -115,6 +302,23 @@ impl Section {
         }
     }
 
+    /// reduced built-in query if the error is caused by a missing permission, queries provided
+    /// in the sql directory have no fallback
+    pub fn select_fallback_query(
+        &self,
+        sql_dir: Option<PathBuf>,
+        instance_version: u32,
+        error: &str,
+    ) -> Option<(String, Permission)> {
+        if self
+            .find_provided_query(sql_dir, instance_version)
+            .is_some()
+        {
+            return None;
+        }
+        registry::find(&self.name).and_then(|d| d.fallback_query(instance_version, error))
+    }
+
     /// try to find the section's query in the sql directory for instance with the given version
     /// or in the known queries if custom sql query is not provided
     pub fn select_query(&self, sql_dir: Option<PathBuf>, instance_version: u32) -> Option<String> {
@@ -129,9 +333,7 @@ impl Section {
     fn find_query(&self, sql_dir: Option<PathBuf>, instance_version: u32) -> Option<String> {
         self.find_provided_query(sql_dir, instance_version)
             .or_else(|| {
-                get_sql_id(&self.name)
-                    .and_then(Self::find_known_query)
-                    .map(|s| s.to_owned())
+                registry::find(&self.name).and_then(|d| d.query_for_version(instance_version))
             })
     }
 
@@ -144,7 +346,7 @@ impl Section {
             if let Ok(versioned_files) = find_sql_files(&dir, &self.name) {
                 for (min_version, sql_file) in versioned_files {
                     if instance_version >= min_version {
-                        return read_to_string(&sql_file)
+                        return custom::read_sql_file(&sql_file)
                             .map_err(|e| {
                                 log::error!("Can't read file {:?} {}", &sql_file, &e);
                                 e
@@ -156,28 +358,29 @@ impl Section {
         }
         None
     }
-    fn find_known_query(id: sqls::Id) -> Option<&'static str> {
-        sqls::find_known_query(id)
-            .map_err(|e| {
-                log::error!("{e}");
-                e
-            })
-            .ok()
+
+    /// unknown version(0) is considered as supported
+    pub fn is_supported(&self, version_major: u32) -> bool {
+        version_major == 0
+            || registry::find(&self.name).map_or(true, |d| version_major >= d.min_version())
+    }
+
+    pub fn is_heavy(&self) -> bool {
+        registry::find(&self.name).map_or(false, |d| d.is_heavy())
     }
 
     pub fn main_db(&self) -> Option<String> {
-        match self.name.as_ref() {
-            section::names::JOBS => Some("msdb"),
-            section::names::MIRRORING => Some("master"),
-            _ => None,
-        }
-        .map(|s| s.to_string())
+        registry::find(&self.name).and_then(|d| d.main_db().map(str::to_string))
     }
 
     pub fn validate_rows(&self, rows: Vec<UniAnswer>) -> Result<Vec<UniAnswer>> {
-        const ALLOW_TO_HAVE_EMPTY_OUTPUT: [&str; 2] = [
+        const ALLOW_TO_HAVE_EMPTY_OUTPUT: [&str; 6] = [
             section::names::MIRRORING,
             section::names::AVAILABILITY_GROUPS,
+            section::names::REPLICATION,
+            section::names::AGENT_ALERTS,
+            section::names::BLOCKING_CHAINS,
+            section::names::CDC,
         ];
         if (!rows.is_empty() && !rows[0].is_empty())
             || (ALLOW_TO_HAVE_EMPTY_OUTPUT.contains(&self.name()))
@@ -190,6 +393,50 @@ impl Section {
     }
 }
 
+/// SQL Server delivers flags as 0/1, YAML as true/false
+fn normalize_value(value: &str) -> String {
+    match value.to_lowercase().as_str() {
+        "true" | "yes" | "on" => "1".to_string(),
+        "false" | "no" | "off" => "0".to_string(),
+        v => v.to_string(),
+    }
+}
+
+/// the first part of the command is a program, the others are its arguments
+async fn run_post_process(command: &[String], body: &str) -> Result<String> {
+    use std::process::Stdio;
+    use tokio::io::AsyncWriteExt;
+
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| anyhow::anyhow!("empty command"))?;
+    let mut child = tokio::process::Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()?;
+    let mut stdin = child
+        .stdin
+        .take()
+        .ok_or_else(|| anyhow::anyhow!("no stdin"))?;
+    let input = body.to_owned();
+    let writer = tokio::spawn(async move {
+        let _ = stdin.write_all(input.as_bytes()).await;
+    });
+    let output = tokio::time::timeout(
+        Duration::from_secs(defaults::POST_PROCESS_TIMEOUT),
+        child.wait_with_output(),
+    )
+    .await??;
+    let _ = writer.await;
+    if !output.status.success() {
+        anyhow::bail!("exit status {}", output.status);
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
 fn find_sql_files(dir: &Path, section_name: &str) -> Result<Vec<(u32, PathBuf)>> {
     let mut paths: Vec<(u32, PathBuf)> = std::fs::read_dir(dir)?
         .filter_map(|res| res.ok())
@@ -230,28 +477,8 @@ fn get_file_version(path: &Path, section_name: &str) -> Option<u32> {
     None
 }
 
-lazy_static::lazy_static! {
-    static ref SECTION_MAP: HashMap<&'static str, sqls::Id> = HashMap::from([
-        (names::INSTANCE, sqls::Id::InstanceProperties),
-        (names::COUNTERS, sqls::Id::Counters),
-        (names::BACKUP, sqls::Id::Backup),
-        (names::BLOCKED_SESSIONS, sqls::Id::BlockedSessions),
-        (names::DATABASES, sqls::Id::Databases),
-        (names::CONNECTIONS, sqls::Id::Connections),
-
-        (names::TRANSACTION_LOG, sqls::Id::TransactionLogs),
-        (names::DATAFILES, sqls::Id::Datafiles),
-        (names::TABLE_SPACES, sqls::Id::TableSpaces),
-        (names::CLUSTERS, sqls::Id::Clusters),
-
-        (names::JOBS, sqls::Id::Jobs),
-        (names::MIRRORING, sqls::Id::Mirroring),
-        (names::AVAILABILITY_GROUPS, sqls::Id::AvailabilityGroups),
-    ]);
-}
-
 pub fn get_sql_id<T: AsRef<str>>(section_name: T) -> Option<sqls::Id> {
-    SECTION_MAP.get(section_name.as_ref()).copied()
+    registry::find(section_name.as_ref()).and_then(|d| d.sql_id())
 }
 
 #[cfg(test)]
@@ -259,7 +486,6 @@ mod tests {
     use super::*;
     use crate::config::ms_sql::Config;
     use crate::config::section;
-    use crate::ms_sql::custom;
 
     #[test]
     fn test_section_header() {
@@ -278,6 +504,15 @@ mod tests {
             .to_work_header()
             .starts_with("<<<mssql_backup:cached("));
         assert!(section.to_work_header().ends_with("100):sep(124)>>>\n"));
+        let now = utils::get_utc_now().unwrap();
+        let cached = section.to_aged_work_header(50);
+        let created = cached["<<<mssql_backup:cached(".len()..]
+            .split(',')
+            .next()
+            .unwrap()
+            .parse::<u64>()
+            .unwrap();
+        assert!(created <= now - 50 && created + 10 > now - 50);
 
         let section = Section::new(&section::SectionBuilder::new("jobs").build(), Some(100));
         assert!(section
@@ -290,6 +525,45 @@ mod tests {
             Some(100),
         );
         assert_eq!(section.to_work_header(), "<<<mssql_jobs:sep(09)>>>\n");
+
+        let section = Section::new(
+            &section::SectionBuilder::new("deadlocks")
+                .cache_age(Some(300))
+                .build(),
+            Some(100),
+        );
+        assert_eq!(section.cache_age(), 300);
+        assert!(section.to_work_header().ends_with("300):sep(124)>>>\n"));
+        assert_eq!(section.fresh_age(), 300);
+        let section = section.refresh_lead(30);
+        assert_eq!(section.fresh_age(), 270);
+        assert!(section.to_work_header().ends_with("300):sep(124)>>>\n"));
+        assert_eq!(section.refresh_lead(500).fresh_age(), 0);
+    }
+
+    #[test]
+    fn test_section_timeout() {
+        let make_section = |timeout: Option<u32>| {
+            Section::new(
+                &section::SectionBuilder::new("jobs")
+                    .timeout(timeout)
+                    .build(),
+                None,
+            )
+        };
+        assert_eq!(make_section(None).timeout(), None);
+        assert_eq!(
+            make_section(None)
+                .default_timeout(Some(Duration::from_secs(60)))
+                .timeout(),
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(
+            make_section(Some(5))
+                .default_timeout(Some(Duration::from_secs(60)))
+                .timeout(),
+            Some(Duration::from_secs(5))
+        );
     }
 
     #[test]
@@ -356,9 +630,128 @@ mod tests {
         assert!(get_sql_id("").is_none());
     }
 
+    #[test]
+    fn test_is_supported() {
+        let make = |name: &str| Section::new(&section::SectionBuilder::new(name).build(), None);
+        assert!(make(names::ADR).is_supported(0));
+        assert!(!make(names::ADR).is_supported(14));
+        assert!(make(names::ADR).is_supported(15));
+        assert!(make(names::JOBS).is_supported(11));
+        assert!(make("custom").is_supported(11));
+    }
+
+    #[test]
+    fn test_select_databases() {
+        let databases = ["master".to_string(), "Sales".to_string(), "crm".to_string()];
+        let make_section = |builder: section::SectionBuilder| Section::new(&builder.build(), None);
+        let section = make_section(section::SectionBuilder::new(names::INDEX_FRAGMENTATION));
+        assert_eq!(section.select_databases(&databases).len(), 3);
+        assert_eq!(section.min_page_count(), defaults::MIN_PAGE_COUNT);
+        assert!(section.is_heavy());
+        assert_eq!(
+            section.concurrency(),
+            Some(defaults::HEAVY_CONCURRENCY_CLASS)
+        );
+        let section = make_section(
+            section::SectionBuilder::new(names::INDEX_FRAGMENTATION)
+                .databases(vec!["sales".to_string(), "absent".to_string()])
+                .min_page_count(Some(10)),
+        );
+        assert_eq!(section.select_databases(&databases), [&databases[1]]);
+        assert_eq!(section.min_page_count(), 10);
+        assert_eq!(
+            section.declare_variables(),
+            "DECLARE @min_page_count BIGINT = 10;\nDECLARE @top INT = 10;\n"
+        );
+    }
+
+    #[test]
+    fn test_declare_tenant_property() {
+        let make_section = |builder: section::SectionBuilder| Section::new(&builder.build(), None);
+        assert_eq!(
+            make_section(section::SectionBuilder::new("tenants")).declare_tenant_property(),
+            "DECLARE @property NVARCHAR(128) = N'tenant';\n"
+        );
+        assert_eq!(
+            make_section(
+                section::SectionBuilder::new("tenants").tenant_property(Some("o'wner".to_string()))
+            )
+            .declare_tenant_property(),
+            "DECLARE @property NVARCHAR(128) = N'o''wner';\n"
+        );
+    }
+
     #[test]
     fn test_header_name() {
         assert_eq!(to_header_name(names::CLUSTERS), "cluster");
         assert_eq!(to_header_name("xxx"), "xxx");
     }
+
+    #[test]
+    fn test_find_drift() {
+        let section = Section::new(
+            &section::SectionBuilder::new(names::DATABASE_OPTIONS)
+                .baseline(vec![
+                    ("recovery_model".to_string(), "FULL".to_string()),
+                    ("auto_shrink".to_string(), "false".to_string()),
+                    ("absent".to_string(), "x".to_string()),
+                ])
+                .build(),
+            None,
+        );
+        assert_eq!(
+            section.find_drift(&[("recovery_model", "full"), ("auto_shrink", "0")]),
+            ""
+        );
+        assert_eq!(
+            section.find_drift(&[("recovery_model", "SIMPLE"), ("auto_shrink", "1")]),
+            "recovery_model=SIMPLE,auto_shrink=1"
+        );
+    }
+
+    #[test]
+    fn test_find_latest_build() {
+        let dir = tempfile::tempdir().unwrap();
+        let catalog = dir.path().join("builds.yml");
+        std::fs::write(&catalog, "catalog:\n  16: 16.0.4135.4\n  15: 15.0.4385.2\n").unwrap();
+        let make = |catalog: Option<PathBuf>| {
+            Section::new(
+                &section::SectionBuilder::new(names::INSTANCE)
+                    .catalog(catalog)
+                    .build(),
+                None,
+            )
+        };
+        let section = make(Some(catalog));
+        assert_eq!(
+            section.find_latest_build("16").as_deref(),
+            Some("16.0.4135.4")
+        );
+        assert!(section.find_latest_build("14").is_none());
+        assert!(make(None).find_latest_build("16").is_none());
+        assert!(make(Some(dir.path().join("absent.yml")))
+            .find_latest_build("16")
+            .is_none());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_post_process() {
+        let make = |command: Option<&str>| {
+            Section::new(
+                &section::SectionBuilder::new("jobs")
+                    .post_process(command.map(|c| c.split(' ').map(str::to_string).collect()))
+                    .build(),
+                None,
+            )
+        };
+        let body = "a|b\nc|d\n".to_string();
+        assert_eq!(make(None).post_process(body.clone()).await, body);
+        assert_eq!(
+            make(Some("tr a-z A-Z")).post_process(body.clone()).await,
+            "A|B\nC|D\n"
+        );
+        assert_eq!(make(Some("false")).post_process(body.clone()).await, "");
+        assert_eq!(make(Some("absent-program")).post_process(body).await, "");
+    }
 }
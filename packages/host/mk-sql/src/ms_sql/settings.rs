@@ -0,0 +1,155 @@
+// Copyright (C) 2024 Checkmk GmbH - License: GNU General Public License v2
+// This file is part of Checkmk (https://checkmk.com). It is subject to the terms and
+// conditions defined in the file COPYING, which is part of this source code package.
+
+//! Effective settings of every instance used by the run: the site may compare them across hosts
+use super::section::Section;
+use crate::config::ms_sql::{AuthType, Config, Endpoint, TlsMode};
+use crate::emit::header;
+use crate::setup::Env;
+use crate::types::InstanceName;
+
+pub const EFFECTIVE_SETTINGS_SECTION_NAME: &str = "effective_settings";
+const SEP: char = '|';
+
+/// `instance|auth|tls_mode|tls_validation|timeout|cache_age|max_connections|sections|filters`
+pub fn generate_effective_settings_section(
+    ms_sql: &Config,
+    environment: &Env,
+    instances: &[(&InstanceName, &Endpoint)],
+    sections: &[Section],
+) -> String {
+    let sections = sections
+        .iter()
+        .map(|s| s.name())
+        .collect::<Vec<_>>()
+        .join(",");
+    let filters = make_filters(ms_sql, environment);
+    header(EFFECTIVE_SETTINGS_SECTION_NAME, SEP)
+        + &instances
+            .iter()
+            .map(|(name, endpoint)| {
+                [
+                    name.to_string(),
+                    auth_to_str(endpoint.auth().auth_type()).to_string(),
+                    tls_mode_to_str(endpoint.conn().tls_mode()).to_string(),
+                    make_tls_validation(endpoint).to_string(),
+                    endpoint.conn().timeout().as_secs().to_string(),
+                    ms_sql.cache_age().to_string(),
+                    ms_sql.options().max_connections().0.to_string(),
+                    sections.clone(),
+                    filters.clone(),
+                ]
+                .iter()
+                .map(|field| field.replace(['\n', SEP], " "))
+                .collect::<Vec<_>>()
+                .join(&SEP.to_string())
+                    + "\n"
+            })
+            .collect::<String>()
+}
+
+fn auth_to_str(auth_type: &AuthType) -> &'static str {
+    match auth_type {
+        AuthType::SqlServer => "sql_server",
+        AuthType::Windows => "windows",
+        AuthType::Integrated => "integrated",
+        AuthType::Token => "token",
+        AuthType::AzureAd => "azure_ad",
+        AuthType::ManagedIdentity => "managed_identity",
+        AuthType::Kerberos => "kerberos",
+        AuthType::Gmsa => "gmsa",
+        AuthType::Undefined => "undefined",
+    }
+}
+
+/// `auto` - encryption with fallback to plain text where allowed
+fn tls_mode_to_str(mode: Option<TlsMode>) -> &'static str {
+    match mode {
+        None => "auto",
+        Some(TlsMode::Disabled) => "disabled",
+        Some(TlsMode::Prefer) => "prefer",
+        Some(TlsMode::Require) => "require",
+        Some(TlsMode::VerifyFull) => "verify-full",
+    }
+}
+
+/// who validates the server certificate: `ca_bundle`, `ca`(tls of the connection),
+/// `trusted`(no validation) or `system`
+fn make_tls_validation(endpoint: &Endpoint) -> &'static str {
+    let conn = endpoint.conn();
+    if conn.ca_bundle().is_some() {
+        return "ca_bundle";
+    }
+    match conn.tls_mode() {
+        Some(mode) if mode.verifies_chain() => "system",
+        Some(_) => "trusted",
+        None if conn.tls().is_some() => "ca",
+        None if conn.trust_server_certificate() => "trusted",
+        None => "system",
+    }
+}
+
+/// `include=a,b;exclude=c;used_sections=yes`, only active filters
+fn make_filters(ms_sql: &Config, environment: &Env) -> String {
+    let discovery = ms_sql.discovery();
+    let mut filters = vec![];
+    if !discovery.include().is_empty() {
+        filters.push(format!("include={}", discovery.include().join(",")));
+    }
+    if !discovery.exclude().is_empty() {
+        filters.push(format!("exclude={}", discovery.exclude().join(",")));
+    }
+    if environment.used_sections().is_some() {
+        filters.push("used_sections=yes".to_string());
+    }
+    filters.join(";")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::section::SectionBuilder;
+
+    #[test]
+    fn test_generate_effective_settings_section() {
+        let config = Config::from_string(
+            r"---
+mssql:
+  main:
+    authentication:
+      username: foo
+      type: sql_server
+    connection:
+      hostname: bar
+      timeout: 7
+      tls:
+        mode: verify-full
+    discovery:
+      include: [A, B]
+",
+        )
+        .unwrap()
+        .unwrap();
+        let endpoint = config.endpoint();
+        let name = InstanceName::from("MSSQLSERVER");
+        let sections = [
+            Section::new(&SectionBuilder::new("jobs").build(), None),
+            Section::new(&SectionBuilder::new("mirroring").build(), None),
+        ];
+        assert_eq!(
+            generate_effective_settings_section(
+                &config,
+                &Env::default(),
+                &[(&name, &endpoint)],
+                &sections
+            ),
+            format!(
+                "<<<mssql_effective_settings:sep(124)>>>\n\
+                 MSSQLSERVER|sql_server|verify-full|system|7|{}|{}|jobs,mirroring|include=A,B\n",
+                config.cache_age(),
+                config.options().max_connections().0
+            )
+        );
+    }
+}
@@ -0,0 +1,178 @@
+// Copyright (C) 2024 Checkmk GmbH - License: GNU General Public License v2
+// This file is part of Checkmk (https://checkmk.com). It is subject to the terms and
+// conditions defined in the file COPYING, which is part of this source code package.
+
+use super::custom::{get_sql_dir, MSSQL_CUSTOM_SQL_SUB_DIR};
+use super::registry;
+use super::section::Section;
+use crate::config::ms_sql::Config;
+use crate::constants::CONFIG_DIR;
+use crate::emit::header;
+use crate::setup::Env;
+use crate::utils;
+use std::path::Path;
+
+pub const SETUP_ERRORS_SECTION_NAME: &str = "setup_errors";
+const SEP: char = '|';
+
+/// Problems of the plugin setup detected at runtime
+#[derive(Debug, PartialEq)]
+pub enum SetupError {
+    UnknownSection(String),
+    SqlDir(String),
+    CacheDir(String),
+}
+
+impl SetupError {
+    fn kind(&self) -> &str {
+        match self {
+            Self::UnknownSection(_) => "unknown_section",
+            Self::SqlDir(_) => "sql_dir",
+            Self::CacheDir(_) => "cache_dir",
+        }
+    }
+
+    fn message(&self) -> &str {
+        match self {
+            Self::UnknownSection(s) | Self::SqlDir(s) | Self::CacheDir(s) => s,
+        }
+    }
+
+    pub fn to_entry(&self, sep: char) -> String {
+        format!(
+            "{}{sep}{}\n",
+            self.kind(),
+            self.message().replace(['\n', sep], " ")
+        )
+    }
+}
+
+/// Always generated: empty section means no problems
+pub fn generate_setup_errors_section(ms_sql: &Config, environment: &Env) -> String {
+    header(SETUP_ERRORS_SECTION_NAME, SEP)
+        + &find_setup_errors(ms_sql, environment)
+            .iter()
+            .map(|e| {
+                log::warn!("Setup error: {}", e.message());
+                e.to_entry(SEP)
+            })
+            .collect::<String>()
+}
+
+pub fn find_setup_errors(ms_sql: &Config, environment: &Env) -> Vec<SetupError> {
+    let mut errors = check_sql_dir(&CONFIG_DIR.join(MSSQL_CUSTOM_SQL_SUB_DIR));
+    errors.extend(check_sections(ms_sql));
+    errors.extend(check_cache_dir(
+        environment
+            .calc_cache_sub_dir(&ms_sql.config_cache_dir())
+            .as_deref(),
+    ));
+    errors
+}
+
+fn check_sql_dir(sql_dir: &Path) -> Option<SetupError> {
+    if !sql_dir.exists() {
+        return None;
+    }
+    if !sql_dir.is_dir() {
+        return Some(SetupError::SqlDir(format!(
+            "sql dir {:?} is not a directory",
+            sql_dir
+        )));
+    }
+    std::fs::read_dir(sql_dir)
+        .err()
+        .map(|e| SetupError::SqlDir(format!("sql dir {:?} is not readable: {e}", sql_dir)))
+}
+
+fn check_sections(ms_sql: &Config) -> Vec<SetupError> {
+    let sql_dir = get_sql_dir();
+    ms_sql
+        .valid_sections()
+        .into_iter()
+        .filter(|s| registry::find(s.name()).is_none())
+        .filter(|s| {
+            Section::new(s, None)
+                .find_provided_query(sql_dir.clone(), u32::MAX)
+                .is_none()
+        })
+        .map(|s| {
+            SetupError::UnknownSection(format!(
+                "section `{}` is neither predefined nor provided in sql dir",
+                s.name()
+            ))
+        })
+        .collect()
+}
+
+fn check_cache_dir(cache_dir: Option<&Path>) -> Option<SetupError> {
+    match cache_dir {
+        Some(dir) if dir.exists() && !dir.is_dir() => Some(SetupError::CacheDir(format!(
+            "cache dir {:?} is not a directory",
+            dir
+        ))),
+        Some(dir) if dir.is_dir() => utils::touch_dir(dir)
+            .err()
+            .map(|e| SetupError::CacheDir(format!("cache dir {:?} is not writable: {e}", dir))),
+        Some(dir) => Some(SetupError::CacheDir(format!(
+            "cache dir {:?} can't be created",
+            dir
+        ))),
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_entry() {
+        assert_eq!(
+            SetupError::UnknownSection("a|b\nc".to_string()).to_entry('|'),
+            "unknown_section|a b c\n"
+        );
+    }
+
+    #[test]
+    fn test_check_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("file");
+        std::fs::write(&file, "").unwrap();
+
+        assert!(check_sql_dir(&dir.path().join("absent")).is_none());
+        assert!(check_sql_dir(dir.path()).is_none());
+        assert!(matches!(check_sql_dir(&file), Some(SetupError::SqlDir(_))));
+
+        assert!(check_cache_dir(None).is_none());
+        assert!(check_cache_dir(Some(dir.path())).is_none());
+        assert!(matches!(
+            check_cache_dir(Some(&file)),
+            Some(SetupError::CacheDir(_))
+        ));
+        assert!(matches!(
+            check_cache_dir(Some(&dir.path().join("absent"))),
+            Some(SetupError::CacheDir(_))
+        ));
+    }
+
+    #[test]
+    fn test_check_sections() {
+        let config = Config::from_string(
+            r"---
+mssql:
+  main:
+    authentication:
+      username: foo
+    sections:
+      - instance:
+      - my_unknown_section:
+",
+        )
+        .unwrap()
+        .unwrap();
+        let errors = check_sections(&config);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].message().contains("my_unknown_section"));
+    }
+}
@@ -14,11 +14,13 @@ pub enum Id {
     Mirroring,
     Jobs,
     AvailabilityGroups,
+    AvailabilityGroupReplicas,
     InstanceProperties,
     UtcEntry,
     ClusterActiveNodes,
     ClusterNodes,
     IsClustered,
+    ContainedAvailabilityGroups,
     DatabaseNames,
     Databases,
     Datafiles,
@@ -32,6 +34,51 @@ pub enum Id {
     BlockedSessions,
     Counters,
     Clusters,
+    Agent,
+    Uptime,
+    DatabaseOptions,
+    Checkpoints,
+    BackupChain,
+    CheckDb,
+    Workers,
+    Locks,
+    ForcedPlans,
+    ServerRoles,
+    BufferPoolExtension,
+    BufferPoolExtensionHybrid,
+    VersionStore,
+    VersionStoreUsage,
+    Adr,
+    InstanceSettings,
+    InstanceSettingsPrivileges,
+    ElasticPool,
+    Deadlocks,
+    ConnectionSources,
+    WaitStats,
+    Connectivity,
+    IndexFragmentation,
+    QueryStore,
+    Memory,
+    UptimeTempdb,
+    CheckpointsReduced,
+    AdrReduced,
+    JobsView,
+    LogShipping,
+    Replication,
+    AgentAlerts,
+    ErrorLog,
+    ErrorLogRingBuffer,
+    FailedLogins,
+    Encryption,
+    EncryptionReduced,
+    BlockingChains,
+    IoStats,
+    PlanCache,
+    LinkedServers,
+    Tenants,
+    Cdc,
+    OrphanedUsers,
+    DatabasesV2,
 }
 
 pub mod query {
@@ -280,6 +327,10 @@ cast(DATABASEPROPERTYEX(name, 'Status') as nvarchar(max)) AS Status, \
   cast(DATABASEPROPERTYEX(name, 'IsAutoShrink') as bigint) AS auto_shrink \
 FROM master.dbo.sysdatabases";
 
+    /// SQL Server 2022+ only
+    pub const CONTAINED_AVAILABILITY_GROUPS: &str =
+        "SELECT cast(COUNT(*) as nvarchar) AS contained_ags FROM sys.availability_groups WHERE is_contained = 1";
+
     pub const IS_CLUSTERED: &str =
         "SELECT cast( SERVERPROPERTY('IsClustered') as nvarchar) AS is_clustered";
     pub const CLUSTER_NODES: &str =
@@ -291,6 +342,8 @@ FROM master.dbo.sysdatabases";
       cast((SELECT COUNT(dbid) AS Num_Of_Connections FROM sys.sysprocesses WHERE dbid > 0 AND name = DB_NAME(dbid) GROUP BY dbid ) as bigint) AS NumberOfConnections  \
 FROM sys.databases";
 
+    /// last_outcome_message is a fixed English token of last_run_outcome: the message stored
+    /// in msdb is localized by the server
     pub const JOBS: &str = "SELECT \
   sj.job_id AS job_id, \
   cast(sj.name  as NVARCHAR(max)) AS job_name, \
@@ -298,7 +351,13 @@ FROM sys.databases";
   CAST(sjs.next_run_date AS NVARCHAR(8)) AS next_run_date, \
   CAST(sjs.next_run_time AS NVARCHAR(6)) AS next_run_time, \
   sjserver.last_run_outcome, \
-  CAST(sjserver.last_outcome_message as NVARCHAR(128)) as last_outcome_message, \
+  CAST(CASE sjserver.last_run_outcome \
+    WHEN 0 THEN 'Failed' \
+    WHEN 1 THEN 'Succeeded' \
+    WHEN 2 THEN 'Retry' \
+    WHEN 3 THEN 'Canceled' \
+    WHEN 4 THEN 'In progress' \
+    ELSE 'Unknown' END AS NVARCHAR(128)) as last_outcome_message, \
   CAST(sjserver.last_run_date AS NVARCHAR(8)) AS last_run_date, \
   CAST(sjserver.last_run_time AS NVARCHAR(6)) AS last_run_time, \
   sjserver.last_run_duration, \
@@ -313,6 +372,970 @@ ORDER BY job_name, \
          next_run_time ASC \
 ";
 
+    /// reduced variant of JOBS without msdb SELECT permission on sysjobs: public sysjobs_view
+    /// shows only own jobs, unless SQLAgentReaderRole is granted, and has no run history
+    pub const JOBS_VIEW: &str = "SELECT \
+  sj.job_id AS job_id, \
+  cast(sj.name  as NVARCHAR(max)) AS job_name, \
+  sj.enabled AS job_enabled, \
+  CAST('' AS NVARCHAR(8)) AS next_run_date, \
+  CAST('' AS NVARCHAR(6)) AS next_run_time, \
+  CAST('' AS NVARCHAR(1)) AS last_run_outcome, \
+  CAST('' AS NVARCHAR(128)) as last_outcome_message, \
+  CAST('' AS NVARCHAR(8)) AS last_run_date, \
+  CAST('' AS NVARCHAR(6)) AS last_run_time, \
+  CAST('' AS NVARCHAR(1)) AS last_run_duration, \
+  CAST('' AS NVARCHAR(1)) AS schedule_enabled, \
+  CONVERT(NVARCHAR, CURRENT_TIMESTAMP, 20) AS server_current_time \
+FROM dbo.sysjobs_view sj \
+ORDER BY job_name";
+
+    /// always one row: not installed agent(Express) gives empty service name
+    pub const AGENT: &str = "SELECT \
+  ISNULL(CAST(s.servicename AS NVARCHAR(256)), '') AS service_name, \
+  ISNULL(CAST(s.status_desc AS NVARCHAR(60)), '') AS status, \
+  ISNULL(CAST(s.startup_type_desc AS NVARCHAR(60)), '') AS start_mode, \
+  ISNULL(CONVERT(NVARCHAR, s.last_startup_time, 20), '') AS last_startup_time, \
+  ISNULL(CAST(DATEDIFF(SECOND, s.last_startup_time, SYSDATETIMEOFFSET()) AS NVARCHAR(20)), '') AS seconds_since_start, \
+  CONVERT(NVARCHAR, CURRENT_TIMESTAMP, 20) AS server_current_time \
+FROM (SELECT 1 AS dummy) AS d \
+LEFT JOIN sys.dm_server_services s ON s.servicename LIKE 'SQL Server Agent%'";
+
+    pub const UPTIME: &str = "SELECT \
+  CONVERT(NVARCHAR, sqlserver_start_time, 20) AS sqlserver_start_time, \
+  CAST(DATEDIFF(SECOND, sqlserver_start_time, GETDATE()) AS NVARCHAR(20)) AS uptime \
+FROM sys.dm_os_sys_info";
+
+    /// reduced variant of UPTIME without VIEW SERVER STATE: tempdb is created on start
+    pub const UPTIME_TEMPDB: &str = "SELECT \
+  CONVERT(NVARCHAR, create_date, 20) AS sqlserver_start_time, \
+  CAST(DATEDIFF(SECOND, create_date, GETDATE()) AS NVARCHAR(20)) AS uptime \
+FROM sys.databases WHERE name = 'tempdb'";
+
+    pub const DATABASE_OPTIONS: &str = "SELECT \
+  CAST(name AS NVARCHAR(128)) AS name, \
+  CAST(recovery_model_desc AS NVARCHAR(60)) AS recovery_model, \
+  CAST(is_auto_close_on AS NVARCHAR(1)) AS auto_close, \
+  CAST(is_auto_shrink_on AS NVARCHAR(1)) AS auto_shrink, \
+  CAST(page_verify_option_desc AS NVARCHAR(60)) AS page_verify, \
+  ISNULL(CAST(SUSER_SNAME(owner_sid) AS NVARCHAR(128)), '') AS owner, \
+  CAST(CASE WHEN database_id <= 4 THEN 1 ELSE 0 END AS NVARCHAR(1)) AS is_system \
+FROM sys.databases";
+
+    /// sys.dm_db_log_stats requires SQL Server 2016 SP2
+    pub const CHECKPOINTS: &str = "SELECT \
+  CAST(d.name AS NVARCHAR(128)) AS name, \
+  CAST(d.target_recovery_time_in_seconds AS NVARCHAR(20)) AS target_recovery_time, \
+  CAST(CASE WHEN d.target_recovery_time_in_seconds > 0 THEN 1 ELSE 0 END AS NVARCHAR(1)) AS indirect_checkpoint, \
+  ISNULL(CAST(ls.log_since_last_checkpoint_mb AS NVARCHAR(40)), '') AS log_since_last_checkpoint_mb, \
+  ISNULL(CAST(ls.log_recovery_size_mb AS NVARCHAR(40)), '') AS log_recovery_size_mb, \
+  ISNULL(CAST(ls.recovery_vlf_count AS NVARCHAR(20)), '') AS recovery_vlf_count \
+FROM sys.databases d \
+OUTER APPLY sys.dm_db_log_stats(d.database_id) ls \
+WHERE d.state = 0";
+
+    /// reduced variant of CHECKPOINTS without VIEW SERVER STATE, log stats are empty
+    pub const CHECKPOINTS_REDUCED: &str = "SELECT \
+  CAST(d.name AS NVARCHAR(128)) AS name, \
+  CAST(d.target_recovery_time_in_seconds AS NVARCHAR(20)) AS target_recovery_time, \
+  CAST(CASE WHEN d.target_recovery_time_in_seconds > 0 THEN 1 ELSE 0 END AS NVARCHAR(1)) AS indirect_checkpoint, \
+  CAST('' AS NVARCHAR(40)) AS log_since_last_checkpoint_mb, \
+  CAST('' AS NVARCHAR(40)) AS log_recovery_size_mb, \
+  CAST('' AS NVARCHAR(20)) AS recovery_vlf_count \
+FROM sys.databases d \
+WHERE d.state = 0";
+
+    /// chain_status of databases in FULL recovery:
+    /// - no_base: no full/diff backup
+    /// - broken: log chain isn't started or there is a LSN gap between log backups after the base
+    /// - bulk_logged: log backup after the base contains bulk-logged operations
+    /// - no_log_backup: no log backups after the base
+    /// - ok
+    /// backups of a dropped database with the same name and copy-only log backups are ignored
+    pub const BACKUP_CHAIN: &str = "WITH logs AS ( \
+  SELECT database_guid, first_lsn, backup_finish_date, has_bulk_logged_data, \
+    LAG(last_lsn) OVER (PARTITION BY database_guid ORDER BY first_lsn) AS prev_last_lsn \
+  FROM msdb.dbo.backupset WHERE type = 'L' AND is_copy_only = 0 \
+), bases AS ( \
+  SELECT database_guid, MAX(backup_finish_date) AS last_base \
+  FROM msdb.dbo.backupset WHERE type IN ('D', 'I') GROUP BY database_guid \
+) \
+SELECT \
+  CAST(d.name AS NVARCHAR(128)) AS name, \
+  ISNULL(CONVERT(NVARCHAR, b.last_base, 20), '') AS last_base_backup, \
+  ISNULL(CONVERT(NVARCHAR, (SELECT MAX(l.backup_finish_date) FROM logs l WHERE l.database_guid = rs.database_guid), 20), '') AS last_log_backup, \
+  CAST(CASE \
+    WHEN b.last_base IS NULL THEN 'no_base' \
+    WHEN rs.last_log_backup_lsn IS NULL \
+      OR EXISTS (SELECT 1 FROM logs l WHERE l.database_guid = rs.database_guid AND l.backup_finish_date > b.last_base \
+        AND l.prev_last_lsn IS NOT NULL AND l.first_lsn <> l.prev_last_lsn) THEN 'broken' \
+    WHEN EXISTS (SELECT 1 FROM logs l WHERE l.database_guid = rs.database_guid AND l.backup_finish_date > b.last_base \
+        AND l.has_bulk_logged_data = 1) THEN 'bulk_logged' \
+    WHEN NOT EXISTS (SELECT 1 FROM logs l WHERE l.database_guid = rs.database_guid AND l.backup_finish_date > b.last_base) \
+      THEN 'no_log_backup' \
+    ELSE 'ok' END AS NVARCHAR(20)) AS chain_status \
+FROM sys.databases d \
+JOIN sys.database_recovery_status rs ON rs.database_id = d.database_id \
+LEFT JOIN bases b ON b.database_guid = rs.database_guid \
+WHERE d.recovery_model = 1 AND d.database_id <> 2 AND d.state = 0";
+
+    /// last known good CHECKDB from the database boot page, requires sysadmin
+    /// age_days is -1 if the database has never been checked
+    pub const CHECKDB: &str = "SET NOCOUNT ON; \
+DECLARE @info TABLE (ParentObject NVARCHAR(255), Object NVARCHAR(255), Field NVARCHAR(255), Value NVARCHAR(255)); \
+DECLARE @result TABLE (name NVARCHAR(128), last_known_good NVARCHAR(40)); \
+DECLARE @db NVARCHAR(128); \
+DECLARE dbs CURSOR LOCAL FAST_FORWARD FOR SELECT name FROM sys.databases WHERE state = 0 AND database_id <> 2; \
+OPEN dbs; \
+FETCH NEXT FROM dbs INTO @db; \
+WHILE @@FETCH_STATUS = 0 \
+BEGIN \
+  DELETE FROM @info; \
+  INSERT INTO @info EXEC ('DBCC DBINFO(''' + REPLACE(@db, '''', '''''') + ''') WITH TABLERESULTS, NO_INFOMSGS'); \
+  INSERT INTO @result SELECT @db, MAX(Value) FROM @info WHERE Field = 'dbi_dbccLastKnownGood'; \
+  FETCH NEXT FROM dbs INTO @db; \
+END; \
+CLOSE dbs; \
+DEALLOCATE dbs; \
+SELECT \
+  CAST(name AS NVARCHAR(128)) AS name, \
+  ISNULL(last_known_good, '') AS last_known_good, \
+  CAST(CASE WHEN last_known_good IS NULL OR last_known_good LIKE '1900-01-01%' THEN -1 \
+    ELSE DATEDIFF(DAY, CAST(last_known_good AS DATETIME), GETDATE()) END AS NVARCHAR(20)) AS age_days \
+FROM @result";
+
+    /// work_queue is count of tasks waiting for a worker: THREADPOOL starvation if above zero
+    pub const WORKERS: &str = "SELECT \
+  CAST(i.max_workers_count AS NVARCHAR(20)) AS max_workers, \
+  CAST(SUM(s.current_workers_count) AS NVARCHAR(20)) AS current_workers, \
+  CAST(SUM(s.active_workers_count) AS NVARCHAR(20)) AS active_workers, \
+  CAST(SUM(s.runnable_tasks_count) AS NVARCHAR(20)) AS runnable_tasks, \
+  CAST(SUM(s.work_queue_count) AS NVARCHAR(20)) AS work_queue, \
+  CAST(SUM(CASE WHEN s.status = 'VISIBLE ONLINE' THEN 1 ELSE 0 END) AS NVARCHAR(20)) AS online_schedulers, \
+  CAST(SUM(CASE WHEN s.status <> 'VISIBLE ONLINE' THEN 1 ELSE 0 END) AS NVARCHAR(20)) AS offline_schedulers \
+FROM sys.dm_os_schedulers s \
+CROSS JOIN sys.dm_os_sys_info i \
+WHERE s.status LIKE 'VISIBLE%' \
+GROUP BY i.max_workers_count";
+
+    /// rows of two kinds:
+    /// - summary|lock_memory_kb|table_lock_escalations|lock_requests|lock_waits, raw counter values
+    /// - wait|resource_type|database|resource_id|waiting_requests, top 10 resources
+    pub const LOCKS: &str = "SELECT \
+  CAST('summary' AS NVARCHAR(20)) AS kind, \
+  CAST(ISNULL((SELECT SUM(pages_kb) FROM sys.dm_os_memory_clerks WHERE type = 'OBJECTSTORE_LOCK_MANAGER'), 0) AS NVARCHAR(128)) AS c1, \
+  CAST(ISNULL((SELECT SUM(cntr_value) FROM sys.dm_os_performance_counters WHERE counter_name = 'Table Lock Escalations/sec'), 0) AS NVARCHAR(128)) AS c2, \
+  CAST(ISNULL((SELECT SUM(cntr_value) FROM sys.dm_os_performance_counters \
+    WHERE object_name LIKE '%:Locks%' AND counter_name = 'Lock Requests/sec' AND instance_name = '_Total'), 0) AS NVARCHAR(128)) AS c3, \
+  CAST(ISNULL((SELECT SUM(cntr_value) FROM sys.dm_os_performance_counters \
+    WHERE object_name LIKE '%:Locks%' AND counter_name = 'Lock Waits/sec' AND instance_name = '_Total'), 0) AS NVARCHAR(128)) AS c4 \
+UNION ALL \
+SELECT kind, c1, c2, c3, c4 FROM ( \
+  SELECT TOP 10 \
+    CAST('wait' AS NVARCHAR(20)) AS kind, \
+    CAST(resource_type AS NVARCHAR(128)) AS c1, \
+    CAST(ISNULL(DB_NAME(resource_database_id), '') AS NVARCHAR(128)) AS c2, \
+    CAST(resource_associated_entity_id AS NVARCHAR(128)) AS c3, \
+    CAST(COUNT(*) AS NVARCHAR(128)) AS c4 \
+  FROM sys.dm_tran_locks \
+  WHERE request_status = 'WAIT' \
+  GROUP BY resource_type, resource_database_id, resource_associated_entity_id \
+  ORDER BY COUNT(*) DESC \
+) AS waits";
+
+    /// plans forced via Query Store including automatic plan correction, databases without
+    /// Query Store are skipped; status is `failed` if the last forcing attempt has failed
+    pub const FORCED_PLANS: &str = "SET NOCOUNT ON; \
+DECLARE @result TABLE (database_name NVARCHAR(128), query_id BIGINT, plan_id BIGINT, \
+  force_failure_count BIGINT, last_force_failure_reason NVARCHAR(128)); \
+DECLARE @db NVARCHAR(128); \
+DECLARE dbs CURSOR LOCAL FAST_FORWARD FOR SELECT name FROM sys.databases WHERE state = 0 AND is_query_store_on = 1; \
+OPEN dbs; \
+FETCH NEXT FROM dbs INTO @db; \
+WHILE @@FETCH_STATUS = 0 \
+BEGIN \
+  INSERT INTO @result EXEC ('SELECT ' + QUOTENAME(@db, '''') + ', query_id, plan_id, force_failure_count, last_force_failure_reason_desc \
+    FROM ' + QUOTENAME(@db) + '.sys.query_store_plan WHERE is_forced_plan = 1'); \
+  FETCH NEXT FROM dbs INTO @db; \
+END; \
+CLOSE dbs; \
+DEALLOCATE dbs; \
+SELECT \
+  CAST(database_name AS NVARCHAR(128)) AS database_name, \
+  CAST(query_id AS NVARCHAR(20)) AS query_id, \
+  CAST(plan_id AS NVARCHAR(20)) AS plan_id, \
+  CAST(force_failure_count AS NVARCHAR(20)) AS force_failure_count, \
+  CAST(ISNULL(last_force_failure_reason, '') AS NVARCHAR(128)) AS last_force_failure_reason, \
+  CAST(CASE WHEN last_force_failure_reason IS NULL OR last_force_failure_reason = 'NONE' THEN 'forced' \
+    ELSE 'failed' END AS NVARCHAR(20)) AS status \
+FROM @result";
+
+    /// all server role memberships, the filtering and change tracking are done by the agent
+    pub const SERVER_ROLES: &str = "SELECT \
+  CAST(r.name AS NVARCHAR(128)) AS role_name, \
+  CAST(m.name AS NVARCHAR(128)) AS member_name, \
+  CAST(m.type_desc AS NVARCHAR(60)) AS member_type, \
+  CAST(m.is_disabled AS NVARCHAR(1)) AS is_disabled, \
+  CONVERT(NVARCHAR(20), m.modify_date, 120) AS modify_date \
+FROM sys.server_role_members rm \
+JOIN sys.server_principals r ON r.principal_id = rm.role_principal_id \
+JOIN sys.server_principals m ON m.principal_id = rm.member_principal_id \
+ORDER BY r.name, m.name";
+
+    /// SQL Server 2014+, hybrid buffer pool columns are empty
+    /// pages in the extension are counted only if the extension is in use
+    pub const BUFFER_POOL_EXTENSION: &str = "SELECT \
+  CAST(ISNULL(c.path, '') AS NVARCHAR(256)) AS path, \
+  CAST(c.state_description AS NVARCHAR(60)) AS state, \
+  CAST(c.current_size_in_kb AS NVARCHAR(20)) AS size_kb, \
+  CAST(CASE WHEN c.current_size_in_kb > 0 THEN \
+    (SELECT COUNT_BIG(*) FROM sys.dm_os_buffer_descriptors WHERE is_in_bpool_extension = 1) * 8 \
+    ELSE 0 END AS NVARCHAR(20)) AS used_kb, \
+  CAST('' AS NVARCHAR(1)) AS hybrid_configured, \
+  CAST('' AS NVARCHAR(1)) AS hybrid_enabled \
+FROM sys.dm_os_buffer_pool_extension_configuration c";
+
+    /// SQL Server 2019+
+    pub const BUFFER_POOL_EXTENSION_HYBRID: &str = "SELECT \
+  CAST(ISNULL(c.path, '') AS NVARCHAR(256)) AS path, \
+  CAST(c.state_description AS NVARCHAR(60)) AS state, \
+  CAST(c.current_size_in_kb AS NVARCHAR(20)) AS size_kb, \
+  CAST(CASE WHEN c.current_size_in_kb > 0 THEN \
+    (SELECT COUNT_BIG(*) FROM sys.dm_os_buffer_descriptors WHERE is_in_bpool_extension = 1) * 8 \
+    ELSE 0 END AS NVARCHAR(20)) AS used_kb, \
+  CAST(h.is_configured AS NVARCHAR(1)) AS hybrid_configured, \
+  CAST(h.is_enabled AS NVARCHAR(1)) AS hybrid_enabled \
+FROM sys.dm_os_buffer_pool_extension_configuration c \
+CROSS JOIN sys.server_memory_optimized_hybrid_buffer_pool_configuration h";
+
+    /// version store usage is available on SQL Server 2017+ only, the column is empty before
+    pub const VERSION_STORE: &str = "SELECT \
+  CAST(d.name AS NVARCHAR(128)) AS name, \
+  CAST(d.snapshot_isolation_state_desc AS NVARCHAR(60)) AS snapshot_isolation, \
+  CAST(d.is_read_committed_snapshot_on AS NVARCHAR(1)) AS read_committed_snapshot, \
+  CAST('' AS NVARCHAR(20)) AS version_store_kb \
+FROM sys.databases d \
+WHERE d.state = 0";
+
+    /// SQL Server 2017+
+    pub const VERSION_STORE_USAGE: &str = "SELECT \
+  CAST(d.name AS NVARCHAR(128)) AS name, \
+  CAST(d.snapshot_isolation_state_desc AS NVARCHAR(60)) AS snapshot_isolation, \
+  CAST(d.is_read_committed_snapshot_on AS NVARCHAR(1)) AS read_committed_snapshot, \
+  CAST(ISNULL(v.reserved_space_kb, 0) AS NVARCHAR(20)) AS version_store_kb \
+FROM sys.databases d \
+LEFT JOIN sys.dm_tran_version_store_space_usage v ON v.database_id = d.database_id \
+WHERE d.state = 0";
+
+    /// SQL Server 2019+
+    pub const ADR: &str = "SELECT \
+  CAST(d.name AS NVARCHAR(128)) AS name, \
+  CAST(d.is_accelerated_database_recovery_on AS NVARCHAR(1)) AS adr_enabled, \
+  CAST(ISNULL(s.persistent_version_store_size_kb, 0) AS NVARCHAR(20)) AS pvs_size_kb, \
+  CAST(ISNULL(s.current_aborted_transaction_count, 0) AS NVARCHAR(20)) AS aborted_transactions \
+FROM sys.databases d \
+LEFT JOIN sys.dm_tran_persistent_version_store_stats s ON s.database_id = d.database_id \
+WHERE d.state = 0";
+
+    /// reduced variant of ADR without VIEW SERVER STATE, version store stats are empty
+    pub const ADR_REDUCED: &str = "SELECT \
+  CAST(d.name AS NVARCHAR(128)) AS name, \
+  CAST(d.is_accelerated_database_recovery_on AS NVARCHAR(1)) AS adr_enabled, \
+  CAST('' AS NVARCHAR(20)) AS pvs_size_kb, \
+  CAST('' AS NVARCHAR(20)) AS aborted_transactions \
+FROM sys.databases d \
+WHERE d.state = 0";
+
+    /// authentication_mode: windows or mixed
+    /// memory model and instant file initialization are available on SQL Server 2017+ only
+    pub const INSTANCE_SETTINGS: &str = "SELECT \
+  CAST(SERVERPROPERTY('Collation') AS NVARCHAR(128)) AS collation, \
+  CAST(ISNULL(l.name, '') AS NVARCHAR(128)) AS default_language, \
+  CAST(CASE WHEN SERVERPROPERTY('IsIntegratedSecurityOnly') = 1 THEN 'windows' ELSE 'mixed' END AS NVARCHAR(10)) AS authentication_mode, \
+  CAST('' AS NVARCHAR(60)) AS memory_model, \
+  CAST('' AS NVARCHAR(1)) AS instant_file_initialization \
+FROM sys.configurations c \
+LEFT JOIN sys.syslanguages l ON l.langid = CAST(c.value_in_use AS INT) \
+WHERE c.name = 'default language'";
+
+    /// SQL Server 2017+, memory_model is LOCK_PAGES if lock pages in memory is used
+    pub const INSTANCE_SETTINGS_PRIVILEGES: &str = "SELECT \
+  CAST(SERVERPROPERTY('Collation') AS NVARCHAR(128)) AS collation, \
+  CAST(ISNULL(l.name, '') AS NVARCHAR(128)) AS default_language, \
+  CAST(CASE WHEN SERVERPROPERTY('IsIntegratedSecurityOnly') = 1 THEN 'windows' ELSE 'mixed' END AS NVARCHAR(10)) AS authentication_mode, \
+  CAST((SELECT sql_memory_model_desc FROM sys.dm_os_sys_info) AS NVARCHAR(60)) AS memory_model, \
+  CAST(ISNULL((SELECT TOP 1 CASE WHEN instant_file_initialization_enabled = 'Y' THEN 1 ELSE 0 END \
+    FROM sys.dm_server_services WHERE filename LIKE '%sqlservr.exe%'), '') AS NVARCHAR(1)) AS instant_file_initialization \
+FROM sys.configurations c \
+LEFT JOIN sys.syslanguages l ON l.langid = CAST(c.value_in_use AS INT) \
+WHERE c.name = 'default language'";
+
+    /// Azure SQL Database(EngineEdition 5) only, empty elsewhere
+    /// rows of kind `pool` are followed by rows of kind `database` with the share of every
+    /// pooled database, percents of database rows are relative to the database limits
+    pub const ELASTIC_POOL: &str = r"
+DECLARE @SQLCommand nvarchar(max);
+IF (SERVERPROPERTY('EngineEdition') = 5)
+BEGIN
+    SET @SQLCommand = '
+    SELECT
+      cast(''pool'' as nvarchar(10)) as kind,
+      cast(s.elastic_pool_name as nvarchar(128)) as pool_name,
+      cast('''' as nvarchar(128)) as database_name,
+      cast(s.avg_cpu_percent as nvarchar(20)) as cpu_percent,
+      cast(s.avg_data_io_percent as nvarchar(20)) as data_io_percent,
+      cast(s.avg_log_write_percent as nvarchar(20)) as log_write_percent,
+      cast(s.max_worker_percent as nvarchar(20)) as worker_percent,
+      cast(s.max_session_percent as nvarchar(20)) as session_percent,
+      cast(cast(s.avg_storage_percent * s.elastic_pool_storage_limit_mb / 100 as bigint) as nvarchar(20)) as storage_mb,
+      cast(s.elastic_pool_dtu_limit as nvarchar(20)) as dtu_limit,
+      cast(s.elastic_pool_storage_limit_mb as nvarchar(20)) as storage_limit_mb
+    FROM sys.elastic_pool_resource_stats s
+    WHERE s.end_time = (SELECT MAX(l.end_time) FROM sys.elastic_pool_resource_stats l
+                        WHERE l.elastic_pool_name = s.elastic_pool_name)
+    UNION ALL
+    SELECT
+      cast(''database'' as nvarchar(10)),
+      cast(o.elastic_pool_name as nvarchar(128)),
+      cast(r.database_name as nvarchar(128)),
+      cast(r.avg_cpu_percent as nvarchar(20)),
+      cast(r.avg_data_io_percent as nvarchar(20)),
+      cast(r.avg_log_write_percent as nvarchar(20)),
+      cast(r.max_worker_percent as nvarchar(20)),
+      cast(r.max_session_percent as nvarchar(20)),
+      cast(cast(r.storage_in_megabytes as bigint) as nvarchar(20)),
+      cast(r.dtu_limit as nvarchar(20)),
+      cast('''' as nvarchar(20))
+    FROM sys.resource_stats r
+    INNER JOIN sys.databases d ON d.name = r.database_name
+    INNER JOIN sys.database_service_objectives o ON o.database_id = d.database_id
+    WHERE o.elastic_pool_name IS NOT NULL
+      AND r.end_time = (SELECT MAX(l.end_time) FROM sys.resource_stats l
+                        WHERE l.database_name = r.database_name)
+    '
+    EXEC (@SQLCommand)
+END";
+
+    /// system_health Extended Events session, event file target in the log dir
+    /// time is UTC, resources are distinct locked objects separated by comma
+    pub const DEADLOCKS: &str = "SELECT TOP 50 \
+  CONVERT(NVARCHAR(19), d.event_time, 120) AS event_time, \
+  CAST(ISNULL(d.victim_id, '') AS NVARCHAR(64)) AS victim, \
+  CAST(ISNULL(d.report.value('(deadlock/process-list/process[@id=sql:column(\"d.victim_id\")]/@loginname)[1]', 'nvarchar(128)'), '') AS NVARCHAR(128)) AS victim_login, \
+  CAST(ISNULL(d.report.value('(deadlock/process-list/process[@id=sql:column(\"d.victim_id\")]/@currentdbname)[1]', 'nvarchar(128)'), '') AS NVARCHAR(128)) AS victim_database, \
+  CAST(d.report.value('count(deadlock/process-list/process)', 'int') AS NVARCHAR(10)) AS processes, \
+  CAST(ISNULL(STUFF(( \
+    SELECT DISTINCT ',' + n.r.value('@objectname', 'nvarchar(256)') \
+    FROM d.report.nodes('deadlock/resource-list/*') AS n(r) \
+    WHERE n.r.value('@objectname', 'nvarchar(256)') IS NOT NULL \
+    FOR XML PATH('')), 1, 1, ''), '') AS NVARCHAR(2000)) AS resources \
+FROM ( \
+  SELECT \
+    e.ev.value('(event/@timestamp)[1]', 'datetime2') AS event_time, \
+    e.ev.query('(event/data/value/deadlock)[1]') AS report, \
+    e.ev.value('(event/data/value/deadlock/victim-list/victimProcess/@id)[1]', 'nvarchar(64)') AS victim_id \
+  FROM ( \
+    SELECT CAST(event_data AS XML) AS ev \
+    FROM sys.fn_xe_file_target_read_file('system_health*.xel', NULL, NULL, NULL) \
+    WHERE object_name = 'xml_deadlock_report') e \
+) d \
+WHERE d.event_time > DATEADD(HOUR, -24, SYSUTCDATETIME()) \
+ORDER BY d.event_time DESC";
+
+    /// top 50 sources of connections, most recently active first: login floods are on top
+    /// age is seconds since connect, histogram counts connections opened within 1, 5 and 15 minutes
+    pub const CONNECTION_SOURCES: &str = "SELECT TOP 50 \
+  CAST(s.login_name AS NVARCHAR(128)) AS login_name, \
+  CAST(ISNULL(s.host_name, '') AS NVARCHAR(128)) AS client_host, \
+  CAST(ISNULL(c.client_net_address, '') AS NVARCHAR(48)) AS client_address, \
+  CAST(ISNULL(s.program_name, '') AS NVARCHAR(128)) AS program_name, \
+  CAST(COUNT(*) AS NVARCHAR(20)) AS connections, \
+  CAST(SUM(CASE WHEN c.connect_time > DATEADD(MINUTE, -1, GETDATE()) THEN 1 ELSE 0 END) AS NVARCHAR(20)) AS new_1m, \
+  CAST(SUM(CASE WHEN c.connect_time > DATEADD(MINUTE, -5, GETDATE()) THEN 1 ELSE 0 END) AS NVARCHAR(20)) AS new_5m, \
+  CAST(SUM(CASE WHEN c.connect_time > DATEADD(MINUTE, -15, GETDATE()) THEN 1 ELSE 0 END) AS NVARCHAR(20)) AS new_15m, \
+  CAST(MIN(DATEDIFF(SECOND, c.connect_time, GETDATE())) AS NVARCHAR(20)) AS min_age, \
+  CAST(MAX(DATEDIFF(SECOND, c.connect_time, GETDATE())) AS NVARCHAR(20)) AS max_age, \
+  CAST(AVG(DATEDIFF(SECOND, c.connect_time, GETDATE())) AS NVARCHAR(20)) AS avg_age \
+FROM sys.dm_exec_connections c \
+INNER JOIN sys.dm_exec_sessions s ON s.session_id = c.session_id \
+WHERE s.is_user_process = 1 \
+GROUP BY s.login_name, s.host_name, c.client_net_address, s.program_name \
+ORDER BY SUM(CASE WHEN c.connect_time > DATEADD(MINUTE, -5, GETDATE()) THEN 1 ELSE 0 END) DESC, COUNT(*) DESC";
+
+    /// counters are cumulative since start or `DBCC SQLPERF` clear, check computes rates
+    /// benign waits of idle background tasks are excluded
+    pub const WAITSTATS: &str = "SELECT \
+  CAST(wait_type AS NVARCHAR(60)) AS wait_type, \
+  CAST(waiting_tasks_count AS NVARCHAR(20)) AS waiting_tasks, \
+  CAST(wait_time_ms AS NVARCHAR(20)) AS wait_time_ms, \
+  CAST(max_wait_time_ms AS NVARCHAR(20)) AS max_wait_time_ms, \
+  CAST(signal_wait_time_ms AS NVARCHAR(20)) AS signal_wait_time_ms \
+FROM sys.dm_os_wait_stats \
+WHERE waiting_tasks_count > 0 \
+  AND wait_type NOT LIKE 'SLEEP[_]%' \
+  AND wait_type NOT LIKE 'PREEMPTIVE[_]XE[_]%' \
+  AND wait_type NOT LIKE 'PARALLEL[_]REDO[_]%' \
+  AND wait_type NOT IN ( \
+    'BROKER_EVENTHANDLER', 'BROKER_RECEIVE_WAITFOR', 'BROKER_TASK_STOP', \
+    'BROKER_TO_FLUSH', 'BROKER_TRANSMITTER', 'CHECKPOINT_QUEUE', 'CHKPT', \
+    'CLR_AUTO_EVENT', 'CLR_MANUAL_EVENT', 'CLR_SEMAPHORE', 'CXCONSUMER', \
+    'DBMIRROR_DBM_EVENT', 'DBMIRROR_EVENTS_QUEUE', 'DBMIRROR_WORKER_QUEUE', \
+    'DBMIRRORING_CMD', 'DIRTY_PAGE_POLL', 'DISPATCHER_QUEUE_SEMAPHORE', \
+    'EXECSYNC', 'FSAGENT', 'FT_IFTS_SCHEDULER_IDLE_WAIT', 'FT_IFTSHC_MUTEX', \
+    'HADR_CLUSAPI_CALL', 'HADR_FILESTREAM_IOMGR_IOCOMPLETION', 'HADR_LOGCAPTURE_WAIT', \
+    'HADR_NOTIFICATION_DEQUEUE', 'HADR_TIMER_TASK', 'HADR_WORK_QUEUE', \
+    'KSOURCE_WAKEUP', 'LAZYWRITER_SLEEP', 'LOGMGR_QUEUE', 'MEMORY_ALLOCATION_EXT', \
+    'ONDEMAND_TASK_QUEUE', 'PREEMPTIVE_OS_FLUSHFILEBUFFERS', \
+    'PREEMPTIVE_XE_GETTARGETSTATE', 'PWAIT_ALL_COMPONENTS_INITIALIZED', \
+    'PWAIT_DIRECTLOGCONSUMER_GETNEXT', 'PWAIT_EXTENSIBILITY_CLEANUP_TASK', \
+    'QDS_PERSIST_TASK_MAIN_LOOP_SLEEP', 'QDS_ASYNC_QUEUE', \
+    'QDS_CLEANUP_STALE_QUERIES_TASK_MAIN_LOOP_SLEEP', 'QDS_SHUTDOWN_QUEUE', \
+    'REDO_THREAD_PENDING_WORK', 'REQUEST_FOR_DEADLOCK_SEARCH', 'RESOURCE_QUEUE', \
+    'SERVER_IDLE_CHECK', 'SNI_HTTP_ACCEPT', 'SOS_WORK_DISPATCHER', 'SP_SERVER_DIAGNOSTICS_SLEEP', \
+    'SQLTRACE_BUFFER_FLUSH', 'SQLTRACE_INCREMENTAL_FLUSH_SLEEP', 'SQLTRACE_WAIT_ENTRIES', \
+    'STARTUP_DEPENDENCY_MANAGER', 'UCS_SESSION_REGISTRATION', 'VDI_CLIENT_OTHER', \
+    'WAIT_FOR_RESULTS', 'WAIT_XTP_CKPT_CLOSE', 'WAIT_XTP_HOST_WAIT', \
+    'WAIT_XTP_OFFLINE_CKPT_NEW_LOG', 'WAIT_XTP_RECOVERY', 'WAITFOR', \
+    'WAITFOR_TASKSHUTDOWN', 'XE_BUFFERMGR_ALLPROCESSED_EVENT', 'XE_DISPATCHER_JOIN', \
+    'XE_DISPATCHER_WAIT', 'XE_LIVE_TARGET_TVF', 'XE_TIMER_EVENT') \
+ORDER BY wait_time_ms DESC";
+
+    /// reason is login_error(error before login), killed, network or error
+    /// regular closes of connections are skipped, time is local time of the server
+    pub const CONNECTIVITY: &str = "SELECT TOP 100 \
+  CONVERT(NVARCHAR(19), r.event_time, 120) AS event_time, \
+  CAST(r.record_type AS NVARCHAR(30)) AS record_type, \
+  CAST(CASE \
+    WHEN r.before_login = 1 THEN 'login_error' \
+    WHEN r.session_killed = 1 OR r.connection_killed = 1 THEN 'killed' \
+    WHEN r.read_error = 1 OR r.network_error = 1 THEN 'network' \
+    ELSE 'error' END AS NVARCHAR(20)) AS reason, \
+  CAST(ISNULL(r.remote_host, '') AS NVARCHAR(48)) AS remote_host, \
+  CAST(ISNULL(r.remote_port, '') AS NVARCHAR(10)) AS remote_port, \
+  CAST(ISNULL(r.spid, '') AS NVARCHAR(10)) AS spid, \
+  CAST(ISNULL(r.sni_error, '') AS NVARCHAR(10)) AS sni_error, \
+  CAST(ISNULL(r.os_error, '') AS NVARCHAR(10)) AS os_error, \
+  CAST(ISNULL(r.state, '') AS NVARCHAR(10)) AS state \
+FROM ( \
+  SELECT \
+    DATEADD(SECOND, -1 * CAST((i.ms_ticks - b.timestamp) / 1000 AS INT), GETDATE()) AS event_time, \
+    x.r.value('(RecordType)[1]', 'nvarchar(30)') AS record_type, \
+    x.r.value('(RemoteHost)[1]', 'nvarchar(48)') AS remote_host, \
+    x.r.value('(RemotePort)[1]', 'nvarchar(10)') AS remote_port, \
+    x.r.value('(Spid)[1]', 'nvarchar(10)') AS spid, \
+    x.r.value('(SniConsumerError)[1]', 'nvarchar(10)') AS sni_error, \
+    x.r.value('(OSError)[1]', 'nvarchar(10)') AS os_error, \
+    x.r.value('(State)[1]', 'nvarchar(10)') AS state, \
+    x.r.value('(TdsDisconnectFlags/ErrorFoundBeforeLogin)[1]', 'int') AS before_login, \
+    x.r.value('(TdsDisconnectFlags/SessionIsKilled)[1]', 'int') AS session_killed, \
+    x.r.value('(TdsDisconnectFlags/PhysicalConnectionIsKilled)[1]', 'int') AS connection_killed, \
+    x.r.value('(TdsDisconnectFlags/DisconnectDueToReadError)[1]', 'int') AS read_error, \
+    x.r.value('(TdsDisconnectFlags/NetworkErrorFoundInInputStream)[1]', 'int') AS network_error \
+  FROM sys.dm_os_ring_buffers b \
+  CROSS JOIN sys.dm_os_sys_info i \
+  CROSS APPLY (SELECT CAST(b.record AS XML) AS record) c \
+  CROSS APPLY c.record.nodes('Record/ConnectivityTraceRecord') AS x(r) \
+  WHERE b.ring_buffer_type = 'RING_BUFFER_CONNECTIVITY' \
+) r \
+WHERE r.event_time > DATEADD(HOUR, -1, GETDATE()) \
+  AND (r.record_type = 'Error' \
+    OR r.before_login = 1 OR r.session_killed = 1 OR r.connection_killed = 1 \
+    OR r.read_error = 1 OR r.network_error = 1) \
+ORDER BY r.event_time DESC";
+
+    /// executed in every database, `@min_page_count` is declared by the caller
+    /// LIMITED mode scans only parent level pages, leaf level of in-row data is reported
+    pub const INDEX_FRAGMENTATION: &str = "SELECT \
+  CAST(DB_NAME() AS NVARCHAR(128)) AS database_name, \
+  CAST(OBJECT_SCHEMA_NAME(s.object_id) + '.' + OBJECT_NAME(s.object_id) AS NVARCHAR(256)) AS table_name, \
+  CAST(ISNULL(i.name, '') AS NVARCHAR(128)) AS index_name, \
+  CAST(s.index_type_desc AS NVARCHAR(60)) AS index_type, \
+  CAST(s.partition_number AS NVARCHAR(10)) AS partition_number, \
+  CAST(s.page_count AS NVARCHAR(20)) AS page_count, \
+  CAST(CAST(s.avg_fragmentation_in_percent AS DECIMAL(5, 2)) AS NVARCHAR(10)) AS fragmentation_percent \
+FROM sys.dm_db_index_physical_stats(DB_ID(), NULL, NULL, NULL, 'LIMITED') s \
+INNER JOIN sys.indexes i ON i.object_id = s.object_id AND i.index_id = s.index_id \
+WHERE s.index_id > 0 \
+  AND s.alloc_unit_type_desc = 'IN_ROW_DATA' \
+  AND s.page_count >= @min_page_count \
+ORDER BY s.avg_fragmentation_in_percent DESC";
+
+    /// executed in every database, `@top` is declared by the caller
+    /// rank_by is duration or cpu, times are in ms, text is flattened and cut to 200 chars
+    pub const QUERY_STORE: &str = "IF EXISTS (SELECT 1 FROM sys.database_query_store_options WHERE actual_state IN (1, 2)) \
+BEGIN \
+WITH stats AS ( \
+  SELECT \
+    p.query_id, \
+    SUM(rs.count_executions) AS executions, \
+    SUM(rs.avg_duration * rs.count_executions) / 1000 AS duration_ms, \
+    SUM(rs.avg_cpu_time * rs.count_executions) / 1000 AS cpu_ms, \
+    MAX(rs.max_duration) / 1000 AS max_duration_ms \
+  FROM sys.query_store_runtime_stats rs \
+  INNER JOIN sys.query_store_runtime_stats_interval i ON i.runtime_stats_interval_id = rs.runtime_stats_interval_id \
+  INNER JOIN sys.query_store_plan p ON p.plan_id = rs.plan_id \
+  WHERE i.end_time > DATEADD(HOUR, -1, SYSUTCDATETIME()) \
+  GROUP BY p.query_id \
+), ranked AS ( \
+  SELECT 'duration' AS rank_by, * FROM (SELECT TOP (@top) * FROM stats ORDER BY duration_ms DESC) d \
+  UNION ALL \
+  SELECT 'cpu' AS rank_by, * FROM (SELECT TOP (@top) * FROM stats ORDER BY cpu_ms DESC) c \
+) \
+SELECT \
+  CAST(DB_NAME() AS NVARCHAR(128)) AS database_name, \
+  CAST(r.rank_by AS NVARCHAR(10)) AS rank_by, \
+  CAST(r.query_id AS NVARCHAR(20)) AS query_id, \
+  CAST(ISNULL(OBJECT_NAME(q.object_id), '') AS NVARCHAR(256)) AS object_name, \
+  CAST(r.executions AS NVARCHAR(20)) AS executions, \
+  CAST(CAST(r.duration_ms AS BIGINT) AS NVARCHAR(20)) AS duration_ms, \
+  CAST(CAST(r.cpu_ms AS BIGINT) AS NVARCHAR(20)) AS cpu_ms, \
+  CAST(CAST(r.max_duration_ms AS BIGINT) AS NVARCHAR(20)) AS max_duration_ms, \
+  CAST(LEFT(REPLACE(REPLACE(REPLACE(qt.query_sql_text, CHAR(13), ' '), CHAR(10), ' '), '|', ' '), 200) AS NVARCHAR(200)) AS query_text \
+FROM ranked r \
+INNER JOIN sys.query_store_query q ON q.query_id = r.query_id \
+INNER JOIN sys.query_store_query_text qt ON qt.query_text_id = q.query_text_id \
+ORDER BY r.rank_by DESC, r.duration_ms DESC; \
+END";
+
+    /// rows of two kinds:
+    /// - clerk|type|pages_kb, top 20 memory clerks by allocated memory
+    /// - counter|name|value, buffer pool and memory manager counters, memory is in KB
+    pub const MEMORY: &str = "SELECT kind, name, value FROM ( \
+  SELECT TOP 20 \
+    CAST('clerk' AS NVARCHAR(10)) AS kind, \
+    CAST(type AS NVARCHAR(128)) AS name, \
+    CAST(SUM(pages_kb) AS NVARCHAR(20)) AS value \
+  FROM sys.dm_os_memory_clerks \
+  GROUP BY type \
+  HAVING SUM(pages_kb) > 0 \
+  ORDER BY SUM(pages_kb) DESC \
+) AS clerks \
+UNION ALL \
+SELECT \
+  CAST('counter' AS NVARCHAR(10)) AS kind, \
+  CAST(RTRIM(counter_name) AS NVARCHAR(128)) AS name, \
+  CAST(cntr_value AS NVARCHAR(20)) AS value \
+FROM sys.dm_os_performance_counters \
+WHERE (object_name LIKE '%:Buffer Manager%' AND counter_name IN ('Page life expectancy', 'Database pages')) \
+  OR (object_name LIKE '%:Memory Manager%' AND counter_name IN ( \
+    'Total Server Memory (KB)', 'Target Server Memory (KB)', 'Stolen Server Memory (KB)', \
+    'Database Cache Memory (KB)', 'Memory Grants Pending'))";
+
+    /// monitor tables of msdb: a primary reports its last backup, a secondary reports last
+    /// copy and restore, last backup is taken from the primary if monitored on the same server
+    /// times are local times of the server, latency is the last restore latency in minutes
+    pub const LOG_SHIPPING: &str = "SELECT \
+  CAST('primary' AS NVARCHAR(10)) AS role, \
+  CAST(p.primary_server AS NVARCHAR(128)) AS primary_server, \
+  CAST(p.primary_database AS NVARCHAR(128)) AS primary_database, \
+  CAST('' AS NVARCHAR(128)) AS secondary_server, \
+  CAST('' AS NVARCHAR(128)) AS secondary_database, \
+  ISNULL(CONVERT(NVARCHAR, p.last_backup_date, 20), '') AS last_backup_date, \
+  CAST('' AS NVARCHAR(20)) AS last_copied_date, \
+  CAST('' AS NVARCHAR(20)) AS last_restored_date, \
+  CAST('' AS NVARCHAR(20)) AS last_restored_latency, \
+  ISNULL(CAST(p.backup_threshold AS NVARCHAR(20)), '') AS threshold, \
+  CONVERT(NVARCHAR, CURRENT_TIMESTAMP, 20) AS server_current_time \
+FROM dbo.log_shipping_monitor_primary p \
+UNION ALL \
+SELECT \
+  CAST('secondary' AS NVARCHAR(10)), \
+  CAST(s.primary_server AS NVARCHAR(128)), \
+  CAST(s.primary_database AS NVARCHAR(128)), \
+  CAST(s.secondary_server AS NVARCHAR(128)), \
+  CAST(s.secondary_database AS NVARCHAR(128)), \
+  ISNULL(CONVERT(NVARCHAR, p.last_backup_date, 20), ''), \
+  ISNULL(CONVERT(NVARCHAR, s.last_copied_date, 20), ''), \
+  ISNULL(CONVERT(NVARCHAR, s.last_restored_date, 20), ''), \
+  ISNULL(CAST(s.last_restored_latency AS NVARCHAR(20)), ''), \
+  ISNULL(CAST(s.restore_threshold AS NVARCHAR(20)), ''), \
+  CONVERT(NVARCHAR, CURRENT_TIMESTAMP, 20) \
+FROM dbo.log_shipping_monitor_secondary s \
+LEFT JOIN dbo.log_shipping_monitor_primary p \
+  ON p.primary_server = s.primary_server AND p.primary_database = s.primary_database";
+
+    /// distribution agents of every local distribution database with the latest history
+    /// entry, nothing if the instance is not a distributor
+    /// publication_type: 0 - transactional, 1 - snapshot, 2 - merge
+    /// subscription_status: 0 - inactive, 1 - subscribed, 2 - active
+    /// runstatus: 1 - start, 2 - succeed, 3 - in progress, 4 - idle, 5 - retry, 6 - fail
+    pub const REPLICATION: &str = "DECLARE @template NVARCHAR(MAX) = N'SELECT \
+  CAST({name} AS NVARCHAR(128)) AS distribution_db, \
+  CAST(ISNULL(ps.name, N'''') AS NVARCHAR(128)) AS publisher, \
+  CAST(a.publisher_db AS NVARCHAR(128)) AS publisher_db, \
+  CAST(a.publication AS NVARCHAR(128)) AS publication, \
+  CAST(ISNULL(ss.name, N'''') AS NVARCHAR(128)) AS subscriber, \
+  CAST(ISNULL(a.subscriber_db, N'''') AS NVARCHAR(128)) AS subscriber_db, \
+  ISNULL(p.publication_type, -1) AS publication_type, \
+  ISNULL(s.status, -1) AS subscription_status, \
+  ISNULL(h.runstatus, 0) AS runstatus, \
+  ISNULL(CONVERT(NVARCHAR, h.time, 20), N'''') AS last_sync, \
+  ISNULL(h.delivery_latency, 0) AS delivery_latency_ms, \
+  CONVERT(NVARCHAR, CURRENT_TIMESTAMP, 20) AS server_current_time \
+FROM {db}.dbo.MSdistribution_agents a \
+LEFT JOIN {db}.dbo.MSpublications p \
+  ON p.publisher_id = a.publisher_id AND p.publisher_db = a.publisher_db AND p.publication = a.publication \
+LEFT JOIN sys.servers ps ON ps.server_id = a.publisher_id \
+LEFT JOIN sys.servers ss ON ss.server_id = a.subscriber_id \
+OUTER APPLY (SELECT TOP 1 status FROM {db}.dbo.MSsubscriptions WHERE agent_id = a.id) s \
+OUTER APPLY (SELECT TOP 1 runstatus, time, delivery_latency FROM {db}.dbo.MSdistribution_history \
+  WHERE agent_id = a.id ORDER BY time DESC) h \
+WHERE a.subscriber_id >= 0'; \
+DECLARE @sql NVARCHAR(MAX) = N''; \
+SELECT @sql = @sql + CASE WHEN @sql = N'' THEN N'' ELSE N' UNION ALL ' END \
+  + REPLACE(REPLACE(@template, N'{db}', QUOTENAME(name)), N'{name}', QUOTENAME(name, '''')) \
+FROM sys.databases WHERE is_distributor = 1; \
+IF @sql <> N'' EXEC sp_executesql @sql;";
+
+    /// notifications: count of operators to be notified by the alert
+    /// enabled_operators: enabled operators among them
+    pub const AGENT_ALERTS: &str = "SELECT \
+  CAST(a.name AS NVARCHAR(128)) AS alert_name, \
+  a.enabled, \
+  a.severity, \
+  a.message_id, \
+  CAST(ISNULL(a.database_name, '') AS NVARCHAR(128)) AS database_name, \
+  a.occurrence_count, \
+  CASE WHEN a.last_occurrence_date > 0 \
+    THEN CONVERT(NVARCHAR, dbo.agent_datetime(a.last_occurrence_date, a.last_occurrence_time), 20) \
+    ELSE '' END AS last_occurrence, \
+  COUNT(o.id) AS notifications, \
+  ISNULL(SUM(CAST(o.enabled AS INT)), 0) AS enabled_operators, \
+  CONVERT(NVARCHAR, CURRENT_TIMESTAMP, 20) AS server_current_time \
+FROM dbo.sysalerts a \
+LEFT JOIN dbo.sysnotifications n ON n.alert_id = a.id \
+LEFT JOIN dbo.sysoperators o ON o.id = n.operator_id \
+GROUP BY a.name, a.enabled, a.severity, a.message_id, a.database_name, a.occurrence_count, \
+  a.last_occurrence_date, a.last_occurrence_time";
+
+    /// entries of the current error log newer than @since with severity 17+, the message is
+    /// the next line of the log, requires securityadmin
+    /// NOTE: @since must be declared before the query
+    pub const ERRORLOG: &str = "DECLARE @log TABLE ( \
+  id INT IDENTITY(1, 1) PRIMARY KEY, \
+  log_date DATETIME, \
+  process_info NVARCHAR(64), \
+  text NVARCHAR(MAX)); \
+INSERT INTO @log (log_date, process_info, text) \
+  EXEC master.dbo.xp_readerrorlog 0, 1, NULL, NULL, @since, NULL, N'asc'; \
+SELECT TOP 100 \
+  CONVERT(NVARCHAR, e.log_date, 121) AS log_date, \
+  v.error, \
+  v.severity, \
+  v.state, \
+  CAST(ISNULL(n.text, '') AS NVARCHAR(4000)) AS message \
+FROM @log e \
+LEFT JOIN @log n ON n.id = e.id + 1 \
+CROSS APPLY (SELECT \
+  CHARINDEX(N', Severity: ', e.text) AS sp, \
+  CHARINDEX(N', State: ', e.text) AS tp) p \
+CROSS APPLY (SELECT \
+  CASE WHEN p.sp > 8 THEN TRY_CAST(SUBSTRING(e.text, 8, p.sp - 8) AS INT) END AS error, \
+  CASE WHEN p.tp > p.sp + 12 THEN TRY_CAST(SUBSTRING(e.text, p.sp + 12, p.tp - p.sp - 12) AS INT) END AS severity, \
+  CASE WHEN p.tp > 0 THEN TRY_CAST(REPLACE(SUBSTRING(e.text, p.tp + 9, 10), '.', '') AS INT) END AS state) v \
+WHERE e.text LIKE N'Error: %, Severity: %, State: %' AND e.log_date > @since AND v.severity >= 17 \
+ORDER BY e.id";
+
+    /// reduced variant of ERRORLOG without securityadmin: exceptions of the ring buffer with
+    /// the message text from sys.messages, requires VIEW SERVER STATE
+    pub const ERRORLOG_RING_BUFFER: &str = "SELECT TOP 100 \
+  CONVERT(NVARCHAR, x.log_date, 121) AS log_date, \
+  x.error, \
+  x.severity, \
+  x.state, \
+  CAST(ISNULL(m.text, '') AS NVARCHAR(4000)) AS message \
+FROM (SELECT \
+    DATEADD(ms, -1 * CAST((i.ms_ticks - r.timestamp) % 1000 AS INT), \
+      DATEADD(s, -1 * CAST((i.ms_ticks - r.timestamp) / 1000 AS INT), GETDATE())) AS log_date, \
+    r.rec.value('(/Record/Exception/Error)[1]', 'INT') AS error, \
+    r.rec.value('(/Record/Exception/Severity)[1]', 'INT') AS severity, \
+    r.rec.value('(/Record/Exception/State)[1]', 'INT') AS state \
+  FROM (SELECT timestamp, CAST(record AS XML) AS rec \
+    FROM sys.dm_os_ring_buffers \
+    WHERE ring_buffer_type = N'RING_BUFFER_EXCEPTION') r \
+  CROSS JOIN sys.dm_os_sys_info i) x \
+LEFT JOIN sys.messages m ON m.message_id = x.error AND m.language_id = 1033 \
+WHERE x.severity >= 17 AND x.log_date > @since \
+ORDER BY x.log_date";
+
+    /// failed logins(error 18456) of the current error log newer than @since per login and
+    /// client, requires securityadmin
+    /// NOTE: @since must be declared before the query
+    pub const FAILED_LOGINS: &str = "DECLARE @log TABLE ( \
+  log_date DATETIME, \
+  process_info NVARCHAR(64), \
+  text NVARCHAR(MAX)); \
+INSERT INTO @log (log_date, process_info, text) \
+  EXEC master.dbo.xp_readerrorlog 0, 1, N'Login failed', NULL, @since, NULL, N'asc'; \
+SELECT \
+  CAST(v.login_name AS NVARCHAR(128)) AS login_name, \
+  CAST(v.client AS NVARCHAR(128)) AS client, \
+  COUNT(*) AS failed, \
+  CONVERT(NVARCHAR, MAX(e.log_date), 121) AS last_failed \
+FROM @log e \
+CROSS APPLY (SELECT \
+  CHARINDEX(N'''', e.text) AS q1, \
+  CHARINDEX(N'[CLIENT: ', e.text) AS c) p \
+CROSS APPLY (SELECT \
+  CHARINDEX(N'''', e.text, p.q1 + 1) AS q2, \
+  CHARINDEX(N']', e.text, p.c) AS ce) p2 \
+CROSS APPLY (SELECT \
+  CASE WHEN p.q1 > 0 AND p2.q2 > p.q1 THEN SUBSTRING(e.text, p.q1 + 1, p2.q2 - p.q1 - 1) ELSE '' END AS login_name, \
+  CASE WHEN p.c > 0 AND p2.ce > p.c + 9 THEN SUBSTRING(e.text, p.c + 9, p2.ce - p.c - 9) ELSE '' END AS client) v \
+WHERE e.text LIKE N'Login failed for user%' AND e.log_date > @since \
+GROUP BY v.login_name, v.client \
+ORDER BY failed DESC";
+
+    /// database rows: TDE state, key and the certificate protecting the key
+    /// certificate rows: user certificates of master, detail is the usage: tde or endpoint
+    /// days_left is negative for expired certificates
+    pub const ENCRYPTION: &str = "SELECT \
+  CAST('database' AS NVARCHAR(16)) AS kind, \
+  CAST(d.name AS NVARCHAR(128)) AS name, \
+  CAST(CASE k.encryption_state \
+    WHEN 1 THEN 'unencrypted' \
+    WHEN 2 THEN 'encrypting' \
+    WHEN 3 THEN 'encrypted' \
+    WHEN 4 THEN 'key_change' \
+    WHEN 5 THEN 'decrypting' \
+    WHEN 6 THEN 'protection_change' \
+    ELSE 'none' END AS NVARCHAR(32)) AS state, \
+  CAST(ISNULL(k.key_algorithm + ' ' + CAST(k.key_length AS NVARCHAR(8)), '') AS NVARCHAR(64)) AS key_algorithm, \
+  CAST(ISNULL(c.name, '') AS NVARCHAR(128)) AS detail, \
+  ISNULL(CONVERT(NVARCHAR, c.expiry_date, 20), '') AS expiry_date, \
+  ISNULL(CAST(DATEDIFF(DAY, GETDATE(), c.expiry_date) AS NVARCHAR(16)), '') AS days_left \
+FROM sys.databases d \
+LEFT JOIN sys.dm_database_encryption_keys k ON k.database_id = d.database_id \
+LEFT JOIN sys.certificates c ON c.thumbprint = k.encryptor_thumbprint \
+UNION ALL \
+SELECT \
+  CAST('certificate' AS NVARCHAR(16)), \
+  CAST(c.name AS NVARCHAR(128)), \
+  CAST(CASE WHEN c.expiry_date < GETDATE() THEN 'expired' ELSE 'valid' END AS NVARCHAR(32)), \
+  CAST('' AS NVARCHAR(64)), \
+  CAST(CASE \
+    WHEN EXISTS (SELECT 1 FROM sys.dm_database_encryption_keys k WHERE k.encryptor_thumbprint = c.thumbprint) THEN 'tde' \
+    WHEN EXISTS (SELECT 1 FROM sys.database_mirroring_endpoints e WHERE e.certificate_id = c.certificate_id) THEN 'endpoint' \
+    ELSE '' END AS NVARCHAR(128)), \
+  CONVERT(NVARCHAR, c.expiry_date, 20), \
+  CAST(DATEDIFF(DAY, GETDATE(), c.expiry_date) AS NVARCHAR(16)) \
+FROM sys.certificates c \
+WHERE c.name NOT LIKE '##%'";
+
+    /// reduced variant of ENCRYPTION without VIEW SERVER STATE: is_encrypted flag only, no
+    /// key, TDE usage of certificates is unknown
+    pub const ENCRYPTION_REDUCED: &str = "SELECT \
+  CAST('database' AS NVARCHAR(16)) AS kind, \
+  CAST(d.name AS NVARCHAR(128)) AS name, \
+  CAST(CASE WHEN d.is_encrypted = 1 THEN 'encrypted' ELSE 'none' END AS NVARCHAR(32)) AS state, \
+  CAST('' AS NVARCHAR(64)) AS key_algorithm, \
+  CAST('' AS NVARCHAR(128)) AS detail, \
+  CAST('' AS NVARCHAR(30)) AS expiry_date, \
+  CAST('' AS NVARCHAR(16)) AS days_left \
+FROM sys.databases d \
+UNION ALL \
+SELECT \
+  CAST('certificate' AS NVARCHAR(16)), \
+  CAST(c.name AS NVARCHAR(128)), \
+  CAST(CASE WHEN c.expiry_date < GETDATE() THEN 'expired' ELSE 'valid' END AS NVARCHAR(32)), \
+  CAST('' AS NVARCHAR(64)), \
+  CAST(CASE \
+    WHEN EXISTS (SELECT 1 FROM sys.database_mirroring_endpoints e WHERE e.certificate_id = c.certificate_id) THEN 'endpoint' \
+    ELSE '' END AS NVARCHAR(128)), \
+  CONVERT(NVARCHAR, c.expiry_date, 20), \
+  CAST(DATEDIFF(DAY, GETDATE(), c.expiry_date) AS NVARCHAR(16)) \
+FROM sys.certificates c \
+WHERE c.name NOT LIKE '##%'";
+
+    /// every blocked session with the head of its chain and depth in the chain, the head
+    /// blocker itself has depth 0, statement of idle head blockers is the most recent one
+    pub const BLOCKING_CHAINS: &str = "WITH blocked AS ( \
+  SELECT session_id, blocking_session_id \
+  FROM sys.dm_exec_requests \
+  WHERE blocking_session_id <> 0 AND blocking_session_id <> session_id), \
+heads AS ( \
+  SELECT DISTINCT b.blocking_session_id AS session_id \
+  FROM blocked b \
+  WHERE b.blocking_session_id NOT IN (SELECT session_id FROM blocked)), \
+chain AS ( \
+  SELECT h.session_id AS head_blocker, h.session_id, 0 AS blocking_session_id, 0 AS depth \
+  FROM heads h \
+  UNION ALL \
+  SELECT c.head_blocker, b.session_id, b.blocking_session_id, c.depth + 1 \
+  FROM chain c \
+  INNER JOIN blocked b ON b.blocking_session_id = c.session_id) \
+SELECT \
+  c.head_blocker, \
+  c.session_id, \
+  c.blocking_session_id, \
+  c.depth, \
+  CAST(ISNULL(r.wait_type, '') AS NVARCHAR(60)) AS wait_type, \
+  ISNULL(r.wait_time, 0) AS wait_time_ms, \
+  CAST(ISNULL(r.wait_resource, '') AS NVARCHAR(256)) AS wait_resource, \
+  CAST(ISNULL(s.login_name, '') AS NVARCHAR(128)) AS login_name, \
+  CAST(ISNULL(DB_NAME(ISNULL(r.database_id, s.database_id)), '') AS NVARCHAR(128)) AS database_name, \
+  CAST(ISNULL(REPLACE(REPLACE(REPLACE(REPLACE(LEFT(t.text, 1000), CHAR(13), ' '), CHAR(10), ' '), CHAR(9), ' '), '|', ' '), '') \
+    AS NVARCHAR(1000)) AS statement \
+FROM chain c \
+LEFT JOIN sys.dm_exec_requests r ON r.session_id = c.session_id \
+LEFT JOIN sys.dm_exec_sessions s ON s.session_id = c.session_id \
+LEFT JOIN sys.dm_exec_connections cn ON cn.session_id = c.session_id AND cn.parent_connection_id IS NULL \
+OUTER APPLY sys.dm_exec_sql_text(ISNULL(r.sql_handle, cn.most_recent_sql_handle)) t \
+ORDER BY c.head_blocker, c.depth, c.session_id \
+OPTION (MAXRECURSION 100)";
+
+    /// cumulative counters per database file since start of the instance, sample_ms is the
+    /// uptime of the instance in ms and sample_time is UTC: both to compute rates
+    pub const IO_STATS: &str = "SELECT \
+  CAST(ISNULL(DB_NAME(s.database_id), '') AS NVARCHAR(128)) AS database_name, \
+  s.file_id, \
+  CAST(ISNULL(f.name, '') AS NVARCHAR(128)) AS file_name, \
+  CAST(ISNULL(f.type_desc, '') AS NVARCHAR(60)) AS file_type, \
+  s.num_of_reads, \
+  s.num_of_bytes_read, \
+  s.io_stall_read_ms, \
+  s.num_of_writes, \
+  s.num_of_bytes_written, \
+  s.io_stall_write_ms, \
+  s.sample_ms, \
+  CONVERT(NVARCHAR, GETUTCDATE(), 20) AS sample_time \
+FROM sys.dm_io_virtual_file_stats(NULL, NULL) s \
+LEFT JOIN sys.master_files f ON f.database_id = s.database_id AND f.file_id = s.file_id \
+ORDER BY database_name, s.file_id";
+
+    /// one row: size of the plan cache and of single-use ad hoc plans in KB, compilations,
+    /// re-compilations and batch requests are cumulative counters of the instance
+    pub const PLAN_CACHE: &str = "SELECT \
+  COUNT_BIG(*) AS plans, \
+  ISNULL(SUM(CAST(p.size_in_bytes AS BIGINT)), 0) / 1024 AS cache_kb, \
+  ISNULL(SUM(CASE WHEN p.usecounts = 1 THEN 1 ELSE 0 END), 0) AS single_use_plans, \
+  ISNULL(SUM(CASE WHEN p.usecounts = 1 THEN CAST(p.size_in_bytes AS BIGINT) ELSE 0 END), 0) / 1024 \
+    AS single_use_kb, \
+  CAST(CASE WHEN COUNT_BIG(*) = 0 THEN 0 \
+    ELSE 100.0 * SUM(CASE WHEN p.usecounts = 1 THEN 1 ELSE 0 END) / COUNT_BIG(*) END AS DECIMAL(5, 2)) \
+    AS single_use_percent, \
+  (SELECT ISNULL(MAX(cntr_value), 0) FROM sys.dm_os_performance_counters \
+    WHERE object_name LIKE '%:SQL Statistics%' AND counter_name = 'SQL Compilations/sec') AS compilations, \
+  (SELECT ISNULL(MAX(cntr_value), 0) FROM sys.dm_os_performance_counters \
+    WHERE object_name LIKE '%:SQL Statistics%' AND counter_name = 'SQL Re-Compilations/sec') AS recompilations, \
+  (SELECT ISNULL(MAX(cntr_value), 0) FROM sys.dm_os_performance_counters \
+    WHERE object_name LIKE '%:SQL Statistics%' AND counter_name = 'Batch Requests/sec') AS batch_requests, \
+  CONVERT(NVARCHAR, GETUTCDATE(), 20) AS sample_time \
+FROM sys.dm_exec_cached_plans p";
+
+    /// linked servers of the instance, the test is executed by the agent per server
+    pub const LINKED_SERVERS: &str = "SELECT \
+  CAST(name AS NVARCHAR(128)) AS name, \
+  CAST(ISNULL(product, '') AS NVARCHAR(128)) AS product, \
+  CAST(ISNULL(provider, '') AS NVARCHAR(128)) AS provider, \
+  CAST(ISNULL(data_source, '') AS NVARCHAR(512)) AS data_source, \
+  CAST(is_rpc_out_enabled AS NVARCHAR(1)) AS rpc_out, \
+  CAST(is_data_access_enabled AS NVARCHAR(1)) AS data_access \
+FROM sys.servers \
+WHERE is_linked = 1 \
+ORDER BY name";
+
+    /// size and age of the last full backup per user database with its tenant from the
+    /// extended property `@property`, backup_age is in seconds, -1 - never backed up
+    pub const TENANTS: &str = "DECLARE @tenants TABLE (database_name NVARCHAR(128), tenant NVARCHAR(128)); \
+DECLARE @template NVARCHAR(MAX) = N'SELECT {name}, CAST(value AS NVARCHAR(128)) \
+FROM {db}.sys.extended_properties WHERE class = 0 AND name = @property'; \
+DECLARE @sql NVARCHAR(MAX) = N''; \
+SELECT @sql = @sql + CASE WHEN @sql = N'' THEN N'' ELSE N' UNION ALL ' END \
+  + REPLACE(REPLACE(@template, N'{db}', QUOTENAME(name)), N'{name}', QUOTENAME(name, '''')) \
+FROM sys.databases WHERE database_id > 4 AND state = 0 AND HAS_DBACCESS(name) = 1; \
+IF @sql <> N'' INSERT INTO @tenants EXEC sp_executesql @sql, N'@property NVARCHAR(128)', @property = @property; \
+SELECT \
+  CAST(d.name AS NVARCHAR(128)) AS database_name, \
+  CAST(ISNULL(t.tenant, '') AS NVARCHAR(128)) AS tenant, \
+  ISNULL((SELECT SUM(CAST(f.size AS BIGINT)) * 8 FROM sys.master_files f \
+    WHERE f.database_id = d.database_id AND f.type = 0), 0) AS data_kb, \
+  ISNULL((SELECT SUM(CAST(f.size AS BIGINT)) * 8 FROM sys.master_files f \
+    WHERE f.database_id = d.database_id AND f.type = 1), 0) AS log_kb, \
+  ISNULL(DATEDIFF(SECOND, (SELECT MAX(b.backup_finish_date) FROM msdb.dbo.backupset b \
+    WHERE b.database_name = d.name AND b.type = 'D'), GETDATE()), -1) AS backup_age \
+FROM sys.databases d \
+LEFT JOIN @tenants t ON t.database_name = d.name \
+WHERE d.database_id > 4 \
+ORDER BY d.name";
+
+    /// per database with CDC or change tracking enabled, statements run in the context of the
+    /// database: latency of the last log scan(cdc) in seconds, last activity, retention and
+    /// age of the oldest retained change in minutes, state of capture(cdc) and cleanup jobs
+    /// -1 - not applicable for change tracking
+    pub const CDC: &str = "IF OBJECT_ID('tempdb..#cdc') IS NOT NULL DROP TABLE #cdc; \
+CREATE TABLE #cdc (database_name NVARCHAR(128), feature NVARCHAR(20), latency_s BIGINT, \
+  last_activity NVARCHAR(30), retention_minutes BIGINT, retained_minutes BIGINT, \
+  capture_enabled INT, cleanup_enabled INT); \
+DECLARE @cdc_stmt NVARCHAR(MAX) = N'SELECT DB_NAME(), N''cdc'', \
+  ISNULL((SELECT TOP 1 latency FROM sys.dm_cdc_log_scan_sessions WHERE session_id <> 0 \
+    ORDER BY end_time DESC), -1), \
+  ISNULL((SELECT CONVERT(NVARCHAR, MAX(end_time), 20) FROM sys.dm_cdc_log_scan_sessions \
+    WHERE session_id <> 0), N''''), \
+  ISNULL((SELECT TOP 1 retention FROM msdb.dbo.cdc_jobs \
+    WHERE database_id = DB_ID() AND job_type = N''cleanup''), 0), \
+  ISNULL((SELECT DATEDIFF(MINUTE, MIN(tran_begin_time), GETDATE()) FROM cdc.lsn_time_mapping), 0), \
+  ISNULL((SELECT TOP 1 CAST(s.enabled AS INT) FROM msdb.dbo.cdc_jobs j \
+    INNER JOIN msdb.dbo.sysjobs s ON s.job_id = j.job_id \
+    WHERE j.database_id = DB_ID() AND j.job_type = N''capture''), 0), \
+  ISNULL((SELECT TOP 1 CAST(s.enabled AS INT) FROM msdb.dbo.cdc_jobs j \
+    INNER JOIN msdb.dbo.sysjobs s ON s.job_id = j.job_id \
+    WHERE j.database_id = DB_ID() AND j.job_type = N''cleanup''), 0)'; \
+DECLARE @ct_stmt NVARCHAR(MAX) = N'SELECT DB_NAME(), N''change_tracking'', -1, \
+  ISNULL((SELECT CONVERT(NVARCHAR, MAX(commit_time), 20) FROM sys.dm_tran_commit_table), N''''), \
+  ISNULL((SELECT retention_period * CASE retention_period_units WHEN 1 THEN 1 WHEN 2 THEN 60 ELSE 1440 END \
+    FROM sys.change_tracking_databases WHERE database_id = DB_ID()), 0), \
+  ISNULL((SELECT DATEDIFF(MINUTE, MIN(commit_time), GETDATE()) FROM sys.dm_tran_commit_table), 0), \
+  -1, \
+  ISNULL((SELECT CAST(is_auto_cleanup_on AS INT) FROM sys.change_tracking_databases \
+    WHERE database_id = DB_ID()), 0)'; \
+DECLARE @sql NVARCHAR(MAX) = N''; \
+SELECT @sql = @sql + N'INSERT INTO #cdc EXEC ' + QUOTENAME(name) + N'.sys.sp_executesql @cdc_stmt; ' \
+FROM sys.databases WHERE is_cdc_enabled = 1 AND state = 0; \
+SELECT @sql = @sql + N'INSERT INTO #cdc EXEC ' + QUOTENAME(DB_NAME(database_id)) + N'.sys.sp_executesql @ct_stmt; ' \
+FROM sys.change_tracking_databases; \
+IF @sql <> N'' EXEC sp_executesql @sql, N'@cdc_stmt NVARCHAR(MAX), @ct_stmt NVARCHAR(MAX)', \
+  @cdc_stmt = @cdc_stmt, @ct_stmt = @ct_stmt; \
+SELECT database_name, feature, latency_s, last_activity, retention_minutes, retained_minutes, \
+  capture_enabled, cleanup_enabled \
+FROM #cdc ORDER BY database_name, feature; \
+DROP TABLE #cdc;";
+
+    /// users of the current database without server login, contained users and special
+    /// principals(dbo, guest, sys, INFORMATION_SCHEMA) are skipped
+    pub const ORPHANED_USERS: &str = "SELECT \
+  CAST(DB_NAME() AS NVARCHAR(128)) AS database_name, \
+  CAST(dp.name AS NVARCHAR(128)) AS user_name, \
+  CAST(dp.type_desc AS NVARCHAR(60)) AS user_type, \
+  ISNULL(CONVERT(NVARCHAR, dp.create_date, 20), '') AS create_date \
+FROM sys.database_principals dp \
+LEFT JOIN sys.server_principals sp ON sp.sid = dp.sid \
+WHERE sp.sid IS NULL \
+  AND dp.type IN ('S', 'U', 'G') \
+  AND dp.authentication_type_desc = 'INSTANCE' \
+  AND dp.principal_id > 4 \
+ORDER BY dp.name";
+
+    /// all databases of the instance in one pass, sizes are allocated sizes from master_files
+    pub const DATABASES_V2: &str = "SELECT \
+  CAST(d.name AS NVARCHAR(128)) AS database_name, \
+  CAST(d.state_desc AS NVARCHAR(60)) AS state, \
+  CAST(d.recovery_model_desc AS NVARCHAR(60)) AS recovery_model, \
+  CAST(d.is_read_only AS INT) AS is_read_only, \
+  ISNULL(f.data_size_kb, 0) AS data_size_kb, \
+  ISNULL(f.log_size_kb, 0) AS log_size_kb, \
+  CAST(d.log_reuse_wait_desc AS NVARCHAR(60)) AS log_reuse_wait, \
+  CAST(ISNULL(SUSER_SNAME(d.owner_sid), '') AS NVARCHAR(128)) AS owner \
+FROM sys.databases d \
+LEFT JOIN ( \
+  SELECT database_id, \
+    SUM(CASE WHEN type = 1 THEN CAST(size AS BIGINT) ELSE 0 END) * 8 AS log_size_kb, \
+    SUM(CASE WHEN type = 1 THEN 0 ELSE CAST(size AS BIGINT) END) * 8 AS data_size_kb \
+  FROM sys.master_files \
+  GROUP BY database_id \
+) f ON f.database_id = d.database_id \
+ORDER BY d.name";
+
     pub const MIRRORING: &str = "SELECT @@SERVERNAME as server_name, \
   DB_NAME(database_id) AS [database_name], \
   mirroring_state, \
@@ -329,14 +1352,47 @@ ORDER BY job_name, \
 FROM sys.database_mirroring \
 WHERE mirroring_state IS NOT NULL";
 
+    /// a row per secondary replica visible from the instance, a row without replica if none
+    /// queues are in KB, estimated data loss is in seconds: commit time lag to the primary,
+    /// values are the worst ones among databases of the replica
     pub const AVAILABILITY_GROUP: &str = "SELECT \
   GroupsName.name, \
   Groups.primary_replica, \
   Groups.synchronization_health, \
   Groups.synchronization_health_desc, \
-  Groups.primary_recovery_health_desc \
+  Groups.primary_recovery_health_desc, \
+  CAST(ISNULL(Replicas.replica_server_name, '') AS NVARCHAR(256)) AS replica_server_name, \
+  ISNULL(Queues.log_send_queue_kb, 0) AS log_send_queue_kb, \
+  ISNULL(Queues.redo_queue_kb, 0) AS redo_queue_kb, \
+  ISNULL(Queues.estimated_data_loss_s, 0) AS estimated_data_loss_s \
 FROM sys.dm_hadr_availability_group_states Groups \
-INNER JOIN master.sys.availability_groups GroupsName ON Groups.group_id = GroupsName.group_id";
+INNER JOIN master.sys.availability_groups GroupsName ON Groups.group_id = GroupsName.group_id \
+LEFT JOIN ( \
+  SELECT \
+    drs.group_id, \
+    drs.replica_id, \
+    MAX(ISNULL(drs.log_send_queue_size, 0)) AS log_send_queue_kb, \
+    MAX(ISNULL(drs.redo_queue_size, 0)) AS redo_queue_kb, \
+    MAX(CASE WHEN p.last_commit_time > drs.last_commit_time \
+      THEN DATEDIFF(SECOND, drs.last_commit_time, p.last_commit_time) ELSE 0 END) AS estimated_data_loss_s \
+  FROM sys.dm_hadr_database_replica_states drs \
+  LEFT JOIN sys.dm_hadr_database_replica_states p \
+    ON p.group_database_id = drs.group_database_id AND p.is_primary_replica = 1 \
+  WHERE drs.is_primary_replica = 0 \
+  GROUP BY drs.group_id, drs.replica_id \
+) Queues ON Queues.group_id = Groups.group_id \
+LEFT JOIN sys.availability_replicas Replicas ON Replicas.replica_id = Queues.replica_id \
+ORDER BY GroupsName.name, replica_server_name";
+
+    /// is_reporting: the local replica is the primary one
+    pub const AVAILABILITY_GROUP_REPLICAS: &str = "SELECT \
+  CAST(ag.name AS NVARCHAR(128)) AS ag_name, \
+  CAST(ISNULL(s.primary_replica, '') AS NVARCHAR(128)) AS primary_replica, \
+  CASE WHEN rs.role = 1 THEN 1 ELSE 0 END AS is_reporting \
+FROM sys.availability_groups ag \
+LEFT JOIN sys.dm_hadr_availability_group_states s ON s.group_id = ag.group_id \
+LEFT JOIN sys.dm_hadr_availability_replica_states rs \
+  ON rs.group_id = ag.group_id AND rs.is_local = 1";
 
     pub const INSTANCE_PROPERTIES: &str = "SELECT \
     cast(SERVERPROPERTY( 'InstanceName' ) as nvarchar(max)) as InstanceName, \
@@ -344,7 +1400,11 @@ INNER JOIN master.sys.availability_groups GroupsName ON Groups.group_id = Groups
     cast(SERVERPROPERTY( 'MachineName' ) as nvarchar(max)) as MachineName, \
     cast(SERVERPROPERTY( 'Edition' ) as nvarchar(max)) as Edition, \
     cast(SERVERPROPERTY( 'ProductLevel' ) as nvarchar(max)) as ProductLevel, \
-    cast(SERVERPROPERTY( 'ComputerNamePhysicalNetBIOS' ) as nvarchar(max)) as NetBios";
+    cast(SERVERPROPERTY( 'ComputerNamePhysicalNetBIOS' ) as nvarchar(max)) as NetBios, \
+    cast(SERVERPROPERTY( 'ProductUpdateLevel' ) as nvarchar(max)) as ProductUpdateLevel, \
+    cast(SERVERPROPERTY( 'ProductUpdateReference' ) as nvarchar(max)) as ProductUpdateReference, \
+    convert(nvarchar(20), SERVERPROPERTY( 'ResourceLastUpdateDateTime' ), 120) as ResourceLastUpdateDateTime, \
+    cast((SELECT authenticating_database_id FROM sys.dm_exec_sessions WHERE session_id = @@SPID) as nvarchar(max)) as AuthenticatingDatabaseId";
 
     #[allow(dead_code)]
     pub const BAD_QUERY: &str = "SELEC name FROM sys.databases";
@@ -372,11 +1432,16 @@ lazy_static::lazy_static! {
         (Id::Mirroring, query::MIRRORING),
         (Id::Jobs, query::JOBS),
         (Id::AvailabilityGroups, query::AVAILABILITY_GROUP),
+        (
+            Id::AvailabilityGroupReplicas,
+            query::AVAILABILITY_GROUP_REPLICAS,
+        ),
         (Id::InstanceProperties, query::INSTANCE_PROPERTIES),
         (Id::UtcEntry, query::UTC_ENTRY),
         (Id::ClusterActiveNodes, query::CLUSTER_ACTIVE_NODES),
         (Id::ClusterNodes, query::CLUSTER_NODES),
         (Id::IsClustered, query::IS_CLUSTERED),
+        (Id::ContainedAvailabilityGroups, query::CONTAINED_AVAILABILITY_GROUPS),
         (Id::DatabaseNames, query::DATABASE_NAMES),
         (Id::Databases, query::DATABASES),
         (Id::Datafiles, query::DATAFILES),
@@ -390,9 +1455,138 @@ lazy_static::lazy_static! {
         (Id::BlockedSessions, BLOCKING_SESSIONS.as_str()),
         (Id::Counters, COUNTERS.as_str()),
         (Id::Clusters, CLUSTERS.as_str()),
+        (Id::Agent, query::AGENT),
+        (Id::Uptime, query::UPTIME),
+        (Id::DatabaseOptions, query::DATABASE_OPTIONS),
+        (Id::Checkpoints, query::CHECKPOINTS),
+        (Id::BackupChain, query::BACKUP_CHAIN),
+        (Id::CheckDb, query::CHECKDB),
+        (Id::Workers, query::WORKERS),
+        (Id::Locks, query::LOCKS),
+        (Id::ForcedPlans, query::FORCED_PLANS),
+        (Id::ServerRoles, query::SERVER_ROLES),
+        (Id::BufferPoolExtension, query::BUFFER_POOL_EXTENSION),
+        (
+            Id::BufferPoolExtensionHybrid,
+            query::BUFFER_POOL_EXTENSION_HYBRID,
+        ),
+        (Id::VersionStore, query::VERSION_STORE),
+        (Id::VersionStoreUsage, query::VERSION_STORE_USAGE),
+        (Id::Adr, query::ADR),
+        (Id::InstanceSettings, query::INSTANCE_SETTINGS),
+        (
+            Id::InstanceSettingsPrivileges,
+            query::INSTANCE_SETTINGS_PRIVILEGES,
+        ),
+        (Id::ElasticPool, query::ELASTIC_POOL),
+        (Id::Deadlocks, query::DEADLOCKS),
+        (Id::ConnectionSources, query::CONNECTION_SOURCES),
+        (Id::WaitStats, query::WAITSTATS),
+        (Id::Connectivity, query::CONNECTIVITY),
+        (Id::IndexFragmentation, query::INDEX_FRAGMENTATION),
+        (Id::QueryStore, query::QUERY_STORE),
+        (Id::Memory, query::MEMORY),
+        (Id::LogShipping, query::LOG_SHIPPING),
+        (Id::UptimeTempdb, query::UPTIME_TEMPDB),
+        (Id::CheckpointsReduced, query::CHECKPOINTS_REDUCED),
+        (Id::AdrReduced, query::ADR_REDUCED),
+        (Id::JobsView, query::JOBS_VIEW),
+        (Id::Replication, query::REPLICATION),
+        (Id::AgentAlerts, query::AGENT_ALERTS),
+        (Id::ErrorLog, query::ERRORLOG),
+        (Id::ErrorLogRingBuffer, query::ERRORLOG_RING_BUFFER),
+        (Id::FailedLogins, query::FAILED_LOGINS),
+        (Id::Encryption, query::ENCRYPTION),
+        (Id::EncryptionReduced, query::ENCRYPTION_REDUCED),
+        (Id::BlockingChains, query::BLOCKING_CHAINS),
+        (Id::IoStats, query::IO_STATS),
+        (Id::PlanCache, query::PLAN_CACHE),
+        (Id::LinkedServers, query::LINKED_SERVERS),
+        (Id::Tenants, query::TENANTS),
+        (Id::Cdc, query::CDC),
+        (Id::OrphanedUsers, query::ORPHANED_USERS),
+        (Id::DatabasesV2, query::DATABASES_V2),
     ]);
 }
 
+/// Permission which absence is recognized in the error text of a failed query
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum Permission {
+    /// also VIEW SERVER PERFORMANCE STATE of SQL Server 2022+
+    ViewServerState,
+    Msdb,
+    /// xp_readerrorlog
+    SecurityAdmin,
+}
+
+impl Permission {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::ViewServerState => "view_server_state",
+            Self::Msdb => "msdb",
+            Self::SecurityAdmin => "securityadmin",
+        }
+    }
+
+    pub fn find_missing(error: &str) -> Option<Self> {
+        let error = error.to_lowercase();
+        if error.contains("view server") && error.contains("permission") {
+            Some(Self::ViewServerState)
+        } else if error.contains("msdb")
+            && (error.contains("permission was denied")
+                || error.contains("not able to access the database"))
+        {
+            Some(Self::Msdb)
+        } else if error.contains("xp_readerrorlog") && error.contains("permission") {
+            Some(Self::SecurityAdmin)
+        } else {
+            None
+        }
+    }
+}
+
+/// Degradation matrix: (query, missing permission, reduced variant of the query)
+/// the reduced variant must keep columns of the query, unavailable values are empty
+const FALLBACKS: [(Id, Permission, Id); 8] = [
+    (Id::Uptime, Permission::ViewServerState, Id::UptimeTempdb),
+    (
+        Id::Checkpoints,
+        Permission::ViewServerState,
+        Id::CheckpointsReduced,
+    ),
+    (
+        Id::VersionStoreUsage,
+        Permission::ViewServerState,
+        Id::VersionStore,
+    ),
+    (Id::Adr, Permission::ViewServerState, Id::AdrReduced),
+    (
+        Id::InstanceSettingsPrivileges,
+        Permission::ViewServerState,
+        Id::InstanceSettings,
+    ),
+    (Id::Jobs, Permission::Msdb, Id::JobsView),
+    (
+        Id::ErrorLog,
+        Permission::SecurityAdmin,
+        Id::ErrorLogRingBuffer,
+    ),
+    (
+        Id::Encryption,
+        Permission::ViewServerState,
+        Id::EncryptionReduced,
+    ),
+];
+
+/// reduced variant of the query if it has failed with the error because of missing permission
+pub fn find_fallback(query_id: Id, error: &str) -> Option<(Id, Permission)> {
+    let missing = Permission::find_missing(error)?;
+    FALLBACKS
+        .iter()
+        .find(|(id, permission, _)| *id == query_id && *permission == missing)
+        .map(|(_, permission, fallback)| (*fallback, *permission))
+}
+
 pub fn find_known_query<T: Borrow<Id>>(query_id: T) -> Result<&'static str> {
     QUERY_MAP
         .get(query_id.borrow())
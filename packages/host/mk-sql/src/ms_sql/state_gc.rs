@@ -0,0 +1,213 @@
+// Copyright (C) 2024 Checkmk GmbH - License: GNU General Public License v2
+// This file is part of Checkmk (https://checkmk.com). It is subject to the terms and
+// conditions defined in the file COPYING, which is part of this source code package.
+
+//! Removal of stale files from the cache dir.
+//!
+//! Removed instances, renamed sections and changed configs(new hash of the sub dir) leave
+//! files behind: those not updated for `MAX_AGE` are removed on every run.
+//! With `--purge-state` files of removed instances and sections in the dirs of the current
+//! config are removed at once. Another config file may share the state dir: dirs not
+//! belonging to the current config are removed only when stale, never by purge.
+use super::trace;
+use crate::config::ms_sql::Config;
+use crate::setup::Env;
+use crate::utils;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// seconds, a week: any living instance/section updates own files much more often
+pub const MAX_AGE: u64 = 7 * 24 * 3600;
+const ENTRY_EXTENSIONS: [&str; 4] = ["mssql", "state", utils::STAMP_EXTENSION, trace::EXTENSION];
+
+/// cache sub dir -> names of sections
+pub type KnownDirs = HashMap<String, HashSet<String>>;
+
+pub fn make_known_dirs(ms_sql: &Config) -> KnownDirs {
+    std::iter::once(ms_sql)
+        .chain(ms_sql.configs())
+        .map(|c| {
+            (
+                c.config_cache_dir(),
+                c.valid_sections()
+                    .iter()
+                    .map(|s| s.name().to_string())
+                    .collect(),
+            )
+        })
+        .collect()
+}
+
+pub fn exec(environment: &Env, ms_sql: &Config) {
+    if let Some(base) = environment.base_cache_dir().filter(|d| d.is_dir()) {
+        let removed = collect(&base, &make_known_dirs(ms_sql), environment.purge_state());
+        if !removed.is_empty() {
+            log::info!("Removed stale cache entries: {:?}", removed);
+        }
+    }
+}
+
+/// returns removed entries
+pub fn collect(base: &Path, known: &KnownDirs, purge: bool) -> Vec<PathBuf> {
+    let mut removed: Vec<PathBuf> = Vec::new();
+    for path in read_dir(base) {
+        match known.get(&file_name(&path)) {
+            Some(sections) if path.is_dir() => removed.extend(
+                read_dir(&path)
+                    .into_iter()
+                    .filter(|p| is_stale(p) || (purge && is_orphan(p, sections)))
+                    .filter(|p| remove(p)),
+            ),
+            // another config file may share the state dir
+            _ => {
+                if is_stale(&path) && remove(&path) {
+                    removed.push(path);
+                }
+            }
+        }
+    }
+    removed
+}
+
+fn read_dir(dir: &Path) -> Vec<PathBuf> {
+    let mut entries = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|d| d.ok())
+        .map(|d| d.path())
+        .collect::<Vec<PathBuf>>();
+    entries.sort();
+    entries
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// dir is stale if all its files are stale
+fn is_stale(path: &Path) -> bool {
+    if path.is_dir() {
+        let entries = read_dir(path);
+        if entries.is_empty() {
+            get_age(path) > MAX_AGE
+        } else {
+            entries.iter().all(|p| is_stale(p))
+        }
+    } else {
+        get_age(path) > MAX_AGE
+    }
+}
+
+fn get_age(path: &Path) -> u64 {
+    utils::get_modified_age(path).unwrap_or_default()
+}
+
+/// entry name is `host;instance;section.mssql`, `host;instance;section.stamp`,
+/// `host;instance;section.trace` or `host;instance;section.state`, anything else is left by
+/// older versions
+fn is_orphan(path: &Path, sections: &HashSet<String>) -> bool {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_string())
+        .unwrap_or_default();
+    if path.is_dir() || !ENTRY_EXTENSIONS.contains(&extension.as_str()) {
+        return true;
+    }
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    match stem.split(';').collect::<Vec<&str>>().as_slice() {
+        [_, _, section] => !sections.contains(*section),
+        _ => true,
+    }
+}
+
+fn remove(path: &Path) -> bool {
+    let result = if path.is_dir() {
+        std::fs::remove_dir_all(path)
+    } else {
+        std::fs::remove_file(path)
+    };
+    result
+        .map_err(|e| log::warn!("Can't remove {:?}: {e}", path))
+        .is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    fn make_file(path: &Path, stale: bool) {
+        std::fs::write(path, "x").unwrap();
+        if stale {
+            std::fs::File::options()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_modified(SystemTime::now() - Duration::from_secs(MAX_AGE + 100))
+                .unwrap();
+        }
+    }
+
+    fn make_tree() -> (tempfile::TempDir, KnownDirs) {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path();
+        for d in ["mssql-cur", "mssql-old", "mssql-other"] {
+            std::fs::create_dir(base.join(d)).unwrap();
+        }
+        make_file(&base.join("mssql-cur/h;A;jobs.mssql"), false);
+        make_file(&base.join("mssql-cur/h;A;uptime.state"), false);
+        make_file(&base.join("mssql-cur/h;A;renamed.mssql"), false);
+        make_file(&base.join("mssql-cur/h;A;jobs.cache"), false);
+        make_file(&base.join("mssql-cur/h;GONE;jobs.mssql"), true);
+        make_file(&base.join("mssql-old/h;A;jobs.mssql"), true);
+        make_file(&base.join("mssql-other/h;A;jobs.mssql"), false);
+        let known = KnownDirs::from([(
+            "mssql-cur".to_string(),
+            HashSet::from(["jobs".to_string(), "uptime".to_string()]),
+        )]);
+        (dir, known)
+    }
+
+    fn names(base: &Path, removed: &[PathBuf]) -> Vec<String> {
+        removed
+            .iter()
+            .map(|p| p.strip_prefix(base).unwrap().display().to_string())
+            .map(|s| s.replace('\\', "/"))
+            .collect()
+    }
+
+    #[test]
+    fn test_collect_stale() {
+        let (dir, known) = make_tree();
+        let removed = collect(dir.path(), &known, false);
+        assert_eq!(
+            names(dir.path(), &removed),
+            ["mssql-cur/h;GONE;jobs.mssql", "mssql-old"]
+        );
+        assert!(dir.path().join("mssql-other/h;A;jobs.mssql").exists());
+        assert!(dir.path().join("mssql-cur/h;A;renamed.mssql").exists());
+    }
+
+    #[test]
+    fn test_collect_purge() {
+        let (dir, known) = make_tree();
+        let removed = collect(dir.path(), &known, true);
+        assert_eq!(
+            names(dir.path(), &removed),
+            [
+                "mssql-cur/h;A;jobs.cache",
+                "mssql-cur/h;A;renamed.mssql",
+                "mssql-cur/h;GONE;jobs.mssql",
+                "mssql-old"
+            ]
+        );
+        assert!(dir.path().join("mssql-other/h;A;jobs.mssql").exists());
+        assert!(dir.path().join("mssql-cur/h;A;jobs.mssql").exists());
+        assert!(dir.path().join("mssql-cur/h;A;uptime.state").exists());
+    }
+}
@@ -0,0 +1,197 @@
+// Copyright (C) 2024 Checkmk GmbH - License: GNU General Public License v2
+// This file is part of Checkmk (https://checkmk.com). It is subject to the terms and
+// conditions defined in the file COPYING, which is part of this source code package.
+
+//! Schedule trace of async sections.
+//!
+//! Every refresh of an async section leaves `host;instance;section.trace` next to its cache:
+//! duration and result of the query, cache age and refresh lead of the run. With the time stamp
+//! of the cache it gives the next refresh, `schedule show` prints the table for all entries.
+use crate::setup::Env;
+use crate::utils;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub const EXTENSION: &str = "trace";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trace {
+    pub duration: Duration,
+    pub result: TraceResult,
+    /// seconds
+    pub cache_age: u32,
+    /// seconds
+    pub refresh_lead: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TraceResult {
+    Ok,
+    Empty,
+    Error,
+}
+
+impl TraceResult {
+    /// errors are embedded into the section body, see `utils::prepare_error`
+    pub fn from_body(body: &str) -> Self {
+        if body.trim().is_empty() {
+            Self::Empty
+        } else if body.contains("ERROR: ") {
+            Self::Error
+        } else {
+            Self::Ok
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::Empty => "empty",
+            Self::Error => "error",
+        }
+    }
+
+    fn parse(text: &str) -> Option<Self> {
+        match text {
+            "ok" => Some(Self::Ok),
+            "empty" => Some(Self::Empty),
+            "error" => Some(Self::Error),
+            _ => None,
+        }
+    }
+}
+
+impl Trace {
+    /// stored as `duration_ms result cache_age refresh_lead`
+    pub fn parse(text: &str) -> Option<Self> {
+        match text.split_whitespace().collect::<Vec<_>>().as_slice() {
+            [duration, result, cache_age, refresh_lead] => Some(Self {
+                duration: Duration::from_millis(duration.parse().ok()?),
+                result: TraceResult::parse(result)?,
+                cache_age: cache_age.parse().ok()?,
+                refresh_lead: refresh_lead.parse().ok()?,
+            }),
+            _ => None,
+        }
+    }
+
+    /// seconds until the next refresh for the cache of the `age`, 0 - due
+    pub fn next_run(&self, age: u64) -> u64 {
+        u64::from(self.cache_age.saturating_sub(self.refresh_lead)).saturating_sub(age)
+    }
+}
+
+impl std::fmt::Display for Trace {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {} {}",
+            self.duration.as_millis(),
+            self.result.as_str(),
+            self.cache_age,
+            self.refresh_lead
+        )
+    }
+}
+
+pub fn write(cache_entry: &Path, trace: &Trace) {
+    std::fs::write(cache_entry.with_extension(EXTENSION), trace.to_string())
+        .unwrap_or_else(|e| log::error!("Error {e} writing trace"));
+}
+
+/// table of all traced entries of all configs
+pub fn show(environment: &Env) -> String {
+    let header = "host;instance;section;age;next_run;duration_ms;result\n".to_string();
+    header
+        + &environment
+            .base_cache_dir()
+            .map(|base| find_traces(&base))
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|path| to_line(path))
+            .collect::<String>()
+}
+
+fn find_traces(base: &Path) -> Vec<PathBuf> {
+    let mut traces = std::fs::read_dir(base)
+        .into_iter()
+        .flatten()
+        .filter_map(|d| d.ok())
+        .map(|d| d.path())
+        .filter(|p| p.is_dir())
+        .flat_map(|d| std::fs::read_dir(d).into_iter().flatten())
+        .filter_map(|d| d.ok())
+        .map(|d| d.path())
+        .filter(|p| p.extension().is_some_and(|e| e == EXTENSION))
+        .collect::<Vec<_>>();
+    traces.sort();
+    traces
+}
+
+/// `age` and `next_run` are empty if the cache is absent
+fn to_line(path: &Path) -> Option<String> {
+    let trace = Trace::parse(&std::fs::read_to_string(path).ok()?)?;
+    let name = path.file_stem()?.to_string_lossy().to_string();
+    let (age, next_run) = match utils::get_stamped_age(path.with_extension("mssql")) {
+        Some(age) => (age.to_string(), trace.next_run(age).to_string()),
+        None => (String::new(), String::new()),
+    };
+    Some(format!(
+        "{name};{age};{next_run};{};{}\n",
+        trace.duration.as_millis(),
+        trace.result.as_str()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trace() {
+        let trace = Trace {
+            duration: Duration::from_millis(1500),
+            result: TraceResult::from_body("a|b\n"),
+            cache_age: 600,
+            refresh_lead: 60,
+        };
+        assert_eq!(trace.to_string(), "1500 ok 600 60");
+        assert_eq!(Trace::parse(&trace.to_string()), Some(trace.clone()));
+        assert_eq!(trace.next_run(100), 440);
+        assert_eq!(trace.next_run(900), 0);
+        assert!(Trace::parse("1500 fine 600 60").is_none());
+        assert!(Trace::parse("1500 ok").is_none());
+        assert_eq!(TraceResult::from_body("\n"), TraceResult::Empty);
+        assert_eq!(
+            TraceResult::from_body("MSSQL|ERROR: timeout\n"),
+            TraceResult::Error
+        );
+    }
+
+    #[test]
+    fn test_show() {
+        let dir = tempfile::tempdir().unwrap();
+        let sub = dir.path().join("mk-sql-cache").join("hash");
+        std::fs::create_dir_all(&sub).unwrap();
+        let entry = sub.join("h;MSSQL;jobs.mssql");
+        std::fs::write(&entry, "data").unwrap();
+        write(
+            &entry,
+            &Trace {
+                duration: Duration::from_millis(20),
+                result: TraceResult::Ok,
+                cache_age: 600,
+                refresh_lead: 0,
+            },
+        );
+        std::fs::write(sub.join("h;MSSQL;mirroring.trace"), "5 error 300 0").unwrap();
+        let lines = find_traces(&dir.path().join("mk-sql-cache"))
+            .iter()
+            .filter_map(|p| to_line(p))
+            .collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("h;MSSQL;jobs;"), "{}", lines[0]);
+        assert!(lines[0].ends_with(";20;ok\n"));
+        assert_eq!(lines[1], "h;MSSQL;mirroring;;;5;error\n");
+    }
+}
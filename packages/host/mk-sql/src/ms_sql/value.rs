@@ -0,0 +1,302 @@
+// Copyright (C) 2024 Checkmk GmbH - License: GNU General Public License v2
+// This file is part of Checkmk (https://checkmk.com). It is subject to the terms and
+// conditions defined in the file COPYING, which is part of this source code package.
+
+//! Canonical values of result columns: every type delivered by tiberius is converted once here
+//! and formatted by the same rules for all sections, NULL is an empty string
+use super::query::{render_bytes, render_text};
+use crate::config::section::Render;
+use crate::utils;
+use tiberius::{ColumnData, Row};
+
+/// days between 1900-01-01(DATETIME, SMALLDATETIME) and 1970-01-01
+const DAYS_1900_TO_EPOCH: i64 = 25_567;
+/// days between 0001-01-01(DATE, DATETIME2) and 1970-01-01
+const DAYS_0001_TO_EPOCH: i64 = 719_162;
+const NANOS_PER_DAY: i128 = 86_400 * 1_000_000_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    /// decimal and numeric, exact text
+    Decimal(String),
+    Text(String),
+    /// upper case in braces as SQL Server shows it
+    Guid(String),
+    Binary(Vec<u8>),
+    Xml(String),
+    /// days since 1970-01-01
+    Date(i64),
+    Time(Time),
+    DateTime {
+        days: i64,
+        time: Time,
+        /// minutes east of UTC, days and time are local
+        offset: Option<i16>,
+    },
+}
+
+/// time of the day, `scale` is count of fractional digits to show
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Time {
+    pub nanos: u64,
+    pub scale: u8,
+}
+
+impl From<ColumnData<'_>> for Value {
+    fn from(data: ColumnData<'_>) -> Self {
+        match data {
+            ColumnData::U8(v) => v.map(|v| Value::Int(v.into())),
+            ColumnData::I16(v) => v.map(|v| Value::Int(v.into())),
+            ColumnData::I32(v) => v.map(|v| Value::Int(v.into())),
+            ColumnData::I64(v) => v.map(Value::Int),
+            ColumnData::F32(v) => v.map(|v| Value::Float(v.into())),
+            ColumnData::F64(v) => v.map(Value::Float),
+            ColumnData::Bit(v) => v.map(Value::Bool),
+            ColumnData::String(v) => v.map(|v| Value::Text(v.into_owned())),
+            ColumnData::Guid(v) => {
+                v.map(|v| Value::Guid(format!("{{{}}}", v.to_string().to_uppercase())))
+            }
+            ColumnData::Binary(v) => v.map(|v| Value::Binary(v.into_owned())),
+            ColumnData::Numeric(v) => v.map(|v| Value::Decimal(v.to_string())),
+            ColumnData::Xml(v) => v.map(|v| Value::Xml(v.into_owned().into_string())),
+            ColumnData::DateTime(v) => v.map(|v| Value::DateTime {
+                days: i64::from(v.days()) - DAYS_1900_TO_EPOCH,
+                // 1/300 of a second
+                time: Time {
+                    nanos: u64::from(v.seconds_fragments()) * 10_000_000 / 3,
+                    scale: 3,
+                },
+                offset: None,
+            }),
+            ColumnData::SmallDateTime(v) => v.map(|v| Value::DateTime {
+                days: i64::from(v.days()) - DAYS_1900_TO_EPOCH,
+                // minutes
+                time: Time {
+                    nanos: u64::from(v.seconds_fragments()) * 60_000_000_000,
+                    scale: 0,
+                },
+                offset: None,
+            }),
+            ColumnData::Date(v) => v.map(|v| Value::Date(to_epoch_days(v))),
+            ColumnData::Time(v) => v.map(|v| Value::Time(to_time(v))),
+            ColumnData::DateTime2(v) => v.map(|v| Value::DateTime {
+                days: to_epoch_days(v.date()),
+                time: to_time(v.time()),
+                offset: None,
+            }),
+            ColumnData::DateTimeOffset(v) => v.map(|v| {
+                // stored as UTC, shown as local time of the offset
+                let utc = v.datetime2();
+                let time = to_time(utc.time());
+                let local = i128::from(to_epoch_days(utc.date())) * NANOS_PER_DAY
+                    + i128::from(time.nanos)
+                    + i128::from(v.offset()) * 60_000_000_000;
+                Value::DateTime {
+                    days: local.div_euclid(NANOS_PER_DAY) as i64,
+                    time: Time {
+                        nanos: local.rem_euclid(NANOS_PER_DAY) as u64,
+                        scale: time.scale,
+                    },
+                    offset: Some(v.offset()),
+                }
+            }),
+        }
+        .unwrap_or(Value::Null)
+    }
+}
+
+fn to_epoch_days(date: tiberius::time::Date) -> i64 {
+    i64::from(date.days()) - DAYS_0001_TO_EPOCH
+}
+
+fn to_time(time: tiberius::time::Time) -> Time {
+    let scale = time.scale().min(9);
+    Time {
+        nanos: time.increments() * 10u64.pow(9 - u32::from(scale)),
+        scale,
+    }
+}
+
+impl Time {
+    /// `hh:mm:ss[.fffffff]`
+    pub fn to_text(self) -> String {
+        let seconds = self.nanos / 1_000_000_000;
+        let text = format!(
+            "{:02}:{:02}:{:02}",
+            seconds / 3_600,
+            seconds % 3_600 / 60,
+            seconds % 60
+        );
+        if self.scale == 0 {
+            return text;
+        }
+        let fraction = format!("{:09}", self.nanos % 1_000_000_000);
+        format!("{text}.{}", &fraction[..usize::from(self.scale)])
+    }
+}
+
+impl Value {
+    pub fn is_null(&self) -> bool {
+        self == &Value::Null
+    }
+
+    /// canonical text: numbers as Rust prints them, bit as `true`/`false`, binary as hex,
+    /// dates as `YYYY-MM-DD hh:mm:ss[.fff][ +hh:mm]`
+    pub fn to_text(&self) -> String {
+        match self {
+            Value::Null => String::new(),
+            Value::Bool(v) => v.to_string(),
+            Value::Int(v) => v.to_string(),
+            Value::Float(v) => v.to_string(),
+            Value::Decimal(v) | Value::Text(v) | Value::Guid(v) | Value::Xml(v) => v.clone(),
+            Value::Binary(v) => render_bytes(v, Render::Hex),
+            Value::Date(days) => utils::format_date(*days),
+            Value::Time(time) => time.to_text(),
+            Value::DateTime { days, time, offset } => {
+                let text = format!("{} {}", utils::format_date(*days), time.to_text());
+                match offset {
+                    Some(offset) => format!(
+                        "{text} {}{:02}:{:02}",
+                        if *offset < 0 { '-' } else { '+' },
+                        offset.unsigned_abs() / 60,
+                        offset.unsigned_abs() % 60
+                    ),
+                    None => text,
+                }
+            }
+        }
+    }
+
+    /// text fit for the section: binary, XML and text breaking the format are rendered
+    pub fn to_field(&self, sep: char, render: Option<Render>) -> String {
+        match (self, render) {
+            (Value::Binary(v), Some(render)) => render_bytes(v, render),
+            (Value::Text(v) | Value::Xml(v), Some(render)) => render_text(v, sep, render),
+            _ => self.to_text(),
+        }
+    }
+}
+
+pub fn to_values(row: Row) -> Vec<Value> {
+    row.into_iter().map(Value::from).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::borrow::Cow;
+    use tiberius::numeric::Numeric;
+    use tiberius::time;
+    use tiberius::xml::XmlData;
+
+    fn text(data: ColumnData<'static>) -> String {
+        Value::from(data).to_text()
+    }
+
+    #[test]
+    fn test_numbers() {
+        assert_eq!(text(ColumnData::U8(Some(7))), "7");
+        assert_eq!(text(ColumnData::I16(Some(-7))), "-7");
+        assert_eq!(text(ColumnData::I32(Some(70_000))), "70000");
+        assert_eq!(text(ColumnData::I64(Some(1 << 40))), "1099511627776");
+        assert_eq!(text(ColumnData::F32(Some(0.5))), "0.5");
+        assert_eq!(text(ColumnData::F64(Some(1.25))), "1.25");
+        assert_eq!(text(ColumnData::Bit(Some(true))), "true");
+        assert_eq!(
+            text(ColumnData::Numeric(Some(Numeric::new_with_scale(12345, 2)))),
+            "123.45"
+        );
+    }
+
+    #[test]
+    fn test_null() {
+        for data in [
+            ColumnData::I64(None),
+            ColumnData::String(None),
+            ColumnData::Binary(None),
+            ColumnData::DateTime(None),
+            ColumnData::DateTimeOffset(None),
+        ] {
+            let value = Value::from(data);
+            assert!(value.is_null());
+            assert_eq!(value.to_field('|', Some(Render::Hex)), "");
+        }
+    }
+
+    #[test]
+    fn test_text_like() {
+        assert_eq!(text(ColumnData::String(Some(Cow::Borrowed("a b")))), "a b");
+        assert_eq!(
+            text(ColumnData::Guid(Some(
+                tiberius::Uuid::parse_str("6f9619ff-8b86-d011-b42d-00c04fc964ff").unwrap()
+            ))),
+            "{6F9619FF-8B86-D011-B42D-00C04FC964FF}"
+        );
+        assert_eq!(
+            text(ColumnData::Binary(Some(Cow::Borrowed(&[0x0A, 0xFF])))),
+            "0x0AFF"
+        );
+        assert_eq!(
+            text(ColumnData::Xml(Some(Cow::Owned(XmlData::new("<a/>"))))),
+            "<a/>"
+        );
+    }
+
+    #[test]
+    fn test_dates() {
+        // 2024-02-29 12:30:15.5
+        assert_eq!(
+            text(ColumnData::DateTime(Some(time::DateTime::new(
+                45_349,
+                (12 * 3_600 + 30 * 60 + 15) * 300 + 150
+            )))),
+            "2024-02-29 12:30:15.500"
+        );
+        assert_eq!(
+            text(ColumnData::SmallDateTime(Some(time::SmallDateTime::new(
+                45_349,
+                12 * 60 + 30
+            )))),
+            "2024-02-29 12:30:00"
+        );
+        let date = time::Date::new(738_944);
+        assert_eq!(text(ColumnData::Date(Some(date))), "2024-02-29");
+        let noon = time::Time::new(120_000_001, 4);
+        assert_eq!(text(ColumnData::Time(Some(noon))), "03:20:00.0001");
+        assert_eq!(
+            text(ColumnData::DateTime2(Some(time::DateTime2::new(
+                date, noon
+            )))),
+            "2024-02-29 03:20:00.0001"
+        );
+        assert_eq!(
+            text(ColumnData::DateTimeOffset(Some(time::DateTimeOffset::new(
+                time::DateTime2::new(date, noon),
+                -330
+            )))),
+            "2024-02-28 21:50:00.0001 -05:30"
+        );
+    }
+
+    #[test]
+    fn test_to_field() {
+        assert_eq!(
+            Value::Text("a|b".to_string()).to_field('|', Some(Render::Truncate(10))),
+            "a b"
+        );
+        assert_eq!(Value::Text("a|b".to_string()).to_field('|', None), "a|b");
+        assert_eq!(
+            Value::Binary(b"f".to_vec()).to_field('|', Some(Render::Base64)),
+            "Zg=="
+        );
+        assert_eq!(
+            Value::Xml("<a/>".to_string()).to_field('|', Some(Render::Drop)),
+            "<a/>"
+        );
+        assert_eq!(Value::Int(1).to_field('|', Some(Render::Drop)), "1");
+    }
+}
@@ -3,7 +3,9 @@
 // conditions defined in the file COPYING, which is part of this source code package.
 
 use crate::types::{InstanceName, Port};
+use std::collections::BTreeMap;
 
+#[derive(Clone, Debug)]
 pub struct Block {
     pub headline: Vec<String>,
     pub rows: Vec<Vec<String>>,
@@ -53,6 +55,23 @@ pub enum Transport {
     SharedMemory,
 }
 
+/// source of the instance information
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Provider {
+    Registry,
+    /// `ComputerManagement` namespace, used if the registry is not accessible
+    Wmi,
+}
+
+impl Provider {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Registry => "registry",
+            Self::Wmi => "wmi",
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct InstanceInfo {
     pub name: InstanceName,
@@ -60,9 +79,14 @@ pub struct InstanceInfo {
     dynamic_port: Option<Port>,
     pipe: Option<String>,
     transports: Vec<Transport>,
+    provider: Provider,
 }
 
 impl InstanceInfo {
+    pub fn provider(&self) -> Provider {
+        self.provider
+    }
+
     pub fn final_port(&self) -> Option<&Port> {
         if !self.is_tcp() {
             return None;
@@ -91,10 +115,189 @@ impl InstanceInfo {
     }
 }
 
+/// instances from the registry or, if nothing is found there(e.g. access is denied), from WMI
+pub fn get_instances() -> Vec<InstanceInfo> {
+    let mut instances = registry::get_instances();
+    if instances.is_empty() {
+        instances = wmi::get_instances();
+    }
+    for i in &instances {
+        log::debug!("Instance {} is provided by {}", i.name, i.provider.as_str());
+    }
+    instances
+}
+
+/// Output of `WMI_PROPERTIES_SCRIPT`: `instance|protocol|ip_address_name|property|str|num`
+/// per line, properties are those of registry keys `SuperSocketNetLib`
+pub fn parse_wmi_properties(output: &str) -> Vec<InstanceInfo> {
+    let mut properties: BTreeMap<&str, Vec<[&str; 5]>> = BTreeMap::new();
+    for line in output.lines().map(|l| l.trim_end_matches('\r')) {
+        if let [instance, protocol, ip, property, str_value, num_value] =
+            line.split('|').collect::<Vec<&str>>()[..]
+        {
+            if !instance.is_empty() {
+                properties
+                    .entry(instance)
+                    .or_default()
+                    .push([protocol, ip, property, str_value, num_value]);
+            }
+        }
+    }
+    properties
+        .into_iter()
+        .map(|(instance, rows)| {
+            let find = |protocol: &str, ip: &str, property: &str| {
+                rows.iter()
+                    .find(|r| {
+                        r[0].eq_ignore_ascii_case(protocol)
+                            && r[1].eq_ignore_ascii_case(ip)
+                            && r[2].eq_ignore_ascii_case(property)
+                    })
+                    .map(|r| (r[3], r[4]))
+            };
+            let is_on = |protocol: &str, property: &str| {
+                find(protocol, "", property)
+                    .map_or(false, |(_, n)| n.trim() != "0" && !n.is_empty())
+            };
+            let port = |property: &str| {
+                find("Tcp", "IPAll", property)
+                    .and_then(|(s, _)| s.split(',').next()?.trim().parse::<u16>().ok())
+                    .map(Port::from)
+            };
+            let mut transports = vec![];
+            if is_on("Tcp", "Enabled") && is_on("Tcp", "ListenOnAllIPs") {
+                transports.push(Transport::Tcp);
+            }
+            if is_on("Sm", "Enabled") {
+                transports.push(Transport::SharedMemory);
+            }
+            if is_on("Np", "Enabled") {
+                transports.push(Transport::NamedPipe);
+            }
+            InstanceInfo {
+                name: InstanceName::from(instance),
+                port: port("TcpPort"),
+                dynamic_port: port("TcpDynamicPorts"),
+                pipe: is_on("Np", "Enabled")
+                    .then(|| find("Np", "", "PipeName").map(|(s, _)| s.to_string()))
+                    .flatten()
+                    .filter(|p| !p.is_empty()),
+                transports,
+                provider: Provider::Wmi,
+            }
+        })
+        .collect()
+}
+
+/// Output of `PDH_COUNTERS_SCRIPT`: `counter<TAB>object<TAB>instance<TAB>raw value` per
+/// line, rows have the column order of the counters query
+pub fn parse_pdh_counters(output: &str) -> Vec<Vec<String>> {
+    output
+        .lines()
+        .map(|l| l.trim_end_matches('\r'))
+        .filter_map(|line| match line.split('\t').collect::<Vec<&str>>()[..] {
+            [counter, object, instance, value]
+                if !counter.is_empty() && value.parse::<i64>().is_ok() =>
+            {
+                Some(vec![
+                    counter.to_string(),
+                    object.to_string(),
+                    instance.to_string(),
+                    value.to_string(),
+                ])
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// prefix of performance objects of the instance, e.g. `SQLServer:Buffer Manager`
+pub fn pdh_object_prefix(instance: &InstanceName) -> String {
+    if instance.to_string().eq_ignore_ascii_case("MSSQLSERVER") {
+        "SQLServer".to_string()
+    } else {
+        format!("MSSQL${}", instance.to_string().to_uppercase())
+    }
+}
+
+/// SQL Server client alias as defined by `cliconfg.exe` or SQL Server Configuration Manager
+/// Registry value has format `<protocol>,<server>[\<instance>][,<port>]`, the server of
+/// the named pipe protocol is the pipe: `DBNMPNTW,\\<host>\pipe\sql\query`
+#[derive(Debug, PartialEq, Clone)]
+pub struct ClientAlias {
+    pub transport: Transport,
+    pub host: String,
+    pub instance: Option<InstanceName>,
+    pub port: Option<Port>,
+    pub pipe: Option<String>,
+}
+
+impl ClientAlias {
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut parts = value.split(',').map(str::trim);
+        let transport = match parts.next()?.to_uppercase().as_str() {
+            "DBMSSOCN" => Transport::Tcp,
+            "DBNMPNTW" => Transport::NamedPipe,
+            "DBMSLPCN" => Transport::SharedMemory,
+            _ => return None,
+        };
+        let server = parts.next().filter(|s| !s.is_empty())?;
+        if transport == Transport::NamedPipe {
+            let host = server.strip_prefix(r"\\")?.split('\\').next()?;
+            return Some(Self {
+                transport,
+                host: host.to_string(),
+                instance: None,
+                port: None,
+                pipe: Some(server.to_string()),
+            });
+        }
+        let (host, instance) = match server.split_once('\\') {
+            Some((host, instance)) => (host, Some(InstanceName::from(instance.to_uppercase()))),
+            None => (server, None),
+        };
+        let port = parts
+            .next()
+            .and_then(|p| p.parse::<u16>().ok())
+            .filter(|&p| p != 0)
+            .map(Port);
+        Some(Self {
+            transport,
+            host: host.to_string(),
+            instance,
+            port,
+            pipe: None,
+        })
+    }
+}
+
+/// DER certificate of the `Blob` value of the Windows certificate store: list of properties
+/// `id: u32, reserved: u32, size: u32, data`, the certificate is the property 32
+pub fn find_certificate_in_blob(blob: &[u8]) -> Option<&[u8]> {
+    const CERT_CERT_PROP_ID: u32 = 32;
+    let read_u32 = |pos: usize| -> Option<u32> {
+        Some(u32::from_le_bytes(blob.get(pos..pos + 4)?.try_into().ok()?))
+    };
+    let mut pos = 0;
+    while pos < blob.len() {
+        let id = read_u32(pos)?;
+        let size = usize::try_from(read_u32(pos + 8)?).ok()?;
+        let data = blob.get(pos + 12..pos + 12 + size)?;
+        if id == CERT_CERT_PROP_ID {
+            return Some(data);
+        }
+        pos += 12 + size;
+    }
+    None
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{
-        platform::{InstanceInfo, Transport},
+        platform::{
+            find_certificate_in_blob, parse_pdh_counters, parse_wmi_properties, pdh_object_prefix,
+            ClientAlias, InstanceInfo, Provider, Transport,
+        },
         types::{InstanceName, Port},
     };
 
@@ -106,6 +309,7 @@ mod tests {
             dynamic_port: dynamic_port.map(|p| p.into()),
             pipe: None,
             transports: vec![Transport::Tcp],
+            provider: Provider::Registry,
         };
 
         let std_port = 1;
@@ -128,12 +332,132 @@ mod tests {
         );
         assert!(make_i(Some(0), Some(0)).final_port().is_none());
     }
+
+    #[test]
+    fn test_parse_pdh_counters() {
+        let output = "page life expectancy\tSQLServer:Buffer Manager\t\t1234\r\n\
+            log flushes/sec\tSQLServer:Databases\tmaster\t17\r\n\
+            broken\tline\r\n\
+            bad value\tSQLServer:Databases\tmaster\t1.5\r\n";
+        assert_eq!(
+            parse_pdh_counters(output),
+            [
+                [
+                    "page life expectancy",
+                    "SQLServer:Buffer Manager",
+                    "",
+                    "1234"
+                ],
+                ["log flushes/sec", "SQLServer:Databases", "master", "17"],
+            ]
+        );
+        assert!(parse_pdh_counters("").is_empty());
+    }
+
+    #[test]
+    fn test_pdh_object_prefix() {
+        assert_eq!(
+            pdh_object_prefix(&InstanceName::from("MSSQLSERVER")),
+            "SQLServer"
+        );
+        assert_eq!(
+            pdh_object_prefix(&InstanceName::from("sqlExpress")),
+            "MSSQL$SQLEXPRESS"
+        );
+    }
+
+    #[test]
+    fn test_parse_wmi_properties() {
+        let output = "MSSQLSERVER|Tcp||Enabled||1\r
+MSSQLSERVER|Tcp||ListenOnAllIPs||1
+MSSQLSERVER|Tcp|IPAll|TcpPort|1433|
+MSSQLSERVER|Tcp|IPAll|TcpDynamicPorts||
+MSSQLSERVER|Np||Enabled||0
+MSSQLSERVER|Np||PipeName|\\\\.\\pipe\\sql\\query|
+MSSQLSERVER|Sm||Enabled||1
+SQLEXPRESS|Tcp||Enabled||0
+SQLEXPRESS|Tcp|IPAll|TcpDynamicPorts|49733|
+SQLEXPRESS|Np||Enabled||1
+SQLEXPRESS|Np||PipeName|\\\\.\\pipe\\MSSQL$SQLEXPRESS\\sql\\query|
+garbage
+";
+        let infos = parse_wmi_properties(output);
+        assert_eq!(infos.len(), 2);
+        assert_eq!(infos[0].name, InstanceName::from("MSSQLSERVER"));
+        assert_eq!(infos[0].final_port(), Some(&Port::from(1433)));
+        assert!(infos[0].is_tcp() && infos[0].is_shared_memory() && !infos[0].is_pipe());
+        assert_eq!(infos[0].provider(), Provider::Wmi);
+        assert_eq!(infos[1].name, InstanceName::from("SQLEXPRESS"));
+        assert!(infos[1].final_port().is_none());
+        assert!(infos[1].is_pipe() && infos[1].is_odbc_only());
+        assert!(parse_wmi_properties("").is_empty());
+    }
+
+    #[test]
+    fn test_client_alias_parse() {
+        assert_eq!(
+            ClientAlias::parse("DBMSSOCN,sql.example.com,1444").unwrap(),
+            ClientAlias {
+                transport: Transport::Tcp,
+                host: "sql.example.com".to_string(),
+                instance: None,
+                port: Some(Port(1444)),
+                pipe: None,
+            }
+        );
+        assert_eq!(
+            ClientAlias::parse("dbmssocn,host\\inst").unwrap(),
+            ClientAlias {
+                transport: Transport::Tcp,
+                host: "host".to_string(),
+                instance: Some(InstanceName::from("INST")),
+                port: None,
+                pipe: None,
+            }
+        );
+        assert_eq!(
+            ClientAlias::parse(r"DBNMPNTW,\\host\pipe\MSSQL$INST\sql\query").unwrap(),
+            ClientAlias {
+                transport: Transport::NamedPipe,
+                host: "host".to_string(),
+                instance: None,
+                port: None,
+                pipe: Some(r"\\host\pipe\MSSQL$INST\sql\query".to_string()),
+            }
+        );
+        assert!(ClientAlias::parse("DBNMPNTW,host").is_none());
+        assert!(ClientAlias::parse("DBMSSOCN,host,0")
+            .unwrap()
+            .port
+            .is_none());
+        assert!(ClientAlias::parse("DBMSSOCN").is_none());
+        assert!(ClientAlias::parse("XXX,host,1433").is_none());
+    }
+
+    #[test]
+    fn test_find_certificate_in_blob() {
+        let property = |id: u32, data: &[u8]| {
+            [
+                id.to_le_bytes().as_slice(),
+                &1u32.to_le_bytes(),
+                &(data.len() as u32).to_le_bytes(),
+                data,
+            ]
+            .concat()
+        };
+        let blob = [property(3, b"sha1"), property(32, b"der")].concat();
+        assert_eq!(find_certificate_in_blob(&blob), Some(b"der".as_slice()));
+        assert_eq!(find_certificate_in_blob(&property(3, b"sha1")), None);
+        assert_eq!(find_certificate_in_blob(&blob[..blob.len() - 1]), None);
+        assert_eq!(find_certificate_in_blob(&[]), None);
+    }
 }
 
 #[cfg(windows)]
 pub mod odbc {
     use super::Block;
     use anyhow::Result;
+    #[cfg(feature = "odbc")]
     use odbc_api::{
         buffers::{ColumnarBuffer, TextColumn, TextRowSet},
         ConnectionOptions, Cursor, Environment, ResultSetMetadata,
@@ -177,9 +501,27 @@ pub mod odbc {
         instance: &InstanceName,
         database: Option<&str>,
         driver: Option<&str>,
+    ) -> String {
+        make_server_connection_string("(local)", instance, database, driver)
+    }
+
+    /// as `make_connection_string`, the driver is forced to use shared memory
+    pub fn make_shared_memory_connection_string(
+        instance: &InstanceName,
+        database: Option<&str>,
+        driver: Option<&str>,
+    ) -> String {
+        make_server_connection_string("lpc:(local)", instance, database, driver)
+    }
+
+    fn make_server_connection_string(
+        server: &str,
+        instance: &InstanceName,
+        database: Option<&str>,
+        driver: Option<&str>,
     ) -> String {
         format!(
-            "Driver={{{}}};SERVER=(local){};Database={};Integrated Security=SSPI;Trusted_Connection=yes;",
+            "Driver={{{}}};SERVER={server}{};Database={};Integrated Security=SSPI;Trusted_Connection=yes;",
             driver.unwrap_or(&ODBC_DRIVER.clone()),
             if instance.to_string().to_uppercase() == *"MSSQLSERVER" {
                 "".to_string()
@@ -190,9 +532,20 @@ pub mod odbc {
         )
     }
 
+    #[cfg(feature = "odbc")]
     type BufferType = ColumnarBuffer<TextColumn<u8>>;
 
+    #[cfg(not(feature = "odbc"))]
+    pub fn execute(
+        connection_string: &str,
+        _query: &str,
+        _timeout: Option<u32>,
+    ) -> Result<Vec<Block>> {
+        anyhow::bail!("Can't use `{connection_string}`: built without feature `odbc`")
+    }
+
     // TODO(sk): make it ASYNC!
+    #[cfg(feature = "odbc")]
     pub fn execute(
         connection_string: &str,
         query: &str,
@@ -243,6 +596,7 @@ pub mod odbc {
         Ok(vec![])
     }
 
+    #[cfg(feature = "odbc")]
     pub fn process_batch(batch: &BufferType) -> Vec<Vec<String>> {
         let mut rows: Vec<Vec<String>> = Vec::new();
         for row in 0..batch.num_rows() {
@@ -285,16 +639,80 @@ pub mod odbc {
                     None),
                     format!("Driver={{{}}};SERVER=(local);Database=master;Integrated Security=SSPI;Trusted_Connection=yes;", ODBC_DRIVER.clone()));
         }
+
+        #[test]
+        fn test_make_shared_memory_connection_string() {
+            assert_eq!(
+                odbc::make_shared_memory_connection_string(
+                    &InstanceName::from("Instance"),
+                    Some("db"),
+                    Some("driver")),
+                "Driver={driver};SERVER=lpc:(local)\\Instance;Database=db;Integrated Security=SSPI;Trusted_Connection=yes;"
+            );
+        }
     }
 }
 
 #[cfg(windows)]
 pub mod registry {
-    use super::{InstanceInfo, Transport};
+    use super::{find_certificate_in_blob, ClientAlias, InstanceInfo, Provider, Transport};
     use crate::types::{InstanceName, Port};
     use std::collections::HashMap;
     use winreg::{enums::HKEY_LOCAL_MACHINE, RegKey};
 
+    const CLIENT_ALIAS_KEYS: [&str; 2] = [
+        r"SOFTWARE\Microsoft\MSSQLServer\Client\ConnectTo",
+        r"SOFTWARE\WOW6432Node\Microsoft\MSSQLServer\Client\ConnectTo",
+    ];
+
+    /// trusted roots of the machine: own, auto-updated from Windows Update, set by group policy
+    /// and by the enterprise
+    const ROOT_STORE_KEYS: [&str; 4] = [
+        r"SOFTWARE\Microsoft\SystemCertificates\ROOT\Certificates",
+        r"SOFTWARE\Microsoft\SystemCertificates\AuthRoot\Certificates",
+        r"SOFTWARE\Policies\Microsoft\SystemCertificates\Root\Certificates",
+        r"SOFTWARE\Microsoft\EnterpriseCertificates\Root\Certificates",
+    ];
+
+    /// DER certificates of the root stores of the local machine
+    pub fn get_root_certificates() -> Vec<Vec<u8>> {
+        let root_key = RegKey::predef(HKEY_LOCAL_MACHINE);
+        ROOT_STORE_KEYS
+            .iter()
+            .filter_map(|store| {
+                root_key
+                    .open_subkey_with_flags(store, winreg::enums::KEY_READ)
+                    .ok()
+            })
+            .flat_map(|store| {
+                store
+                    .enum_keys()
+                    .filter_map(|name| name.ok())
+                    .filter_map(|name| store.open_subkey(name).ok())
+                    .filter_map(|certificate| certificate.get_raw_value("Blob").ok())
+                    .filter_map(|blob| find_certificate_in_blob(&blob.bytes).map(<[u8]>::to_vec))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// finds alias in 64 and 32 bit client configuration, 64 bit wins, case insensitive
+    pub fn get_client_alias(name: &str) -> Option<ClientAlias> {
+        let root_key = RegKey::predef(HKEY_LOCAL_MACHINE);
+        CLIENT_ALIAS_KEYS.iter().find_map(|sub_key| {
+            root_key
+                .open_subkey_with_flags(
+                    sub_key,
+                    winreg::enums::KEY_READ | winreg::enums::KEY_WOW64_64KEY,
+                )
+                .ok()?
+                .enum_values()
+                .filter_map(|x| x.ok())
+                .find(|(alias, _)| alias.eq_ignore_ascii_case(name))
+                .and_then(|(_, value)| ClientAlias::parse(&value.to_string()))
+        })
+    }
+
     pub fn get_instances() -> Vec<InstanceInfo> {
         let instances_std = get_instances_on_key(r"SOFTWARE\Microsoft\Microsoft SQL Server\");
         let instances_wow =
@@ -406,6 +824,7 @@ pub mod registry {
                     .map(Port::from),
                 pipe: get_pipe(sql_key, registry_instance_name),
                 transports: get_enabled_transports(sql_key, registry_instance_name),
+                provider: Provider::Registry,
             })
         } else {
             log::warn!("cannot open key: {}", instance_tcp_ip_all_key);
@@ -432,10 +851,17 @@ pub mod registry {
 
 #[cfg(unix)]
 pub mod registry {
-    use super::InstanceInfo;
+    use super::{ClientAlias, InstanceInfo};
     pub fn get_instances() -> Vec<InstanceInfo> {
         vec![]
     }
+    pub fn get_client_alias(_name: &str) -> Option<ClientAlias> {
+        None
+    }
+    /// OpenSSL finds the system store itself
+    pub fn get_root_certificates() -> Vec<Vec<u8>> {
+        vec![]
+    }
     #[cfg(test)]
     mod tests {
         use super::get_instances;
@@ -445,3 +871,91 @@ pub mod registry {
         }
     }
 }
+
+#[cfg(windows)]
+pub mod wmi {
+    use super::{parse_wmi_properties, InstanceInfo};
+
+    /// the newest `ComputerManagement` namespace manages instances of older versions too
+    const WMI_PROPERTIES_SCRIPT: &str = r#"$ns = Get-CimInstance -Namespace root\Microsoft\SqlServer -ClassName __NAMESPACE -ErrorAction SilentlyContinue | Where-Object Name -like 'ComputerManagement*' | Sort-Object Name -Descending | Select-Object -First 1
+if ($ns) { Get-CimInstance -Namespace "root\Microsoft\SqlServer\$($ns.Name)" -ClassName ServerNetworkProtocolProperty | ForEach-Object { "$($_.InstanceName)|$($_.ProtocolName)|$($_.IPAddressName)|$($_.PropertyName)|$($_.PropertyStrVal)|$($_.PropertyNumVal)" } }"#;
+
+    lazy_static::lazy_static! {
+        // powershell is slow and instances are requested per instance
+        static ref INSTANCES: Vec<InstanceInfo> = query_instances();
+    }
+
+    pub fn get_instances() -> Vec<InstanceInfo> {
+        INSTANCES.clone()
+    }
+
+    fn query_instances() -> Vec<InstanceInfo> {
+        match std::process::Command::new("powershell")
+            .args(["-NoProfile", "-Command", WMI_PROPERTIES_SCRIPT])
+            .output()
+        {
+            Ok(output) => {
+                let instances = parse_wmi_properties(&String::from_utf8_lossy(&output.stdout));
+                log::info!("Found {} instances by WMI", instances.len());
+                instances
+            }
+            Err(e) => {
+                log::error!("Failed to query WMI: {e}");
+                vec![]
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+pub mod wmi {
+    use super::InstanceInfo;
+    pub fn get_instances() -> Vec<InstanceInfo> {
+        vec![]
+    }
+}
+
+#[cfg(windows)]
+pub mod pdh {
+    use super::{parse_pdh_counters, pdh_object_prefix};
+    use crate::types::InstanceName;
+    use anyhow::Result;
+
+    /// raw values as `sys.dm_os_performance_counters` provides them, `$prefix` is set before
+    const PDH_COUNTERS_SCRIPT: &str = r#"foreach ($set in Get-Counter -ListSet "$($prefix):*" -ErrorAction SilentlyContinue) {
+  $paths = if ($set.CounterSetType -eq 'MultiInstance') { $set.PathsWithInstances } else { $set.Paths }
+  foreach ($s in (Get-Counter -Counter $paths -MaxSamples 1 -ErrorAction SilentlyContinue).CounterSamples) {
+    "{0}`t{1}`t{2}`t{3}" -f $s.Path.Substring($s.Path.LastIndexOf('\') + 1), $set.CounterSetName, $s.InstanceName, $s.RawValue
+  }
+}"#;
+
+    /// counters of the local instance: rows of the counters query
+    pub fn get_counters(instance: &InstanceName) -> Result<Vec<Vec<String>>> {
+        let script = format!(
+            "$prefix = '{}'\n{PDH_COUNTERS_SCRIPT}",
+            pdh_object_prefix(instance)
+        );
+        let output = std::process::Command::new("powershell")
+            .args(["-NoProfile", "-Command", &script])
+            .output()?;
+        let counters = parse_pdh_counters(&String::from_utf8_lossy(&output.stdout));
+        if counters.is_empty() {
+            anyhow::bail!("No performance counters of `{instance}` found");
+        }
+        log::info!(
+            "Found {} performance counters of `{instance}`",
+            counters.len()
+        );
+        Ok(counters)
+    }
+}
+
+#[cfg(unix)]
+pub mod pdh {
+    use crate::types::InstanceName;
+    use anyhow::Result;
+
+    pub fn get_counters(_instance: &InstanceName) -> Result<Vec<Vec<String>>> {
+        anyhow::bail!("Performance counters are available only on Windows")
+    }
+}
@@ -0,0 +1,339 @@
+// Copyright (C) 2024 Checkmk GmbH - License: GNU General Public License v2
+// This file is part of Checkmk (https://checkmk.com). It is subject to the terms and
+// conditions defined in the file COPYING, which is part of this source code package.
+
+//! Minimal local HTTP API over already collected(cached) sections, no database access
+//! The daemon keeps nothing in memory: every request reads the cache files
+//! `host;instance;section.mssql` which regular runs write into the cache dir
+//! - `GET /sections` - list of cached entries
+//! - `GET /sections/{name}?instance={instance}` - data of the section with its schema,
+//!   instance is optional
+//! - `GET /schema` - schema of all known sections
+//!
+//! HTTP without TLS: a bare port is bound to loopback, any other address requires the token of
+//! `MK_SQL_REST_TOKEN` sent as `Authorization: Bearer <token>`
+use crate::constants::environment::REST_TOKEN_ENV_VAR;
+use crate::ms_sql::schema;
+use crate::utils;
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+
+const CACHE_EXTENSION: &str = "mssql";
+const MAX_HEADERS: usize = 64;
+/// of the request line and of every header, including the line end
+const MAX_LINE_SIZE: u64 = 8192;
+
+#[derive(Debug, PartialEq)]
+struct CacheEntry {
+    host: String,
+    instance: String,
+    section: String,
+    path: PathBuf,
+}
+
+impl CacheEntry {
+    /// cache file name is `host;instance;section.mssql`
+    fn from_path(path: &Path) -> Option<Self> {
+        if path.extension().map_or(true, |e| e != CACHE_EXTENSION) {
+            return None;
+        }
+        let stem = path.file_stem()?.to_string_lossy().to_string();
+        match stem.split(';').collect::<Vec<&str>>().as_slice() {
+            [host, instance, section] => Some(Self {
+                host: host.to_string(),
+                instance: instance.to_string(),
+                section: section.to_string(),
+                path: path.to_owned(),
+            }),
+            _ => None,
+        }
+    }
+
+    fn to_json(&self, with_data: bool) -> String {
+        let age = utils::get_stamped_age(&self.path).unwrap_or_default();
+        let data = if with_data {
+            format!(
+                "{},\"data\":\"{}\"",
+                schema::find_json(&self.section)
+                    .map(|s| format!(",\"schema\":{s}"))
+                    .unwrap_or_default(),
+                utils::escape_json(&std::fs::read_to_string(&self.path).unwrap_or_default())
+            )
+        } else {
+            String::new()
+        };
+        format!(
+            "{{\"host\":\"{}\",\"instance\":\"{}\",\"section\":\"{}\",\"age\":{age}{data}}}",
+            utils::escape_json(&self.host),
+            utils::escape_json(&self.instance),
+            utils::escape_json(&self.section)
+        )
+    }
+}
+
+/// `8090` -> `127.0.0.1:8090`
+fn to_bind_address(address: &str) -> String {
+    if address.parse::<u16>().is_ok() {
+        format!("127.0.0.1:{address}")
+    } else {
+        address.to_string()
+    }
+}
+
+fn is_authorized(headers: &[String], token: Option<&str>) -> bool {
+    let token = match token {
+        Some(token) => token,
+        None => return true,
+    };
+    headers.iter().any(|h| match h.split_once(':') {
+        Some((name, value)) if name.trim().eq_ignore_ascii_case("authorization") => {
+            value.trim().strip_prefix("Bearer ").map_or(false, |v| {
+                v.len() == token.len() && openssl::memcmp::eq(v.as_bytes(), token.as_bytes())
+            })
+        }
+        _ => false,
+    })
+}
+
+pub async fn serve(address: &str, cache_dir: &Path) -> Result<()> {
+    let token = std::env::var(REST_TOKEN_ENV_VAR)
+        .ok()
+        .filter(|t| !t.is_empty());
+    let address = to_bind_address(address);
+    let listener = TcpListener::bind(&address).await?;
+    if token.is_none() && !listener.local_addr()?.ip().is_loopback() {
+        anyhow::bail!("REST API on `{address}` requires token in `{REST_TOKEN_ENV_VAR}`");
+    }
+    log::info!(
+        "REST API listens on {address}, cache dir {:?}, token {}",
+        cache_dir,
+        if token.is_some() {
+            "required"
+        } else {
+            "not set"
+        }
+    );
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let cache_dir = cache_dir.to_owned();
+        let token = token.clone();
+        tokio::spawn(async move {
+            let (reader, mut writer) = stream.into_split();
+            let (status, body) = match read_head(&mut BufReader::new(reader)).await {
+                Ok((request_line, headers)) if is_authorized(&headers, token.as_deref()) => {
+                    handle(request_line.trim_end(), &cache_dir)
+                }
+                Ok(_) => {
+                    log::warn!("Unauthorized request from {peer}");
+                    ("401 Unauthorized", error_json("bearer token is required"))
+                }
+                Err((status, message)) => {
+                    log::warn!("Bad request from {peer}: {message}");
+                    (status, error_json(message))
+                }
+            };
+            let response = format!(
+                "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            writer
+                .write_all(response.as_bytes())
+                .await
+                .unwrap_or_else(|e| log::warn!("Failed to respond to {peer}: {e}"));
+        });
+    }
+}
+
+/// request line and headers, the size is limited: a client can't exhaust the memory
+/// Err: status and reason of the rejection
+async fn read_head<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+) -> std::result::Result<(String, Vec<String>), (&'static str, &'static str)> {
+    let request_line = read_line(reader)
+        .await
+        .map_err(|_| ("400 Bad Request", "request line is unreadable"))?
+        .ok_or(("414 URI Too Long", "request line is too long"))?;
+    let mut headers = Vec::new();
+    loop {
+        let line = read_line(reader)
+            .await
+            .map_err(|_| ("400 Bad Request", "header is unreadable"))?
+            .ok_or(("431 Request Header Fields Too Large", "header is too long"))?;
+        if line.trim_end().is_empty() {
+            return Ok((request_line, headers));
+        }
+        if headers.len() == MAX_HEADERS {
+            return Err(("431 Request Header Fields Too Large", "too many headers"));
+        }
+        headers.push(line);
+    }
+}
+
+/// None: the line is longer than MAX_LINE_SIZE, empty string: end of stream
+async fn read_line<R: AsyncBufRead + Unpin>(reader: &mut R) -> std::io::Result<Option<String>> {
+    let mut line = String::new();
+    let size = reader.take(MAX_LINE_SIZE).read_line(&mut line).await?;
+    if size as u64 == MAX_LINE_SIZE && !line.ends_with('\n') {
+        return Ok(None);
+    }
+    Ok(Some(line))
+}
+
+fn handle(request_line: &str, cache_dir: &Path) -> (&'static str, String) {
+    let mut parts = request_line.split(' ');
+    let (method, target) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    if method != "GET" {
+        return (
+            "405 Method Not Allowed",
+            error_json("only GET is supported"),
+        );
+    }
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let instance = query
+        .split('&')
+        .filter_map(|p| p.split_once('='))
+        .find(|(k, _)| *k == "instance")
+        .map(|(_, v)| v);
+    let entries = find_entries(cache_dir);
+    match path.trim_end_matches('/').split('/').collect::<Vec<&str>>()[..] {
+        ["", "sections"] => ("200 OK", to_json_list(entries.iter(), false)),
+        ["", "schema"] => ("200 OK", schema::to_json()),
+        ["", "sections", name] => {
+            let selected = entries
+                .iter()
+                .filter(|e| e.section == name)
+                .filter(|e| instance.map_or(true, |i| e.instance.eq_ignore_ascii_case(i)))
+                .collect::<Vec<_>>();
+            if selected.is_empty() {
+                ("404 Not Found", error_json("no cached data"))
+            } else {
+                ("200 OK", to_json_list(selected.into_iter(), true))
+            }
+        }
+        _ => ("404 Not Found", error_json("unknown path")),
+    }
+}
+
+fn find_entries(cache_dir: &Path) -> Vec<CacheEntry> {
+    let mut entries = std::fs::read_dir(cache_dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|d| d.ok())
+        .map(|d| d.path())
+        .filter(|p| p.is_dir())
+        .flat_map(|d| std::fs::read_dir(d).into_iter().flatten())
+        .filter_map(|d| d.ok())
+        .filter_map(|d| CacheEntry::from_path(&d.path()))
+        .collect::<Vec<_>>();
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    entries
+}
+
+fn to_json_list<'a>(entries: impl Iterator<Item = &'a CacheEntry>, with_data: bool) -> String {
+    format!(
+        "[{}]",
+        entries
+            .map(|e| e.to_json(with_data))
+            .collect::<Vec<_>>()
+            .join(",")
+    )
+}
+
+fn error_json(message: &str) -> String {
+    format!("{{\"error\":\"{}\"}}", utils::escape_json(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handle() {
+        let dir = tempfile::tempdir().unwrap();
+        let sub_dir = dir.path().join("mssql-0123");
+        std::fs::create_dir(&sub_dir).unwrap();
+        std::fs::write(sub_dir.join("host;SQLEXPRESS;jobs.mssql"), "a\tb\n").unwrap();
+        std::fs::write(sub_dir.join("garbage.txt"), "").unwrap();
+
+        let (status, body) = handle("GET /sections HTTP/1.1", dir.path());
+        assert_eq!(status, "200 OK");
+        assert!(
+            body.starts_with(r#"[{"host":"host","instance":"SQLEXPRESS","section":"jobs","age":"#)
+        );
+        assert!(!body.contains("data"));
+
+        let (status, body) = handle(
+            "GET /sections/jobs?instance=sqlexpress HTTP/1.1",
+            dir.path(),
+        );
+        assert_eq!(status, "200 OK");
+        assert!(body.ends_with(r#","data":"a\tb\n"}]"#));
+        assert!(body.contains(r#","schema":{"name":"jobs","#));
+
+        let (status, body) = handle("GET /schema HTTP/1.1", dir.path());
+        assert_eq!(status, "200 OK");
+        assert!(body.starts_with(r#"{"version":"#));
+
+        let (status, _) = handle("GET /sections/jobs?instance=other HTTP/1.1", dir.path());
+        assert_eq!(status, "404 Not Found");
+        let (status, _) = handle("GET /unknown HTTP/1.1", dir.path());
+        assert_eq!(status, "404 Not Found");
+        let (status, _) = handle("POST /sections HTTP/1.1", dir.path());
+        assert_eq!(status, "405 Method Not Allowed");
+    }
+
+    #[tokio::test]
+    async fn test_read_head() {
+        let request = b"GET /sections HTTP/1.1\r\nHost: x\r\n\r\nignored";
+        let (request_line, headers) = read_head(&mut &request[..]).await.unwrap();
+        assert_eq!(request_line, "GET /sections HTTP/1.1\r\n");
+        assert_eq!(headers, vec!["Host: x\r\n".to_string()]);
+
+        let long = format!(
+            "GET /{} HTTP/1.1\r\n\r\n",
+            "a".repeat(MAX_LINE_SIZE as usize)
+        );
+        let e = read_head(&mut long.as_bytes()).await.unwrap_err();
+        assert_eq!(e.0, "414 URI Too Long");
+
+        let long = format!(
+            "GET / HTTP/1.1\r\nX: {}\r\n\r\n",
+            "a".repeat(MAX_LINE_SIZE as usize)
+        );
+        let e = read_head(&mut long.as_bytes()).await.unwrap_err();
+        assert_eq!(e.0, "431 Request Header Fields Too Large");
+
+        let many = format!(
+            "GET / HTTP/1.1\r\n{}\r\n",
+            "X: y\r\n".repeat(MAX_HEADERS + 1)
+        );
+        let e = read_head(&mut many.as_bytes()).await.unwrap_err();
+        assert_eq!(e.0, "431 Request Header Fields Too Large");
+
+        // the stream ends before the empty line
+        assert!(read_head(&mut &b"GET / HTTP/1.1\r\nHost: x\r\n"[..])
+            .await
+            .is_ok());
+    }
+
+    #[test]
+    fn test_to_bind_address() {
+        assert_eq!(to_bind_address("8090"), "127.0.0.1:8090");
+        assert_eq!(to_bind_address("0.0.0.0:8090"), "0.0.0.0:8090");
+    }
+
+    #[test]
+    fn test_is_authorized() {
+        let headers = [
+            "Host: x\r\n".to_string(),
+            "authorization: Bearer abc\r\n".to_string(),
+        ];
+        assert!(is_authorized(&[], None));
+        assert!(is_authorized(&headers, Some("abc")));
+        assert!(!is_authorized(&headers, Some("abd")));
+        assert!(!is_authorized(&headers, Some("abcd")));
+        assert!(!is_authorized(&headers[..1], Some("abc")));
+    }
+}
@@ -0,0 +1,248 @@
+// Copyright (C) 2024 Checkmk GmbH - License: GNU General Public License v2
+// This file is part of Checkmk (https://checkmk.com). It is subject to the terms and
+// conditions defined in the file COPYING, which is part of this source code package.
+
+/// Protection against overlapping runs with the same config
+/// - the lock is an OS lock(flock, LockFileEx) of a file in the state dir, the OS releases it
+///   when the run ends or crashes: no staleness by age, any long run keeps its lock
+/// - the file is never removed: a waiter can't lock a file unlinked by the previous owner
+/// - the file contains PID of the owner for diagnostics only
+/// - a busy run waits a bit and then reuses output of the last successful run
+/// - output of the last run is replayed if it is younger than `options.min_interval`
+use crate::setup::Env;
+use crate::utils;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+pub const WAIT_FOR_LOCK: Duration = Duration::from_secs(5);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// the lock is held while the file is open
+#[derive(Debug)]
+pub struct RunLock {
+    _file: File,
+}
+
+#[derive(Debug)]
+pub enum Lock {
+    /// this run owns the lock
+    Owned(RunLock),
+    /// another run is still active
+    Busy,
+    /// locking is not possible, for example no state dir
+    Unavailable,
+}
+
+pub fn acquire(environment: &Env, wait: Duration) -> Lock {
+    match environment.state_dir() {
+        Some(dir) => acquire_in(&lock_file(dir, environment.run_name()), wait),
+        None => {
+            log::warn!("No state dir, overlapping runs are not prevented");
+            Lock::Unavailable
+        }
+    }
+}
+
+fn acquire_in(file: &Path, wait: Duration) -> Lock {
+    let start = Instant::now();
+    let mut f = match OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(file)
+    {
+        Ok(f) => f,
+        Err(e) => {
+            log::error!("Failed to open lock {:?}: {e}", file);
+            return Lock::Unavailable;
+        }
+    };
+    loop {
+        match try_lock(&f) {
+            Ok(true) => {
+                f.set_len(0)
+                    .and_then(|_| writeln!(f, "{}", std::process::id()))
+                    .unwrap_or_else(|e| log::warn!("Failed to write PID into {:?}: {e}", file));
+                log::info!("Lock {:?} acquired", file);
+                return Lock::Owned(RunLock { _file: f });
+            }
+            Ok(false) if start.elapsed() >= wait => {
+                log::warn!("Lock {:?} is held by another run", file);
+                return Lock::Busy;
+            }
+            Ok(false) => std::thread::sleep(POLL_INTERVAL),
+            Err(e) => {
+                log::error!("Failed to lock {:?}: {e}", file);
+                return Lock::Unavailable;
+            }
+        }
+    }
+}
+
+/// exclusive lock without waiting, false: the file is locked by another owner
+#[cfg(unix)]
+fn try_lock(file: &File) -> std::io::Result<bool> {
+    use std::os::unix::io::AsRawFd;
+    const LOCK_EX: i32 = 2;
+    const LOCK_NB: i32 = 4;
+    extern "C" {
+        fn flock(fd: i32, operation: i32) -> i32;
+    }
+    // SAFETY: the descriptor is valid while `file` lives
+    if unsafe { flock(file.as_raw_fd(), LOCK_EX | LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+    let e = std::io::Error::last_os_error();
+    if e.kind() == std::io::ErrorKind::WouldBlock {
+        Ok(false)
+    } else {
+        Err(e)
+    }
+}
+
+/// exclusive lock without waiting, false: the file is locked by another owner
+#[cfg(windows)]
+fn try_lock(file: &File) -> std::io::Result<bool> {
+    use std::ffi::c_void;
+    use std::os::windows::io::AsRawHandle;
+    const LOCKFILE_FAIL_IMMEDIATELY: u32 = 1;
+    const LOCKFILE_EXCLUSIVE_LOCK: u32 = 2;
+    const ERROR_LOCK_VIOLATION: i32 = 33;
+    #[repr(C)]
+    struct Overlapped {
+        internal: usize,
+        internal_high: usize,
+        offset: u32,
+        offset_high: u32,
+        event: *mut c_void,
+    }
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn LockFileEx(
+            file: *mut c_void,
+            flags: u32,
+            reserved: u32,
+            bytes_low: u32,
+            bytes_high: u32,
+            overlapped: *mut Overlapped,
+        ) -> i32;
+    }
+    let mut overlapped = Overlapped {
+        internal: 0,
+        internal_high: 0,
+        offset: 0,
+        offset_high: 0,
+        event: std::ptr::null_mut(),
+    };
+    // SAFETY: the handle is valid while `file` lives, the call is synchronous
+    let locked = unsafe {
+        LockFileEx(
+            file.as_raw_handle(),
+            LOCKFILE_EXCLUSIVE_LOCK | LOCKFILE_FAIL_IMMEDIATELY,
+            0,
+            u32::MAX,
+            u32::MAX,
+            &mut overlapped,
+        )
+    };
+    if locked != 0 {
+        return Ok(true);
+    }
+    let e = std::io::Error::last_os_error();
+    if e.raw_os_error() == Some(ERROR_LOCK_VIOLATION) {
+        Ok(false)
+    } else {
+        Err(e)
+    }
+}
+
+/// stores output of the successful run to be reused by busy runs
+pub fn store_output(environment: &Env, output: &str) {
+    if let Some(dir) = environment.state_dir() {
+        store_output_in(&output_file(dir, environment.run_name()), output);
+    }
+}
+
+fn store_output_in(file: &Path, output: &str) {
+    fs::write(file, output)
+        .and_then(|_| {
+            fs::write(
+                file.with_extension(utils::STAMP_EXTENSION),
+                utils::TimeStamp::now().to_string(),
+            )
+        })
+        .unwrap_or_else(|e| log::warn!("Failed to store output {:?}: {e}", file));
+}
+
+/// output of the last successful run if it is younger than `max_age`
+pub fn load_recent_output(environment: &Env, max_age: Duration) -> Option<String> {
+    environment
+        .state_dir()
+        .and_then(|dir| load_recent_output_in(&output_file(dir, environment.run_name()), max_age))
+}
+
+fn load_recent_output_in(file: &Path, max_age: Duration) -> Option<String> {
+    utils::get_stamped_age(file)
+        .filter(|&age| age < max_age.as_secs())
+        .and_then(|_| utils::read_file(file).ok())
+}
+
+pub fn load_output(environment: &Env) -> Option<String> {
+    environment
+        .state_dir()
+        .and_then(|dir| utils::read_file(&output_file(dir, environment.run_name())).ok())
+}
+
+fn lock_file(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{name}.lock"))
+}
+
+fn output_file(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{name}.last-output"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_acquire_in() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = lock_file(dir.path(), "mk-sql");
+        let lock = acquire_in(&file, Duration::ZERO);
+        assert!(matches!(lock, Lock::Owned(_)));
+        assert!(matches!(acquire_in(&file, Duration::ZERO), Lock::Busy));
+        drop(lock);
+        assert!(file.exists());
+        assert!(matches!(acquire_in(&file, Duration::ZERO), Lock::Owned(_)));
+    }
+
+    #[test]
+    fn test_acquire_in_left_by_crash() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = lock_file(dir.path(), "mk-sql");
+        fs::write(&file, "999999999\nleft\n").unwrap();
+        let lock = acquire_in(&file, Duration::ZERO);
+        assert!(matches!(lock, Lock::Owned(_)));
+        drop(lock);
+        assert_eq!(
+            fs::read_to_string(&file).unwrap(),
+            format!("{}\n", std::process::id())
+        );
+    }
+
+    #[test]
+    fn test_load_recent_output_in() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = output_file(dir.path(), "mk-sql");
+        assert!(load_recent_output_in(&file, Duration::from_secs(30)).is_none());
+        store_output_in(&file, "<<<mssql_instance>>>\n");
+        assert_eq!(
+            load_recent_output_in(&file, Duration::from_secs(30)).as_deref(),
+            Some("<<<mssql_instance>>>\n")
+        );
+        assert!(load_recent_output_in(&file, Duration::ZERO).is_none());
+    }
+}
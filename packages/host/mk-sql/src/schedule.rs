@@ -0,0 +1,355 @@
+// Copyright (C) 2024 Checkmk GmbH - License: GNU General Public License v2
+// This file is part of Checkmk (https://checkmk.com). It is subject to the terms and
+// conditions defined in the file COPYING, which is part of this source code package.
+
+//! Periodic runs refreshing caches of async sections: `mk-sql install-schedule`.
+//!
+//! Linux: entry in the crontab of the user, marked with `# mk-sql:<run name>`.
+//! Windows: Scheduled Task `Checkmk mk-sql <run name>`.
+use crate::setup::Env;
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+#[cfg(any(unix, test))]
+const CRON_MARKER: &str = "# mk-sql:";
+#[cfg(any(windows, test))]
+const TASK_PREFIX: &str = "Checkmk mk-sql";
+/// Windows service account of the agent
+#[cfg(any(windows, test))]
+const DEFAULT_TASK_USER: &str = "SYSTEM";
+
+pub struct Schedule {
+    /// minutes
+    pub interval: u32,
+    pub user: Option<String>,
+    pub spool_dir: Option<PathBuf>,
+}
+
+pub fn exec(environment: &Env, schedule: &Schedule, remove: bool, dry_run: bool) -> Result<String> {
+    let exe = std::env::current_exe()?;
+    let args = make_run_args(environment);
+    #[cfg(unix)]
+    {
+        exec_cron(
+            &exe,
+            &args,
+            environment.run_name(),
+            schedule,
+            remove,
+            dry_run,
+        )
+    }
+    #[cfg(windows)]
+    {
+        exec_task(
+            &exe,
+            &args,
+            environment.run_name(),
+            schedule,
+            remove,
+            dry_run,
+        )
+    }
+}
+
+/// scheduler has neither working dir nor environment of the agent: everything is explicit
+fn make_run_args(environment: &Env) -> Vec<String> {
+    let absolute = |p: &Path| {
+        std::fs::canonicalize(p)
+            .unwrap_or_else(|_| p.to_owned())
+            .display()
+            .to_string()
+    };
+    let mut args = vec![
+        "--config-file".to_string(),
+        absolute(environment.config_file()),
+    ];
+    if let Some(dir) = environment.state_dir() {
+        args.extend(["--state-dir".to_string(), absolute(dir)]);
+    }
+    if let Some(dir) = environment.log_dir() {
+        args.extend(["--log-dir".to_string(), absolute(dir)]);
+    }
+    args
+}
+
+#[cfg(unix)]
+fn exec_cron(
+    exe: &Path,
+    args: &[String],
+    run_name: &str,
+    schedule: &Schedule,
+    remove: bool,
+    dry_run: bool,
+) -> Result<String> {
+    let line = if remove {
+        None
+    } else {
+        Some(make_cron_line(exe, args, run_name, schedule)?)
+    };
+    let user = schedule.user.as_deref();
+    let current = read_crontab(user)?;
+    let updated = update_crontab(&current, line.as_deref(), run_name);
+    if dry_run {
+        return Ok(updated);
+    }
+    write_crontab(user, &updated)?;
+    Ok(match line {
+        Some(line) => format!("Installed: {line}\n"),
+        None => format!("Removed schedule of `{run_name}`\n"),
+    })
+}
+
+#[cfg(any(unix, test))]
+fn make_cron_line(
+    exe: &Path,
+    args: &[String],
+    run_name: &str,
+    schedule: &Schedule,
+) -> Result<String> {
+    let when = match schedule.interval {
+        i @ 1..=59 => format!("*/{i} * * * *"),
+        i if i % 60 == 0 && i / 60 < 24 => format!("0 */{} * * *", i / 60),
+        i => anyhow::bail!("Interval {i} can't be expressed in cron: use below 60 or hours"),
+    };
+    let command = std::iter::once(exe.display().to_string())
+        .chain(args.iter().cloned())
+        .map(|a| shell_quote(&a))
+        .collect::<Vec<String>>()
+        .join(" ");
+    let output = match &schedule.spool_dir {
+        Some(dir) => {
+            let file = make_spool_file(dir, run_name, schedule.interval);
+            let target = shell_quote(&file.display().to_string());
+            let temp = shell_quote(&format!("{}.tmp", file.display()));
+            format!(" > {temp} 2>/dev/null && mv {temp} {target}")
+        }
+        None => " > /dev/null 2>&1".to_string(),
+    };
+    Ok(format!("{when} {command}{output} {CRON_MARKER}{run_name}"))
+}
+
+/// spool file name starts with max age in seconds, twice the interval is tolerated
+fn make_spool_file(dir: &Path, run_name: &str, interval: u32) -> PathBuf {
+    dir.join(format!("{}_mk-sql-{run_name}", interval * 120))
+}
+
+/// replaces the entry of the run keeping the rest of crontab untouched
+#[cfg(any(unix, test))]
+fn update_crontab(current: &str, line: Option<&str>, run_name: &str) -> String {
+    let marker = format!("{CRON_MARKER}{run_name}");
+    current
+        .lines()
+        .filter(|l| !l.trim_end().ends_with(&marker))
+        .chain(line)
+        .map(|l| format!("{l}\n"))
+        .collect()
+}
+
+#[cfg(any(unix, test))]
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(unix)]
+fn crontab_command(user: Option<&str>) -> std::process::Command {
+    let mut command = std::process::Command::new("crontab");
+    if let Some(user) = user {
+        command.args(["-u", user]);
+    }
+    command
+}
+
+#[cfg(unix)]
+fn read_crontab(user: Option<&str>) -> Result<String> {
+    let output = crontab_command(user).arg("-l").output()?;
+    // crontab -l fails if the user has no crontab yet
+    Ok(if output.status.success() {
+        String::from_utf8_lossy(&output.stdout).to_string()
+    } else {
+        String::new()
+    })
+}
+
+#[cfg(unix)]
+fn write_crontab(user: Option<&str>, content: &str) -> Result<()> {
+    use std::io::Write;
+    let mut child = crontab_command(user)
+        .arg("-")
+        .stdin(std::process::Stdio::piped())
+        .spawn()?;
+    child
+        .stdin
+        .take()
+        .ok_or_else(|| anyhow::anyhow!("No stdin for crontab"))?
+        .write_all(content.as_bytes())?;
+    let status = child.wait()?;
+    if !status.success() {
+        anyhow::bail!("crontab failed with {status}");
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+fn exec_task(
+    exe: &Path,
+    args: &[String],
+    run_name: &str,
+    schedule: &Schedule,
+    remove: bool,
+    dry_run: bool,
+) -> Result<String> {
+    let schtasks_args = if remove {
+        make_task_delete_args(run_name)
+    } else {
+        make_task_create_args(exe, args, run_name, schedule)?
+    };
+    if dry_run {
+        return Ok(format!("schtasks {}\n", schtasks_args.join(" ")));
+    }
+    let output = std::process::Command::new("schtasks")
+        .args(&schtasks_args)
+        .output()?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "schtasks failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+#[cfg(any(windows, test))]
+fn make_task_name(run_name: &str) -> String {
+    format!("{TASK_PREFIX} {run_name}")
+}
+
+#[cfg(any(windows, test))]
+fn make_task_create_args(
+    exe: &Path,
+    args: &[String],
+    run_name: &str,
+    schedule: &Schedule,
+) -> Result<Vec<String>> {
+    // Scheduled Task has no redirection of output: the run writes the spool file itself
+    let spool_args = schedule.spool_dir.iter().flat_map(|dir| {
+        [
+            "--spool-file".to_string(),
+            make_spool_file(dir, run_name, schedule.interval)
+                .display()
+                .to_string(),
+        ]
+    });
+    let task_run = std::iter::once(exe.display().to_string())
+        .chain(args.iter().cloned())
+        .chain(spool_args)
+        .map(|a| format!("\"{a}\""))
+        .collect::<Vec<String>>()
+        .join(" ");
+    Ok([
+        "/Create",
+        "/TN",
+        make_task_name(run_name).as_str(),
+        "/TR",
+        task_run.as_str(),
+        "/SC",
+        "MINUTE",
+        "/MO",
+        schedule.interval.to_string().as_str(),
+        "/RU",
+        schedule.user.as_deref().unwrap_or(DEFAULT_TASK_USER),
+        "/F",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect())
+}
+
+#[cfg(any(windows, test))]
+fn make_task_delete_args(run_name: &str) -> Vec<String> {
+    ["/Delete", "/TN", make_task_name(run_name).as_str(), "/F"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_schedule(interval: u32, spool_dir: Option<&str>) -> Schedule {
+        Schedule {
+            interval,
+            user: None,
+            spool_dir: spool_dir.map(PathBuf::from),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_make_cron_line() {
+        let exe = Path::new("/usr/bin/mk-sql");
+        let args = ["--config-file".to_string(), "/etc/it's.yml".to_string()];
+        assert_eq!(
+            make_cron_line(exe, &args, "mk-sql", &make_schedule(5, None)).unwrap(),
+            r"*/5 * * * * '/usr/bin/mk-sql' '--config-file' '/etc/it'\''s.yml' > /dev/null 2>&1 # mk-sql:mk-sql"
+        );
+        assert_eq!(
+            make_cron_line(exe, &[], "a", &make_schedule(120, Some("/spool"))).unwrap(),
+            "0 */2 * * * '/usr/bin/mk-sql' > '/spool/14400_mk-sql-a.tmp' 2>/dev/null \
+             && mv '/spool/14400_mk-sql-a.tmp' '/spool/14400_mk-sql-a' # mk-sql:a"
+        );
+        assert!(make_cron_line(exe, &[], "a", &make_schedule(90, None)).is_err());
+    }
+
+    #[test]
+    fn test_update_crontab() {
+        let current = "MAILTO=x\n*/5 * * * * old # mk-sql:a\n1 * * * * other # mk-sql:b\n";
+        assert_eq!(
+            update_crontab(current, Some("*/1 * * * * new # mk-sql:a"), "a"),
+            "MAILTO=x\n1 * * * * other # mk-sql:b\n*/1 * * * * new # mk-sql:a\n"
+        );
+        assert_eq!(
+            update_crontab(current, None, "a"),
+            "MAILTO=x\n1 * * * * other # mk-sql:b\n"
+        );
+        assert_eq!(
+            update_crontab("", Some("x # mk-sql:a"), "a"),
+            "x # mk-sql:a\n"
+        );
+    }
+
+    #[test]
+    fn test_make_task_args() {
+        let exe = Path::new(r"C:\mk-sql.exe");
+        let args = ["--config-file".to_string(), r"C:\my cfg.yml".to_string()];
+        assert_eq!(
+            make_task_create_args(exe, &args, "mk-sql", &make_schedule(10, None)).unwrap(),
+            [
+                "/Create",
+                "/TN",
+                "Checkmk mk-sql mk-sql",
+                "/TR",
+                r#""C:\mk-sql.exe" "--config-file" "C:\my cfg.yml""#,
+                "/SC",
+                "MINUTE",
+                "/MO",
+                "10",
+                "/RU",
+                "SYSTEM",
+                "/F"
+            ]
+        );
+        assert_eq!(
+            make_task_create_args(exe, &[], "a", &make_schedule(10, Some(r"C:\spool"))).unwrap()[4],
+            format!(
+                r#""C:\mk-sql.exe" "--spool-file" "{}""#,
+                Path::new(r"C:\spool").join("1200_mk-sql-a").display()
+            )
+        );
+        assert_eq!(
+            make_task_delete_args("a"),
+            ["/Delete", "/TN", "Checkmk mk-sql a", "/F"]
+        );
+    }
+}
@@ -2,10 +2,12 @@
 // This file is part of Checkmk (https://checkmk.com). It is subject to the terms and
 // conditions defined in the file COPYING, which is part of this source code package.
 
-use crate::args::Args;
+use crate::args::{Args, Command, Tool};
 use crate::config::system::{Logging, SystemConfig};
 use crate::config::CheckConfig;
 use crate::constants;
+use crate::crypto;
+use crate::ms_sql::fixture;
 use anyhow::Result;
 use clap::Parser;
 use flexi_logger::{self, Cleanup, Criterion, DeferredNow, FileSpec, LogSpecification, Record};
@@ -25,12 +27,33 @@ pub struct Env {
 
     ///
     disable_caching: bool,
+
+    /// remove all foreign cache and state files, not only stale ones
+    purge_state: bool,
+
+    config_file: PathBuf,
+
+    /// name of the config file without extension, used for run lock
+    run_name: String,
+
+    /// daemon mode: serve cached data instead of collecting
+    rest_api: Option<String>,
+
+    /// output is written into the file instead of stdout
+    spool_file: Option<PathBuf>,
+
+    /// subcommand to execute instead of collecting
+    command: Option<Command>,
+
+    /// sections used by the site, None - all
+    used_sections: Option<Vec<String>>,
 }
 
 impl Env {
     pub fn new(args: &Args) -> Self {
         let log_dir = Env::build_dir(&args.log_dir, &constants::ENV_LOG_DIR.as_deref());
         let temp_dir = Env::build_dir(&args.temp_dir, &constants::ENV_TEMP_DIR.as_deref());
+        Env::prepare_custom_dir(&args.state_dir);
         #[cfg(windows)]
         let state_dir = Env::build_dir(&args.state_dir, &constants::ENV_STATE_DIR.as_deref());
         #[cfg(unix)]
@@ -39,7 +62,18 @@ impl Env {
             temp_dir,
             log_dir,
             state_dir,
-            disable_caching: args.no_spool,
+            // fixtures must contain all queries: cache is not used
+            disable_caching: args.no_spool || args.use_fixture(),
+            purge_state: args.purge_state,
+            config_file: get_config_file(args),
+            run_name: get_config_file(args)
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_else(|| "mk-sql".to_string()),
+            rest_api: args.rest_api.clone(),
+            spool_file: args.spool_file.clone(),
+            command: args.command.clone(),
+            used_sections: args.used_sections.as_deref().and_then(read_used_sections),
         }
     }
 
@@ -62,12 +96,67 @@ impl Env {
         self.disable_caching
     }
 
+    pub fn purge_state(&self) -> bool {
+        self.purge_state
+    }
+
+    pub fn config_file(&self) -> &Path {
+        &self.config_file
+    }
+
+    pub fn run_name(&self) -> &str {
+        &self.run_name
+    }
+
+    pub fn rest_api(&self) -> Option<&str> {
+        self.rest_api.as_deref()
+    }
+
+    pub fn spool_file(&self) -> Option<&Path> {
+        self.spool_file.as_deref()
+    }
+
+    /// the tool to run instead of sections
+    pub fn tool(&self) -> Option<&Tool> {
+        match &self.command {
+            Some(Command::Tool(tool)) => Some(tool),
+            _ => None,
+        }
+    }
+
+    /// only async sections are generated, existing caches are ignored
+    pub fn is_warmup(&self) -> bool {
+        self.command == Some(Command::Warmup)
+    }
+
+    /// sections used by the site, None - all
+    pub fn used_sections(&self) -> Option<&[String]> {
+        self.used_sections.as_deref()
+    }
+
+    /// the instance section is always used: it signals that the plugin works
+    pub fn is_section_used(&self, name: &str) -> bool {
+        name == crate::config::section::names::INSTANCE
+            || self
+                .used_sections
+                .as_ref()
+                .map_or(true, |used| used.iter().any(|u| u == name))
+    }
+
     /// guaranteed to return cache dir or None
     pub fn base_cache_dir(&self) -> Option<PathBuf> {
         self.state_dir()
             .map(|state_dir| state_dir.join("mk-sql-cache"))
     }
 
+    /// custom dir is a namespace defined by user: it must be usable even if absent
+    fn prepare_custom_dir(dir: &Option<PathBuf>) {
+        if let Some(dir) = dir.as_deref().filter(|d| !d.exists()) {
+            std::fs::create_dir_all(dir)
+                .unwrap_or_else(|e| eprintln!("Failed to create dir {:?}: {e}", dir));
+        }
+    }
+
     fn build_dir(dir: &Option<PathBuf>, fallback: &Option<&Path>) -> Option<PathBuf> {
         if dir.is_some() {
             dir.as_deref()
@@ -114,14 +203,19 @@ pub fn init(args: ArgsOs) -> Result<(CheckConfig, Env)> {
     let args = Args::parse_from(args);
     let config_file = get_config_file(&args);
 
-    let logging_config = get_system_config(&config_file)
-        .map(|x| Some(x.logging().to_owned()))
-        .unwrap_or(None);
+    let system_config = get_system_config(&config_file).ok();
+    let logging_config = system_config.as_ref().map(|x| x.logging().to_owned());
     let environment = Env::new(&args);
     init_logging(&args, &environment, logging_config)?;
     if !config_file.exists() {
         anyhow::bail!("The config file {:?} doesn't exist", config_file);
     }
+    crypto::init(system_config.map(|x| x.fips()).unwrap_or_default())?;
+    fixture::init(
+        args.record_fixture.as_deref(),
+        args.replay_fixture.as_deref(),
+        args.anonymize,
+    )?;
     Ok((get_check_config(&config_file)?, environment))
 }
 
@@ -254,6 +348,24 @@ fn make_log_file_spec(log_dir: &Path) -> FileSpec {
         .basename("mk-sql")
 }
 
+/// names with or without `mssql_` prefix, empty lines and `#` comments are ignored
+fn read_used_sections(file: &Path) -> Option<Vec<String>> {
+    match std::fs::read_to_string(file) {
+        Ok(content) => Some(
+            content
+                .lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                .map(|l| l.strip_prefix("mssql_").unwrap_or(l).to_string())
+                .collect(),
+        ),
+        Err(e) => {
+            log::warn!("Can't read used sections {file:?}: {e}, all sections are generated");
+            None
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -285,6 +397,56 @@ mod tests {
         );
     }
     #[test]
+    fn test_env_custom_state_dir_created() {
+        let dir = tempfile::tempdir().unwrap();
+        let state_dir = dir.path().join("archive");
+        let args = Args {
+            state_dir: Some(state_dir.clone()),
+            config_file: Some(PathBuf::from("archive.yml")),
+            ..Default::default()
+        };
+        let e = Env::new(&args);
+        assert_eq!(e.state_dir(), Some(state_dir.as_path()));
+        assert_eq!(e.run_name(), "archive");
+    }
+    #[test]
+    fn test_env_warmup() {
+        assert!(!Env::new(&Args::default()).is_warmup());
+        let args = Args {
+            command: Some(Command::Warmup),
+            ..Default::default()
+        };
+        assert!(Env::new(&args).is_warmup());
+        assert_eq!(Env::new(&args).tool(), None);
+        let args = Args {
+            command: Some(Command::Tool(Tool::Schema)),
+            ..Default::default()
+        };
+        assert!(!Env::new(&args).is_warmup());
+        assert_eq!(Env::new(&args).tool(), Some(&Tool::Schema));
+    }
+    #[test]
+    fn test_env_used_sections() {
+        assert!(Env::new(&Args::default()).is_section_used("jobs"));
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("used");
+        std::fs::write(&file, "# site labels\nmssql_jobs\n\n  backup\n").unwrap();
+        let args = Args {
+            used_sections: Some(file),
+            ..Default::default()
+        };
+        let e = Env::new(&args);
+        assert!(e.is_section_used("jobs"));
+        assert!(e.is_section_used("backup"));
+        assert!(e.is_section_used("instance"));
+        assert!(!e.is_section_used("mirroring"));
+        let args = Args {
+            used_sections: Some(dir.path().join("absent")),
+            ..Default::default()
+        };
+        assert!(Env::new(&args).is_section_used("mirroring"));
+    }
+    #[test]
     fn test_env_dir_absent() {
         let args = Args {
             log_dir: Some(PathBuf::from("weird-dir")),
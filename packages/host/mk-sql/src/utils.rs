@@ -42,19 +42,203 @@ pub fn get_utc_now() -> Result<u64> {
         .as_secs())
 }
 
+/// `YYYY-MM-DD hh:mm:ss` as `CONVERT(nvarchar, GETUTCDATE(), 20)` gives
+pub fn format_utc(seconds: u64) -> String {
+    let time = seconds % 86_400;
+    format!(
+        "{} {:02}:{:02}:{:02}",
+        format_date((seconds / 86_400) as i64),
+        time / 3_600,
+        time % 3_600 / 60,
+        time % 60
+    )
+}
+
+/// `YYYY-MM-DD` of the day since 1970-01-01, negative days are before
+pub fn format_date(days: i64) -> String {
+    let (year, month, day) = civil_from_days(days);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// (year, month, day), Howard Hinnant's algorithm
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (yoe + era * 400 + i64::from(month <= 2), month, day)
+}
+
 pub fn get_modified_age<P: AsRef<Path>>(path: P) -> Result<u64> {
     let modified = get_modified_utc_time(path)?;
     let now = get_utc_now()?;
     Ok(if now >= modified { now - modified } else { 0 })
 }
 
+/// seconds since boot, unlike the wall clock not affected by NTP corrections and manual
+/// changes of the system time
+#[cfg(target_os = "linux")]
+pub fn get_uptime() -> Option<u64> {
+    fs::read_to_string("/proc/uptime")
+        .ok()?
+        .split_whitespace()
+        .next()?
+        .parse::<f64>()
+        .ok()
+        .map(|u| u as u64)
+}
+
+#[cfg(windows)]
+pub fn get_uptime() -> Option<u64> {
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetTickCount64() -> u64;
+    }
+    // SAFETY: no arguments, no side effects
+    Some(unsafe { GetTickCount64() } / 1000)
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+pub fn get_uptime() -> Option<u64> {
+    None
+}
+
+/// Moment as wall clock and uptime pair, stored as `wall uptime`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeStamp {
+    wall: u64,
+    uptime: Option<u64>,
+}
+
+impl TimeStamp {
+    pub fn now() -> Self {
+        Self {
+            wall: get_utc_now().unwrap_or_default(),
+            uptime: get_uptime(),
+        }
+    }
+
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut parts = text.split_whitespace();
+        let wall = parts.next()?.parse::<u64>().ok()?;
+        let uptime = parts.next().and_then(|u| u.parse::<u64>().ok());
+        Some(Self { wall, uptime })
+    }
+
+    pub fn wall(&self) -> u64 {
+        self.wall
+    }
+
+    /// Seconds elapsed since `self`:
+    /// - same boot(uptime didn't decrease): uptime difference, clock jumps are ignored
+    /// - otherwise: wall clock difference, None if the clock went back
+    pub fn age(&self, now: &TimeStamp) -> Option<u64> {
+        match (self.uptime, now.uptime) {
+            (Some(then), Some(up)) if up >= then => Some(up - then),
+            _ => now.wall.checked_sub(self.wall),
+        }
+    }
+}
+
+impl std::fmt::Display for TimeStamp {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.uptime {
+            Some(uptime) => write!(f, "{} {}", self.wall, uptime),
+            None => write!(f, "{}", self.wall),
+        }
+    }
+}
+
+/// extension of the file with the time stamp of the file with the same stem
+pub const STAMP_EXTENSION: &str = "stamp";
+
+/// age by the time stamp file next to the path, by the modification time if there is no stamp
+/// None if the stamp is invalid or in the future
+pub fn get_stamped_age<P: AsRef<Path>>(path: P) -> Option<u64> {
+    match fs::read_to_string(path.as_ref().with_extension(STAMP_EXTENSION)) {
+        Ok(text) => TimeStamp::parse(&text).and_then(|stamp| stamp.age(&TimeStamp::now())),
+        Err(_) => get_modified_age(path).ok(),
+    }
+}
+
+/// content of a JSON string literal, quotes are not added
+pub fn escape_json(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            '"' => "\\\"".to_string(),
+            '\\' => "\\\\".to_string(),
+            '\n' => "\\n".to_string(),
+            '\r' => "\\r".to_string(),
+            '\t' => "\\t".to_string(),
+            c if (c as u32) < 0x20 => format!("\\u{:04x}", c as u32),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
-    use super::get_modified_utc_time;
+    use super::{
+        escape_json, format_date, format_utc, get_modified_utc_time, get_stamped_age, TimeStamp,
+        STAMP_EXTENSION,
+    };
 
     #[test]
     fn test_get_utc_modified_time() {
         let e = get_modified_utc_time(".").unwrap();
         assert!(e > 1700000000);
     }
+
+    #[test]
+    fn test_format_utc() {
+        assert_eq!(format_utc(0), "1970-01-01 00:00:00");
+        assert_eq!(format_utc(951_782_400), "2000-02-29 00:00:00");
+        assert_eq!(format_utc(1_704_067_199), "2023-12-31 23:59:59");
+        assert_eq!(format_date(-25_567), "1900-01-01");
+        assert_eq!(format_date(-719_162), "0001-01-01");
+    }
+
+    #[test]
+    fn test_escape_json() {
+        assert_eq!(
+            escape_json("a\"b\\c\nd\te\u{1}"),
+            "a\\\"b\\\\c\\nd\\te\\u0001"
+        );
+    }
+
+    #[test]
+    fn test_time_stamp_age() {
+        let stamp = TimeStamp::parse("1000 50").unwrap();
+        assert_eq!(stamp.to_string(), "1000 50");
+        assert_eq!(stamp.wall(), 1000);
+        // clock went back/forward within the same boot
+        assert_eq!(stamp.age(&TimeStamp::parse("500 80").unwrap()), Some(30));
+        assert_eq!(stamp.age(&TimeStamp::parse("90000 80").unwrap()), Some(30));
+        // reboot
+        assert_eq!(stamp.age(&TimeStamp::parse("1100 10").unwrap()), Some(100));
+        assert_eq!(stamp.age(&TimeStamp::parse("900 10").unwrap()), None);
+        // no uptime
+        let stamp = TimeStamp::parse("1000").unwrap();
+        assert_eq!(stamp.to_string(), "1000");
+        assert_eq!(stamp.age(&TimeStamp::parse("1200 10").unwrap()), Some(200));
+        assert!(TimeStamp::parse("").is_none());
+        assert!(TimeStamp::now().wall() > 1700000000);
+    }
+
+    #[test]
+    fn test_get_stamped_age() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("h;A;jobs.mssql");
+        std::fs::write(&path, "data").unwrap();
+        assert!(get_stamped_age(&path).unwrap() < 10);
+        let stamp = path.with_extension(STAMP_EXTENSION);
+        std::fs::write(&stamp, TimeStamp::now().to_string()).unwrap();
+        assert!(get_stamped_age(&path).unwrap() < 10);
+        std::fs::write(&stamp, "99999999999").unwrap();
+        assert!(get_stamped_age(&path).is_none());
+    }
 }
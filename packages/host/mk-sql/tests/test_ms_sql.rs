@@ -14,7 +14,7 @@ use std::{collections::HashSet, fs::create_dir_all};
 
 use mk_sql::ms_sql::{
     client::{self, UniClient},
-    instance::{self, SqlInstance, SqlInstanceBuilder},
+    instance::{self, InstanceContext, SqlInstance, SqlInstanceBuilder},
     query,
     section::Section,
     sqls::{self, find_known_query},
@@ -372,7 +372,11 @@ async fn validate_database_names(instance: &SqlInstance, client: &mut UniClient)
 
 async fn validate_counters(instance: &SqlInstance, client: &mut UniClient) {
     let counters = instance
-        .generate_counters_section(client, find_known_query(sqls::Id::Counters).unwrap(), '|')
+        .generate_counters_section(
+            client,
+            &make_section(names::COUNTERS),
+            find_known_query(sqls::Id::Counters).unwrap(),
+        )
         .await;
     let result = counters.split('\n').collect::<Vec<&str>>();
     assert!(result[0].starts_with("None|utc_time|None|"));
@@ -670,7 +674,12 @@ async fn validate_clusters(_instance: &SqlInstance, _client: &mut UniClient, _en
 
 async fn validate_jobs(instance: &SqlInstance, endpoint: &Endpoint) {
     let result = instance
-        .generate_unified_section(endpoint, &make_section(names::JOBS), None)
+        .generate_unified_section(
+            endpoint,
+            &make_section(names::JOBS),
+            &InstanceContext::default(),
+            None,
+        )
         .await;
     let lines: Vec<&str> = result.split('\n').collect();
     assert_eq!(lines.len(), 3, "{:?}", lines);
@@ -689,6 +698,18 @@ async fn validate_jobs(instance: &SqlInstance, endpoint: &Endpoint) {
             values[column]
         );
     }
+    assert!(
+        [
+            "Failed",
+            "Succeeded",
+            "Retry",
+            "Canceled",
+            "In progress",
+            "Unknown"
+        ]
+        .contains(&values[6]),
+        "{values:?}"
+    );
     assert!(values[11].len() > 10, "{values:?}");
 }
 
@@ -697,6 +718,7 @@ async fn validate_query_error(instance: &SqlInstance, endpoint: &Endpoint, secti
         .generate_unified_section(
             endpoint,
             section,
+            &InstanceContext::default(),
             sqls::find_known_query(sqls::Id::BadQuery).ok(),
         )
         .await;
@@ -711,7 +733,7 @@ async fn validate_query_error(instance: &SqlInstance, endpoint: &Endpoint, secti
 async fn validate_mirroring_section(instance: &SqlInstance, endpoint: &Endpoint) {
     let section = make_section(names::MIRRORING);
     let lines: Vec<String> = instance
-        .generate_unified_section(endpoint, &section, None)
+        .generate_unified_section(endpoint, &section, &InstanceContext::default(), None)
         .await
         .split('\n')
         .map(|l| l.to_string())
@@ -724,7 +746,7 @@ async fn validate_mirroring_section(instance: &SqlInstance, endpoint: &Endpoint)
 async fn validate_availability_groups_section(instance: &SqlInstance, endpoint: &Endpoint) {
     let section = make_section(names::AVAILABILITY_GROUPS);
     let lines: Vec<String> = instance
-        .generate_unified_section(endpoint, &section, None)
+        .generate_unified_section(endpoint, &section, &InstanceContext::default(), None)
         .await
         .split('\n')
         .map(|l| l.to_string())
@@ -1292,7 +1314,8 @@ async fn test_lack_of_sql_db() {
 <<<mssql_backup:sep(124)>>>
 <<<mssql_jobs:sep(09)>>>
 <<<mssql_instance:sep(124)>>>
-ERROR: Failed to gather SQL server instances\n"
+ERROR: Failed to gather SQL server instances
+<<<mssql_setup_errors:sep(124)>>>\n"
         .to_owned();
     assert_eq!(output.to_owned(), awaited);
 }